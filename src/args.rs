@@ -1,12 +1,26 @@
 use anyhow::Result;
 
-use crate::redis::config::Config;
+use crate::{
+    redis::{config::Config, notify::NotifyFlags},
+    sentinel::Monitor,
+};
 
 pub struct Args {
     pub port: u16,
     pub replica_of: Option<(String, u16)>,
     pub dir: Option<String>,
     pub db_file_name: Option<String>,
+    pub replica_read_only: bool,
+    pub min_replicas_to_write: usize,
+    pub min_replicas_max_lag: u64,
+    pub repl_diskless_sync: bool,
+    pub repl_diskless_sync_delay: u64,
+    pub notify_keyspace_events: NotifyFlags,
+    //Run as a sentinel watching the monitored masters instead of serving data
+    pub sentinel: bool,
+    pub monitors: Vec<Monitor>,
+    pub down_after_milliseconds: u64,
+    pub failover_timeout: u64,
 }
 
 impl Args {
@@ -14,15 +28,26 @@ impl Args {
         let mut dir = None;
         let mut db_file_name = None;
         let mut replica_of = None;
-        let mut port: u16 = 6379;
+        let mut port = None;
+        let mut replica_read_only = true;
+        let mut min_replicas_to_write = 0;
+        let mut min_replicas_max_lag = 10;
+        let mut repl_diskless_sync = false;
+        let mut repl_diskless_sync_delay = 5;
+        let mut notify_keyspace_events = NotifyFlags::default();
+        let mut sentinel = false;
+        let mut monitors = vec![];
+        let mut down_after_milliseconds = 30000;
+        let mut failover_timeout = 180000;
         let mut args = std::env::args().skip(1);
         while let Some(arg) = args.next() {
             match arg.as_str() {
                 "--port" => {
-                    port = args
-                        .next()
-                        .ok_or_else(|| anyhow::anyhow!("Missing value for --port"))?
-                        .parse()?;
+                    port = Some(
+                        args.next()
+                            .ok_or_else(|| anyhow::anyhow!("Missing value for --port"))?
+                            .parse()?,
+                    );
                 }
                 "--replicaof" => {
                     let replica_of_s = args
@@ -47,14 +72,102 @@ impl Args {
                             .ok_or_else(|| anyhow::anyhow!("Missing value for --dbfilename"))?,
                     );
                 }
+                "--replica-read-only" | "--slave-read-only" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?;
+                    replica_read_only = match value.to_lowercase().as_str() {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(anyhow::anyhow!("Invalid value for {}: {}", arg, value)),
+                    };
+                }
+                "--min-replicas-to-write" | "--min-slaves-to-write" => {
+                    min_replicas_to_write = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?
+                        .parse()?;
+                }
+                "--min-replicas-max-lag" | "--min-slaves-max-lag" => {
+                    min_replicas_max_lag = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?
+                        .parse()?;
+                }
+                "--repl-diskless-sync" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?;
+                    repl_diskless_sync = match value.to_lowercase().as_str() {
+                        "yes" => true,
+                        "no" => false,
+                        _ => return Err(anyhow::anyhow!("Invalid value for {}: {}", arg, value)),
+                    };
+                }
+                "--repl-diskless-sync-delay" => {
+                    repl_diskless_sync_delay = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?
+                        .parse()?;
+                }
+                "--notify-keyspace-events" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?;
+                    notify_keyspace_events = value
+                        .parse()
+                        .map_err(|_| anyhow::anyhow!("Invalid value for {}: {}", arg, value))?;
+                }
+                "--sentinel" => sentinel = true,
+                "--monitor" => {
+                    let mut value = || {
+                        args.next()
+                            .ok_or_else(|| anyhow::anyhow!("Missing value for --monitor"))
+                    };
+                    monitors.push(Monitor {
+                        name: value()?,
+                        host: value()?,
+                        port: value()?.parse()?,
+                        quorum: value()?.parse()?,
+                    });
+                }
+                "--down-after-milliseconds" => {
+                    down_after_milliseconds = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?
+                        .parse()?;
+                }
+                "--failover-timeout" => {
+                    failover_timeout = args
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("Missing value for {}", arg))?
+                        .parse()?;
+                }
                 _ => return Err(anyhow::anyhow!("Unknown argument: {}", arg)),
             }
         }
+        if !sentinel && !monitors.is_empty() {
+            return Err(anyhow::anyhow!(
+                "--monitor can only be used with --sentinel"
+            ));
+        }
+        //Same default ports as redis-server and redis-sentinel
+        let port = port.unwrap_or(if sentinel { 26379 } else { 6379 });
         Ok(Args {
             port,
             replica_of,
             dir,
             db_file_name,
+            replica_read_only,
+            min_replicas_to_write,
+            min_replicas_max_lag,
+            repl_diskless_sync,
+            repl_diskless_sync_delay,
+            notify_keyspace_events,
+            sentinel,
+            monitors,
+            down_after_milliseconds,
+            failover_timeout,
         })
     }
 }
@@ -66,6 +179,12 @@ impl Into<Config> for Args {
             replica_of: self.replica_of,
             dir: self.dir,
             db_file_name: self.db_file_name,
+            replica_read_only: self.replica_read_only,
+            min_replicas_to_write: self.min_replicas_to_write,
+            min_replicas_max_lag: self.min_replicas_max_lag,
+            repl_diskless_sync: self.repl_diskless_sync,
+            repl_diskless_sync_delay: self.repl_diskless_sync_delay,
+            notify_keyspace_events: self.notify_keyspace_events,
         }
     }
 }
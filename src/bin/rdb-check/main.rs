@@ -0,0 +1,445 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use rdb::{Checksum, Database, Entry};
+use types::RedisType;
+use value::{stream::StreamId, ValueType};
+
+//Only the RDB decoding (and the value types it produces) is shared with the server
+#[allow(dead_code)]
+#[path = "../../redis/rdb/mod.rs"]
+mod rdb;
+#[allow(dead_code)]
+#[path = "../../redis/types.rs"]
+mod types;
+#[allow(dead_code)]
+#[path = "../../redis/value/mod.rs"]
+mod value;
+
+//Rough per key bookkeeping cost (dict entry, key object and value object)
+const ENTRY_OVERHEAD: usize = 64;
+//Stream IDs are two u64
+const STREAM_ID_SIZE: usize = 16;
+
+#[derive(Debug, PartialEq)]
+enum Export {
+    None,
+    Json,
+    Resp,
+}
+
+struct Args {
+    path: String,
+    keys: bool,
+    export: Export,
+}
+
+impl Args {
+    fn parse() -> Result<Args> {
+        let mut path = None;
+        let mut keys = false;
+        let mut export = Export::None;
+        for arg in std::env::args().skip(1) {
+            match arg.as_str() {
+                "--keys" => keys = true,
+                "--json" => export = Export::Json,
+                "--resp" => export = Export::Resp,
+                _ if arg.starts_with("--") => {
+                    return Err(anyhow::anyhow!("Unknown argument: {}", arg))
+                }
+                _ => path = Some(arg),
+            }
+        }
+        let path = path
+            .ok_or_else(|| anyhow::anyhow!("Usage: rdb-check <file> [--keys] [--json | --resp]"))?;
+        Ok(Args { path, keys, export })
+    }
+}
+
+//When exporting, stdout is reserved for the export so the report goes to stderr
+struct Report {
+    to_stderr: bool,
+}
+
+impl Report {
+    fn info(&self, line: &str) {
+        if self.to_stderr {
+            eprintln!("[info] {}", line);
+        } else {
+            println!("[info] {}", line);
+        }
+    }
+
+    fn error(&self, line: &str) {
+        eprintln!("[error] {}", line);
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse()?;
+    let report = Report {
+        to_stderr: args.export != Export::None,
+    };
+    report.info(&format!("Checking RDB file {}", args.path));
+
+    let bytes = std::fs::read(&args.path)?;
+    let file = match rdb::decode(&bytes) {
+        Ok(file) => file,
+        Err(e) => {
+            report.error(&format!("Failed to decode RDB file: {}", e));
+            std::process::exit(1);
+        }
+    };
+
+    report.info(&format!("RDB version {}", file.version_number()));
+    for (key, value) in &file.aux {
+        report.info(&format!("AUX {} = {}", key, value));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    for database in &file.databases {
+        report_database(&report, database, args.keys, now);
+    }
+
+    let valid = match file.checksum {
+        Checksum::Missing => {
+            report.info("No checksum (RDB version < 5)");
+            true
+        }
+        Checksum::Disabled => {
+            report.info("Checksum disabled");
+            true
+        }
+        Checksum::Valid(checksum) => {
+            report.info(&format!("Checksum OK ({:#x})", checksum));
+            true
+        }
+        Checksum::Invalid { stored, computed } => {
+            report.error(&format!(
+                "Wrong RDB checksum expected: ({:#x}) got: ({:#x})",
+                stored, computed
+            ));
+            false
+        }
+    };
+
+    match args.export {
+        Export::None => {}
+        Export::Json => println!("{}", to_json(&file.databases)),
+        Export::Resp => {
+            let resp = to_resp(&file.databases, now);
+            std::io::Write::write_all(&mut std::io::stdout(), &resp)?;
+        }
+    }
+
+    if !valid {
+        std::process::exit(1);
+    }
+    report.info("RDB looks OK!");
+    Ok(())
+}
+
+fn report_database(report: &Report, database: &Database, list_keys: bool, now: u64) {
+    let expiring = database
+        .entries
+        .iter()
+        .filter(|e| e.expires_at.is_some())
+        .count();
+    report.info(&format!(
+        "DB {}: {} keys ({} with expiry)",
+        database.index,
+        database.entries.len(),
+        expiring
+    ));
+
+    for type_name in ["string", "stream"] {
+        let entries: Vec<&Entry> = database
+            .entries
+            .iter()
+            .filter(|e| value_type_name(&e.value) == type_name)
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+        let memory: usize = entries.iter().map(|e| memory_usage(e)).sum();
+        report.info(&format!(
+            "DB {} {}: {} keys, ~{} bytes",
+            database.index,
+            type_name,
+            entries.len(),
+            memory
+        ));
+    }
+
+    if !list_keys {
+        return;
+    }
+    for entry in &database.entries {
+        let ttl = match entry.expires_at {
+            Some(expires_at) if expires_at <= now => "expired".to_string(),
+            Some(expires_at) => format!("{}ms", expires_at - now),
+            None => "-1".to_string(),
+        };
+        report.info(&format!(
+            "DB {} key {} type {} ttl {}",
+            database.index,
+            entry.key,
+            value_type_name(&entry.value),
+            ttl
+        ));
+    }
+}
+
+fn value_type_name(value: &ValueType) -> &'static str {
+    match value {
+        ValueType::String(_) => "string",
+        ValueType::Stream(_) => "stream",
+    }
+}
+
+fn memory_usage(entry: &Entry) -> usize {
+    let value = match &entry.value {
+        ValueType::String(s) => s.len(),
+        ValueType::Stream(stream) => stream
+            .entries
+            .iter()
+            .map(|data| {
+                let fields: usize = data.fields.iter().map(|(f, v)| f.len() + v.len()).sum();
+                STREAM_ID_SIZE + fields
+            })
+            .sum(),
+    };
+    ENTRY_OVERHEAD + entry.key.len() + value
+}
+
+fn to_json(databases: &[Database]) -> String {
+    let mut json = String::from("{");
+    for (i, database) in databases.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(&format!("\n  \"db{}\": {{", database.index));
+        for (j, entry) in database.entries.iter().enumerate() {
+            if j > 0 {
+                json.push(',');
+            }
+            json.push_str(&format!(
+                "\n    {}: {{\"type\": \"{}\", \"value\": ",
+                json_string(&entry.key),
+                value_type_name(&entry.value)
+            ));
+            match &entry.value {
+                ValueType::String(s) => json.push_str(&json_string(s)),
+                ValueType::Stream(stream) => {
+                    let entries: Vec<String> = stream
+                        .entries
+                        .iter()
+                        .map(|data| {
+                            let fields: Vec<String> = data
+                                .fields
+                                .iter()
+                                .map(|(f, v)| format!("{}: {}", json_string(f), json_string(v)))
+                                .collect();
+                            format!(
+                                "{{\"id\": \"{}\", \"fields\": {{{}}}}}",
+                                data.id,
+                                fields.join(", ")
+                            )
+                        })
+                        .collect();
+                    json.push_str(&format!("[{}]", entries.join(", ")));
+                }
+            }
+            if let Some(expires_at) = entry.expires_at {
+                json.push_str(&format!(", \"expires_at\": {}", expires_at));
+            }
+            json.push('}');
+        }
+        json.push_str("\n  }");
+    }
+    json.push_str("\n}");
+    json
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+//Commands that rebuild the dataset when piped into the server (expired keys are skipped)
+fn to_resp(databases: &[Database], now: u64) -> Vec<u8> {
+    let mut resp = vec![];
+    for database in databases {
+        if database.index != 0 {
+            let select = command(vec!["SELECT".to_string(), database.index.to_string()]);
+            resp.extend(select.encode());
+        }
+        for entry in &database.entries {
+            let ttl = match entry.expires_at {
+                Some(expires_at) if expires_at <= now => continue,
+                Some(expires_at) => Some(expires_at - now),
+                None => None,
+            };
+            match &entry.value {
+                ValueType::String(s) => {
+                    let mut args = vec!["SET".to_string(), entry.key.clone(), s.clone()];
+                    if let Some(ttl) = ttl {
+                        args.push("px".to_string());
+                        args.push(ttl.to_string());
+                    }
+                    resp.extend(command(args).encode());
+                }
+                ValueType::Stream(stream) => {
+                    for data in stream.entries.iter() {
+                        let id = data.id.to_string();
+                        let mut args = vec!["XADD".to_string(), entry.key.clone(), id];
+                        for (field, value) in &data.fields {
+                            args.push(field.clone());
+                            args.push(value.clone());
+                        }
+                        resp.extend(command(args).encode());
+                    }
+                    //A stream whose entries were all deleted is created empty, like an AOF rewrite
+                    if stream.entries.is_empty() {
+                        let id = match stream.last_id {
+                            StreamId::MIN => "0-1".to_string(),
+                            id => id.to_string(),
+                        };
+                        let args = vec![
+                            "XADD".to_string(),
+                            entry.key.clone(),
+                            "MAXLEN".to_string(),
+                            "0".to_string(),
+                            id,
+                            "x".to_string(),
+                            "y".to_string(),
+                        ];
+                        resp.extend(command(args).encode());
+                    }
+                    let args = vec![
+                        "XSETID".to_string(),
+                        entry.key.clone(),
+                        stream.last_id.to_string(),
+                        "ENTRIESADDED".to_string(),
+                        stream.entries_added.to_string(),
+                        "MAXDELETEDID".to_string(),
+                        stream.max_deleted_id.to_string(),
+                    ];
+                    resp.extend(command(args).encode());
+                    //Groups with their pending entries, the way a master replicates them
+                    for (name, group) in &stream.groups {
+                        let last_id = group.last_id.to_string();
+                        let mut args = vec![
+                            "XGROUP".to_string(),
+                            "CREATE".to_string(),
+                            entry.key.clone(),
+                            name.clone(),
+                            last_id,
+                        ];
+                        if let Some(entries_read) = group.entries_read {
+                            args.push("ENTRIESREAD".to_string());
+                            args.push(entries_read.to_string());
+                        }
+                        resp.extend(command(args).encode());
+                        for (id, pending) in &group.pending {
+                            let args = vec![
+                                "XCLAIM".to_string(),
+                                entry.key.clone(),
+                                name.clone(),
+                                pending.consumer.clone(),
+                                "0".to_string(),
+                                id.to_string(),
+                                "TIME".to_string(),
+                                pending.delivery_time.to_string(),
+                                "RETRYCOUNT".to_string(),
+                                pending.delivery_count.to_string(),
+                                "FORCE".to_string(),
+                                "JUSTID".to_string(),
+                            ];
+                            resp.extend(command(args).encode());
+                        }
+                    }
+                }
+            }
+        }
+    }
+    resp
+}
+
+fn command(args: Vec<String>) -> RedisType {
+    RedisType::Array(args.into_iter().map(RedisType::BulkString).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        rdb::{Database, Entry},
+        types::RedisType,
+        value::ValueType,
+    };
+
+    use super::{json_string, to_json, to_resp};
+
+    fn database() -> Database {
+        Database {
+            index: 0,
+            table_size: 2,
+            expiry_size: 1,
+            entries: vec![
+                Entry {
+                    key: "key".to_string(),
+                    value: ValueType::String("va\"lue".to_string()),
+                    expires_at: None,
+                },
+                Entry {
+                    key: "expiring".to_string(),
+                    value: ValueType::String("value".to_string()),
+                    expires_at: Some(2_000),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\n\"");
+    }
+
+    #[test]
+    fn test_to_json() {
+        let json = to_json(&[database()]);
+        assert_eq!(
+            json,
+            "{\n  \"db0\": {\n    \"key\": {\"type\": \"string\", \"value\": \"va\\\"lue\"},\n    \"expiring\": {\"type\": \"string\", \"value\": \"value\", \"expires_at\": 2000}\n  }\n}"
+        );
+    }
+
+    #[test]
+    fn test_to_resp() {
+        let resp = to_resp(&[database()], 1_000);
+        let commands = RedisType::from_buffer(&resp).unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[1].to_string(), "5SETexpiringvaluepx1000");
+
+        //Expired keys are not exported
+        let resp = to_resp(&[database()], 3_000);
+        let commands = RedisType::from_buffer(&resp).unwrap();
+        assert_eq!(commands.len(), 1);
+    }
+}
@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-use crate::redis::replication::RWStream;
+use crate::redis::{replication::RWStream, types::RedisType};
 
 use super::{CommandReturn, Handler, HandlerParams};
 
@@ -36,16 +36,15 @@ impl Handler for ConfigHandler {
         let args = &params.args;
         let writer = &mut params.writer;
         let should_reply = params.should_reply;
-        if !should_reply {
-            return CommandReturn::Ok;
-        }
         let sub_command = match args.get(0) {
             Some(sub_command) => sub_command,
             None => {
-                let response =
-                    "-ERR wrong number of arguments for 'config' command\r\n".to_string();
-                let bytes = response.as_bytes();
-                let _ = writer.write_all(bytes).await;
+                if should_reply {
+                    let response =
+                        "-ERR wrong number of arguments for 'config' command\r\n".to_string();
+                    let bytes = response.as_bytes();
+                    let _ = writer.write_all(bytes).await;
+                }
                 return CommandReturn::Error;
             }
         };
@@ -53,16 +52,25 @@ impl Handler for ConfigHandler {
         let sub_command = match SubCommand::from_str(sub_command) {
             Ok(sub_command) => sub_command,
             Err(_) => {
-                let response = "-ERR invalid subcommand\r\n".to_string();
-                let bytes = response.as_bytes();
-                let _ = writer.write_all(bytes).await;
+                if should_reply {
+                    let response = "-ERR invalid subcommand\r\n".to_string();
+                    let bytes = response.as_bytes();
+                    let _ = writer.write_all(bytes).await;
+                }
                 return CommandReturn::Error;
             }
         };
 
         match sub_command {
             SubCommand::Get => handle_get_command(params).await,
-            _ => todo!(),
+            SubCommand::Set => handle_set_command(params).await,
+            SubCommand::ResetStat | SubCommand::Rewrite => {
+                if should_reply {
+                    let response = format!("-ERR CONFIG {} is not supported\r\n", args[0]);
+                    let _ = writer.write_all(response.as_bytes()).await;
+                }
+                CommandReturn::Error
+            }
         }
     }
 }
@@ -70,6 +78,10 @@ impl Handler for ConfigHandler {
 async fn handle_get_command<W: AsyncWrite + Unpin, S: RWStream>(
     params: HandlerParams<'_, W, S>,
 ) -> CommandReturn {
+    //Reading the config changes nothing, there is only the reply to send
+    if !params.should_reply {
+        return CommandReturn::Ok;
+    }
     let args = &params.args;
     let mut writer = params.writer;
     let redis = params.redis;
@@ -99,3 +111,106 @@ async fn handle_get_command<W: AsyncWrite + Unpin, S: RWStream>(
     let _ = writer.write_all(&response).await;
     CommandReturn::Ok
 }
+
+async fn handle_set_command<W: AsyncWrite + Unpin, S: RWStream>(
+    params: HandlerParams<'_, W, S>,
+) -> CommandReturn {
+    let args = &params.args;
+    let mut writer = params.writer;
+    let redis = params.redis;
+    if args.len() < 3 || args[1..].chunks(2).any(|pair| pair.len() != 2) {
+        if params.should_reply {
+            let response =
+                "-ERR wrong number of arguments for 'config set' command\r\n".to_string();
+            let _ = writer.write_all(response.as_bytes()).await;
+        }
+        return CommandReturn::Error;
+    }
+
+    //All the parameters are applied or none of them
+    let mut redis = redis.write().await;
+    let mut config = redis.config.clone();
+    for pair in args[1..].chunks(2) {
+        if let Err(e) = config.set_value(&pair[0], &pair[1]) {
+            drop(redis);
+            if params.should_reply {
+                let response = RedisType::SimpleError(e);
+                let _ = writer.write_all(&response.encode()).await;
+            }
+            return CommandReturn::Error;
+        }
+    }
+    redis.config = config;
+    drop(redis);
+
+    if params.should_reply {
+        let response = RedisType::SimpleString("OK".to_string());
+        let _ = writer.write_all(&response.encode()).await;
+    }
+    CommandReturn::Ok
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{
+            config::ConfigHandler, test_util::args, CommandReturn, Handler, HandlerParams,
+        },
+        redis::{config::Config, Redis},
+    };
+
+    #[tokio::test]
+    async fn test_config_unsupported() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        for sub_command in ["REWRITE", "resetstat"] {
+            let response = format!("-ERR CONFIG {} is not supported\r\n", sub_command);
+            let mock = Builder::new().write(response.as_bytes()).build();
+            let handler_params = HandlerParams {
+                args: args(&[sub_command]),
+                redis: &redis,
+                should_reply: true,
+                writer: mock,
+                closed: None,
+            };
+            let result = ConfigHandler::handle(handler_params).await;
+            assert_eq!(result, CommandReturn::Error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_config_set_from_master() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        //Applied without a reply
+        let mut writer = Vec::new();
+        let handler_params = HandlerParams {
+            args: args(&["SET", "min-replicas-to-write", "2"]),
+            redis: &redis,
+            should_reply: false,
+            writer: &mut writer,
+            closed: None,
+        };
+        let result = ConfigHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+        assert!(writer.is_empty());
+        assert_eq!(redis.read().await.config.min_replicas_to_write, 2);
+
+        let handler_params = HandlerParams {
+            args: args(&["SET", "min-replicas-to-write", "x"]),
+            redis: &redis,
+            should_reply: false,
+            writer: &mut writer,
+            closed: None,
+        };
+        let result = ConfigHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Error);
+        assert!(writer.is_empty());
+        assert_eq!(redis.read().await.config.min_replicas_to_write, 2);
+    }
+}
@@ -1,8 +1,8 @@
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-use crate::redis::{replication::RWStream, types::RedisType};
+use crate::redis::{notify::Event, replication::RWStream, types::RedisType};
 
-use super::{Command, CommandReturn, Handler, HandlerParams};
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
 
 pub struct DelHandler;
 
@@ -15,14 +15,18 @@ impl Handler for DelHandler {
         let redis = params.redis;
         let mut del_count = 0;
 
-        let mut redis = redis.write().await;
+        let mut redis = match lock_for_write(redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
         let mut command: Vec<RedisType> = vec![Command::Del.into()];
         for arg in args.into_iter() {
             let deleted = redis.delete(&arg);
-            command.push(RedisType::BulkString(arg));
             if deleted {
+                redis.notify(Event::Generic, "del", &arg);
                 del_count += 1;
             }
+            command.push(RedisType::BulkString(arg));
         }
         let command = RedisType::Array(command);
         if params.should_reply {
@@ -43,7 +47,11 @@ mod test {
     use tokio_test::io::{Builder, Mock};
 
     use crate::{
-        client::command::{del::DelHandler, CommandReturn, Handler, HandlerParams},
+        client::command::{
+            del::DelHandler,
+            test_util::{args, event, events, subscribe_events},
+            CommandReturn, Handler, HandlerParams,
+        },
         redis::{config::Config, types::RedisType, value::ValueType, Redis},
     };
 
@@ -75,6 +83,7 @@ mod test {
             redis: &redis.clone(),
             writer: &mut mock,
             should_reply: true,
+            closed: None,
         };
         let result = DelHandler::handle(params).await;
         assert_eq!(result, CommandReturn::Ok);
@@ -114,6 +123,7 @@ mod test {
             redis: &redis.clone(),
             writer: &mut mock,
             should_reply: false,
+            closed: None,
         };
         let result = DelHandler::handle(params).await;
         assert_eq!(result, CommandReturn::Ok);
@@ -125,4 +135,31 @@ mod test {
             Some(&ValueType::String("value3".to_string()))
         );
     }
+
+    #[tokio::test]
+    async fn test_del_notify() {
+        let config = Config {
+            notify_keyspace_events: "KEg".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        let value = ValueType::String("value".to_string());
+        redis.set("key".to_string(), value, None);
+        let mut messages = subscribe_events(&mut redis);
+        let redis = RwLock::new(redis);
+        let params = HandlerParams {
+            args: args(&["key", "missing"]),
+            redis: &redis,
+            should_reply: false,
+            writer: Vec::new(),
+            closed: None,
+        };
+        DelHandler::handle(params).await;
+        //Only for the keys that were there
+        let expected = vec![
+            event("__keyspace@0__:key", "del"),
+            event("__keyevent@0__:del", "key"),
+        ];
+        assert_eq!(events(&mut messages), expected);
+    }
 }
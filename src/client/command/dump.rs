@@ -0,0 +1,88 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{rdb, replication::RWStream, types::RedisType};
+
+use super::{CommandReturn, Handler, HandlerParams};
+
+pub struct DumpHandler;
+
+impl Handler for DumpHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        if !params.should_reply {
+            return CommandReturn::Ok;
+        }
+        let mut writer = params.writer;
+        let args = params.args;
+        let redis = params.redis;
+
+        let key = match (args.first(), args.len()) {
+            (Some(key), 1) => key,
+            _ => {
+                let response = RedisType::SimpleError(
+                    "ERR wrong number of arguments for 'dump' command".to_string(),
+                );
+                let _ = writer.write_all(&response.encode()).await;
+                return CommandReturn::Error;
+            }
+        };
+
+        let redis = redis.read().await;
+        let response = match redis.get_value(key) {
+            Some(value) => RedisType::BulkBytes(rdb::dump(value)),
+            None => RedisType::NullBulkString,
+        };
+        let _ = writer.write_all(&response.encode()).await;
+        CommandReturn::Ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{dump::DumpHandler, CommandReturn, Handler, HandlerParams},
+        redis::{config::Config, rdb, types::RedisType, value::ValueType, Redis},
+    };
+
+    #[tokio::test]
+    async fn test_dump() {
+        let value = ValueType::String("value".to_string());
+        let response = RedisType::BulkBytes(rdb::dump(&value));
+        let mock = Builder::new().write(&response.encode()).build();
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        redis.set("key".to_string(), value, None);
+        let redis = Arc::new(RwLock::new(redis));
+        let handler_params = HandlerParams {
+            args: vec!["key".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = DumpHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_dump_missing_key() {
+        let response = RedisType::NullBulkString;
+        let mock = Builder::new().write(&response.encode()).build();
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        let handler_params = HandlerParams {
+            args: vec!["key".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = DumpHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+    }
+}
@@ -0,0 +1,219 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    replication::{
+        failover::{abort_in_progress, FailoverState},
+        RWStream,
+    },
+    types::RedisType,
+};
+
+use super::{CommandReturn, Handler, HandlerParams};
+
+pub struct FailoverHandler;
+
+//FAILOVER [TO host port [FORCE]] [TIMEOUT milliseconds] | ABORT
+impl Handler for FailoverHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let redis = params.redis;
+        let should_reply = params.should_reply;
+
+        let mut target = None;
+        let mut timeout = None;
+        let mut force = false;
+        let mut abort = false;
+        let mut args = params.args.iter();
+        while let Some(arg) = args.next() {
+            match arg.to_uppercase().as_str() {
+                "TO" if target.is_none() => {
+                    let host = args.next();
+                    let port = args.next().map(|port| port.parse::<u16>());
+                    match (host, port) {
+                        (Some(host), Some(Ok(port))) => target = Some((host.clone(), port)),
+                        (Some(_), Some(Err(_))) => {
+                            let e = "ERR value is not an integer or out of range";
+                            return reply_error(&mut writer, e, should_reply).await;
+                        }
+                        _ => {
+                            return reply_error(&mut writer, "ERR syntax error", should_reply).await
+                        }
+                    }
+                }
+                "TIMEOUT" if timeout.is_none() => {
+                    let ms = match args.next().map(|ms| ms.parse::<i64>()) {
+                        Some(Ok(ms)) => ms,
+                        Some(Err(_)) => {
+                            let e = "ERR value is not an integer or out of range";
+                            return reply_error(&mut writer, e, should_reply).await;
+                        }
+                        None => {
+                            return reply_error(&mut writer, "ERR syntax error", should_reply).await
+                        }
+                    };
+                    if ms <= 0 {
+                        let e = "ERR FAILOVER timeout must be greater than 0";
+                        return reply_error(&mut writer, e, should_reply).await;
+                    }
+                    timeout = Some(Duration::from_millis(ms as u64));
+                }
+                "FORCE" if !force => force = true,
+                "ABORT" if !abort => abort = true,
+                _ => return reply_error(&mut writer, "ERR syntax error", should_reply).await,
+            }
+        }
+
+        let mut redis = redis.write().await;
+        if abort {
+            if !redis.replication.failover.in_progress() {
+                let e = "ERR FAILOVER is not in progress.";
+                return reply_error(&mut writer, e, should_reply).await;
+            }
+            if target.is_some() || timeout.is_some() || force {
+                let e = "ERR FAILOVER abort cannot be used with other options.";
+                return reply_error(&mut writer, e, should_reply).await;
+            }
+            if redis.replication.failover.state == FailoverState::InProgress {
+                abort_in_progress(&mut redis, "Failover manually aborted").await;
+            } else {
+                println!("FAILOVER aborted: Failover manually aborted");
+                redis.replication.failover.end();
+            }
+        } else {
+            if force && (timeout.is_none() || target.is_none()) {
+                let e = "ERR FAILOVER with force option requires both a timeout and target HOST and IP.";
+                return reply_error(&mut writer, e, should_reply).await;
+            }
+            if !redis.is_master() {
+                let e = "ERR FAILOVER is not valid when server is a replica.";
+                return reply_error(&mut writer, e, should_reply).await;
+            }
+            if redis.replication.replicas.is_empty() {
+                let e = "ERR FAILOVER requires connected replicas.";
+                return reply_error(&mut writer, e, should_reply).await;
+            }
+            if redis.replication.failover.in_progress() {
+                let e = "ERR FAILOVER already in progress.";
+                return reply_error(&mut writer, e, should_reply).await;
+            }
+            if let Some((host, port)) = &target {
+                if !redis.replication.has_replica(host, *port) {
+                    let e = "ERR FAILOVER target HOST and PORT is not a replica.";
+                    return reply_error(&mut writer, e, should_reply).await;
+                }
+            }
+            redis.replication.failover.start(target, timeout, force);
+        }
+
+        if should_reply {
+            let response = RedisType::SimpleString("OK".to_string());
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        CommandReturn::Ok
+    }
+}
+
+async fn reply_error<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    error: &str,
+    should_reply: bool,
+) -> CommandReturn {
+    if should_reply {
+        let response = RedisType::SimpleError(error.to_string());
+        let _ = writer.write_all(&response.encode()).await;
+    }
+    CommandReturn::Error
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{failover::FailoverHandler, CommandReturn, Handler, HandlerParams},
+        redis::{config::Config, replication::failover::FailoverState, types::RedisType, Redis},
+    };
+
+    async fn failover(
+        redis: &RwLock<Redis<Mock>>,
+        args: &[&str],
+        response: RedisType,
+    ) -> CommandReturn {
+        let mock = Builder::new().write(&response.encode()).build();
+        let handler_params = HandlerParams {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        FailoverHandler::handle(handler_params).await
+    }
+
+    #[tokio::test]
+    async fn test_failover_errors() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+
+        let response =
+            RedisType::SimpleError("ERR FAILOVER requires connected replicas.".to_string());
+        assert_eq!(failover(&redis, &[], response).await, CommandReturn::Error);
+
+        let response = RedisType::SimpleError(
+            "ERR FAILOVER with force option requires both a timeout and target HOST and IP."
+                .to_string(),
+        );
+        let args = ["TO", "127.0.0.1", "6380", "FORCE"];
+        assert_eq!(
+            failover(&redis, &args, response).await,
+            CommandReturn::Error
+        );
+
+        let response =
+            RedisType::SimpleError("ERR FAILOVER timeout must be greater than 0".to_string());
+        let args = ["TIMEOUT", "0"];
+        assert_eq!(
+            failover(&redis, &args, response).await,
+            CommandReturn::Error
+        );
+
+        let response = RedisType::SimpleError("ERR FAILOVER is not in progress.".to_string());
+        assert_eq!(
+            failover(&redis, &["ABORT"], response).await,
+            CommandReturn::Error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failover_abort() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        redis
+            .write()
+            .await
+            .replication
+            .failover
+            .start(None, None, false);
+        assert!(redis
+            .read()
+            .await
+            .replication_info()
+            .contains("master_failover_state:waiting-for-sync"));
+
+        let response = RedisType::SimpleString("OK".to_string());
+        assert_eq!(
+            failover(&redis, &["ABORT"], response).await,
+            CommandReturn::Ok
+        );
+        let redis = redis.read().await;
+        assert_eq!(redis.replication.failover.state, FailoverState::NoFailover);
+        assert!(redis.is_master());
+    }
+}
@@ -68,6 +68,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
@@ -77,6 +78,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: writer_mock,
+            closed: None,
         };
         GetHandler::handle(handler_params).await;
     }
@@ -90,6 +92,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let mut redis: Redis<Mock> = Redis::new(config);
@@ -104,6 +107,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: writer_mock,
+            closed: None,
         };
         GetHandler::handle(handler_params).await;
     }
@@ -119,16 +123,22 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let mut redis: Redis<Mock> = Redis::new(config);
-        redis.set("key".to_string(), ValueType::Stream(vec![]), None);
+        redis.set(
+            "key".to_string(),
+            ValueType::Stream(Default::default()),
+            None,
+        );
         let redis = Arc::new(RwLock::new(redis));
         let handler_params = HandlerParams {
             args: vec!["key".to_string()],
             redis: &redis,
             should_reply: true,
             writer: writer_mock,
+            closed: None,
         };
         GetHandler::handle(handler_params).await;
     }
@@ -141,16 +151,22 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let mut redis: Redis<Mock> = Redis::new(config);
-        redis.set("key".to_string(), ValueType::Stream(vec![]), None);
+        redis.set(
+            "key".to_string(),
+            ValueType::Stream(Default::default()),
+            None,
+        );
         let redis = Arc::new(RwLock::new(redis));
         let handler_params = HandlerParams {
             args: vec!["key".to_string()],
             redis: &redis,
             should_reply: false,
             writer: writer_mock,
+            closed: None,
         };
         let response = GetHandler::handle(handler_params).await;
         assert_eq!(response, CommandReturn::Ok);
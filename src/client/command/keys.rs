@@ -49,6 +49,7 @@ mod test {
             args,
             redis: &redis,
             should_reply: true,
+            closed: None,
         };
         let result = KeysHandler::handle(params).await;
         assert_eq!(result, CommandReturn::Ok);
@@ -63,7 +64,11 @@ mod test {
             ValueType::String("value1".to_string()),
             None,
         );
-        redis.set("key2".to_string(), ValueType::Stream(vec![]), None);
+        redis.set(
+            "key2".to_string(),
+            ValueType::Stream(Default::default()),
+            None,
+        );
         let keys = redis.get_keys();
         let redis = Arc::new(RwLock::new(redis));
 
@@ -74,6 +79,7 @@ mod test {
             args,
             redis: &redis,
             should_reply: true,
+            closed: None,
         };
         let result = KeysHandler::handle(params).await;
         assert_eq!(result, CommandReturn::Ok);
@@ -88,7 +94,11 @@ mod test {
             ValueType::String("value1".to_string()),
             None,
         );
-        redis.set("key2".to_string(), ValueType::Stream(vec![]), None);
+        redis.set(
+            "key2".to_string(),
+            ValueType::Stream(Default::default()),
+            None,
+        );
         let redis = Arc::new(RwLock::new(redis));
 
         let mut stream = Builder::new().build();
@@ -98,6 +108,7 @@ mod test {
             args,
             redis: &redis,
             should_reply: false,
+            closed: None,
         };
         let result = KeysHandler::handle(params).await;
         assert_eq!(result, CommandReturn::Ok);
@@ -1,24 +1,54 @@
 use std::str::FromStr;
 
-use tokio::{io::AsyncWrite, sync::RwLock};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::{Notify, RwLock, RwLockWriteGuard},
+};
 
-use crate::redis::{replication::RWStream, types::RedisType, Redis};
+use crate::redis::{
+    replication::{failover::wait_writes, RWStream},
+    types::RedisType,
+    Redis,
+};
 
 mod config;
 mod del;
+mod dump;
 mod echo;
+mod failover;
 mod get;
 mod info;
 mod keys;
 mod ping;
 mod psync;
+mod publish;
+mod pubsub;
 mod r_type;
 mod repl_conf;
+mod replica_of;
+mod restore;
 mod set;
+mod shutdown;
+#[cfg(test)]
+pub mod test_util;
 mod wait;
+mod x_ack;
 mod x_add;
+mod x_auto_claim;
+mod x_claim;
+mod x_del;
+mod x_group;
+mod x_info;
+mod x_len;
+mod x_pending;
 mod x_range;
 mod x_read;
+mod x_read_group;
+mod x_rev_range;
+mod x_set_id;
+mod x_trim;
+
+pub use wait::wait_now;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum CommandReturn {
@@ -26,7 +56,8 @@ pub enum CommandReturn {
     Ok,
     HandShakeStarted(u16),
     HandShakeCapaReceived,
-    HandShakeCompleted,
+    //Offset of the last byte the replica has
+    HandShakeCompleted(u64),
 }
 
 struct HandlerParams<'a, W: AsyncWrite + Unpin, S: RWStream> {
@@ -34,6 +65,8 @@ struct HandlerParams<'a, W: AsyncWrite + Unpin, S: RWStream> {
     redis: &'a RwLock<Redis<S>>,
     writer: W,
     should_reply: bool,
+    //Notified when the client disconnects, blocking commands stop waiting
+    closed: Option<&'a Notify>,
 }
 
 trait Handler {
@@ -59,6 +92,39 @@ pub enum Command {
     XAdd,
     XRange,
     XRead,
+    XGroup,
+    XReadGroup,
+    XAck,
+    XPending,
+    XClaim,
+    XAutoClaim,
+    XLen,
+    XDel,
+    XTrim,
+    XInfo,
+    XSetId,
+    XRevRange,
+    Shutdown,
+    Dump,
+    Restore,
+    ReplicaOf,
+    Failover,
+    Subscribe,
+    Unsubscribe,
+    PSubscribe,
+    PUnsubscribe,
+    SSubscribe,
+    SUnsubscribe,
+    Publish,
+    SPublish,
+    PubSub,
+    Quit,
+    Reset,
+    Multi,
+    Exec,
+    Discard,
+    Watch,
+    Unwatch,
 }
 
 impl FromStr for Command {
@@ -81,27 +147,197 @@ impl FromStr for Command {
             "XADD" => Ok(Command::XAdd),
             "XRANGE" => Ok(Command::XRange),
             "XREAD" => Ok(Command::XRead),
+            "XGROUP" => Ok(Command::XGroup),
+            "XREADGROUP" => Ok(Command::XReadGroup),
+            "XACK" => Ok(Command::XAck),
+            "XPENDING" => Ok(Command::XPending),
+            "XCLAIM" => Ok(Command::XClaim),
+            "XAUTOCLAIM" => Ok(Command::XAutoClaim),
+            "XLEN" => Ok(Command::XLen),
+            "XDEL" => Ok(Command::XDel),
+            "XTRIM" => Ok(Command::XTrim),
+            "XINFO" => Ok(Command::XInfo),
+            "XSETID" => Ok(Command::XSetId),
+            "XREVRANGE" => Ok(Command::XRevRange),
+            "SHUTDOWN" => Ok(Command::Shutdown),
+            "DUMP" => Ok(Command::Dump),
+            "RESTORE" => Ok(Command::Restore),
+            "REPLICAOF" | "SLAVEOF" => Ok(Command::ReplicaOf),
+            "FAILOVER" => Ok(Command::Failover),
+            "SUBSCRIBE" => Ok(Command::Subscribe),
+            "UNSUBSCRIBE" => Ok(Command::Unsubscribe),
+            "PSUBSCRIBE" => Ok(Command::PSubscribe),
+            "PUNSUBSCRIBE" => Ok(Command::PUnsubscribe),
+            "SSUBSCRIBE" => Ok(Command::SSubscribe),
+            "SUNSUBSCRIBE" => Ok(Command::SUnsubscribe),
+            "PUBLISH" => Ok(Command::Publish),
+            "SPUBLISH" => Ok(Command::SPublish),
+            "PUBSUB" => Ok(Command::PubSub),
+            "QUIT" => Ok(Command::Quit),
+            "RESET" => Ok(Command::Reset),
+            "MULTI" => Ok(Command::Multi),
+            "EXEC" => Ok(Command::Exec),
+            "DISCARD" => Ok(Command::Discard),
+            "WATCH" => Ok(Command::Watch),
+            "UNWATCH" => Ok(Command::Unwatch),
             _ => Err(()),
         }
     }
 }
 
 impl Command {
-    pub fn split_type(redis_type: RedisType) -> Result<(Command, Vec<String>), ()> {
+    //Commands that modify the dataset, every new command has to pick a side
+    pub fn is_write(&self) -> bool {
+        match self {
+            Command::Set
+            | Command::Del
+            | Command::XAdd
+            | Command::XGroup
+            | Command::XReadGroup
+            | Command::XAck
+            | Command::XClaim
+            | Command::XAutoClaim
+            | Command::XDel
+            | Command::XTrim
+            | Command::XSetId
+            | Command::Restore => true,
+            Command::Ping
+            | Command::Echo
+            | Command::Type
+            | Command::Get
+            | Command::Info
+            | Command::ReplConf
+            | Command::Psync
+            | Command::Config
+            | Command::Wait
+            | Command::Keys
+            | Command::XRange
+            | Command::XRead
+            | Command::XPending
+            | Command::XLen
+            | Command::XInfo
+            | Command::XRevRange
+            | Command::Shutdown
+            | Command::Dump
+            | Command::ReplicaOf
+            | Command::Failover
+            | Command::Subscribe
+            | Command::Unsubscribe
+            | Command::PSubscribe
+            | Command::PUnsubscribe
+            | Command::SSubscribe
+            | Command::SUnsubscribe
+            | Command::Publish
+            | Command::SPublish
+            | Command::PubSub
+            | Command::Quit
+            | Command::Reset
+            | Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Watch
+            | Command::Unwatch => false,
+        }
+    }
+
+    //Number of arguments with the command name, negative means at least that many
+    pub fn arity(&self) -> i32 {
+        match self {
+            Command::Ping => -1,
+            Command::Echo => 2,
+            Command::Set => -3,
+            Command::Get => 2,
+            Command::Info => -1,
+            Command::ReplConf => -1,
+            Command::Psync => -3,
+            Command::Wait => 3,
+            Command::Config => -2,
+            Command::Keys => 2,
+            Command::Del => -2,
+            Command::Type => 2,
+            Command::XAdd => -5,
+            Command::XRange => -4,
+            Command::XRead => -4,
+            Command::XGroup => -2,
+            Command::XReadGroup => -7,
+            Command::XAck => -4,
+            Command::XPending => -3,
+            Command::XClaim => -6,
+            Command::XAutoClaim => -6,
+            Command::XLen => 2,
+            Command::XDel => -3,
+            Command::XTrim => -4,
+            Command::XInfo => -2,
+            Command::XSetId => -3,
+            Command::XRevRange => -4,
+            Command::Shutdown => -1,
+            Command::Dump => 2,
+            Command::Restore => -4,
+            Command::ReplicaOf => 3,
+            Command::Failover => -1,
+            Command::Subscribe => -2,
+            Command::Unsubscribe => -1,
+            Command::PSubscribe => -2,
+            Command::PUnsubscribe => -1,
+            Command::SSubscribe => -2,
+            Command::SUnsubscribe => -1,
+            Command::Publish => 3,
+            Command::SPublish => 3,
+            Command::PubSub => -2,
+            Command::Quit => -1,
+            Command::Reset => 1,
+            Command::Multi => 1,
+            Command::Exec => 1,
+            Command::Discard => 1,
+            Command::Watch => -2,
+            Command::Unwatch => 1,
+        }
+    }
+
+    pub fn check_arity(&self, args: &[String]) -> bool {
+        let arity = self.arity();
+        let len = args.len() as i32 + 1;
+        if arity < 0 {
+            len >= -arity
+        } else {
+            len == arity
+        }
+    }
+
+    pub fn arity_error(&self) -> RedisType {
+        let name: RedisType = self.clone().into();
+        RedisType::SimpleError(format!(
+            "ERR wrong number of arguments for '{}' command",
+            name.to_string().to_lowercase()
+        ))
+    }
+
+    //The error is the message to reply with
+    pub fn split_type(redis_type: RedisType) -> Result<(Command, Vec<String>), String> {
+        let unknown = || "ERR unknown command".to_string();
         match redis_type {
             RedisType::Array(array) => {
                 let mut iter = array.into_iter();
-                let command = iter
+                let command: Command = iter
                     .next()
-                    .ok_or(())?
+                    .ok_or_else(unknown)?
                     .to_string()
                     .to_uppercase()
                     .parse()
-                    .map_err(|_| ())?;
-                let args = iter.map(|arg| arg.to_string()).collect();
+                    .map_err(|_| unknown())?;
+                let mut args = vec![];
+                for (i, arg) in iter.enumerate() {
+                    //Only the RESTORE payload keeps binary bytes, one char per byte
+                    if matches!(arg, RedisType::BulkBytes(_))
+                        && !(command == Command::Restore && i == 2)
+                    {
+                        return Err("ERR invalid argument, not valid UTF-8".to_string());
+                    }
+                    args.push(arg.to_string());
+                }
                 Ok((command, args))
             }
-            _ => return Err(()),
+            _ => Err(unknown()),
         }
     }
 }
@@ -124,6 +360,39 @@ impl Into<RedisType> for Command {
             Command::XAdd => RedisType::BulkString("XADD".to_string()),
             Command::XRange => RedisType::BulkString("XRANGE".to_string()),
             Command::XRead => RedisType::BulkString("XREAD".to_string()),
+            Command::XGroup => RedisType::BulkString("XGROUP".to_string()),
+            Command::XReadGroup => RedisType::BulkString("XREADGROUP".to_string()),
+            Command::XAck => RedisType::BulkString("XACK".to_string()),
+            Command::XPending => RedisType::BulkString("XPENDING".to_string()),
+            Command::XClaim => RedisType::BulkString("XCLAIM".to_string()),
+            Command::XAutoClaim => RedisType::BulkString("XAUTOCLAIM".to_string()),
+            Command::XLen => RedisType::BulkString("XLEN".to_string()),
+            Command::XDel => RedisType::BulkString("XDEL".to_string()),
+            Command::XTrim => RedisType::BulkString("XTRIM".to_string()),
+            Command::XInfo => RedisType::BulkString("XINFO".to_string()),
+            Command::XSetId => RedisType::BulkString("XSETID".to_string()),
+            Command::XRevRange => RedisType::BulkString("XREVRANGE".to_string()),
+            Command::Shutdown => RedisType::BulkString("SHUTDOWN".to_string()),
+            Command::Dump => RedisType::BulkString("DUMP".to_string()),
+            Command::Restore => RedisType::BulkString("RESTORE".to_string()),
+            Command::ReplicaOf => RedisType::BulkString("REPLICAOF".to_string()),
+            Command::Failover => RedisType::BulkString("FAILOVER".to_string()),
+            Command::Subscribe => RedisType::BulkString("SUBSCRIBE".to_string()),
+            Command::Unsubscribe => RedisType::BulkString("UNSUBSCRIBE".to_string()),
+            Command::PSubscribe => RedisType::BulkString("PSUBSCRIBE".to_string()),
+            Command::PUnsubscribe => RedisType::BulkString("PUNSUBSCRIBE".to_string()),
+            Command::SSubscribe => RedisType::BulkString("SSUBSCRIBE".to_string()),
+            Command::SUnsubscribe => RedisType::BulkString("SUNSUBSCRIBE".to_string()),
+            Command::Publish => RedisType::BulkString("PUBLISH".to_string()),
+            Command::SPublish => RedisType::BulkString("SPUBLISH".to_string()),
+            Command::PubSub => RedisType::BulkString("PUBSUB".to_string()),
+            Command::Quit => RedisType::BulkString("QUIT".to_string()),
+            Command::Reset => RedisType::BulkString("RESET".to_string()),
+            Command::Multi => RedisType::BulkString("MULTI".to_string()),
+            Command::Exec => RedisType::BulkString("EXEC".to_string()),
+            Command::Discard => RedisType::BulkString("DISCARD".to_string()),
+            Command::Watch => RedisType::BulkString("WATCH".to_string()),
+            Command::Unwatch => RedisType::BulkString("UNWATCH".to_string()),
         }
     }
 }
@@ -132,14 +401,23 @@ pub async fn handle_command<'a, W: AsyncWrite + Unpin, S: RWStream>(
     command: Command,
     args: Vec<String>,
     redis: &'a RwLock<Redis<S>>,
-    writer: W,
+    mut writer: W,
     should_reply: bool,
+    closed: Option<&'a Notify>,
 ) -> CommandReturn {
+    //Same reply as when queued in MULTI, handlers can index the arguments the arity guarantees
+    if !command.check_arity(&args) {
+        if should_reply {
+            let _ = writer.write_all(&command.arity_error().encode()).await;
+        }
+        return CommandReturn::Error;
+    }
     let params = HandlerParams {
         args,
         redis,
         writer,
         should_reply,
+        closed,
     };
     match command {
         Command::Ping => ping::PingHandler::handle(params).await,
@@ -157,5 +435,247 @@ pub async fn handle_command<'a, W: AsyncWrite + Unpin, S: RWStream>(
         Command::XAdd => x_add::XAddHandler::handle(params).await,
         Command::XRange => x_range::XRangeHandler::handle(params).await,
         Command::XRead => x_read::XReadHandler::handle(params).await,
+        Command::XGroup => x_group::XGroupHandler::handle(params).await,
+        Command::XReadGroup => x_read_group::XReadGroupHandler::handle(params).await,
+        Command::XAck => x_ack::XAckHandler::handle(params).await,
+        Command::XPending => x_pending::XPendingHandler::handle(params).await,
+        Command::XClaim => x_claim::XClaimHandler::handle(params).await,
+        Command::XAutoClaim => x_auto_claim::XAutoClaimHandler::handle(params).await,
+        Command::XLen => x_len::XLenHandler::handle(params).await,
+        Command::XDel => x_del::XDelHandler::handle(params).await,
+        Command::XTrim => x_trim::XTrimHandler::handle(params).await,
+        Command::XInfo => x_info::XInfoHandler::handle(params).await,
+        Command::XSetId => x_set_id::XSetIdHandler::handle(params).await,
+        Command::XRevRange => x_rev_range::XRevRangeHandler::handle(params).await,
+        Command::Shutdown => shutdown::ShutdownHandler::handle(params).await,
+        Command::Dump => dump::DumpHandler::handle(params).await,
+        Command::Restore => restore::RestoreHandler::handle(params).await,
+        Command::ReplicaOf => replica_of::ReplicaOfHandler::handle(params).await,
+        Command::Failover => failover::FailoverHandler::handle(params).await,
+        //Subscriptions belong to the connection, the client handles them
+        Command::Subscribe
+        | Command::Unsubscribe
+        | Command::PSubscribe
+        | Command::PUnsubscribe
+        | Command::SSubscribe
+        | Command::SUnsubscribe
+        | Command::Quit
+        | Command::Reset => CommandReturn::Error,
+        //Transactions are per connection too
+        Command::Multi | Command::Exec | Command::Discard | Command::Watch => CommandReturn::Error,
+        //Only reached inside a transaction, EXEC unwatches the keys anyway
+        Command::Unwatch => {
+            let mut writer = params.writer;
+            if should_reply {
+                let response = RedisType::SimpleString("OK".to_string());
+                let _ = writer.write_all(&response.encode()).await;
+            }
+            CommandReturn::Ok
+        }
+        Command::Publish => publish::PublishHandler::handle(params).await,
+        Command::SPublish => publish::SPublishHandler::handle(params).await,
+        Command::PubSub => pubsub::PubSubHandler::handle(params).await,
+    }
+}
+
+/*
+ The lock write commands change the dataset with. Writes wait here while a failover
+ is running, and the checks are made with the lock held so a failover can't start
+ between them and the write. Commands from the master (should_reply == false) are
+ always applied.
+*/
+async fn lock_for_write<'a, W: AsyncWrite + Unpin, S: RWStream>(
+    redis: &'a RwLock<Redis<S>>,
+    writer: &mut W,
+    should_reply: bool,
+) -> Option<RwLockWriteGuard<'a, Redis<S>>> {
+    if !should_reply {
+        return Some(redis.write().await);
+    }
+    let guard = loop {
+        //Paused while a failover is running, after it this is a replica
+        wait_writes(redis).await;
+        let guard = redis.write().await;
+        if !guard.replication.failover.in_progress() {
+            break guard;
+        }
+    };
+    let response = if !guard.is_master() && guard.config.replica_read_only {
+        RedisType::SimpleError("READONLY You can't write against a read only replica.".to_string())
+    } else if guard.is_master()
+        && guard
+            .good_replicas()
+            .is_some_and(|good| good < guard.config.min_replicas_to_write)
+    {
+        RedisType::SimpleError("NOREPLICAS Not enough good replicas to write.".to_string())
+    } else {
+        return Some(guard);
+    };
+    drop(guard);
+    let _ = writer.write_all(&response.encode()).await;
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{handle_command, Command, CommandReturn},
+        redis::{config::Config, types::RedisType, Redis},
+    };
+
+    #[tokio::test]
+    async fn test_read_only_replica() {
+        let response = RedisType::SimpleError(
+            "READONLY You can't write against a read only replica.".to_string(),
+        );
+        let config = Config {
+            replica_of: Some(("localhost".to_string(), 6380)),
+            ..Default::default()
+        };
+        let redis: Redis<Mock> = Redis::new(config);
+        let redis = Arc::new(RwLock::new(redis));
+        let args = vec!["key".to_string(), "value".to_string()];
+
+        let mock = Builder::new().write(&response.encode()).build();
+        let result = handle_command(Command::Set, args.clone(), &redis, mock, true, None).await;
+        assert_eq!(result, CommandReturn::Error);
+        assert!(redis.read().await.get("key").is_none());
+
+        //The master link can still write
+        let mock = Builder::new().build();
+        let result = handle_command(Command::Set, args.clone(), &redis, mock, false, None).await;
+        assert_eq!(result, CommandReturn::Ok);
+        assert!(redis.read().await.get("key").is_some());
+
+        redis.write().await.config.replica_read_only = false;
+        let response = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let result = handle_command(Command::Set, args, &redis, mock, true, None).await;
+        assert_eq!(result, CommandReturn::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_min_replicas_to_write() {
+        let response =
+            RedisType::SimpleError("NOREPLICAS Not enough good replicas to write.".to_string());
+        let config = Config {
+            min_replicas_to_write: 1,
+            ..Default::default()
+        };
+        let redis: Redis<Mock> = Redis::new(config);
+        let redis = Arc::new(RwLock::new(redis));
+        let args = vec!["key".to_string(), "value".to_string()];
+
+        let mock = Builder::new().write(&response.encode()).build();
+        let result = handle_command(Command::Set, args.clone(), &redis, mock, true, None).await;
+        assert_eq!(result, CommandReturn::Error);
+        assert!(redis.read().await.get("key").is_none());
+        assert!(redis
+            .read()
+            .await
+            .replication_info()
+            .ends_with("min_slaves_good_slaves:0"));
+
+        redis.write().await.config.min_replicas_to_write = 0;
+        let response = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let result = handle_command(Command::Set, args, &redis, mock, true, None).await;
+        assert_eq!(result, CommandReturn::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_write_during_failover() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        let response = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+
+        //The write gets past the pause before the failover starts, then waits for the lock
+        let mut guard = redis.write().await;
+        let args = vec!["key".to_string(), "value".to_string()];
+        let write = tokio::spawn({
+            let redis = Arc::clone(&redis);
+            async move { handle_command(Command::Set, args, &redis, mock, true, None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        guard.replication.failover.start(None, None, false);
+        drop(guard);
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(redis.read().await.get("key").is_none());
+        redis.write().await.replication.failover.end();
+        assert_eq!(write.await.unwrap(), CommandReturn::Ok);
+        assert!(redis.read().await.get("key").is_some());
+    }
+
+    #[test]
+    fn test_split_type_binary() {
+        let payload = RedisType::BulkBytes(vec![0x00, 0xff]);
+        let restore = RedisType::Array(vec![
+            Command::Restore.into(),
+            RedisType::BulkString("key".to_string()),
+            RedisType::BulkString("0".to_string()),
+            payload.clone(),
+        ]);
+        let (command, args) = Command::split_type(restore).unwrap();
+        assert_eq!(command, Command::Restore);
+        assert_eq!(args[2], "\u{0}\u{ff}");
+
+        let set = RedisType::Array(vec![
+            Command::Set.into(),
+            RedisType::BulkString("key".to_string()),
+            payload,
+        ]);
+        assert_eq!(
+            Command::split_type(set),
+            Err("ERR invalid argument, not valid UTF-8".to_string())
+        );
+        let unknown = RedisType::Array(vec![RedisType::BulkString("NOPE".to_string())]);
+        assert_eq!(
+            Command::split_type(unknown),
+            Err("ERR unknown command".to_string())
+        );
+    }
+
+    async fn assert_wrong_arity(command: Command, args: &[&str]) {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = RwLock::new(redis);
+        let args = args.iter().map(|arg| arg.to_string()).collect();
+        let mock = Builder::new()
+            .write(&command.arity_error().encode())
+            .build();
+        let result = handle_command(command, args, &redis, mock, true, None).await;
+        assert_eq!(result, CommandReturn::Error);
+    }
+
+    #[tokio::test]
+    async fn test_wrong_arity() {
+        assert_eq!(
+            Command::XAck.arity_error(),
+            RedisType::SimpleError("ERR wrong number of arguments for 'xack' command".to_string())
+        );
+        assert_wrong_arity(Command::XClaim, &["k", "g", "c"]).await;
+        assert_wrong_arity(Command::XAutoClaim, &["k", "g", "c", "0"]).await;
+        assert_wrong_arity(Command::XAck, &["k"]).await;
+        assert_wrong_arity(Command::XPending, &["k"]).await;
+        assert_wrong_arity(Command::XLen, &[]).await;
+        assert_wrong_arity(Command::XDel, &["k"]).await;
+        assert_wrong_arity(Command::XSetId, &["k"]).await;
+        assert_wrong_arity(Command::XRevRange, &["k", "+"]).await;
+        assert_wrong_arity(Command::XTrim, &["k", "MAXLEN"]).await;
+        assert_wrong_arity(Command::XRange, &["k"]).await;
+        assert_wrong_arity(Command::XRange, &["k", "-"]).await;
+        assert_wrong_arity(Command::Get, &[]).await;
+        assert_wrong_arity(Command::Get, &["a", "b"]).await;
+        assert_wrong_arity(Command::Echo, &[]).await;
+        assert_wrong_arity(Command::Set, &["a"]).await;
+        assert_wrong_arity(Command::Type, &[]).await;
+        assert_wrong_arity(Command::Del, &[]).await;
+        assert_wrong_arity(Command::Wait, &["1"]).await;
     }
 }
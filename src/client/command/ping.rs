@@ -48,6 +48,7 @@ mod test {
             args,
             redis: &redis,
             should_reply: true,
+            closed: None,
         };
         let result = PingHandler::handle(params).await;
         assert_eq!(result, CommandReturn::Ok);
@@ -66,6 +67,7 @@ mod test {
             args,
             redis: &redis,
             should_reply: false,
+            closed: None,
         };
         let result = PingHandler::handle(params).await;
         assert_eq!(result, CommandReturn::Ok);
@@ -1,12 +1,27 @@
+use std::time::Duration;
+
 use tokio::{
+    fs::File,
     io::{AsyncWrite, AsyncWriteExt},
-    sync::RwLock,
+    sync::{mpsc, RwLock, RwLockWriteGuard},
 };
 
-use crate::redis::{replication::RWStream, types::RedisType, Redis};
+use crate::redis::{
+    rdb,
+    replication::{
+        full_sync::{receive, stream_snapshot},
+        link::LinkState,
+        RWStream,
+    },
+    types::RedisType,
+    Redis,
+};
 
 use super::{CommandReturn, Handler};
 
+//Chunks of a diskless transfer buffered for a slow replica
+const SYNC_QUEUE: usize = 16;
+
 pub struct PsyncHandler;
 
 impl Handler for PsyncHandler {
@@ -17,34 +32,321 @@ impl Handler for PsyncHandler {
         let args = params.args;
         let redis = params.redis;
 
-        let response = handle_psync(args, redis).await;
-        let bytes = response.encode();
-        let _ = writer.write_all(&bytes).await;
-        let redis = redis.read().await;
-        let file = redis.rdb_file_bytes();
-        let file = RedisType::Bytes(file);
-        let _ = writer.write_all(&file.encode()).await;
-        match response {
-            RedisType::SimpleString(_) => CommandReturn::HandShakeCompleted,
-            _ => CommandReturn::Error,
+        let result = match handle_psync(args, redis).await {
+            Ok(Psync::Continue { replid, offset }) => {
+                let response = RedisType::SimpleString(format!("CONTINUE {}", replid));
+                writer.write_all(&response.encode()).await.map(|_| offset)
+            }
+            Ok(Psync::FullResync) => full_resync(redis, &mut writer).await,
+            Err(response) => {
+                let _ = writer.write_all(&response.encode()).await;
+                return CommandReturn::Error;
+            }
+        };
+        match result {
+            Ok(offset) => CommandReturn::HandShakeCompleted(offset),
+            Err(e) => {
+                println!("Failed to sync replica: {}", e);
+                CommandReturn::Error
+            }
         }
     }
 }
-async fn handle_psync<S: RWStream>(args: Vec<String>, redis: &RwLock<Redis<S>>) -> RedisType {
-    let _ = match args.get(0) {
+
+enum Psync {
+    //`offset` is the last byte the replica already has
+    Continue { replid: String, offset: u64 },
+    FullResync,
+}
+
+async fn handle_psync<S: RWStream>(
+    args: Vec<String>,
+    redis: &RwLock<Redis<S>>,
+) -> Result<Psync, RedisType> {
+    let replid = match args.get(0) {
         Some(id) => id,
-        None => return RedisType::SimpleError("ERR invalid id".to_string()),
+        None => return Err(RedisType::SimpleError("ERR invalid id".to_string())),
     };
-    let _ = match args.get(1) {
-        Some(offset) => match offset.parse::<i64>() {
-            Ok(offset) => offset,
-            Err(_) => return RedisType::SimpleError("ERR invalid offset".to_string()),
-        },
-        None => return RedisType::SimpleError("ERR invalid offset".to_string()),
+    let offset = match args.get(1).map(|offset| offset.parse::<i64>()) {
+        Some(Ok(offset)) => offset,
+        _ => return Err(RedisType::SimpleError("ERR invalid offset".to_string())),
     };
+    let failover = args
+        .get(2)
+        .is_some_and(|arg| arg.eq_ignore_ascii_case("failover"));
+    if failover {
+        //Our master is handing over to us (FAILOVER), it keeps its history with us
+        let mut redis = redis.write().await;
+        if *replid != redis.replication.master_replid {
+            return Err(RedisType::SimpleError(
+                "ERR PSYNC FAILOVER replid must match my replid.".to_string(),
+            ));
+        }
+        if !redis.is_master() {
+            println!("Failover request received for replid {}.", replid);
+            redis.config.replica_of = None;
+            redis.replication.promote().await;
+        }
+    }
     let redis = redis.read().await;
-    let redis_id = redis.replication.master_replid.clone();
-    let offset = redis.replication.master_repl_offset;
-    let resp = format!("FULLRESYNC {} {}", redis_id, offset);
-    return RedisType::SimpleString(resp);
+    //A replica can only serve the history it got from its master
+    if !redis.is_master() && redis.replication.master_link.state != LinkState::Connected {
+        return Err(RedisType::SimpleError(
+            "NOMASTERLINK Can't SYNC while not connected with my master".to_string(),
+        ));
+    }
+    //The missing bytes are sent from the backlog when the replica is attached
+    if offset > 0 && redis.replication.partial_resync(replid, offset as u64) {
+        return Ok(Psync::Continue {
+            replid: redis.replication.master_replid.clone(),
+            offset: offset as u64 - 1,
+        });
+    }
+    Ok(Psync::FullResync)
+}
+
+//Sends +FULLRESYNC and the RDB file, returns the offset the file was taken at
+async fn full_resync<W: AsyncWrite + Unpin, S: RWStream>(
+    redis: &RwLock<Redis<S>>,
+    writer: &mut W,
+) -> std::io::Result<u64> {
+    let mut guard = redis.write().await;
+    //The replica will need the commands propagated while it gets the RDB file
+    guard.replication.create_backlog();
+    if !guard.config.repl_diskless_sync {
+        return disk_sync(redis, guard, writer).await;
+    }
+
+    let (sender, receiver) = mpsc::channel(SYNC_QUEUE);
+    match &mut guard.replication.pending_sync {
+        //A transfer is about to start, this replica gets the same snapshot
+        Some(pending) => {
+            pending.push(sender);
+            drop(guard);
+            receive(receiver, writer).await
+        }
+        None => {
+            guard.replication.pending_sync = Some(vec![sender]);
+            let delay = Duration::from_secs(guard.config.repl_diskless_sync_delay);
+            drop(guard);
+            let (_, result) = tokio::join!(diskless_sync(redis, delay), receive(receiver, writer));
+            result
+        }
+    }
+}
+
+//Waits for more replicas to join, then streams one snapshot to all of them
+async fn diskless_sync<S: RWStream>(redis: &RwLock<Redis<S>>, delay: Duration) {
+    tokio::time::sleep(delay).await;
+    let (replicas, replid, offset, memory) = {
+        let mut redis = redis.write().await;
+        let replicas = redis.replication.pending_sync.take().unwrap_or_default();
+        let replication = &redis.replication;
+        (
+            replicas,
+            replication.master_replid.clone(),
+            replication.master_repl_offset,
+            redis.snapshot(),
+        )
+    };
+    stream_snapshot(&memory, replid, offset, replicas).await;
+}
+
+/*
+ Sends the RDB file from disk as $<len>. The last file saved is reused while the
+ backlog still has everything written since, otherwise the dataset is saved again.
+*/
+async fn disk_sync<W: AsyncWrite + Unpin, S: RWStream>(
+    redis: &RwLock<Redis<S>>,
+    guard: RwLockWriteGuard<'_, Redis<S>>,
+    writer: &mut W,
+) -> std::io::Result<u64> {
+    let path = guard.rdb_path();
+    let snapshot = guard.replication.rdb_snapshot.clone();
+    let reusable = match &snapshot {
+        Some((replid, offset)) => guard.replication.partial_resync(replid, offset + 1),
+        None => false,
+    };
+    //Opened while holding the lock, a later save replaces the file instead of changing it
+    let file = match reusable {
+        true => File::open(&path).await.ok(),
+        false => None,
+    };
+    let (mut file, (replid, offset)) = match (file, snapshot) {
+        (Some(file), Some(snapshot)) => {
+            drop(guard);
+            (file, snapshot)
+        }
+        _ => save(redis, guard).await?,
+    };
+
+    let len = file.metadata().await?.len();
+    let response = RedisType::SimpleString(format!("FULLRESYNC {} {}", replid, offset));
+    writer.write_all(&response.encode()).await?;
+    writer.write_all(format!("${}\r\n", len).as_bytes()).await?;
+    tokio::io::copy(&mut file, writer).await?;
+    Ok(offset)
+}
+
+/*
+ Saves the dataset as it is now, the file is encoded and written without the lock
+ so other clients aren't stopped for as long as that takes.
+ Returns the saved file opened and the replid and offset it was taken at.
+*/
+async fn save<S: RWStream>(
+    redis: &RwLock<Redis<S>>,
+    guard: RwLockWriteGuard<'_, Redis<S>>,
+) -> std::io::Result<(File, (String, u64))> {
+    let memory = guard.snapshot();
+    let snapshot = (
+        guard.replication.master_replid.clone(),
+        guard.replication.master_repl_offset,
+    );
+    let temp_path = guard.temp_rdb_path();
+    drop(guard);
+
+    let written = temp_path.clone();
+    let result = tokio::task::spawn_blocking(move || std::fs::write(written, rdb::encode(&memory)))
+        .await
+        .map_err(std::io::Error::other)
+        .and_then(|result| result);
+
+    let mut redis = redis.write().await;
+    if let Err(e) = result.and_then(|_| redis.install_rdb(&temp_path, snapshot.clone())) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    let file = File::open(redis.rdb_path()).await?;
+    Ok((file, snapshot))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{psync::PsyncHandler, CommandReturn, Handler, HandlerParams},
+        redis::{config::Config, rdb, types::RedisType, value::ValueType, Redis},
+    };
+
+    #[tokio::test]
+    async fn test_psync_continue() {
+        let dir = std::env::temp_dir().join(format!("psync-{}", crate::util::gen_rand_string(8)));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            dir: Some(dir.to_string_lossy().to_string()),
+            db_file_name: Some("dump.rdb".to_string()),
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        redis.replication.create_backlog();
+        let set = RedisType::Array(vec![
+            RedisType::BulkString("SET".to_string()),
+            RedisType::BulkString("key".to_string()),
+            RedisType::BulkString("value".to_string()),
+        ]);
+        redis.replication.propagate_message(set.encode()).await;
+        let replid = redis.replication.master_replid.clone();
+
+        //The missing commands are sent when the replica is attached
+        let response = RedisType::SimpleString(format!("CONTINUE {}", replid));
+        let mock = Builder::new().write(&response.encode()).build();
+        let redis = Arc::new(RwLock::new(redis));
+        let handler_params = HandlerParams {
+            args: vec![replid.clone(), "1".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = PsyncHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::HandShakeCompleted(0));
+
+        //Unknown replication id, the RDB file is saved and sent from disk
+        let offset = set.encode().len();
+        let response = RedisType::SimpleString(format!("FULLRESYNC {} {}", replid, offset));
+        let mut writer = Vec::new();
+        let handler_params = HandlerParams {
+            args: vec!["?".to_string(), "-1".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: &mut writer,
+            closed: None,
+        };
+        let result = PsyncHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::HandShakeCompleted(offset as u64));
+        let file = RedisType::Bytes(std::fs::read(dir.join("dump.rdb")).unwrap());
+        assert_eq!(writer, [response.encode(), file.encode()].concat());
+        //Only the saved file is left, the temp file was moved in place
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        //Reused while nothing was written since
+        std::fs::write(dir.join("dump.rdb"), b"reused").unwrap();
+        let mock = Builder::new()
+            .write(&response.encode())
+            .write(b"$6\r\nreused")
+            .build();
+        let handler_params = HandlerParams {
+            args: vec!["?".to_string(), "-1".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = PsyncHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::HandShakeCompleted(offset as u64));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_psync_diskless() {
+        let config = Config {
+            repl_diskless_sync: true,
+            repl_diskless_sync_delay: 1,
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        redis.set(
+            "key".to_string(),
+            ValueType::String("value".to_string()),
+            None,
+        );
+        let replid = redis.replication.master_replid.clone();
+        let redis = RwLock::new(redis);
+
+        //Both replicas connect during the delay and share the same transfer
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        let first_params = HandlerParams {
+            args: vec!["?".to_string(), "-1".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: &mut first,
+            closed: None,
+        };
+        let second_params = HandlerParams {
+            args: vec!["?".to_string(), "-1".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: &mut second,
+            closed: None,
+        };
+        let (first_result, second_result) = tokio::join!(
+            PsyncHandler::handle(first_params),
+            PsyncHandler::handle(second_params)
+        );
+        assert_eq!(first_result, CommandReturn::HandShakeCompleted(0));
+        assert_eq!(second_result, CommandReturn::HandShakeCompleted(0));
+        assert_eq!(first, second);
+
+        let header = format!("+FULLRESYNC {} 0\r\n$EOF:", replid);
+        assert!(first.starts_with(header.as_bytes()));
+        let mark = &first[header.len()..header.len() + 40];
+        let file = &first[header.len() + 42..first.len() - 40];
+        assert_eq!(&first[first.len() - 40..], mark);
+        let file = rdb::decode(file).unwrap();
+        assert_eq!(file.databases[0].entries[0].key, "key");
+    }
 }
@@ -0,0 +1,95 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{pubsub::PubSub, replication::RWStream, types::RedisType};
+
+use super::{Command, CommandReturn, Handler, HandlerParams};
+
+pub struct PublishHandler;
+
+pub struct SPublishHandler;
+
+//PUBLISH channel message
+impl Handler for PublishHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        publish(params, Command::Publish, PubSub::publish).await
+    }
+}
+
+//SPUBLISH shardchannel message
+impl Handler for SPublishHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        publish(params, Command::SPublish, PubSub::spublish).await
+    }
+}
+
+//Both reply with the number of clients that got the message
+async fn publish<'a, W: AsyncWrite + Unpin, S: RWStream>(
+    params: HandlerParams<'a, W, S>,
+    command: Command,
+    publish: fn(&mut PubSub, &str, &str) -> usize,
+) -> CommandReturn {
+    let mut writer = params.writer;
+    let args = params.args;
+    if args.len() != 2 {
+        if params.should_reply {
+            let _ = writer.write_all(&command.arity_error().encode()).await;
+        }
+        return CommandReturn::Error;
+    }
+    let receivers = publish(&mut params.redis.write().await.pubsub, &args[0], &args[1]);
+    if params.should_reply {
+        let response = RedisType::Integer(receivers as i64);
+        let _ = writer.write_all(&response.encode()).await;
+    }
+    CommandReturn::Ok
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{
+            publish::{PublishHandler, SPublishHandler},
+            test_util::args,
+            Command, CommandReturn, Handler, HandlerParams,
+        },
+        redis::{config::Config, Redis},
+    };
+
+    #[tokio::test]
+    async fn test_publish_wrong_arity() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = RwLock::new(redis);
+        let mock = Builder::new()
+            .write(&Command::Publish.arity_error().encode())
+            .build();
+        let handler_params = HandlerParams {
+            args: args(&["channel"]),
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = PublishHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Error);
+
+        let mock = Builder::new()
+            .write(&Command::SPublish.arity_error().encode())
+            .build();
+        let handler_params = HandlerParams {
+            args: vec![],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = SPublishHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Error);
+    }
+}
@@ -0,0 +1,119 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{pubsub::PubSub, replication::RWStream, types::RedisType};
+
+use super::{CommandReturn, Handler, HandlerParams};
+
+pub struct PubSubHandler;
+
+//PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT | SHARDCHANNELS [pattern] | SHARDNUMSUB [channel ...]
+impl Handler for PubSubHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        if !params.should_reply {
+            return CommandReturn::Ok;
+        }
+        let mut writer = params.writer;
+        let response = introspect(&params.redis.read().await.pubsub, &params.args);
+        let _ = writer.write_all(&response.encode()).await;
+        match response {
+            RedisType::SimpleError(_) => CommandReturn::Error,
+            _ => CommandReturn::Ok,
+        }
+    }
+}
+
+fn introspect(pubsub: &PubSub, args: &[String]) -> RedisType {
+    let subcommand = args.first().map(|s| s.to_lowercase()).unwrap_or_default();
+    let rest = &args[1.min(args.len())..];
+    let names = |channels: Vec<String>| {
+        RedisType::Array(channels.into_iter().map(RedisType::BulkString).collect())
+    };
+    let counts = |count: &dyn Fn(&str) -> usize| {
+        RedisType::Array(
+            rest.iter()
+                .flat_map(|channel| {
+                    [
+                        RedisType::BulkString(channel.clone()),
+                        RedisType::Integer(count(channel) as i64),
+                    ]
+                })
+                .collect(),
+        )
+    };
+    match (subcommand.as_str(), rest.len()) {
+        ("channels", 0 | 1) => names(pubsub.channels(rest.first().map(|s| s.as_str()))),
+        ("shardchannels", 0 | 1) => names(pubsub.shard_channels(rest.first().map(|s| s.as_str()))),
+        ("numsub", _) => counts(&|channel| pubsub.numsub(channel)),
+        ("shardnumsub", _) => counts(&|channel| pubsub.shard_numsub(channel)),
+        ("numpat", 0) => RedisType::Integer(pubsub.numpat() as i64),
+        ("channels" | "shardchannels" | "numpat", _) => RedisType::SimpleError(format!(
+            "ERR wrong number of arguments for 'pubsub|{}' command",
+            subcommand
+        )),
+        _ => RedisType::SimpleError(format!(
+            "ERR unknown subcommand '{}'. Try PUBSUB HELP.",
+            subcommand
+        )),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use crate::{
+        client::command::test_util::args,
+        redis::{pubsub::PubSub, types::RedisType},
+    };
+
+    use super::introspect;
+
+    #[test]
+    fn test_introspect() {
+        let mut pubsub = PubSub::default();
+        let (subscriber, _messages) = unbounded_channel();
+        pubsub.subscribe("news".to_string(), &subscriber);
+        pubsub.psubscribe("n*".to_string(), &subscriber);
+        pubsub.ssubscribe("orders".to_string(), &subscriber);
+
+        let news = RedisType::BulkString("news".to_string());
+        assert_eq!(
+            introspect(&pubsub, &args(&["CHANNELS"])),
+            RedisType::Array(vec![news.clone()])
+        );
+        assert_eq!(
+            introspect(&pubsub, &args(&["channels", "w*"])),
+            RedisType::Array(vec![])
+        );
+        let expected = RedisType::Array(vec![
+            news,
+            RedisType::Integer(1),
+            RedisType::BulkString("weather".to_string()),
+            RedisType::Integer(0),
+        ]);
+        assert_eq!(
+            introspect(&pubsub, &args(&["NUMSUB", "news", "weather"])),
+            expected
+        );
+        assert_eq!(
+            introspect(&pubsub, &args(&["NUMPAT"])),
+            RedisType::Integer(1)
+        );
+        assert_eq!(
+            introspect(&pubsub, &args(&["SHARDCHANNELS"])),
+            RedisType::Array(vec![RedisType::BulkString("orders".to_string())])
+        );
+        assert_eq!(
+            introspect(&pubsub, &args(&["NUMPAT", "x"])),
+            RedisType::SimpleError(
+                "ERR wrong number of arguments for 'pubsub|numpat' command".to_string()
+            )
+        );
+        assert_eq!(
+            introspect(&pubsub, &args(&["FOO"])),
+            RedisType::SimpleError("ERR unknown subcommand 'foo'. Try PUBSUB HELP.".to_string())
+        );
+    }
+}
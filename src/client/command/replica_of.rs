@@ -0,0 +1,140 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{replication::RWStream, types::RedisType};
+
+use super::{CommandReturn, Handler, HandlerParams};
+
+pub struct ReplicaOfHandler;
+
+impl Handler for ReplicaOfHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let args = params.args;
+        let redis = params.redis;
+
+        if args.len() != 2 {
+            let response = RedisType::SimpleError(
+                "ERR wrong number of arguments for 'replicaof' command".to_string(),
+            );
+            if params.should_reply {
+                let _ = writer.write_all(&response.encode()).await;
+            }
+            return CommandReturn::Error;
+        }
+
+        let response = if args[0].eq_ignore_ascii_case("no") && args[1].eq_ignore_ascii_case("one")
+        {
+            let mut redis = redis.write().await;
+            if !redis.is_master() {
+                redis.replication.promote().await;
+                println!("MASTER MODE enabled (user request)");
+            }
+            RedisType::SimpleString("OK".to_string())
+        } else {
+            let host = args[0].clone();
+            let port = match args[1].parse::<u16>() {
+                Ok(port) => port,
+                Err(_) => {
+                    let response = RedisType::SimpleError(
+                        "ERR value is not an integer or out of range".to_string(),
+                    );
+                    if params.should_reply {
+                        let _ = writer.write_all(&response.encode()).await;
+                    }
+                    return CommandReturn::Error;
+                }
+            };
+            let mut redis = redis.write().await;
+            let master = Some((host.clone(), port));
+            if redis.replication.replica_of == master {
+                RedisType::SimpleString("OK Already connected to specified master".to_string())
+            } else {
+                println!("REPLICAOF {}:{} enabled (user request)", host, port);
+                redis.config.replica_of = master;
+                redis.replication.set_master(host, port);
+                RedisType::SimpleString("OK".to_string())
+            }
+        };
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        CommandReturn::Ok
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{replica_of::ReplicaOfHandler, CommandReturn, Handler, HandlerParams},
+        redis::{
+            config::Config,
+            replication::{link::LinkState, role::Role},
+            types::RedisType,
+            Redis,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_replica_of() {
+        let response = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        let handler_params = HandlerParams {
+            args: vec!["localhost".to_string(), "6380".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = ReplicaOfHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+        let redis = redis.read().await;
+        assert_eq!(redis.replication.role, Role::Slave);
+        assert_eq!(
+            redis.replication.replica_of,
+            Some(("localhost".to_string(), 6380))
+        );
+        assert_eq!(redis.replication.master_link.state, LinkState::Connect);
+    }
+
+    #[tokio::test]
+    async fn test_replica_of_no_one() {
+        let response = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let config = Config {
+            replica_of: Some(("localhost".to_string(), 6380)),
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        redis.replication.slave_read_repl_offset = 100;
+        let old_replid = redis.replication.master_replid.clone();
+        let redis = Arc::new(RwLock::new(redis));
+        let handler_params = HandlerParams {
+            args: vec!["NO".to_string(), "ONE".to_string()],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = ReplicaOfHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+        let redis = redis.read().await;
+        let replication = &redis.replication;
+        assert_eq!(replication.role, Role::Master);
+        assert_eq!(replication.replica_of, None);
+        assert_ne!(replication.master_replid, old_replid);
+        assert_eq!(replication.master_replid2, old_replid);
+        assert_eq!(replication.master_repl_offset, 100);
+        assert_eq!(replication.second_repl_offset, 101);
+        //Replicas of the old master can continue from where they were
+        assert!(replication.partial_resync(&old_replid, 101));
+    }
+}
@@ -0,0 +1,370 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    notify::Event,
+    rdb::{self, RdbError},
+    replication::RWStream,
+    types::RedisType,
+    value::ValueType,
+};
+
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
+
+pub struct RestoreHandler;
+
+struct RestoreOptions {
+    replace: bool,
+    abs_ttl: bool,
+}
+
+impl Handler for RestoreHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let args = params.args;
+        let redis = params.redis;
+
+        if args.len() < 3 {
+            return reply_error(
+                &mut writer,
+                params.should_reply,
+                "ERR wrong number of arguments for 'restore' command",
+            )
+            .await;
+        }
+        let key = args[0].clone();
+        let ttl = match args[1].parse::<i64>() {
+            Ok(ttl) if ttl >= 0 => ttl as u64,
+            Ok(_) => {
+                return reply_error(
+                    &mut writer,
+                    params.should_reply,
+                    "ERR Invalid TTL value, must be >= 0",
+                )
+                .await
+            }
+            Err(_) => {
+                return reply_error(
+                    &mut writer,
+                    params.should_reply,
+                    "ERR value is not an integer or out of range",
+                )
+                .await
+            }
+        };
+        let options = match parse_options(&args[3..]) {
+            Ok(options) => options,
+            Err(e) => return reply_error(&mut writer, params.should_reply, e).await,
+        };
+        let (value, payload) = match decode_payload(&args[2]) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                let message = format!("ERR {}", e);
+                return reply_error(&mut writer, params.should_reply, &message).await;
+            }
+        };
+
+        let mut redis = match lock_for_write(redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        if !options.replace && redis.get_value(&key).is_some() {
+            return reply_error(
+                &mut writer,
+                params.should_reply,
+                "BUSYKEY Target key name already exists.",
+            )
+            .await;
+        }
+
+        let expiration = match (ttl, options.abs_ttl) {
+            (0, _) => None,
+            (ttl, false) => Some(ttl),
+            (ttl, true) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis() as u64;
+                //Already expired, the key is not created
+                if ttl <= now {
+                    let deleted = options.replace && redis.delete(&key);
+                    if deleted {
+                        redis.notify(Event::Generic, "del", &key);
+                    }
+                    if params.should_reply {
+                        let response = RedisType::SimpleString("OK".to_string());
+                        let _ = writer.write_all(&response.encode()).await;
+                    }
+                    //Replicas drop the replaced key too
+                    if deleted {
+                        let command =
+                            RedisType::Array(vec![Command::Del.into(), RedisType::BulkString(key)]);
+                        redis.replication.propagate_message(command.encode()).await;
+                    }
+                    return CommandReturn::Ok;
+                }
+                Some(ttl - now)
+            }
+        };
+
+        let mut command: Vec<RedisType> = vec![Command::Restore.into()];
+        for (i, arg) in args.into_iter().enumerate() {
+            if i == 2 {
+                command.push(RedisType::BulkBytes(payload.clone()));
+            } else {
+                command.push(RedisType::BulkString(arg));
+            }
+        }
+        let command = RedisType::Array(command);
+        redis.set(key.clone(), value, expiration);
+        redis.notify(Event::Generic, "restore", &key);
+        if params.should_reply {
+            let response = RedisType::SimpleString("OK".to_string());
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        redis.replication.propagate_message(command.encode()).await;
+        CommandReturn::Ok
+    }
+}
+
+fn parse_options(args: &[String]) -> Result<RestoreOptions, &'static str> {
+    let mut options = RestoreOptions {
+        replace: false,
+        abs_ttl: false,
+    };
+    let mut idle_time = false;
+    let mut freq = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.to_uppercase().as_str() {
+            "REPLACE" => options.replace = true,
+            "ABSTTL" => options.abs_ttl = true,
+            //IDLETIME and FREQ are validated but not used since there is no eviction
+            "IDLETIME" if !freq => {
+                let value = iter.next().ok_or("ERR syntax error")?;
+                let value = value
+                    .parse::<i64>()
+                    .map_err(|_| "ERR value is not an integer or out of range")?;
+                if value < 0 {
+                    return Err("ERR Invalid IDLE value, must be >= 0");
+                }
+                idle_time = true;
+            }
+            "FREQ" if !idle_time => {
+                let value = iter.next().ok_or("ERR syntax error")?;
+                let value = value
+                    .parse::<i64>()
+                    .map_err(|_| "ERR value is not an integer or out of range")?;
+                if !(0..=255).contains(&value) {
+                    return Err("ERR Invalid FREQ value, must be >= 0 and <= 255");
+                }
+                freq = true;
+            }
+            _ => return Err("ERR syntax error"),
+        }
+    }
+    Ok(options)
+}
+
+/*
+ Arguments are Strings, binary payloads arrive with one char per byte (see RedisType::BulkBytes)
+ while valid utf-8 payloads arrive as is, the checksum tells which one it was
+*/
+fn decode_payload(arg: &str) -> Result<(ValueType, Vec<u8>), RdbError> {
+    let payload = arg.as_bytes().to_vec();
+    let error = match rdb::restore(&payload) {
+        Ok(value) => return Ok((value, payload)),
+        Err(e) => e,
+    };
+    if arg.chars().all(|c| (c as u32) <= 0xFF) {
+        let payload: Vec<u8> = arg.chars().map(|c| c as u8).collect();
+        match rdb::restore(&payload) {
+            Ok(value) => return Ok((value, payload)),
+            //The checksum matched, so this was the payload and its error is the one to report
+            Err(e) if e != RdbError::InvalidDumpPayload => return Err(e),
+            Err(_) => {}
+        }
+    }
+    Err(error)
+}
+
+async fn reply_error<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    should_reply: bool,
+    message: &str,
+) -> CommandReturn {
+    if should_reply {
+        let response = RedisType::SimpleError(message.to_string());
+        let _ = writer.write_all(&response.encode()).await;
+    }
+    CommandReturn::Error
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{restore::RestoreHandler, CommandReturn, Handler, HandlerParams},
+        redis::{config::Config, rdb, types::RedisType, value::ValueType, Redis},
+    };
+
+    fn payload(value: &str) -> String {
+        let payload = rdb::dump(&ValueType::String(value.to_string()));
+        RedisType::BulkBytes(payload).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_restore() {
+        let response = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        let handler_params = HandlerParams {
+            args: vec!["key".to_string(), "1000".to_string(), payload("value")],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = RestoreHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+        let redis = redis.read().await;
+        let value = redis.get("key").unwrap();
+        assert_eq!(value.value, ValueType::String("value".to_string()));
+        assert!(value.expires_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_restore_busy_key() {
+        let response =
+            RedisType::SimpleError("BUSYKEY Target key name already exists.".to_string());
+        let ok = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new()
+            .write(&response.encode())
+            .write(&ok.encode())
+            .build();
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        redis.set(
+            "key".to_string(),
+            ValueType::String("old".to_string()),
+            None,
+        );
+        let redis = Arc::new(RwLock::new(redis));
+        let mut handler_params = HandlerParams {
+            args: vec!["key".to_string(), "0".to_string(), payload("new")],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = RestoreHandler::handle(HandlerParams {
+            args: handler_params.args.clone(),
+            redis: &redis,
+            should_reply: true,
+            writer: &mut handler_params.writer,
+            closed: None,
+        })
+        .await;
+        assert_eq!(result, CommandReturn::Error);
+
+        handler_params.args.push("REPLACE".to_string());
+        let result = RestoreHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+        let redis = redis.read().await;
+        let value = redis.get_value("key").unwrap();
+        assert_eq!(value, &ValueType::String("new".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_restore_invalid_payload() {
+        let response =
+            RedisType::SimpleError("ERR DUMP payload version or checksum are wrong".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        let mut payload = payload("value");
+        payload.replace_range(0..1, "\u{1}");
+        let handler_params = HandlerParams {
+            args: vec!["key".to_string(), "0".to_string(), payload],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = RestoreHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Error);
+        assert!(redis.read().await.get("key").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_restore_lzf_oversized() {
+        let response = RedisType::SimpleError("ERR Bad data format".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        //LZF string claiming 1<<40 bytes once decompressed, with a valid checksum
+        let mut payload = vec![0x00, 0xC3, 0x05, 0x81];
+        payload.extend_from_slice(&(1u64 << 40).to_be_bytes());
+        payload.extend_from_slice(&[0x00, b'a', 0xE0, 0x00, 0x00, 11, 0]);
+        let checksum = rdb::crc64::crc64(0, &payload);
+        payload.extend_from_slice(&checksum.to_le_bytes());
+        let handler_params = HandlerParams {
+            args: vec![
+                "key".to_string(),
+                "0".to_string(),
+                RedisType::BulkBytes(payload).to_string(),
+            ],
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = RestoreHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Error);
+        assert!(redis.read().await.get("key").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_restore_expired_replace() {
+        let response = RedisType::SimpleString("OK".to_string());
+        let mock = Builder::new().write(&response.encode()).build();
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        redis.replication.create_backlog();
+        redis.set(
+            "key".to_string(),
+            ValueType::String("old".to_string()),
+            None,
+        );
+        let redis = Arc::new(RwLock::new(redis));
+        let args = ["key", "1", "REPLACE", "ABSTTL"].map(|arg| arg.to_string());
+        let mut args = args.to_vec();
+        args.insert(2, payload("new"));
+        let handler_params = HandlerParams {
+            args,
+            redis: &redis,
+            should_reply: true,
+            writer: mock,
+            closed: None,
+        };
+        let result = RestoreHandler::handle(handler_params).await;
+        assert_eq!(result, CommandReturn::Ok);
+
+        //The old key is gone on the replicas too
+        let redis = redis.read().await;
+        assert!(redis.get("key").is_none());
+        let propagated = redis
+            .replication
+            .backlog
+            .as_ref()
+            .and_then(|backlog| backlog.range_from(1))
+            .unwrap();
+        assert_eq!(propagated, b"*2\r\n$3\r\nDEL\r\n$3\r\nkey\r\n");
+    }
+}
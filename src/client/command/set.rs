@@ -1,8 +1,8 @@
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-use crate::redis::{replication::RWStream, types::RedisType, value::ValueType};
+use crate::redis::{notify::Event, replication::RWStream, types::RedisType, value::ValueType};
 
-use super::{Command, CommandReturn};
+use super::{lock_for_write, Command, CommandReturn};
 
 pub struct SetHandler;
 
@@ -86,9 +86,16 @@ impl super::Handler for SetHandler {
             command.push(RedisType::BulkString(arg));
         }
         let command = RedisType::Array(command);
-        let mut redis = redis.write().await;
+        let mut redis = match lock_for_write(redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
         let value = ValueType::String(value);
-        redis.set(key, value, expires_in);
+        redis.set(key.clone(), value, expires_in);
+        redis.notify(Event::String, "set", &key);
+        if expires_in.is_some() {
+            redis.notify(Event::Generic, "expire", &key);
+        }
         if params.should_reply {
             let response = RedisType::SimpleString("OK".to_string());
             let bytes = response.encode();
@@ -107,7 +114,11 @@ mod test {
     use tokio_test::io::{Builder, Mock};
 
     use crate::{
-        client::command::{set::SetHandler, Handler, HandlerParams},
+        client::command::{
+            set::SetHandler,
+            test_util::{args, event, events, subscribe_events},
+            Handler, HandlerParams,
+        },
         redis::{config::Config, types::RedisType, value::ValueType, Redis},
     };
 
@@ -120,6 +131,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
@@ -129,6 +141,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let redis = redis.read().await;
@@ -147,6 +160,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
@@ -156,6 +170,7 @@ mod test {
             redis: &redis,
             should_reply: false,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let redis = redis.read().await;
@@ -175,6 +190,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
@@ -189,6 +205,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let redis = redis.read().await;
@@ -208,6 +225,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
@@ -217,6 +235,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let redis = redis.read().await;
@@ -233,6 +252,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
@@ -242,6 +262,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let redis = redis.read().await;
@@ -259,6 +280,7 @@ mod test {
             dir: None,
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
@@ -274,6 +296,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let redis_w = redis.read().await;
@@ -287,6 +310,7 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let value = redis_w.get("key");
@@ -304,9 +328,36 @@ mod test {
             redis: &redis,
             should_reply: true,
             writer: mock,
+            closed: None,
         };
         SetHandler::handle(handler_params).await;
         let value = redis_w.get("key");
         assert!(value.is_none());
     }
+
+    #[tokio::test]
+    async fn test_set_notify() {
+        let config = Config {
+            notify_keyspace_events: "KEA".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        let mut messages = subscribe_events(&mut redis);
+        let redis = RwLock::new(redis);
+        let params = HandlerParams {
+            args: args(&["key", "value", "px", "100"]),
+            redis: &redis,
+            should_reply: false,
+            writer: Vec::new(),
+            closed: None,
+        };
+        SetHandler::handle(params).await;
+        let expected = vec![
+            event("__keyspace@0__:key", "set"),
+            event("__keyevent@0__:set", "key"),
+            event("__keyspace@0__:key", "expire"),
+            event("__keyevent@0__:expire", "key"),
+        ];
+        assert_eq!(events(&mut messages), expected);
+    }
 }
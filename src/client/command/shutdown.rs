@@ -0,0 +1,134 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    replication::RWStream,
+    shutdown::{self, ShutdownOptions},
+    types::RedisType,
+};
+
+use super::{CommandReturn, Handler, HandlerParams};
+
+pub struct ShutdownHandler;
+
+impl Handler for ShutdownHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let redis = params.redis;
+        let should_reply = params.should_reply;
+
+        let mut options = ShutdownOptions::default();
+        let mut abort = false;
+        for arg in &params.args {
+            match arg.to_uppercase().as_str() {
+                "NOSAVE" if options.save.is_none() => options.save = Some(false),
+                "SAVE" if options.save.is_none() => options.save = Some(true),
+                "NOW" => options.now = true,
+                "FORCE" => options.force = true,
+                "ABORT" => abort = true,
+                _ => {
+                    if !should_reply {
+                        return CommandReturn::Error;
+                    }
+                    let response = RedisType::SimpleError("ERR syntax error".to_string());
+                    let _ = writer.write_all(&response.encode()).await;
+                    return CommandReturn::Error;
+                }
+            }
+        }
+
+        if abort {
+            if options != ShutdownOptions::default() {
+                if !should_reply {
+                    return CommandReturn::Error;
+                }
+                let response = RedisType::SimpleError("ERR syntax error".to_string());
+                let _ = writer.write_all(&response.encode()).await;
+                return CommandReturn::Error;
+            }
+            let aborted = redis.read().await.shutdown.abort();
+            let response = if aborted {
+                RedisType::SimpleString("OK".to_string())
+            } else {
+                RedisType::SimpleError("ERR No shutdown in progress.".to_string())
+            };
+            if should_reply {
+                let _ = writer.write_all(&response.encode()).await;
+            }
+            return match aborted {
+                true => CommandReturn::Ok,
+                false => CommandReturn::Error,
+            };
+        }
+
+        match shutdown::run(redis, options).await {
+            Ok(redis) => {
+                //On success the connection is closed without a reply,
+                //keep the lock until the main loop exits the process
+                redis.shutdown.complete();
+                std::future::pending::<()>().await;
+                CommandReturn::Ok
+            }
+            Err(e) => {
+                if should_reply {
+                    let response = RedisType::SimpleError(e);
+                    let _ = writer.write_all(&response.encode()).await;
+                }
+                CommandReturn::Error
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::{
+        client::command::{shutdown::ShutdownHandler, CommandReturn, Handler, HandlerParams},
+        redis::{config::Config, types::RedisType, Redis},
+    };
+
+    #[tokio::test]
+    async fn test_shutdown_abort_without_shutdown() {
+        let response = RedisType::SimpleError("ERR No shutdown in progress.".to_string());
+        let mut mock = Builder::new().write(&response.encode()).build();
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        let params = HandlerParams {
+            args: vec!["abort".to_string()],
+            redis: &redis,
+            writer: &mut mock,
+            should_reply: true,
+            closed: None,
+        };
+        let result = ShutdownHandler::handle(params).await;
+        assert_eq!(result, CommandReturn::Error);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_syntax_error() {
+        let response = RedisType::SimpleError("ERR syntax error".to_string());
+        let response = response.encode();
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+
+        for args in [vec!["SAVE", "NOSAVE"], vec!["ABORT", "NOW"], vec!["LATER"]] {
+            let mut mock = Builder::new().write(&response).build();
+            let params = HandlerParams {
+                args: args.into_iter().map(|a| a.to_string()).collect(),
+                redis: &redis,
+                writer: &mut mock,
+                should_reply: true,
+                closed: None,
+            };
+            let result = ShutdownHandler::handle(params).await;
+            assert_eq!(result, CommandReturn::Error);
+        }
+        assert!(!redis.read().await.shutdown.in_progress());
+    }
+}
@@ -0,0 +1,59 @@
+//Helpers shared by the tests of the commands
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::redis::{
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{Stream, StreamData, StreamId},
+    Redis,
+};
+
+pub fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+pub fn bulk(s: &str) -> RedisType {
+    RedisType::BulkString(s.to_string())
+}
+
+//A stream with an entry i-0 for each i, without fields
+pub fn stream(ids: impl IntoIterator<Item = u64>) -> Stream {
+    stream_with(ids, |_| vec![])
+}
+
+pub fn stream_with(
+    ids: impl IntoIterator<Item = u64>,
+    fields: impl Fn(u64) -> Vec<(String, String)>,
+) -> Stream {
+    let entries = ids
+        .into_iter()
+        .map(|i| StreamData {
+            id: StreamId::new(i, 0),
+            fields: fields(i),
+        })
+        .collect::<Vec<_>>();
+    Stream::from(entries)
+}
+
+//Listens to every keyspace and keyevent channel
+pub fn subscribe_events<S: RWStream>(redis: &mut Redis<S>) -> UnboundedReceiver<RedisType> {
+    let (subscriber, messages) = unbounded_channel();
+    redis
+        .pubsub
+        .psubscribe("__key*@0__:*".to_string(), &subscriber);
+    messages
+}
+
+//The channels and messages published so far
+pub fn events(messages: &mut UnboundedReceiver<RedisType>) -> Vec<(String, String)> {
+    let mut events = vec![];
+    while let Ok(RedisType::Array(message)) = messages.try_recv() {
+        events.push((message[2].to_string(), message[3].to_string()));
+    }
+    events
+}
+
+pub fn event(channel: &str, message: &str) -> (String, String) {
+    (channel.to_string(), message.to_string())
+}
@@ -1,79 +1,80 @@
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use std::time::Duration;
 
-use crate::redis::{replication::RWStream, types::RedisType};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::RwLock,
+};
+
+use crate::redis::{replication::RWStream, types::RedisType, Redis};
 
 use super::{CommandReturn, Handler};
 
 pub struct WaitHandler;
 
 impl Handler for WaitHandler {
-    #[allow(dead_code, unused)]
     async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
         params: super::HandlerParams<'a, W, S>,
     ) -> CommandReturn {
         let mut writer = params.writer;
-        let args = params.args;
         let redis = params.redis;
         let should_reply = params.should_reply;
 
-        let target = match args.get(0) {
-            Some(num_replicas) => match num_replicas.parse::<usize>() {
-                Ok(num_replicas) => num_replicas,
-                Err(_) => {
-                    if !should_reply {
-                        return CommandReturn::Error;
-                    }
-                    let response =
-                        RedisType::SimpleError("ERR invalid number of replicas".to_string());
-                    let bytes = response.encode();
-                    let _ = writer.write_all(&bytes).await;
-                    return CommandReturn::Error;
-                }
-            },
-            None => {
-                if !should_reply {
-                    return CommandReturn::Error;
+        let (target, time_in_ms) = match parse(&params.args) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                if should_reply {
+                    let _ = writer.write_all(&e.encode()).await;
                 }
-                let response = RedisType::SimpleError("ERR missing number of replicas".to_string());
-                let bytes = response.encode();
-                let _ = writer.write_all(&bytes).await;
                 return CommandReturn::Error;
             }
         };
 
-        let time_in_ms = match args.get(1) {
-            Some(time_in_ms) => match time_in_ms.parse::<u64>() {
-                Ok(time_in_ms) => time_in_ms,
-                Err(_) => {
-                    if !should_reply {
-                        return CommandReturn::Error;
-                    }
-                    let response = RedisType::SimpleError("ERR invalid time in ms".to_string());
-                    let bytes = response.encode();
-                    let _ = writer.write_all(&bytes).await;
-                    return CommandReturn::Error;
-                }
-            },
-            None => {
-                if !should_reply {
-                    return CommandReturn::Error;
-                }
-                let response = RedisType::SimpleError("ERR missing time in ms".to_string());
-                let bytes = response.encode();
-                let _ = writer.write_all(&bytes).await;
-                return CommandReturn::Error;
-            }
+        let waiter = redis.write().await.replication.request_acks().await;
+        //The lock is released while waiting, the ACKs are tracked by each replica's reader
+        let timeout = match time_in_ms {
+            0 => None,
+            time_in_ms => Some(Duration::from_millis(time_in_ms)),
         };
-
-        let mut redis = redis.write().await;
-        let count_synced = redis
-            .replication
-            .count_sync_replicas(target, time_in_ms)
-            .await;
+        let count_synced = waiter.wait(target, timeout).await;
 
         let resp = RedisType::Integer(count_synced as i64);
         let bytes = resp.encode();
         let _ = writer.write_all(&bytes).await;
-        return CommandReturn::Ok;
+        CommandReturn::Ok
+    }
+}
+
+/*
+ WAIT inside a transaction, the lock is held so nothing could arrive while waiting.
+ Like redis, it replies with the replicas already in sync.
+*/
+pub async fn wait_now<S: RWStream>(args: &[String], redis: &RwLock<Redis<S>>) -> RedisType {
+    match parse(args) {
+        Ok(_) => {
+            let synced = redis.read().await.replication.synced_replicas();
+            RedisType::Integer(synced as i64)
+        }
+        Err(e) => e,
     }
 }
+
+//The number of replicas and the timeout in milliseconds
+fn parse(args: &[String]) -> Result<(usize, u64), RedisType> {
+    let target = match args.first() {
+        Some(num_replicas) => num_replicas
+            .parse::<usize>()
+            .map_err(|_| RedisType::SimpleError("ERR invalid number of replicas".to_string()))?,
+        None => {
+            return Err(RedisType::SimpleError(
+                "ERR missing number of replicas".to_string(),
+            ))
+        }
+    };
+    let time_in_ms = match args.get(1) {
+        Some(time_in_ms) => time_in_ms
+            .parse::<u64>()
+            .map_err(|_| RedisType::SimpleError("ERR invalid time in ms".to_string()))?,
+        None => return Err(RedisType::SimpleError("ERR missing time in ms".to_string())),
+    };
+    Ok((target, time_in_ms))
+}
@@ -0,0 +1,105 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_id, StreamId},
+    Redis,
+};
+
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
+
+pub struct XAckHandler;
+
+//XACK key group id [id ...]
+impl Handler for XAckHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let mut redis = match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        let (response, result) = match x_ack(&mut redis, &params.args) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
+        };
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        if response != RedisType::Integer(0) && result == CommandReturn::Ok {
+            let mut command: Vec<RedisType> = vec![Command::XAck.into()];
+            command.extend(params.args.into_iter().map(RedisType::BulkString));
+            let command = RedisType::Array(command);
+            redis.replication.propagate_message(command.encode()).await;
+        }
+        result
+    }
+}
+
+fn x_ack<S: RWStream>(redis: &mut Redis<S>, args: &[String]) -> Result<RedisType, RedisType> {
+    //All the IDs are checked before acknowledging any
+    let ids = args[2..]
+        .iter()
+        .map(|id| parse_id(id, 0))
+        .collect::<Option<Vec<StreamId>>>()
+        .ok_or_else(|| {
+            RedisType::SimpleError(
+                "ERR Invalid stream ID specified as stream command argument".to_string(),
+            )
+        })?;
+    let group = match redis.get_stream_mut(&args[0])? {
+        Some(stream) => stream.groups.get_mut(&args[1]),
+        None => None,
+    };
+    let group = match group {
+        Some(group) => group,
+        None => return Ok(RedisType::Integer(0)),
+    };
+    let acknowledged = ids.into_iter().filter(|id| group.ack(*id)).count();
+    if acknowledged > 0 {
+        redis.touch(&args[0]);
+    }
+    Ok(RedisType::Integer(acknowledged as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::args,
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{ConsumerGroup, Stream, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::x_ack;
+
+    #[test]
+    fn test_x_ack() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut group = ConsumerGroup::new(StreamId::new(2, 0));
+        group.create_consumer("alice", 0);
+        group.assign(StreamId::new(1, 0), "alice", 0, 1);
+        group.assign(StreamId::new(2, 0), "alice", 0, 1);
+        let mut stream = Stream::default();
+        stream.groups.insert("g".to_string(), group);
+        redis.set("s".to_string(), ValueType::Stream(stream), None);
+
+        let ack = args(&["s", "g", "1-0", "2", "3-0"]);
+        assert_eq!(x_ack(&mut redis, &ack), Ok(RedisType::Integer(2)));
+        assert_eq!(x_ack(&mut redis, &ack), Ok(RedisType::Integer(0)));
+        let ack = args(&["s", "h", "1-0"]);
+        assert_eq!(x_ack(&mut redis, &ack), Ok(RedisType::Integer(0)));
+        let ack = args(&["s", "g", "1-0", "x"]);
+        assert!(x_ack(&mut redis, &ack).is_err());
+    }
+}
@@ -1,20 +1,26 @@
-use std::{
-    collections::HashMap,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
 use crate::redis::{
+    notify::Event,
     replication::RWStream,
     types::RedisType,
-    value::{stream::StreamData, ValueType},
+    value::{
+        stream::{Stream, StreamData, StreamId},
+        ValueType,
+    },
 };
 
-use super::{Command, CommandReturn, Handler, HandlerParams};
+use super::{
+    lock_for_write,
+    x_trim::{exact_trim, parse_trim},
+    Command, CommandReturn, Handler, HandlerParams,
+};
 
 pub struct XAddHandler;
 
+//XADD key [NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]] *|id field value [field value ...]
 impl Handler for XAddHandler {
     async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
         params: HandlerParams<'a, W, S>,
@@ -22,7 +28,7 @@ impl Handler for XAddHandler {
         let mut writer = params.writer;
         let should_reply = params.should_reply;
         let redis = params.redis;
-        let args = params.args;
+        let mut args = params.args;
 
         //Key present
         let key = match args.get(0).cloned() {
@@ -39,8 +45,34 @@ impl Handler for XAddHandler {
             }
         };
 
+        //Options before the ID
+        let mut no_mkstream = false;
+        let mut trim = None;
+        let mut id_index = 1;
+        loop {
+            match args.get(id_index).map(|arg| arg.to_uppercase()).as_deref() {
+                Some("NOMKSTREAM") => {
+                    no_mkstream = true;
+                    id_index += 1;
+                }
+                Some("MAXLEN" | "MINID") => match parse_trim(&args[id_index..]) {
+                    Ok((parsed, taken)) => {
+                        trim = Some((parsed, id_index..id_index + taken));
+                        id_index += taken;
+                    }
+                    Err(e) => {
+                        if should_reply {
+                            let _ = writer.write_all(&e.encode()).await;
+                        }
+                        return CommandReturn::Error;
+                    }
+                },
+                _ => break,
+            }
+        }
+
         //ID present
-        let id = match args.get(1).cloned() {
+        let id = match args.get(id_index).cloned() {
             Some(id) => id,
             None => {
                 if !should_reply {
@@ -54,46 +86,47 @@ impl Handler for XAddHandler {
             }
         };
 
-        let mut redis = redis.write().await;
+        let mut redis = match lock_for_write(redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
         let value = redis.get_mut(&key);
-        let last_value = match value {
-            Some(ref v) => match v {
-                ValueType::Stream(stream) => match stream.last() {
-                    Some(last) => Some(last.id),
-                    None => None,
-                },
-                _ => None,
-            },
-            None => None,
+        //A new stream starts from 0-0
+        let last = match value {
+            Some(ValueType::Stream(ref stream)) => stream.last_id,
+            _ => StreamId::MIN,
         };
-        let key_id: (u64, u64);
+        let key_id: StreamId;
 
         //ID generation
         if id == "*" {
-            let first = SystemTime::now()
+            let now = SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .unwrap()
                 .as_millis() as u64;
-            let second = 0;
-            key_id = (first, second);
-        } else {
-            //ID parsing
-            let slash_i = match id.find('-') {
-                Some(i) => i,
+            //The clock may go backwards, the ID can't
+            let generated = if now > last.ms {
+                Some(StreamId::new(now, 0))
+            } else {
+                last.next()
+            };
+            key_id = match generated {
+                Some(generated) => generated,
                 None => {
                     if !should_reply {
                         return CommandReturn::Error;
                     }
                     let e = RedisType::SimpleError(
-                        "ERR wrong id format for 'xadd' command".to_string(),
+                        "ERR The stream has exhausted the last possible ID, unable to add more items"
+                            .to_string(),
                     );
                     let _ = writer.write_all(&e.encode()).await;
                     return CommandReturn::Error;
                 }
             };
-
-            let (first, second) = id.split_at(slash_i);
-            let mut second_value = 1;
+        } else {
+            //ID parsing, a missing sequence is 0
+            let (first, second) = id.split_once('-').unwrap_or((&id, "0"));
             let first_value = match first.parse::<u64>() {
                 Ok(f) => f,
                 Err(_) => {
@@ -107,20 +140,18 @@ impl Handler for XAddHandler {
                     return CommandReturn::Error;
                 }
             };
-            let second = &second[1..];
 
             //Partial ID generation
-            if second == "*" {
-                if let Some(last) = last_value {
-                    if last.0 == first_value {
-                        second_value = last.1 + 1;
-                    } else if first_value > 0 {
-                        second_value = 0;
-                    }
+            //A full millisecond is left for the validation below
+            let second_value = if second == "*" {
+                if last.ms == first_value {
+                    last.seq.saturating_add(1)
+                } else {
+                    0
                 }
             } else {
                 //ID parsing
-                second_value = match second.parse::<u64>() {
+                match second.parse::<u64>() {
                     Ok(s) => s,
                     Err(_) => {
                         if !should_reply {
@@ -132,47 +163,35 @@ impl Handler for XAddHandler {
                         let _ = writer.write_all(&e.encode()).await;
                         return CommandReturn::Error;
                     }
-                };
-            }
+                }
+            };
 
             //ID validation
-            if let Some(last) = last_value {
-                if first_value == 0 && second_value == 0 {
-                    if !should_reply {
-                        return CommandReturn::Error;
-                    }
-                    let e = RedisType::SimpleError(
-                        "ERR The ID specified in XADD must be greater than 0-0".to_string(),
-                    );
-                    let _ = writer.write_all(&e.encode()).await;
-                    return CommandReturn::Error;
-                }
-                if first_value < last.0 {
-                    if !should_reply {
-                        return CommandReturn::Error;
-                    }
-                    let e = RedisType::SimpleError(
-                        "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string(),
-                    );
-                    let _ = writer.write_all(&e.encode()).await;
+            if first_value == 0 && second_value == 0 {
+                if !should_reply {
                     return CommandReturn::Error;
                 }
-                if first_value == last.0 && second_value <= last.1 {
-                    if !should_reply {
-                        return CommandReturn::Error;
-                    }
-                    let e = RedisType::SimpleError(
-                        "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string(),
-                    );
-                    let _ = writer.write_all(&e.encode()).await;
+                let e = RedisType::SimpleError(
+                    "ERR The ID specified in XADD must be greater than 0-0".to_string(),
+                );
+                let _ = writer.write_all(&e.encode()).await;
+                return CommandReturn::Error;
+            }
+            key_id = StreamId::new(first_value, second_value);
+            if key_id <= last {
+                if !should_reply {
                     return CommandReturn::Error;
                 }
+                let e = RedisType::SimpleError(
+                    "ERR The ID specified in XADD is equal or smaller than the target stream top item".to_string(),
+                );
+                let _ = writer.write_all(&e.encode()).await;
+                return CommandReturn::Error;
             }
-            key_id = (first_value, second_value);
         }
 
-        let mut hash_map = HashMap::new();
-        let mut i = 2;
+        let mut fields = Vec::new();
+        let mut i = id_index + 1;
 
         //Fields parsing
         while i < args.len() {
@@ -195,12 +214,12 @@ impl Handler for XAddHandler {
                     return CommandReturn::Error;
                 }
             };
-            hash_map.insert(key, value);
+            fields.push((key, value));
             i += 2;
         }
 
         //Fields validation
-        if hash_map.is_empty() {
+        if fields.is_empty() {
             if !should_reply {
                 return CommandReturn::Error;
             }
@@ -211,36 +230,53 @@ impl Handler for XAddHandler {
             return CommandReturn::Error;
         }
 
-        let stream = StreamData {
-            id: key_id,
-            fields: hash_map,
-        };
+        let stream_data = StreamData { id: key_id, fields };
 
         //Stream creation
-        match value {
-            Some(v) => match v {
-                ValueType::Stream(v) => {
-                    v.push(stream);
-                }
-                _ => {
-                    if !should_reply {
-                        return CommandReturn::Error;
-                    }
-                    let e = RedisType::SimpleError(
-                        "WRONGTYPE Operation against a key holding the wrong kind of value"
-                            .to_string(),
-                    );
-                    let _ = writer.write_all(&e.encode()).await;
+        let stream = match value {
+            Some(ValueType::Stream(stream)) => stream,
+            Some(_) => {
+                if !should_reply {
                     return CommandReturn::Error;
                 }
-            },
+                let e = RedisType::SimpleError(
+                    "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+                );
+                let _ = writer.write_all(&e.encode()).await;
+                return CommandReturn::Error;
+            }
+            None if no_mkstream => {
+                if should_reply {
+                    let _ = writer.write_all(&RedisType::NullBulkString.encode()).await;
+                }
+                return CommandReturn::Ok;
+            }
             None => {
-                let value = ValueType::Stream(vec![stream]);
-                redis.set(key, value, None);
+                redis.set(key.clone(), ValueType::Stream(Stream::default()), None);
+                redis.get_stream_mut(&key).unwrap().unwrap()
+            }
+        };
+        stream.add(stream_data);
+
+        //Replication uses the generated ID
+        args[id_index] = key_id.to_string();
+
+        //Trimming, replicas get it exact
+        let trimmed = match trim {
+            Some((trim, range)) => {
+                let trimmed = stream.trim(&trim);
+                args.splice(range, exact_trim(stream, &trim));
+                trimmed
             }
+            None => 0,
+        };
+        redis.touch(&key);
+        redis.notify(Event::Stream, "xadd", &key);
+        redis.blocking.wake(&key);
+        if trimmed > 0 {
+            redis.notify(Event::Stream, "xtrim", &key);
         }
 
-        //Replication
         let mut command: Vec<RedisType> = vec![Command::XAdd.into()];
         for arg in args {
             command.push(RedisType::BulkString(arg));
@@ -249,8 +285,7 @@ impl Handler for XAddHandler {
         redis.replication.propagate_message(command.encode()).await;
 
         if should_reply {
-            let e = format!("{}-{}", key_id.0, key_id.1);
-            let e = RedisType::BulkString(e);
+            let e = RedisType::BulkString(key_id.to_string());
             let _ = writer.write_all(&e.encode()).await;
         }
         return CommandReturn::Ok;
@@ -269,12 +304,21 @@ mod test {
 
     use crate::redis::{
         config::Config,
-        value::{stream::StreamData, ValueType},
+        value::{
+            stream::{StreamData, StreamId},
+            ValueType,
+        },
         Redis,
     };
     use tokio_test::io::Builder;
 
-    use crate::{client::command::Handler, redis::types::RedisType};
+    use crate::{
+        client::command::{
+            test_util::{args, event, events, subscribe_events},
+            Handler,
+        },
+        redis::types::RedisType,
+    };
 
     #[tokio::test]
     async fn test_xadd_no_key() {
@@ -290,6 +334,7 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Error);
@@ -309,6 +354,7 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Error);
@@ -328,6 +374,7 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Error);
@@ -339,31 +386,31 @@ mod test {
         let mut redis: Redis<Mock> = Redis::new(config);
         let stream = vec![
             StreamData {
-                id: (1, 0),
+                id: StreamId::new(1, 0),
                 fields: vec![("field".to_string(), "value".to_string())]
                     .into_iter()
                     .collect(),
             },
             StreamData {
-                id: (1, 1),
+                id: StreamId::new(1, 1),
                 fields: vec![("field".to_string(), "value".to_string())]
                     .into_iter()
                     .collect(),
             },
             StreamData {
-                id: (2, 0),
+                id: StreamId::new(2, 0),
                 fields: vec![("field".to_string(), "value".to_string())]
                     .into_iter()
                     .collect(),
             },
             StreamData {
-                id: (2, 10),
+                id: StreamId::new(2, 10),
                 fields: vec![("field".to_string(), "value".to_string())]
                     .into_iter()
                     .collect(),
             },
         ];
-        redis.set("key".to_string(), ValueType::Stream(stream), None);
+        redis.set("key".to_string(), ValueType::Stream(stream.into()), None);
         let redis = Arc::new(RwLock::new(redis));
 
         //0-0 case
@@ -382,13 +429,14 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Error);
         let redis_ref = redis.read().await;
         let values = redis_ref.get_value("key").unwrap();
         let stream = match values {
-            ValueType::Stream(s) => s,
+            ValueType::Stream(s) => s.entries.iter().collect::<Vec<_>>(),
             _ => panic!(),
         };
         assert_eq!(stream.len(), 4);
@@ -411,13 +459,14 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Error);
         let redis_ref = redis.read().await;
         let values = redis_ref.get_value("key").unwrap();
         let stream = match values {
-            ValueType::Stream(s) => s,
+            ValueType::Stream(s) => s.entries.iter().collect::<Vec<_>>(),
             _ => panic!(),
         };
         assert_eq!(stream.len(), 4);
@@ -440,13 +489,14 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Error);
         let redis_ref = redis.read().await;
         let values = redis_ref.get_value("key").unwrap();
         let stream = match values {
-            ValueType::Stream(s) => s,
+            ValueType::Stream(s) => s.entries.iter().collect::<Vec<_>>(),
             _ => panic!(),
         };
         assert_eq!(stream.len(), 4);
@@ -469,13 +519,14 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Error);
         let redis_ref = redis.read().await;
         let values = redis_ref.get_value("key").unwrap();
         let stream = match values {
-            ValueType::Stream(s) => s,
+            ValueType::Stream(s) => s.entries.iter().collect::<Vec<_>>(),
             _ => panic!(),
         };
         assert_eq!(stream.len(), 4);
@@ -502,19 +553,23 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Ok);
         let redis_ref = redis.read().await;
         let values = redis_ref.get_value("key").unwrap();
         let stream = match values {
-            ValueType::Stream(s) => s,
+            ValueType::Stream(s) => s.entries.iter().collect::<Vec<_>>(),
             _ => panic!(),
         };
         assert_eq!(stream.len(), 1);
-        assert_eq!(stream[0].id, (1, 0));
+        assert_eq!(stream[0].id, StreamId::new(1, 0));
         assert_eq!(stream[0].fields.len(), 1);
-        assert_eq!(stream[0].fields.get("field").unwrap(), "value");
+        assert_eq!(
+            stream[0].fields[0],
+            ("field".to_string(), "value".to_string())
+        );
         drop(redis_ref);
 
         //timestamp only id case
@@ -531,19 +586,23 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Ok);
         let redis_ref = redis.read().await;
         let values = redis_ref.get_value("key").unwrap();
         let stream = match values {
-            ValueType::Stream(s) => s,
+            ValueType::Stream(s) => s.entries.iter().collect::<Vec<_>>(),
             _ => panic!(),
         };
         assert_eq!(stream.len(), 2);
-        assert_eq!(stream[1].id, (2, 0));
+        assert_eq!(stream[1].id, StreamId::new(2, 0));
         assert_eq!(stream[1].fields.len(), 1);
-        assert_eq!(stream[1].fields.get("field").unwrap(), "value");
+        assert_eq!(
+            stream[1].fields[0],
+            ("field".to_string(), "value".to_string())
+        );
         drop(redis_ref);
 
         //Fully generated id case
@@ -564,19 +623,141 @@ mod test {
             should_reply: true,
             redis: &redis,
             args,
+            closed: None,
         };
         let result = super::XAddHandler::handle(params).await;
         assert_eq!(result, super::CommandReturn::Ok);
         let redis_ref = redis.read().await;
         let values = redis_ref.get_value("key").unwrap();
         let stream = match values {
-            ValueType::Stream(s) => s,
+            ValueType::Stream(s) => s.entries.iter().collect::<Vec<_>>(),
             _ => panic!(),
         };
         assert_eq!(stream.len(), 3);
-        assert_eq!(stream[2].id.0, ts);
+        assert_eq!(stream[2].id.ms, ts);
         assert_eq!(stream[2].fields.len(), 1);
-        assert_eq!(stream[2].fields.get("field").unwrap(), "value");
+        assert_eq!(
+            stream[2].fields[0],
+            ("field".to_string(), "value".to_string())
+        );
         drop(redis_ref);
     }
+
+    #[tokio::test]
+    async fn test_xadd_options() {
+        let config = Config::default();
+        let redis: Redis<Mock> = Redis::new(config);
+        let redis = Arc::new(RwLock::new(redis));
+
+        //NOMKSTREAM on a missing key
+        let response = RedisType::NullBulkString;
+        let mut writer = Builder::new().write(&response.encode()).build();
+        let args = vec![
+            "key".to_string(),
+            "NOMKSTREAM".to_string(),
+            "1-0".to_string(),
+            "field".to_string(),
+            "value".to_string(),
+        ];
+        let params = super::HandlerParams {
+            writer: &mut writer,
+            should_reply: true,
+            redis: &redis,
+            args,
+            closed: None,
+        };
+        let result = super::XAddHandler::handle(params).await;
+        assert_eq!(result, super::CommandReturn::Ok);
+        assert!(redis.read().await.get_value("key").is_none());
+
+        //MAXLEN keeps the newest entries
+        for i in 1..=3 {
+            let response = RedisType::BulkString(format!("{}-0", i));
+            let mut writer = Builder::new().write(&response.encode()).build();
+            let args = vec![
+                "key".to_string(),
+                "MAXLEN".to_string(),
+                "=".to_string(),
+                "2".to_string(),
+                format!("{}-0", i),
+                "field".to_string(),
+                "value".to_string(),
+            ];
+            let params = super::HandlerParams {
+                writer: &mut writer,
+                should_reply: true,
+                redis: &redis,
+                args,
+                closed: None,
+            };
+            let result = super::XAddHandler::handle(params).await;
+            assert_eq!(result, super::CommandReturn::Ok);
+        }
+        let redis_ref = redis.read().await;
+        let values = redis_ref.get_value("key").unwrap();
+        let stream = match values {
+            ValueType::Stream(s) => s,
+            _ => panic!(),
+        };
+        assert_eq!(stream.entries.len(), 2);
+        assert_eq!(stream.first_id(), StreamId::new(2, 0));
+        assert_eq!(stream.entries_added, 3);
+        drop(redis_ref);
+
+        //LIMIT needs ~
+        let response = RedisType::SimpleError(
+            "ERR syntax error, LIMIT cannot be used without the special ~ option".to_string(),
+        );
+        let mut writer = Builder::new().write(&response.encode()).build();
+        let args = vec![
+            "key".to_string(),
+            "MINID".to_string(),
+            "2".to_string(),
+            "LIMIT".to_string(),
+            "10".to_string(),
+            "4-0".to_string(),
+            "field".to_string(),
+            "value".to_string(),
+        ];
+        let params = super::HandlerParams {
+            writer: &mut writer,
+            should_reply: true,
+            redis: &redis,
+            args,
+            closed: None,
+        };
+        let result = super::XAddHandler::handle(params).await;
+        assert_eq!(result, super::CommandReturn::Error);
+    }
+
+    #[tokio::test]
+    async fn test_x_add_notify() {
+        let config = Config {
+            notify_keyspace_events: "KEt".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        let mut messages = subscribe_events(&mut redis);
+        let redis = RwLock::new(redis);
+        for id in ["1-0", "2-0"] {
+            let params = super::HandlerParams {
+                args: args(&["s", "MAXLEN", "1", id, "f", "v"]),
+                redis: &redis,
+                should_reply: false,
+                writer: Vec::new(),
+                closed: None,
+            };
+            super::XAddHandler::handle(params).await;
+        }
+        //The second one trims the first entry
+        let expected = vec![
+            event("__keyspace@0__:s", "xadd"),
+            event("__keyevent@0__:xadd", "s"),
+            event("__keyspace@0__:s", "xadd"),
+            event("__keyevent@0__:xadd", "s"),
+            event("__keyspace@0__:s", "xtrim"),
+            event("__keyevent@0__:xtrim", "s"),
+        ];
+        assert_eq!(events(&mut messages), expected);
+    }
 }
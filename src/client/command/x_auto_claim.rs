@@ -0,0 +1,200 @@
+use std::{collections::BTreeMap, ops::Bound};
+
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_range_id, PendingEntry, Stream, StreamId},
+    Redis,
+};
+
+use super::{
+    lock_for_write,
+    x_claim::{ack_command, claim_command, no_group},
+    CommandReturn, Handler, HandlerParams,
+};
+
+pub struct XAutoClaimHandler;
+
+//Each entry claimed can cost this many looked at
+const ATTEMPTS_FACTOR: usize = 10;
+
+//XAUTOCLAIM key group consumer min-idle-time start [COUNT count] [JUSTID]
+impl Handler for XAutoClaimHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let mut redis = match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        let now = crate::util::now_ms();
+        let (response, propagate, result) = match x_auto_claim(&mut redis, &params.args, now) {
+            Ok((response, propagate)) => (response, propagate, CommandReturn::Ok),
+            Err(e) => (e, vec![], CommandReturn::Error),
+        };
+        if !propagate.is_empty() {
+            redis.touch(&params.args[0]);
+        }
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        for command in propagate {
+            redis.replication.propagate_message(command.encode()).await;
+        }
+        result
+    }
+}
+
+fn x_auto_claim<S: RWStream>(
+    redis: &mut Redis<S>,
+    args: &[String],
+    now: u64,
+) -> Result<(RedisType, Vec<RedisType>), RedisType> {
+    let (key, group_name, consumer) = (&args[0], &args[1], &args[2]);
+    let min_idle = args[3].parse::<i64>().map_err(|_| {
+        RedisType::SimpleError("ERR Invalid min-idle-time argument for XAUTOCLAIM".to_string())
+    })?;
+    let min_idle = min_idle.max(0) as u64;
+    let start = parse_range_id(&args[4], true).ok_or_else(|| {
+        RedisType::SimpleError(
+            "ERR Invalid stream ID specified as stream command argument".to_string(),
+        )
+    })?;
+    let mut count = 100;
+    let mut just_id = false;
+    let mut rest = args[5..].iter();
+    while let Some(arg) = rest.next() {
+        match arg.to_uppercase().as_str() {
+            "COUNT" => {
+                count = rest
+                    .next()
+                    .and_then(|count| count.parse::<usize>().ok())
+                    .filter(|count| (1..=usize::MAX / ATTEMPTS_FACTOR).contains(count))
+                    .ok_or_else(|| RedisType::SimpleError("ERR COUNT must be > 0".to_string()))?;
+            }
+            "JUSTID" => just_id = true,
+            _ => return Err(RedisType::SimpleError("ERR syntax error".to_string())),
+        }
+    }
+
+    let stream = redis.get_stream_mut(key)?;
+    let Stream {
+        entries, groups, ..
+    } = stream.ok_or_else(|| no_group(key, group_name))?;
+    let group = groups
+        .get_mut(group_name)
+        .ok_or_else(|| no_group(key, group_name))?;
+    group.create_consumer(consumer, now);
+
+    let mut attempts = count * ATTEMPTS_FACTOR;
+    //The PEL changes as entries are claimed, so each step looks up the next ID
+    let next_after = |pending: &BTreeMap<StreamId, PendingEntry>, bound| {
+        pending
+            .range((bound, Bound::Unbounded))
+            .next()
+            .map(|(id, _)| *id)
+    };
+    let mut next = next_after(&group.pending, Bound::Included(start));
+    let mut claimed = vec![];
+    let mut deleted = vec![];
+    let mut propagate = vec![];
+    while attempts > 0 && claimed.len() < count {
+        let id = match next {
+            Some(id) => id,
+            None => break,
+        };
+        next = next_after(&group.pending, Bound::Excluded(id));
+        attempts -= 1;
+        let delivery_time = group.pending[&id].delivery_time;
+        let data = match entries.get(id) {
+            Some(data) => data,
+            None => {
+                group.ack(id);
+                deleted.push(RedisType::BulkString(id.to_string()));
+                propagate.push(ack_command(key, group_name, id));
+                continue;
+            }
+        };
+        if now.saturating_sub(delivery_time) < min_idle {
+            continue;
+        }
+        let delivery_count = group.pending[&id].delivery_count + if just_id { 0 } else { 1 };
+        group.assign(id, consumer, now, delivery_count);
+        let entry = &group.pending[&id];
+        propagate.push(claim_command(key, group_name, id, entry, group.last_id));
+        claimed.push(match just_id {
+            true => RedisType::BulkString(id.to_string()),
+            false => RedisType::from(&data),
+        });
+    }
+    group.seen(consumer, now, !claimed.is_empty());
+
+    //Where the next call should start, 0-0 once the whole PEL was scanned
+    let cursor = next.unwrap_or(StreamId::MIN);
+    let response = RedisType::Array(vec![
+        RedisType::BulkString(cursor.to_string()),
+        RedisType::Array(claimed),
+        RedisType::Array(deleted),
+    ]);
+    Ok((response, propagate))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, bulk, stream},
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{ConsumerGroup, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::x_auto_claim;
+
+    #[test]
+    fn test_x_auto_claim() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut stream = stream([1, 3, 4]);
+        let mut group = ConsumerGroup::new(StreamId::new(4, 0));
+        group.create_consumer("alice", 0);
+        for (i, time) in [(1, 100), (2, 100), (3, 190), (4, 100)] {
+            group.assign(StreamId::new(i, 0), "alice", time, 1);
+        }
+        stream.groups.insert("g".to_string(), group);
+        redis.set("s".to_string(), ValueType::Stream(stream), None);
+
+        //2-0 was deleted and 3-0 is too recent
+        let claim = args(&["s", "g", "bob", "50", "0", "COUNT", "1", "JUSTID"]);
+        let (response, _) = x_auto_claim(&mut redis, &claim, 200).unwrap();
+        let expected = RedisType::Array(vec![
+            bulk("2-0"),
+            RedisType::Array(vec![bulk("1-0")]),
+            RedisType::Array(vec![]),
+        ]);
+        assert_eq!(response, expected);
+        let claim = args(&["s", "g", "bob", "50", "2-0", "JUSTID"]);
+        let (response, propagate) = x_auto_claim(&mut redis, &claim, 200).unwrap();
+        let expected = RedisType::Array(vec![
+            bulk("0-0"),
+            RedisType::Array(vec![bulk("4-0")]),
+            RedisType::Array(vec![bulk("2-0")]),
+        ]);
+        assert_eq!(response, expected);
+        assert_eq!(propagate.len(), 2);
+
+        let group = &redis.get_stream("s").unwrap().unwrap().groups["g"];
+        assert_eq!(group.consumers["bob"].pending.len(), 2);
+        assert_eq!(group.consumers["alice"].pending.len(), 1);
+        assert!(!group.pending.contains_key(&StreamId::new(2, 0)));
+    }
+}
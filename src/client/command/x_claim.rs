@@ -0,0 +1,307 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_id, PendingEntry, Stream, StreamId},
+    Redis,
+};
+
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
+
+pub struct XClaimHandler;
+
+/*
+ XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+ [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID lastid]
+*/
+impl Handler for XClaimHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let mut redis = match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        let now = crate::util::now_ms();
+        let (response, propagate, result) = match x_claim(&mut redis, &params.args, now) {
+            Ok((response, propagate)) => (response, propagate, CommandReturn::Ok),
+            Err(e) => (e, vec![], CommandReturn::Error),
+        };
+        if !propagate.is_empty() {
+            redis.touch(&params.args[0]);
+        }
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        //What happened to each entry, so replicas end up with the same times and counts
+        for command in propagate {
+            redis.replication.propagate_message(command.encode()).await;
+        }
+        result
+    }
+}
+
+struct Options {
+    delivery_time: Option<u64>,
+    retry_count: Option<u64>,
+    force: bool,
+    just_id: bool,
+    last_id: Option<StreamId>,
+}
+
+fn x_claim<S: RWStream>(
+    redis: &mut Redis<S>,
+    args: &[String],
+    now: u64,
+) -> Result<(RedisType, Vec<RedisType>), RedisType> {
+    let (key, group_name, consumer) = (&args[0], &args[1], &args[2]);
+    let not_integer =
+        || RedisType::SimpleError("ERR value is not an integer or out of range".to_string());
+    let min_idle = args[3].parse::<i64>().map_err(|_| {
+        RedisType::SimpleError("ERR Invalid min-idle-time argument for XCLAIM".to_string())
+    })?;
+    let min_idle = min_idle.max(0) as u64;
+
+    //The IDs go until the first option
+    let mut ids = vec![];
+    let mut rest = args[4..].iter();
+    let mut option = None;
+    for arg in rest.by_ref() {
+        match parse_id(arg, 0) {
+            Some(id) => ids.push(id),
+            None if ids.is_empty() => {
+                return Err(RedisType::SimpleError(
+                    "ERR Invalid stream ID specified as stream command argument".to_string(),
+                ))
+            }
+            None => {
+                option = Some(arg);
+                break;
+            }
+        }
+    }
+    let mut options = Options {
+        delivery_time: None,
+        retry_count: None,
+        force: false,
+        just_id: false,
+        last_id: None,
+    };
+    while let Some(arg) = option {
+        let mut value = || {
+            rest.next()
+                .ok_or_else(|| RedisType::SimpleError("ERR syntax error".to_string()))
+        };
+        match arg.to_uppercase().as_str() {
+            "IDLE" => {
+                let idle = value()?.parse::<i64>().map_err(|_| not_integer())?;
+                options.delivery_time = Some(now.saturating_sub(idle.max(0) as u64));
+            }
+            "TIME" => {
+                let time = value()?.parse::<i64>().map_err(|_| not_integer())?;
+                options.delivery_time = Some(time.max(0) as u64);
+            }
+            "RETRYCOUNT" => {
+                let count = value()?.parse::<u64>().map_err(|_| not_integer())?;
+                options.retry_count = Some(count);
+            }
+            "LASTID" => {
+                let id = parse_id(value()?, 0).ok_or_else(|| {
+                    RedisType::SimpleError(
+                        "ERR Invalid stream ID specified as stream command argument".to_string(),
+                    )
+                })?;
+                options.last_id = Some(id);
+            }
+            "FORCE" => options.force = true,
+            "JUSTID" => options.just_id = true,
+            _ => {
+                return Err(RedisType::SimpleError(format!(
+                    "ERR Unrecognized XCLAIM option '{}'",
+                    arg
+                )))
+            }
+        }
+        option = rest.next();
+    }
+
+    let stream = redis.get_stream_mut(key)?;
+    let Stream {
+        entries, groups, ..
+    } = stream.ok_or_else(|| no_group(key, group_name))?;
+    let group = groups
+        .get_mut(group_name)
+        .ok_or_else(|| no_group(key, group_name))?;
+    let mut last_id_changed = false;
+    if let Some(last_id) = options.last_id {
+        if last_id > group.last_id {
+            group.last_id = last_id;
+            last_id_changed = true;
+        }
+    }
+    group.create_consumer(consumer, now);
+    let delivery_time = options.delivery_time.unwrap_or(now);
+
+    let mut claimed = vec![];
+    let mut propagate = vec![];
+    for id in ids {
+        let data = entries.get(id);
+        let pending = match group.pending.get(&id) {
+            Some(pending) => pending.clone(),
+            //FORCE creates the entry as long as it is in the stream
+            None if options.force && data.is_some() => PendingEntry {
+                consumer: consumer.clone(),
+                delivery_time,
+                delivery_count: 0,
+            },
+            None => continue,
+        };
+        let data = match data {
+            Some(data) => data,
+            //Deleted from the stream, the group forgets it
+            None => {
+                group.ack(id);
+                propagate.push(ack_command(key, group_name, id));
+                continue;
+            }
+        };
+        if min_idle > 0 && now.saturating_sub(pending.delivery_time) < min_idle {
+            continue;
+        }
+        let delivery_count = match options.retry_count {
+            Some(count) => count,
+            None if options.just_id => pending.delivery_count,
+            None => pending.delivery_count + 1,
+        };
+        group.assign(id, consumer, delivery_time, delivery_count);
+        propagate.push(claim_command(
+            key,
+            group_name,
+            id,
+            &group.pending[&id],
+            group.last_id,
+        ));
+        claimed.push(match options.just_id {
+            true => RedisType::BulkString(id.to_string()),
+            false => RedisType::from(&data),
+        });
+    }
+    group.seen(consumer, now, !claimed.is_empty());
+    if last_id_changed && propagate.is_empty() {
+        propagate.push(RedisType::Array(
+            [
+                "XGROUP".to_string(),
+                "SETID".to_string(),
+                key.clone(),
+                group_name.clone(),
+                group.last_id.to_string(),
+            ]
+            .into_iter()
+            .map(RedisType::BulkString)
+            .collect(),
+        ));
+    }
+    Ok((RedisType::Array(claimed), propagate))
+}
+
+pub(super) fn no_group(key: &str, group: &str) -> RedisType {
+    RedisType::SimpleError(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        key, group
+    ))
+}
+
+//Forces the pending entry on a replica as it is here
+pub(super) fn claim_command(
+    key: &str,
+    group: &str,
+    id: StreamId,
+    entry: &PendingEntry,
+    last_id: StreamId,
+) -> RedisType {
+    let mut command: Vec<RedisType> = vec![Command::XClaim.into()];
+    let args = [
+        key.to_string(),
+        group.to_string(),
+        entry.consumer.clone(),
+        "0".to_string(),
+        id.to_string(),
+        "TIME".to_string(),
+        entry.delivery_time.to_string(),
+        "RETRYCOUNT".to_string(),
+        entry.delivery_count.to_string(),
+        "FORCE".to_string(),
+        "JUSTID".to_string(),
+        "LASTID".to_string(),
+        last_id.to_string(),
+    ];
+    command.extend(args.into_iter().map(RedisType::BulkString));
+    RedisType::Array(command)
+}
+
+pub(super) fn ack_command(key: &str, group: &str, id: StreamId) -> RedisType {
+    let mut command: Vec<RedisType> = vec![Command::XAck.into()];
+    let args = [key.to_string(), group.to_string(), id.to_string()];
+    command.extend(args.into_iter().map(RedisType::BulkString));
+    RedisType::Array(command)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, stream},
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{ConsumerGroup, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::x_claim;
+
+    #[test]
+    fn test_x_claim() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut stream = stream(1..=3);
+        let mut group = ConsumerGroup::new(StreamId::new(4, 0));
+        group.create_consumer("alice", 0);
+        group.assign(StreamId::new(1, 0), "alice", 100, 1);
+        group.assign(StreamId::new(2, 0), "alice", 190, 1);
+        //Deleted from the stream
+        group.assign(StreamId::new(4, 0), "alice", 100, 1);
+        stream.groups.insert("g".to_string(), group);
+        redis.set("s".to_string(), ValueType::Stream(stream), None);
+
+        let claim = args(&["s", "g", "bob", "50", "1-0", "2-0", "3-0", "4-0", "JUSTID"]);
+        let (response, propagate) = x_claim(&mut redis, &claim, 200).unwrap();
+        assert_eq!(
+            response,
+            RedisType::Array(vec![RedisType::BulkString("1-0".to_string())])
+        );
+        //The claim and the deleted entry
+        assert_eq!(propagate.len(), 2);
+
+        let group = &redis.get_stream("s").unwrap().unwrap().groups["g"];
+        assert_eq!(group.pending[&StreamId::new(1, 0)].consumer, "bob");
+        assert_eq!(group.pending[&StreamId::new(1, 0)].delivery_count, 1);
+        assert!(!group.pending.contains_key(&StreamId::new(4, 0)));
+        assert_eq!(group.consumers["alice"].pending.len(), 1);
+
+        let claim = args(&["s", "g", "bob", "0", "3-0", "FORCE", "RETRYCOUNT", "5"]);
+        let (response, _) = x_claim(&mut redis, &claim, 200).unwrap();
+        assert!(matches!(response, RedisType::Array(items) if items.len() == 1));
+        let group = &redis.get_stream("s").unwrap().unwrap().groups["g"];
+        assert_eq!(group.pending[&StreamId::new(3, 0)].delivery_count, 5);
+
+        let claim = args(&["s", "h", "bob", "0", "3-0"]);
+        assert!(x_claim(&mut redis, &claim, 200).is_err());
+    }
+}
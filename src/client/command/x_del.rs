@@ -0,0 +1,99 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    notify::Event,
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_id, StreamId},
+    Redis,
+};
+
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
+
+pub struct XDelHandler;
+
+//XDEL key id [id ...]
+impl Handler for XDelHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let mut redis = match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        let (response, result) = match x_del(&mut redis, &params.args) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
+        };
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        if response != RedisType::Integer(0) && result == CommandReturn::Ok {
+            let mut command: Vec<RedisType> = vec![Command::XDel.into()];
+            command.extend(params.args.into_iter().map(RedisType::BulkString));
+            let command = RedisType::Array(command);
+            redis.replication.propagate_message(command.encode()).await;
+        }
+        result
+    }
+}
+
+fn x_del<S: RWStream>(redis: &mut Redis<S>, args: &[String]) -> Result<RedisType, RedisType> {
+    let key = &args[0];
+    //All the IDs are checked before deleting any
+    let ids = args[1..]
+        .iter()
+        .map(|id| parse_id(id, 0))
+        .collect::<Option<Vec<StreamId>>>()
+        .ok_or_else(|| {
+            RedisType::SimpleError(
+                "ERR Invalid stream ID specified as stream command argument".to_string(),
+            )
+        })?;
+    let stream = match redis.get_stream_mut(key)? {
+        Some(stream) => stream,
+        None => return Ok(RedisType::Integer(0)),
+    };
+    let deleted = ids.into_iter().filter(|id| stream.delete(*id)).count();
+    if deleted > 0 {
+        redis.touch(key);
+        redis.notify(Event::Stream, "xdel", key);
+    }
+    Ok(RedisType::Integer(deleted as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, stream},
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{stream::StreamId, ValueType},
+            Redis,
+        },
+    };
+
+    use super::x_del;
+
+    #[test]
+    fn test_x_del() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        redis.set("s".to_string(), ValueType::Stream(stream(1..=3)), None);
+
+        let result = x_del(&mut redis, &args(&["s", "2-0", "3", "5-0", "2-0"]));
+        assert_eq!(result, Ok(RedisType::Integer(2)));
+        assert!(x_del(&mut redis, &args(&["s", "1-0", "x"])).is_err());
+
+        //The last ID stays even when its entry is gone
+        let stream = redis.get_stream("s").unwrap().unwrap();
+        assert_eq!(stream.entries.len(), 1);
+        assert_eq!(stream.last_id, StreamId::new(3, 0));
+        assert_eq!(stream.max_deleted_id, StreamId::new(3, 0));
+        let result = x_del(&mut redis, &args(&["missing", "1-0"]));
+        assert_eq!(result, Ok(RedisType::Integer(0)));
+    }
+}
@@ -0,0 +1,237 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    notify::Event,
+    replication::RWStream,
+    types::RedisType,
+    value::{
+        stream::{parse_id, ConsumerGroup, Stream},
+        ValueType,
+    },
+    Redis,
+};
+
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
+
+pub struct XGroupHandler;
+
+/*
+ XGROUP CREATE key group id|$ [MKSTREAM] [ENTRIESREAD entries-read]
+ XGROUP SETID key group id|$ [ENTRIESREAD entries-read]
+ XGROUP DESTROY key group
+ XGROUP CREATECONSUMER key group consumer
+ XGROUP DELCONSUMER key group consumer
+*/
+impl Handler for XGroupHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let mut redis = match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        let (response, result) = match x_group(&mut redis, &params.args) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
+        };
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        if result == CommandReturn::Ok {
+            let mut command: Vec<RedisType> = vec![Command::XGroup.into()];
+            command.extend(params.args.into_iter().map(RedisType::BulkString));
+            let command = RedisType::Array(command);
+            redis.replication.propagate_message(command.encode()).await;
+        }
+        result
+    }
+}
+
+fn x_group<S: RWStream>(redis: &mut Redis<S>, args: &[String]) -> Result<RedisType, RedisType> {
+    let subcommand = args.first().map(|s| s.to_lowercase()).unwrap_or_default();
+    let arity = match subcommand.as_str() {
+        "create" => 4..=7,
+        "setid" => 4..=6,
+        "createconsumer" | "delconsumer" => 4..=4,
+        "destroy" => 3..=3,
+        _ => {
+            return Err(RedisType::SimpleError(format!(
+                "ERR unknown subcommand '{}'. Try XGROUP HELP.",
+                subcommand
+            )))
+        }
+    };
+    if !arity.contains(&args.len()) {
+        return Err(RedisType::SimpleError(format!(
+            "ERR wrong number of arguments for 'xgroup|{}' command",
+            subcommand
+        )));
+    }
+    let key = &args[1];
+    let group = &args[2];
+    let now = crate::util::now_ms();
+
+    //None for $, the last ID of the stream
+    let id = match subcommand.as_str() {
+        "create" | "setid" if args[3] == "$" => Some(None),
+        "create" | "setid" => match parse_id(&args[3], 0) {
+            Some(id) => Some(Some(id)),
+            None => {
+                return Err(RedisType::SimpleError(
+                    "ERR Invalid stream ID specified as stream command argument".to_string(),
+                ))
+            }
+        },
+        _ => None,
+    };
+    let mut mkstream = false;
+    let mut entries_read = None;
+    let mut options = args[4.min(args.len())..].iter();
+    while let Some(option) = options.next() {
+        match option.to_uppercase().as_str() {
+            "MKSTREAM" if subcommand == "create" => mkstream = true,
+            "ENTRIESREAD" if id.is_some() => {
+                //-1 is unknown
+                let read = options.next().and_then(|read| read.parse::<i64>().ok());
+                entries_read = match read {
+                    Some(-1) => None,
+                    Some(read) if read >= 0 => Some(read as u64),
+                    _ => {
+                        return Err(RedisType::SimpleError(
+                            "ERR value for ENTRIESREAD must be positive or -1".to_string(),
+                        ))
+                    }
+                };
+            }
+            _ => return Err(RedisType::SimpleError("ERR syntax error".to_string())),
+        }
+    }
+
+    if redis.get_stream(key)?.is_none() {
+        if !mkstream {
+            return Err(RedisType::SimpleError(
+                "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.".to_string(),
+            ));
+        }
+        redis.set(key.clone(), ValueType::Stream(Stream::default()), None);
+    }
+    let stream = redis.get_stream_mut(key)?.unwrap();
+    let last_id = id.map(|id| id.unwrap_or(stream.last_id));
+    let no_group = || {
+        RedisType::SimpleError(format!(
+            "NOGROUP No such consumer group '{}' for key name '{}'",
+            group, key
+        ))
+    };
+
+    let (response, event) = match subcommand.as_str() {
+        "create" => {
+            if stream.groups.contains_key(group) {
+                return Err(RedisType::SimpleError(
+                    "BUSYGROUP Consumer Group name already exists".to_string(),
+                ));
+            }
+            let mut consumer_group = ConsumerGroup::new(last_id.unwrap());
+            consumer_group.entries_read = entries_read;
+            stream.groups.insert(group.clone(), consumer_group);
+            (
+                RedisType::SimpleString("OK".to_string()),
+                Some("xgroup-create"),
+            )
+        }
+        "setid" => {
+            let consumer_group = stream.groups.get_mut(group).ok_or_else(no_group)?;
+            consumer_group.last_id = last_id.unwrap();
+            consumer_group.entries_read = entries_read;
+            (
+                RedisType::SimpleString("OK".to_string()),
+                Some("xgroup-setid"),
+            )
+        }
+        "destroy" => match stream.groups.remove(group) {
+            Some(_) => (RedisType::Integer(1), Some("xgroup-destroy")),
+            None => (RedisType::Integer(0), None),
+        },
+        "createconsumer" => {
+            let consumer_group = stream.groups.get_mut(group).ok_or_else(no_group)?;
+            match consumer_group.create_consumer(&args[3], now) {
+                true => (RedisType::Integer(1), Some("xgroup-createconsumer")),
+                false => (RedisType::Integer(0), None),
+            }
+        }
+        _ => {
+            let consumer_group = stream.groups.get_mut(group).ok_or_else(no_group)?;
+            match consumer_group.delete_consumer(&args[3]) {
+                Some(pending) => (
+                    RedisType::Integer(pending as i64),
+                    Some("xgroup-delconsumer"),
+                ),
+                None => (RedisType::Integer(0), None),
+            }
+        }
+    };
+    if let Some(event) = event {
+        redis.touch(key);
+        redis.notify(Event::Stream, event, key);
+    }
+    //Clients blocked on the group get an error
+    if event == Some("xgroup-destroy") {
+        redis.blocking.wake(key);
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::args,
+        redis::{config::Config, types::RedisType, value::stream::StreamId, Redis},
+    };
+
+    use super::x_group;
+
+    #[test]
+    fn test_x_group() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let ok = Ok(RedisType::SimpleString("OK".to_string()));
+
+        let result = x_group(&mut redis, &args(&["CREATE", "s", "g", "$"]));
+        assert!(
+            matches!(result, Err(RedisType::SimpleError(e)) if e.starts_with("ERR The XGROUP"))
+        );
+        assert_eq!(
+            x_group(&mut redis, &args(&["CREATE", "s", "g", "$", "MKSTREAM"])),
+            ok
+        );
+        assert_eq!(
+            x_group(&mut redis, &args(&["CREATE", "s", "g", "0"])),
+            Err(RedisType::SimpleError(
+                "BUSYGROUP Consumer Group name already exists".to_string()
+            ))
+        );
+        let setid = args(&["SETID", "s", "g", "5-1", "ENTRIESREAD", "3"]);
+        assert_eq!(x_group(&mut redis, &setid), ok);
+        assert_eq!(
+            x_group(&mut redis, &args(&["SETID", "s", "h", "5-1"])),
+            Err(RedisType::SimpleError(
+                "NOGROUP No such consumer group 'h' for key name 's'".to_string()
+            ))
+        );
+
+        let create = args(&["CREATECONSUMER", "s", "g", "alice"]);
+        assert_eq!(x_group(&mut redis, &create), Ok(RedisType::Integer(1)));
+        assert_eq!(x_group(&mut redis, &create), Ok(RedisType::Integer(0)));
+        let delete = args(&["DELCONSUMER", "s", "g", "alice"]);
+        assert_eq!(x_group(&mut redis, &delete), Ok(RedisType::Integer(0)));
+
+        let stream = redis.get_stream("s").unwrap().unwrap();
+        assert_eq!(stream.groups["g"].last_id, StreamId::new(5, 1));
+        assert_eq!(stream.groups["g"].entries_read, Some(3));
+        let destroy = args(&["DESTROY", "s", "g"]);
+        assert_eq!(x_group(&mut redis, &destroy), Ok(RedisType::Integer(1)));
+        assert_eq!(x_group(&mut redis, &destroy), Ok(RedisType::Integer(0)));
+    }
+}
@@ -0,0 +1,366 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{ConsumerGroup, Stream, StreamData},
+    Redis,
+};
+
+use super::{CommandReturn, Handler, HandlerParams};
+
+pub struct XInfoHandler;
+
+//Entries XINFO STREAM FULL shows without COUNT
+const FULL_DEFAULT_COUNT: usize = 10;
+
+/*
+ XINFO STREAM key [FULL [COUNT count]]
+ XINFO GROUPS key
+ XINFO CONSUMERS key group
+*/
+impl Handler for XInfoHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        if !params.should_reply {
+            return CommandReturn::Ok;
+        }
+        let mut writer = params.writer;
+        let redis = params.redis.read().await;
+        let now = crate::util::now_ms();
+        let (response, result) = match x_info(&redis, &params.args, now) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
+        };
+        let _ = writer.write_all(&response.encode()).await;
+        result
+    }
+}
+
+fn x_info<S: RWStream>(
+    redis: &Redis<S>,
+    args: &[String],
+    now: u64,
+) -> Result<RedisType, RedisType> {
+    let subcommand = args[0].to_lowercase();
+    let arity = match subcommand.as_str() {
+        "stream" => 2..=5,
+        "groups" => 2..=2,
+        "consumers" => 3..=3,
+        _ => {
+            return Err(RedisType::SimpleError(format!(
+                "ERR unknown subcommand '{}'. Try XINFO HELP.",
+                subcommand
+            )))
+        }
+    };
+    if !arity.contains(&args.len()) {
+        return Err(RedisType::SimpleError(format!(
+            "ERR wrong number of arguments for 'xinfo|{}' command",
+            subcommand
+        )));
+    }
+    let key = &args[1];
+    let stream = redis
+        .get_stream(key)?
+        .ok_or_else(|| RedisType::SimpleError("ERR no such key".to_string()))?;
+
+    match subcommand.as_str() {
+        "stream" => {
+            //None is the summary, Some(0) every entry
+            let full = match &args[2..] {
+                [] => None,
+                [full] if full.eq_ignore_ascii_case("FULL") => Some(FULL_DEFAULT_COUNT),
+                [full, option, count]
+                    if full.eq_ignore_ascii_case("FULL")
+                        && option.eq_ignore_ascii_case("COUNT") =>
+                {
+                    let count = count.parse::<i64>().map_err(|_| {
+                        RedisType::SimpleError(
+                            "ERR value is not an integer or out of range".to_string(),
+                        )
+                    })?;
+                    Some(count.max(0) as usize)
+                }
+                _ => return Err(RedisType::SimpleError("ERR syntax error".to_string())),
+            };
+            Ok(stream_info(stream, full))
+        }
+        "groups" => {
+            let groups = stream
+                .groups
+                .iter()
+                .map(|(name, group)| {
+                    RedisType::Array(vec![
+                        bulk("name"),
+                        bulk(name),
+                        bulk("consumers"),
+                        RedisType::Integer(group.consumers.len() as i64),
+                        bulk("pending"),
+                        RedisType::Integer(group.pending.len() as i64),
+                        bulk("last-delivered-id"),
+                        bulk(&group.last_id.to_string()),
+                        bulk("entries-read"),
+                        optional(group.entries_read),
+                        bulk("lag"),
+                        optional(stream.lag(group)),
+                    ])
+                })
+                .collect();
+            Ok(RedisType::Array(groups))
+        }
+        _ => {
+            let group = stream.groups.get(&args[2]).ok_or_else(|| {
+                RedisType::SimpleError(format!(
+                    "NOGROUP No such consumer group '{}' for key name '{}'",
+                    args[2], key
+                ))
+            })?;
+            let consumers = group
+                .consumers
+                .iter()
+                .map(|(name, consumer)| {
+                    //-1 for a consumer that never got anything
+                    let inactive = consumer
+                        .active_time
+                        .map_or(-1, |active| now.saturating_sub(active) as i64);
+                    RedisType::Array(vec![
+                        bulk("name"),
+                        bulk(name),
+                        bulk("pending"),
+                        RedisType::Integer(consumer.pending.len() as i64),
+                        bulk("idle"),
+                        RedisType::Integer(now.saturating_sub(consumer.seen_time) as i64),
+                        bulk("inactive"),
+                        RedisType::Integer(inactive),
+                    ])
+                })
+                .collect();
+            Ok(RedisType::Array(consumers))
+        }
+    }
+}
+
+fn stream_info(stream: &Stream, full: Option<usize>) -> RedisType {
+    //Each node of entries is a key of the radix tree
+    let nodes = stream.entries.node_count() as i64;
+    let mut info = vec![
+        bulk("length"),
+        RedisType::Integer(stream.entries.len() as i64),
+        bulk("radix-tree-keys"),
+        RedisType::Integer(nodes),
+        bulk("radix-tree-nodes"),
+        RedisType::Integer(nodes),
+        bulk("last-generated-id"),
+        bulk(&stream.last_id.to_string()),
+        bulk("max-deleted-entry-id"),
+        bulk(&stream.max_deleted_id.to_string()),
+        bulk("entries-added"),
+        RedisType::Integer(stream.entries_added as i64),
+        bulk("recorded-first-entry-id"),
+        bulk(&stream.first_id().to_string()),
+    ];
+    let count = match full {
+        Some(count) => count,
+        None => {
+            info.extend([
+                bulk("groups"),
+                RedisType::Integer(stream.groups.len() as i64),
+                bulk("first-entry"),
+                entry(stream.entries.first().as_ref()),
+                bulk("last-entry"),
+                entry(stream.entries.last().as_ref()),
+            ]);
+            return RedisType::Array(info);
+        }
+    };
+    let count = if count == 0 { usize::MAX } else { count };
+    let entries = stream
+        .entries
+        .iter()
+        .take(count)
+        .map(|data| (&data).into())
+        .collect();
+    let groups = stream
+        .groups
+        .iter()
+        .map(|(name, group)| group_info(stream, name, group, count))
+        .collect();
+    info.extend([
+        bulk("entries"),
+        RedisType::Array(entries),
+        bulk("groups"),
+        RedisType::Array(groups),
+    ]);
+    RedisType::Array(info)
+}
+
+//A group with its pending entries and consumers, for XINFO STREAM FULL
+fn group_info(stream: &Stream, name: &str, group: &ConsumerGroup, count: usize) -> RedisType {
+    let pending = group
+        .pending
+        .iter()
+        .take(count)
+        .map(|(id, entry)| {
+            RedisType::Array(vec![
+                bulk(&id.to_string()),
+                bulk(&entry.consumer),
+                RedisType::Integer(entry.delivery_time as i64),
+                RedisType::Integer(entry.delivery_count as i64),
+            ])
+        })
+        .collect();
+    let consumers = group
+        .consumers
+        .iter()
+        .map(|(consumer_name, consumer)| {
+            let pending = consumer
+                .pending
+                .iter()
+                .take(count)
+                .filter_map(|id| group.pending.get(id).map(|entry| (id, entry)))
+                .map(|(id, entry)| {
+                    RedisType::Array(vec![
+                        bulk(&id.to_string()),
+                        RedisType::Integer(entry.delivery_time as i64),
+                        RedisType::Integer(entry.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            RedisType::Array(vec![
+                bulk("name"),
+                bulk(consumer_name),
+                bulk("seen-time"),
+                RedisType::Integer(consumer.seen_time as i64),
+                bulk("active-time"),
+                RedisType::Integer(consumer.active_time.map_or(-1, |active| active as i64)),
+                bulk("pel-count"),
+                RedisType::Integer(consumer.pending.len() as i64),
+                bulk("pending"),
+                RedisType::Array(pending),
+            ])
+        })
+        .collect();
+    RedisType::Array(vec![
+        bulk("name"),
+        bulk(name),
+        bulk("last-delivered-id"),
+        bulk(&group.last_id.to_string()),
+        bulk("entries-read"),
+        optional(group.entries_read),
+        bulk("lag"),
+        optional(stream.lag(group)),
+        bulk("pel-count"),
+        RedisType::Integer(group.pending.len() as i64),
+        bulk("pending"),
+        RedisType::Array(pending),
+        bulk("consumers"),
+        RedisType::Array(consumers),
+    ])
+}
+
+fn bulk(s: &str) -> RedisType {
+    RedisType::BulkString(s.to_string())
+}
+
+//Nil when it isn't known
+fn optional(value: Option<u64>) -> RedisType {
+    match value {
+        Some(value) => RedisType::Integer(value as i64),
+        None => RedisType::NullBulkString,
+    }
+}
+
+fn entry(data: Option<&StreamData>) -> RedisType {
+    match data {
+        Some(data) => data.into(),
+        None => RedisType::NullBulkString,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, bulk, stream},
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{ConsumerGroup, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::x_info;
+
+    //The value of a field in a flat field-value reply
+    fn field(reply: &RedisType, name: &str) -> RedisType {
+        match reply {
+            RedisType::Array(items) => {
+                let i = items.iter().position(|item| *item == bulk(name)).unwrap();
+                items[i + 1].clone()
+            }
+            _ => panic!("not an array"),
+        }
+    }
+
+    #[test]
+    fn test_x_info() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut stream = stream(1..=3);
+        stream.delete(StreamId::new(3, 0));
+        let mut group = ConsumerGroup::new(StreamId::new(1, 0));
+        group.entries_read = Some(1);
+        group.create_consumer("alice", 100);
+        group.assign(StreamId::new(1, 0), "alice", 150, 1);
+        group.seen("alice", 150, true);
+        group.create_consumer("bob", 120);
+        stream.groups.insert("g".to_string(), group);
+        redis.set("s".to_string(), ValueType::Stream(stream), None);
+
+        let info = x_info(&redis, &args(&["STREAM", "s"]), 200).unwrap();
+        assert_eq!(field(&info, "length"), RedisType::Integer(2));
+        assert_eq!(field(&info, "last-generated-id"), bulk("3-0"));
+        assert_eq!(field(&info, "max-deleted-entry-id"), bulk("3-0"));
+        assert_eq!(field(&info, "entries-added"), RedisType::Integer(3));
+        assert_eq!(field(&info, "groups"), RedisType::Integer(1));
+        let last = RedisType::Array(vec![bulk("2-0"), RedisType::Array(vec![])]);
+        assert_eq!(field(&info, "last-entry"), last);
+
+        let info = x_info(&redis, &args(&["STREAM", "s", "FULL", "COUNT", "1"]), 200).unwrap();
+        assert!(matches!(field(&info, "entries"), RedisType::Array(entries) if entries.len() == 1));
+
+        //A deletion after the group position makes the lag unknown
+        let groups = x_info(&redis, &args(&["GROUPS", "s"]), 200).unwrap();
+        let RedisType::Array(groups) = groups else {
+            panic!("not an array")
+        };
+        assert_eq!(field(&groups[0], "pending"), RedisType::Integer(1));
+        assert_eq!(field(&groups[0], "entries-read"), RedisType::Integer(1));
+        assert_eq!(field(&groups[0], "lag"), RedisType::NullBulkString);
+
+        let consumers = x_info(&redis, &args(&["CONSUMERS", "s", "g"]), 200).unwrap();
+        let RedisType::Array(consumers) = consumers else {
+            panic!("not an array")
+        };
+        assert_eq!(field(&consumers[0], "idle"), RedisType::Integer(50));
+        assert_eq!(field(&consumers[0], "inactive"), RedisType::Integer(50));
+        assert_eq!(field(&consumers[1], "inactive"), RedisType::Integer(-1));
+
+        assert_eq!(
+            x_info(&redis, &args(&["CONSUMERS", "s", "h"]), 200),
+            Err(RedisType::SimpleError(
+                "NOGROUP No such consumer group 'h' for key name 's'".to_string()
+            ))
+        );
+        assert_eq!(
+            x_info(&redis, &args(&["GROUPS", "missing"]), 200),
+            Err(RedisType::SimpleError("ERR no such key".to_string()))
+        );
+    }
+}
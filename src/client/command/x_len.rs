@@ -0,0 +1,29 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{replication::RWStream, types::RedisType};
+
+use super::{CommandReturn, Handler, HandlerParams};
+
+pub struct XLenHandler;
+
+//XLEN key
+impl Handler for XLenHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        if !params.should_reply {
+            return CommandReturn::Ok;
+        }
+        let mut writer = params.writer;
+        let redis = params.redis.read().await;
+        let (response, result) = match redis.get_stream(&params.args[0]) {
+            Ok(stream) => {
+                let len = stream.map_or(0, |stream| stream.entries.len());
+                (RedisType::Integer(len as i64), CommandReturn::Ok)
+            }
+            Err(e) => (e, CommandReturn::Error),
+        };
+        let _ = writer.write_all(&response.encode()).await;
+        result
+    }
+}
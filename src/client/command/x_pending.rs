@@ -0,0 +1,216 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_range_id, ConsumerGroup},
+    Redis,
+};
+
+use super::{x_claim::no_group, CommandReturn, Handler, HandlerParams};
+
+pub struct XPendingHandler;
+
+//XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+impl Handler for XPendingHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        if !params.should_reply {
+            return CommandReturn::Ok;
+        }
+        let mut writer = params.writer;
+        let redis = params.redis.read().await;
+        let now = crate::util::now_ms();
+        let (response, result) = match x_pending(&redis, &params.args, now) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
+        };
+        let _ = writer.write_all(&response.encode()).await;
+        result
+    }
+}
+
+fn x_pending<S: RWStream>(
+    redis: &Redis<S>,
+    args: &[String],
+    now: u64,
+) -> Result<RedisType, RedisType> {
+    let syntax_error = || RedisType::SimpleError("ERR syntax error".to_string());
+    let (key, group) = (&args[0], &args[1]);
+    let mut rest = &args[2..];
+    let mut min_idle = 0;
+    if rest
+        .first()
+        .is_some_and(|arg| arg.eq_ignore_ascii_case("IDLE"))
+    {
+        min_idle = rest
+            .get(1)
+            .and_then(|idle| idle.parse::<i64>().ok())
+            .ok_or_else(|| {
+                RedisType::SimpleError("ERR value is not an integer or out of range".to_string())
+            })?
+            .max(0) as u64;
+        rest = &rest[2..];
+    }
+    //The summary form takes nothing else
+    if !matches!(rest.len(), 0 | 3 | 4) || (rest.is_empty() && args.len() > 2) {
+        return Err(syntax_error());
+    }
+    let range = match rest {
+        [start, end, count, ..] => {
+            let invalid_id = || {
+                RedisType::SimpleError(
+                    "ERR Invalid stream ID specified as stream command argument".to_string(),
+                )
+            };
+            let start = parse_range_id(start, true).ok_or_else(invalid_id)?;
+            let end = parse_range_id(end, false).ok_or_else(invalid_id)?;
+            let count = count.parse::<i64>().map_err(|_| {
+                RedisType::SimpleError("ERR value is not an integer or out of range".to_string())
+            })?;
+            Some((start, end, count.max(0) as usize, rest.get(3)))
+        }
+        _ => None,
+    };
+
+    let group = redis
+        .get_stream(key)?
+        .and_then(|stream| stream.groups.get(group))
+        .ok_or_else(|| no_group(key, group))?;
+    Ok(match range {
+        None => summary(group),
+        Some((start, end, count, consumer)) => {
+            if start > end {
+                return Ok(RedisType::Array(vec![]));
+            }
+            let entries = group
+                .pending
+                .range(start..=end)
+                .filter(|(_, entry)| consumer.is_none_or(|c| *c == entry.consumer))
+                .map(|(id, entry)| (id, entry, now.saturating_sub(entry.delivery_time)))
+                .filter(|(_, _, idle)| *idle >= min_idle)
+                .take(count)
+                .map(|(id, entry, idle)| {
+                    RedisType::Array(vec![
+                        RedisType::BulkString(id.to_string()),
+                        RedisType::BulkString(entry.consumer.clone()),
+                        RedisType::Integer(idle as i64),
+                        RedisType::Integer(entry.delivery_count as i64),
+                    ])
+                })
+                .collect();
+            RedisType::Array(entries)
+        }
+    })
+}
+
+//The count, the smallest and greatest IDs and how many entries each consumer has
+fn summary(group: &ConsumerGroup) -> RedisType {
+    let (min, max) = match (
+        group.pending.first_key_value(),
+        group.pending.last_key_value(),
+    ) {
+        (Some((min, _)), Some((max, _))) => (*min, *max),
+        _ => {
+            return RedisType::Array(vec![
+                RedisType::Integer(0),
+                RedisType::NullBulkString,
+                RedisType::NullBulkString,
+                RedisType::NullArray,
+            ])
+        }
+    };
+    let consumers = group
+        .consumers
+        .iter()
+        .filter(|(_, consumer)| !consumer.pending.is_empty())
+        .map(|(name, consumer)| {
+            RedisType::Array(vec![
+                RedisType::BulkString(name.clone()),
+                RedisType::BulkString(consumer.pending.len().to_string()),
+            ])
+        })
+        .collect();
+    RedisType::Array(vec![
+        RedisType::Integer(group.pending.len() as i64),
+        RedisType::BulkString(min.to_string()),
+        RedisType::BulkString(max.to_string()),
+        RedisType::Array(consumers),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, bulk},
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{ConsumerGroup, Stream, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::x_pending;
+
+    #[test]
+    fn test_x_pending() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut group = ConsumerGroup::new(StreamId::new(3, 0));
+        group.create_consumer("alice", 0);
+        group.create_consumer("bob", 0);
+        group.assign(StreamId::new(1, 0), "alice", 100, 1);
+        group.assign(StreamId::new(2, 0), "bob", 150, 2);
+        group.assign(StreamId::new(3, 0), "alice", 190, 1);
+        let mut stream = Stream::default();
+        stream.groups.insert("g".to_string(), group);
+        stream
+            .groups
+            .insert("empty".to_string(), ConsumerGroup::new(StreamId::new(0, 0)));
+        redis.set("s".to_string(), ValueType::Stream(stream), None);
+
+        let expected = RedisType::Array(vec![
+            RedisType::Integer(3),
+            bulk("1-0"),
+            bulk("3-0"),
+            RedisType::Array(vec![
+                RedisType::Array(vec![bulk("alice"), bulk("2")]),
+                RedisType::Array(vec![bulk("bob"), bulk("1")]),
+            ]),
+        ]);
+        assert_eq!(x_pending(&redis, &args(&["s", "g"]), 200), Ok(expected));
+        let expected = RedisType::Array(vec![
+            RedisType::Integer(0),
+            RedisType::NullBulkString,
+            RedisType::NullBulkString,
+            RedisType::NullArray,
+        ]);
+        assert_eq!(x_pending(&redis, &args(&["s", "empty"]), 200), Ok(expected));
+
+        let expected = RedisType::Array(vec![RedisType::Array(vec![
+            bulk("2-0"),
+            bulk("bob"),
+            RedisType::Integer(50),
+            RedisType::Integer(2),
+        ])]);
+        let extended = args(&["s", "g", "IDLE", "20", "(1-0", "+", "10"]);
+        assert_eq!(x_pending(&redis, &extended, 200), Ok(expected));
+        let extended = args(&["s", "g", "-", "+", "10", "alice"]);
+        let result = x_pending(&redis, &extended, 200).unwrap();
+        assert!(matches!(result, RedisType::Array(items) if items.len() == 2));
+
+        assert_eq!(
+            x_pending(&redis, &args(&["s", "h"]), 200),
+            Err(RedisType::SimpleError(
+                "NOGROUP No such key 's' or consumer group 'h'".to_string()
+            ))
+        );
+        assert!(x_pending(&redis, &args(&["s", "g", "-", "+"]), 200).is_err());
+    }
+}
@@ -1,171 +1,132 @@
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
-use crate::redis::{replication::RWStream, types::RedisType};
+use crate::redis::{replication::RWStream, types::RedisType, value::stream::parse_range_id, Redis};
 
 use super::{CommandReturn, Handler, HandlerParams};
 
 pub struct XRangeHandler;
 
+//XRANGE key start end [COUNT count]
 impl Handler for XRangeHandler {
     async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
         params: HandlerParams<'a, W, S>,
     ) -> CommandReturn {
+        if !params.should_reply {
+            return CommandReturn::Ok;
+        }
         let mut writer = params.writer;
-
-        let key = match params.args.get(0).cloned() {
-            Some(key) => key,
-            None => {
-                if !params.should_reply {
-                    return CommandReturn::Error;
-                }
-                let e = RedisType::SimpleError(
-                    "ERR wrong number of arguments for 'xrange' command".to_string(),
-                );
-                let _ = writer.write_all(&e.encode()).await;
-                return CommandReturn::Error;
-            }
+        let redis = params.redis.read().await;
+        let (response, result) = match x_range(&redis, &params.args) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
         };
+        let _ = writer.write_all(&response.encode()).await;
+        result
+    }
+}
 
-        let start = match params.args.get(1).cloned() {
-            Some(start) => start,
-            None => {
-                if !params.should_reply {
-                    return CommandReturn::Error;
-                }
-                let e = RedisType::SimpleError(
-                    "ERR wrong number of arguments for 'xrange' command".to_string(),
-                );
-                let _ = writer.write_all(&e.encode()).await;
-                return CommandReturn::Error;
-            }
-        };
+fn x_range<S: RWStream>(redis: &Redis<S>, args: &[String]) -> Result<RedisType, RedisType> {
+    let invalid_id = || {
+        RedisType::SimpleError(
+            "ERR Invalid stream ID specified as stream command argument".to_string(),
+        )
+    };
+    let start = parse_range_id(&args[1], true).ok_or_else(invalid_id)?;
+    let end = parse_range_id(&args[2], false).ok_or_else(invalid_id)?;
+    let count = parse_count(&args[3..])?;
+    if count == Some(0) {
+        return Ok(RedisType::NullArray);
+    }
+    match redis.get_x_range(&args[0], start, end, count) {
+        RedisType::SimpleError(e) => Err(RedisType::SimpleError(e)),
+        response => Ok(response),
+    }
+}
 
-        let end = match params.args.get(2).cloned() {
-            Some(end) => end,
-            None => {
-                if !params.should_reply {
-                    return CommandReturn::Error;
-                }
-                let e = RedisType::SimpleError(
-                    "ERR wrong number of arguments for 'xrange' command".to_string(),
-                );
-                let _ = writer.write_all(&e.encode()).await;
-                return CommandReturn::Error;
+//[COUNT count] of XRANGE and XREVRANGE, a negative count is 0 and gets a nil reply
+pub(super) fn parse_count(args: &[String]) -> Result<Option<usize>, RedisType> {
+    let mut count = None;
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match options.next() {
+            Some(value) if option.eq_ignore_ascii_case("COUNT") => {
+                let value = value.parse::<i64>().map_err(|_| {
+                    RedisType::SimpleError(
+                        "ERR value is not an integer or out of range".to_string(),
+                    )
+                })?;
+                count = Some(value.max(0) as usize);
             }
-        };
+            _ => return Err(RedisType::SimpleError("ERR syntax error".to_string())),
+        }
+    }
+    Ok(count)
+}
 
-        let start_r: (u64, u64);
-        let end_r: (u64, u64);
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
 
-        if start == "-" {
-            start_r = (0, 0);
-        } else {
-            if start.contains("-") {
-                let (first, second) = start.split_at(start.find("-").unwrap());
-                let first = match first.parse::<u64>() {
-                    Ok(first) => first,
-                    Err(_) => {
-                        if !params.should_reply {
-                            return CommandReturn::Error;
-                        }
-                        let e = RedisType::SimpleError(
-                            "ERR wrong number of arguments for 'xrange' command".to_string(),
-                        );
-                        let _ = writer.write_all(&e.encode()).await;
-                        return CommandReturn::Error;
-                    }
-                };
-                let second = match second[1..].parse::<u64>() {
-                    Ok(second) => second,
-                    Err(_) => {
-                        if !params.should_reply {
-                            return CommandReturn::Error;
-                        }
-                        let e = RedisType::SimpleError(
-                            "ERR wrong number of arguments for 'xrange' command".to_string(),
-                        );
-                        let _ = writer.write_all(&e.encode()).await;
-                        return CommandReturn::Error;
-                    }
-                };
+    use crate::{
+        client::command::test_util::args,
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{Stream, StreamData, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
 
-                start_r = (first, second);
-            } else {
-                let first = match start.parse::<u64>() {
-                    Ok(first) => first,
-                    Err(_) => {
-                        if !params.should_reply {
-                            return CommandReturn::Error;
-                        }
-                        let e = RedisType::SimpleError(
-                            "ERR wrong number of arguments for 'xrange' command".to_string(),
-                        );
-                        let _ = writer.write_all(&e.encode()).await;
-                        return CommandReturn::Error;
-                    }
-                };
+    use super::x_range;
 
-                start_r = (first, 0);
-            }
+    fn ids(response: RedisType) -> Vec<String> {
+        match response {
+            RedisType::Array(entries) => entries
+                .into_iter()
+                .map(|entry| match entry {
+                    RedisType::Array(entry) => entry[0].to_string(),
+                    _ => panic!("not an entry"),
+                })
+                .collect(),
+            _ => panic!("not an array"),
         }
+    }
 
-        if end == "+" {
-            end_r = (u64::MAX, u64::MAX);
-        } else {
-            if end.contains("-") {
-                let (first, second) = end.split_at(end.find("-").unwrap());
-                let first = match first.parse::<u64>() {
-                    Ok(first) => first,
-                    Err(_) => {
-                        if !params.should_reply {
-                            return CommandReturn::Error;
-                        }
-                        let e = RedisType::SimpleError(
-                            "ERR wrong number of arguments for 'xrange' command".to_string(),
-                        );
-                        let _ = writer.write_all(&e.encode()).await;
-                        return CommandReturn::Error;
-                    }
-                };
-                let second = match second[1..].parse::<u64>() {
-                    Ok(second) => second,
-                    Err(_) => {
-                        if !params.should_reply {
-                            return CommandReturn::Error;
-                        }
-                        let e = RedisType::SimpleError(
-                            "ERR wrong number of arguments for 'xrange' command".to_string(),
-                        );
-                        let _ = writer.write_all(&e.encode()).await;
-                        return CommandReturn::Error;
-                    }
-                };
+    #[test]
+    fn test_x_range() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let entries = [(1, 5), (1, 7), (2, 0), (2, 3), (5, 0)]
+            .into_iter()
+            .map(|(ms, seq)| StreamData {
+                id: StreamId::new(ms, seq),
+                fields: vec![],
+            })
+            .collect::<Vec<_>>();
+        redis.set(
+            "s".to_string(),
+            ValueType::Stream(Stream::from(entries)),
+            None,
+        );
 
-                end_r = (first, second);
-            } else {
-                let first = match end.parse::<u64>() {
-                    Ok(first) => first,
-                    Err(_) => {
-                        if !params.should_reply {
-                            return CommandReturn::Error;
-                        }
-                        let e = RedisType::SimpleError(
-                            "ERR wrong number of arguments for 'xrange' command".to_string(),
-                        );
-                        let _ = writer.write_all(&e.encode()).await;
-                        return CommandReturn::Error;
-                    }
-                };
+        //The sequence only matters within the same millisecond
+        let response = x_range(&redis, &args(&["s", "1-5", "2-0"])).unwrap();
+        assert_eq!(ids(response), vec!["1-5", "1-7", "2-0"]);
+        //Incomplete IDs take the whole millisecond
+        let response = x_range(&redis, &args(&["s", "2", "2"])).unwrap();
+        assert_eq!(ids(response), vec!["2-0", "2-3"]);
+        let response = x_range(&redis, &args(&["s", "(1-5", "+", "COUNT", "2"])).unwrap();
+        assert_eq!(ids(response), vec!["1-7", "2-0"]);
+        let response = x_range(&redis, &args(&["s", "-", "(2-3"])).unwrap();
+        assert_eq!(ids(response), vec!["1-5", "1-7", "2-0"]);
 
-                end_r = (first, 0);
-            }
-        }
-        let redis = params.redis.read().await;
-        let resp = redis.get_x_range(&key, start_r, end_r);
-        if !params.should_reply {
-            return CommandReturn::Ok;
-        }
-        let _ = writer.write_all(&resp.encode()).await;
-        CommandReturn::Ok
+        let response = x_range(&redis, &args(&["s", "-", "+", "COUNT", "0"]));
+        assert_eq!(response, Ok(RedisType::NullArray));
+        let response = x_range(&redis, &args(&["missing", "-", "+"]));
+        assert_eq!(response, Ok(RedisType::Array(vec![])));
+        assert!(x_range(&redis, &args(&["s", "(-", "+"])).is_err());
+        assert!(x_range(&redis, &args(&["s", "-", "+", "COUNT"])).is_err());
     }
 }
@@ -1,38 +1,41 @@
-use tokio::{io::AsyncWrite, sync::RwLock};
+use tokio::{
+    io::AsyncWrite,
+    time::{Duration, Instant},
+};
 
-use crate::redis::{replication::RWStream, types::RedisType, value::ValueType, Redis};
+use crate::redis::{
+    blocking::Wake,
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_id, StreamId},
+    Redis,
+};
 use tokio::io::AsyncWriteExt;
 
 use super::{CommandReturn, Handler, HandlerParams};
 
 enum IdType {
-    Id(u64, u64),
+    Id(StreamId),
+    //"$", only entries added from now on
     Last,
+    //"+", the last entry
+    LastEntry,
 }
 
 impl IdType {
-    async fn to_id<S: RWStream>(self, key: &str, redis: &RwLock<Redis<S>>) -> (u64, u64) {
+    //The ID entries are read after, a missing stream reads everything
+    fn to_id<S: RWStream>(self, key: &str, redis: &Redis<S>) -> StreamId {
+        let stream = match self {
+            IdType::Id(id) => return id,
+            _ => redis.get_stream(key).ok().flatten(),
+        };
         match self {
-            IdType::Id(first, second) => (first, second),
-            IdType::Last => {
-                let redis_w = redis.read().await;
-                let response = redis_w.get(key);
-                let value = match response {
-                    Some(value) => value,
-                    None => return (0, 0),
-                };
-                let value = match &value.value {
-                    ValueType::Stream(value) => value,
-                    _ => return (0, 0),
-                };
-                let last = value.last();
-                if last.is_none() {
-                    return (0, 0);
-                }
-                let last = last.unwrap();
-                let id = last.id;
-                (id.0, id.1)
-            }
+            //Deleted entries still count, new ones have to be greater
+            IdType::Last => stream.map_or(StreamId::MIN, |stream| stream.last_id),
+            _ => stream
+                .and_then(|stream| stream.entries.last())
+                .and_then(|data| data.id.previous())
+                .unwrap_or(StreamId::MIN),
         }
     }
 }
@@ -118,7 +121,11 @@ impl Handler for XReadHandler {
 
         //Map Streams
         while let Some(stream) = iter.next() {
-            if stream.contains("-") || stream.parse::<u64>().is_ok() || stream == "$" {
+            if stream.contains("-")
+                || stream.parse::<u64>().is_ok()
+                || stream == "$"
+                || stream == "+"
+            {
                 let id = str_to_id(stream);
                 if id.is_err() {
                     if !should_reply {
@@ -174,33 +181,30 @@ impl Handler for XReadHandler {
             return CommandReturn::Error;
         }
 
-        let key_to_ids: Vec<(&&String, IdType)> = streams.iter().zip(ids.into_iter()).collect();
-        let mut ids = vec![];
-        for (key, id) in key_to_ids {
-            let id = id.to_id(key, &redis).await;
-            ids.push(id);
-        }
-
-        let redis_w = redis.read().await;
-        let mut response = redis_w.get_x_read(&streams, &ids, count);
-        drop(redis_w);
-        if response == RedisType::NullArray {
-            if let Some(blocks) = blocks {
-                //start blocking
-                let blocks = if blocks == 0 { u64::MAX } else { blocks };
-                let mut now = std::time::SystemTime::now();
-                let time_out = now
-                    .checked_add(std::time::Duration::from_millis(blocks))
-                    .unwrap();
-                while now < time_out {
-                    let redis_w = redis.read().await;
-                    response = redis_w.get_x_read(&streams, &ids, count);
-                    if response != RedisType::NullArray {
-                        break;
-                    }
-                    //Careful with deadlocks
-                    drop(redis_w);
-                    now = std::time::SystemTime::now();
+        //$ and + are resolved once, before blocking
+        let redis_r = redis.read().await;
+        let ids: Vec<StreamId> = streams
+            .iter()
+            .zip(ids)
+            .map(|(key, id)| id.to_id(key, &redis_r))
+            .collect();
+        let mut response = redis_r.get_x_read(&streams, &ids, count);
+        //Blocked before the lock goes so an XADD in between isn't missed
+        let blocked = match blocks {
+            Some(blocks) if response == RedisType::NullArray => {
+                let keys: Vec<String> = streams.iter().map(|key| key.to_string()).collect();
+                Some((blocks, redis_r.blocking.block(&keys)))
+            }
+            _ => None,
+        };
+        drop(redis_r);
+        if let Some((blocks, blocked)) = blocked {
+            let deadline = (blocks > 0).then(|| Instant::now() + Duration::from_millis(blocks));
+            while blocked.wait(deadline, params.closed).await == Wake::Ready {
+                let redis_r = redis.read().await;
+                response = redis_r.get_x_read(&streams, &ids, count);
+                if response != RedisType::NullArray {
+                    break;
                 }
             }
         }
@@ -211,18 +215,108 @@ impl Handler for XReadHandler {
     }
 }
 
+//A missing sequence is 0, entries are read after the ID
 fn str_to_id(s: &str) -> Result<IdType, ()> {
-    if s.contains("-") {
-        let mut iter = s.split("-");
-        let first = iter.next().ok_or(())?.parse().map_err(|_| ())?;
-        let second = iter.next().ok_or(())?.parse().map_err(|_| ())?;
-        let id = IdType::Id(first, second);
-        Ok(id)
-    } else if s == "$" {
-        Ok(IdType::Last)
-    } else {
-        let first = s.parse().map_err(|_| ())?;
-        let id = IdType::Id(first, 0);
-        Ok(id)
+    match s {
+        "$" => return Ok(IdType::Last),
+        "+" => return Ok(IdType::LastEntry),
+        _ => {}
+    }
+    parse_id(s, 0).map(IdType::Id).ok_or(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::sync::{Notify, RwLock};
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::args,
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{Stream, StreamData, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::{
+        super::{x_add::XAddHandler, CommandReturn, Handler, HandlerParams},
+        XReadHandler,
+    };
+
+    async fn x_read(
+        redis: &RwLock<Redis<Mock>>,
+        args: Vec<String>,
+        closed: Option<&Notify>,
+    ) -> Vec<u8> {
+        let mut reply = vec![];
+        let params = HandlerParams {
+            args,
+            redis,
+            writer: &mut reply,
+            should_reply: true,
+            closed,
+        };
+        assert_eq!(XReadHandler::handle(params).await, CommandReturn::Ok);
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_x_read_block() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let entries = vec![StreamData {
+            id: StreamId::new(1, 1),
+            fields: vec![("a".to_string(), "1".to_string())],
+        }];
+        redis.set(
+            "s".to_string(),
+            ValueType::Stream(Stream::from(entries)),
+            None,
+        );
+        let redis = RwLock::new(redis);
+
+        //Only what is added after blocking, XADD wakes it up
+        let add = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let params = HandlerParams {
+                args: args(&["s", "2-0", "b", "2"]),
+                redis: &redis,
+                writer: Vec::new(),
+                should_reply: false,
+                closed: None,
+            };
+            XAddHandler::handle(params).await
+        };
+        let read = x_read(&redis, args(&["BLOCK", "0", "STREAMS", "s", "$"]), None);
+        let (reply, _) = tokio::join!(read, add);
+        let key = "s".to_string();
+        let expected = redis
+            .read()
+            .await
+            .get_x_read(&[&key], &[StreamId::new(1, 1)], None);
+        assert_eq!(reply, expected.encode());
+
+        //The last entry, without blocking
+        let reply = x_read(&redis, args(&["STREAMS", "s", "+"]), None).await;
+        assert_eq!(reply, expected.encode());
+
+        //Gone clients stop waiting
+        let closed = Notify::new();
+        closed.notify_one();
+        let reply = x_read(
+            &redis,
+            args(&["BLOCK", "0", "STREAMS", "s", "$"]),
+            Some(&closed),
+        )
+        .await;
+        assert_eq!(reply, RedisType::NullArray.encode());
+        let reply = x_read(&redis, args(&["BLOCK", "10", "STREAMS", "s", "$"]), None).await;
+        assert_eq!(reply, RedisType::NullArray.encode());
     }
 }
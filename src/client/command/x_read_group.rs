@@ -0,0 +1,482 @@
+use std::ops::Bound;
+
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    time::{Duration, Instant},
+};
+
+use crate::redis::{
+    blocking::Wake,
+    notify::Event,
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_id, StreamId},
+    Redis,
+};
+
+use super::{lock_for_write, x_claim::claim_command, CommandReturn, Handler, HandlerParams};
+
+pub struct XReadGroupHandler;
+
+struct Request {
+    group: String,
+    consumer: String,
+    count: Option<usize>,
+    //Milliseconds, zero waits forever
+    block: Option<u64>,
+    no_ack: bool,
+    keys: Vec<String>,
+    //None for ">", the entries never delivered to the group
+    ids: Vec<Option<StreamId>>,
+}
+
+/*
+ XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK]
+ STREAMS key [key ...] id [id ...]
+*/
+impl Handler for XReadGroupHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let request = match parse(&params.args) {
+            Ok(request) => request,
+            Err(e) => {
+                if params.should_reply {
+                    let _ = writer.write_all(&e.encode()).await;
+                }
+                return CommandReturn::Error;
+            }
+        };
+        let deadline = request
+            .block
+            .filter(|block| *block > 0)
+            .map(|block| Instant::now() + Duration::from_millis(block));
+        let mut woken = false;
+        let (response, result) = loop {
+            let mut redis =
+                match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+                    Some(redis) => redis,
+                    None => return CommandReturn::Error,
+                };
+            if let Some(e) = woken.then(|| gone(&redis, &request)).flatten() {
+                break (e, CommandReturn::Error);
+            }
+            let (response, propagate) = match read_group(&mut redis, &request) {
+                Ok(read) => read,
+                Err(e) => break (e, CommandReturn::Error),
+            };
+            //Replicas get the outcome, the pending entries as they are here
+            for command in propagate {
+                redis.replication.propagate_message(command.encode()).await;
+            }
+            if response != RedisType::NullArray || request.block.is_none() {
+                break (response, CommandReturn::Ok);
+            }
+            //Blocked before the lock goes so an XADD in between isn't missed
+            let blocked = redis.blocking.block(&request.keys);
+            drop(redis);
+            match blocked.wait(deadline, params.closed).await {
+                Wake::Ready => woken = true,
+                Wake::Timeout | Wake::Closed => break (response, CommandReturn::Ok),
+            }
+        };
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        result
+    }
+}
+
+//The error for a client woken up because its stream or group is gone
+fn gone<S: RWStream>(redis: &Redis<S>, request: &Request) -> Option<RedisType> {
+    for key in &request.keys {
+        let error = match redis.get_stream(key) {
+            Ok(Some(stream)) if stream.groups.contains_key(&request.group) => continue,
+            Ok(Some(_)) => "NOGROUP the consumer group this client was blocked on no longer exists",
+            _ => "UNBLOCKED the stream key no longer exists",
+        };
+        return Some(RedisType::SimpleError(error.to_string()));
+    }
+    None
+}
+
+fn parse(args: &[String]) -> Result<Request, RedisType> {
+    let syntax_error = || RedisType::SimpleError("ERR syntax error".to_string());
+    let mut group = None;
+    let mut count = None;
+    let mut block = None;
+    let mut no_ack = false;
+    let mut iter = args.iter();
+    let streams = loop {
+        let arg = iter.next().ok_or_else(syntax_error)?;
+        match arg.to_uppercase().as_str() {
+            "GROUP" => {
+                let name = iter.next().ok_or_else(syntax_error)?;
+                let consumer = iter.next().ok_or_else(syntax_error)?;
+                group = Some((name.clone(), consumer.clone()));
+            }
+            "COUNT" => {
+                let n = iter.next().ok_or_else(syntax_error)?;
+                let n = n.parse::<i64>().map_err(|_| {
+                    RedisType::SimpleError(
+                        "ERR value is not an integer or out of range".to_string(),
+                    )
+                })?;
+                //Zero or less is no limit
+                count = (n > 0).then_some(n as usize);
+            }
+            "BLOCK" => {
+                let ms = iter.next().ok_or_else(syntax_error)?;
+                let ms = ms.parse::<i64>().map_err(|_| {
+                    RedisType::SimpleError(
+                        "ERR timeout is not an integer or out of range".to_string(),
+                    )
+                })?;
+                if ms < 0 {
+                    return Err(RedisType::SimpleError(
+                        "ERR timeout is negative".to_string(),
+                    ));
+                }
+                block = Some(ms as u64);
+            }
+            "NOACK" => no_ack = true,
+            "STREAMS" => break iter.as_slice(),
+            _ => return Err(syntax_error()),
+        }
+    };
+    let (group, consumer) = group.ok_or_else(|| {
+        RedisType::SimpleError("ERR Missing GROUP option for XREADGROUP".to_string())
+    })?;
+    if streams.is_empty() || streams.len() % 2 != 0 {
+        return Err(RedisType::SimpleError(
+            "ERR Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified.".to_string(),
+        ));
+    }
+    let (keys, ids) = streams.split_at(streams.len() / 2);
+    let ids = ids
+        .iter()
+        .map(|id| match id.as_str() {
+            ">" => Ok(None),
+            "$" => Err(RedisType::SimpleError(
+                "ERR The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set.".to_string(),
+            )),
+            id => parse_id(id, 0).map(Some).ok_or_else(|| {
+                RedisType::SimpleError(
+                    "ERR Invalid stream ID specified as stream command argument".to_string(),
+                )
+            }),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Request {
+        group,
+        consumer,
+        count,
+        block,
+        no_ack,
+        keys: keys.to_vec(),
+        ids,
+    })
+}
+
+//The reply, a null array if nothing was read, and the commands to propagate
+fn read_group<S: RWStream>(
+    redis: &mut Redis<S>,
+    request: &Request,
+) -> Result<(RedisType, Vec<RedisType>), RedisType> {
+    //Every stream has to have the group before anything is read
+    for key in &request.keys {
+        let stream = redis.get_stream(key)?;
+        if !stream.is_some_and(|stream| stream.groups.contains_key(&request.group)) {
+            return Err(RedisType::SimpleError(format!(
+                "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                key, request.group
+            )));
+        }
+    }
+
+    let now = crate::util::now_ms();
+    let (group_name, consumer) = (&request.group, &request.consumer);
+    let mut replies = vec![];
+    let mut propagate = vec![];
+    let mut created = vec![];
+    let mut changed = vec![];
+    for (key, id) in request.keys.iter().zip(&request.ids) {
+        let propagated = propagate.len();
+        let stream = redis.get_stream_mut(key)?.unwrap();
+        let last_id = stream.groups[group_name].last_id;
+        let delivered = match id {
+            None => stream.after(last_id, request.count),
+            Some(_) => vec![],
+        };
+        let history = match id {
+            Some(id) => {
+                stream.groups[group_name]
+                    .consumers
+                    .get(consumer)
+                    .map_or(vec![], |consumer| {
+                        consumer
+                            .pending
+                            .range((Bound::Excluded(*id), Bound::Unbounded))
+                            .take(request.count.unwrap_or(usize::MAX))
+                            .map(|id| (*id, stream.get(*id)))
+                            .collect()
+                    })
+            }
+            None => vec![],
+        };
+        let entries_read = delivered
+            .iter()
+            .fold(stream.groups[group_name].entries_read, |read, data| {
+                stream.read_entry(read, data.id)
+            });
+
+        let group = stream.groups.get_mut(group_name).unwrap();
+        if !delivered.is_empty() {
+            group.entries_read = entries_read;
+        }
+        if group.create_consumer(consumer, now) {
+            let command = [
+                "XGROUP".to_string(),
+                "CREATECONSUMER".to_string(),
+                key.clone(),
+                group_name.clone(),
+                consumer.clone(),
+            ];
+            propagate.push(RedisType::Array(
+                command.into_iter().map(RedisType::BulkString).collect(),
+            ));
+            created.push(key.clone());
+        }
+        //Delivered again, unless deleted from the stream
+        for (id, _) in history.iter().filter(|(_, data)| data.is_some()) {
+            let entry = group.pending.get_mut(id).unwrap();
+            entry.delivery_time = now;
+            entry.delivery_count += 1;
+            propagate.push(claim_command(key, group_name, *id, entry, group.last_id));
+        }
+        for data in &delivered {
+            group.last_id = data.id;
+            if !request.no_ack {
+                group.assign(data.id, consumer, now, 1);
+                let entry = &group.pending[&data.id];
+                propagate.push(claim_command(
+                    key,
+                    group_name,
+                    data.id,
+                    entry,
+                    group.last_id,
+                ));
+            }
+        }
+        //The last ID for NOACK reads, and the entries read for the lag either way
+        if !delivered.is_empty() {
+            let entries_read = group.entries_read.map_or(-1, |read| read as i64);
+            let command = [
+                "XGROUP".to_string(),
+                "SETID".to_string(),
+                key.clone(),
+                group_name.clone(),
+                group.last_id.to_string(),
+                "ENTRIESREAD".to_string(),
+                entries_read.to_string(),
+            ];
+            propagate.push(RedisType::Array(
+                command.into_iter().map(RedisType::BulkString).collect(),
+            ));
+        }
+        group.seen(consumer, now, !delivered.is_empty() || !history.is_empty());
+        if propagate.len() > propagated {
+            changed.push(key.clone());
+        }
+
+        //The history is always replied to, even if empty
+        let entries = match id {
+            None if delivered.is_empty() => continue,
+            None => delivered.iter().map(RedisType::from).collect(),
+            Some(_) => history
+                .into_iter()
+                .map(|(id, data)| match data {
+                    Some(data) => RedisType::from(&data),
+                    //Deleted from the stream but still pending
+                    None => RedisType::Array(vec![
+                        RedisType::BulkString(id.to_string()),
+                        RedisType::NullArray,
+                    ]),
+                })
+                .collect(),
+        };
+        replies.push(RedisType::Array(vec![
+            RedisType::BulkString(key.clone()),
+            RedisType::Array(entries),
+        ]));
+    }
+    for key in changed {
+        redis.touch(&key);
+    }
+    for key in created {
+        redis.notify(Event::Stream, "xgroup-createconsumer", &key);
+    }
+    let response = match replies.is_empty() {
+        true => RedisType::NullArray,
+        false => RedisType::Array(replies),
+    };
+    Ok((response, propagate))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::{
+            del::DelHandler,
+            test_util::{args, bulk, stream_with},
+            x_group::XGroupHandler,
+            Handler, HandlerParams,
+        },
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{ConsumerGroup, Stream, StreamId},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::{claim_command, parse, read_group, XReadGroupHandler};
+
+    #[test]
+    fn test_read_group() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut stream = stream_with(1..=3, |i| vec![("f".to_string(), i.to_string())]);
+        stream
+            .groups
+            .insert("g".to_string(), ConsumerGroup::new(StreamId::new(1, 0)));
+        redis.set("s".to_string(), ValueType::Stream(stream), None);
+
+        let request = parse(&args(&[
+            "GROUP", "g", "alice", "COUNT", "1", "STREAMS", "s", ">",
+        ]));
+        let (response, propagate) = read_group(&mut redis, &request.unwrap()).unwrap();
+        let entry = RedisType::Array(vec![
+            bulk("2-0"),
+            RedisType::Array(vec![bulk("f"), bulk("2")]),
+        ]);
+        let expected = RedisType::Array(vec![RedisType::Array(vec![
+            bulk("s"),
+            RedisType::Array(vec![entry.clone()]),
+        ])]);
+        assert_eq!(response, expected);
+        //The new consumer, the delivered entry and the group position
+        assert_eq!(propagate.len(), 3);
+
+        let request = parse(&args(&["GROUP", "g", "bob", "NOACK", "STREAMS", "s", ">"]));
+        read_group(&mut redis, &request.unwrap()).unwrap();
+        let request = parse(&args(&["GROUP", "g", "bob", "STREAMS", "s", ">"]));
+        let (response, _) = read_group(&mut redis, &request.unwrap()).unwrap();
+        assert_eq!(response, RedisType::NullArray);
+
+        //alice's history, then bob's which NOACK left empty
+        let request = parse(&args(&["GROUP", "g", "alice", "STREAMS", "s", "0"]));
+        let (response, _) = read_group(&mut redis, &request.unwrap()).unwrap();
+        assert_eq!(response, expected);
+        let request = parse(&args(&["GROUP", "g", "bob", "STREAMS", "s", "0"]));
+        let (response, _) = read_group(&mut redis, &request.unwrap()).unwrap();
+        let expected = RedisType::Array(vec![RedisType::Array(vec![
+            bulk("s"),
+            RedisType::Array(vec![]),
+        ])]);
+        assert_eq!(response, expected);
+
+        let group = &redis.get_stream("s").unwrap().unwrap().groups["g"];
+        assert_eq!(group.last_id, StreamId::new(3, 0));
+        assert_eq!(group.pending.len(), 1);
+
+        let request = parse(&args(&["GROUP", "h", "alice", "STREAMS", "s", ">"]));
+        assert!(read_group(&mut redis, &request.unwrap()).is_err());
+        assert!(parse(&args(&["GROUP", "g", "alice", "STREAMS", "s", "$"])).is_err());
+        assert!(parse(&args(&["GROUP", "g", "alice", "STREAMS", "s"])).is_err());
+    }
+
+    #[test]
+    fn test_read_group_history() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut stream = stream_with(1..=3, |i| vec![("f".to_string(), i.to_string())]);
+        stream
+            .groups
+            .insert("g".to_string(), ConsumerGroup::new(StreamId::new(0, 0)));
+        redis.set("s".to_string(), ValueType::Stream(stream), None);
+        let request = parse(&args(&["GROUP", "g", "alice", "STREAMS", "s", ">"]));
+        read_group(&mut redis, &request.unwrap()).unwrap();
+        let stream = redis.get_stream_mut("s").unwrap().unwrap();
+        stream.delete(StreamId::new(3, 0));
+
+        //Every entry still in the stream is delivered again
+        let request = parse(&args(&["GROUP", "g", "alice", "STREAMS", "s", "0"]));
+        let (_, propagate) = read_group(&mut redis, &request.unwrap()).unwrap();
+        let pending = &redis.get_stream("s").unwrap().unwrap().groups["g"].pending;
+        for (id, count) in [(1, 2), (2, 2), (3, 1)] {
+            assert_eq!(pending[&StreamId::new(id, 0)].delivery_count, count);
+        }
+        let claims = propagate
+            .iter()
+            .filter(|command| command.to_string().contains("XCLAIM"))
+            .count();
+        assert_eq!(claims, 2);
+        let entry = &pending[&StreamId::new(1, 0)];
+        let claim = claim_command("s", "g", StreamId::new(1, 0), entry, StreamId::new(3, 0));
+        assert!(propagate.contains(&claim));
+    }
+
+    async fn handle<H: Handler>(redis: &RwLock<Redis<Mock>>, args: Vec<String>) -> Vec<u8> {
+        let mut reply = vec![];
+        let params = HandlerParams {
+            args,
+            redis,
+            writer: &mut reply,
+            should_reply: true,
+            closed: None,
+        };
+        H::handle(params).await;
+        reply
+    }
+
+    #[tokio::test]
+    async fn test_read_group_block_gone() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let mut stream = Stream::default();
+        stream
+            .groups
+            .insert("g".to_string(), ConsumerGroup::new(StreamId::MIN));
+        redis.set("s".to_string(), ValueType::Stream(stream.clone()), None);
+        let redis = RwLock::new(redis);
+        let block = || args(&["GROUP", "g", "c", "BLOCK", "0", "STREAMS", "s", ">"]);
+
+        //Blocked clients find out the group or the key is gone
+        let destroy = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            handle::<XGroupHandler>(&redis, args(&["DESTROY", "s", "g"])).await
+        };
+        let (reply, _) = tokio::join!(handle::<XReadGroupHandler>(&redis, block()), destroy);
+        let error = "NOGROUP the consumer group this client was blocked on no longer exists";
+        assert_eq!(reply, RedisType::SimpleError(error.to_string()).encode());
+
+        redis
+            .write()
+            .await
+            .set("s".to_string(), ValueType::Stream(stream), None);
+        let delete = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            handle::<DelHandler>(&redis, args(&["s"])).await
+        };
+        let (reply, _) = tokio::join!(handle::<XReadGroupHandler>(&redis, block()), delete);
+        let error = "UNBLOCKED the stream key no longer exists";
+        assert_eq!(reply, RedisType::SimpleError(error.to_string()).encode());
+    }
+}
@@ -0,0 +1,91 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{replication::RWStream, types::RedisType, value::stream::parse_range_id, Redis};
+
+use super::{x_range::parse_count, CommandReturn, Handler, HandlerParams};
+
+pub struct XRevRangeHandler;
+
+//XREVRANGE key end start [COUNT count]
+impl Handler for XRevRangeHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        if !params.should_reply {
+            return CommandReturn::Ok;
+        }
+        let mut writer = params.writer;
+        let redis = params.redis.read().await;
+        let (response, result) = match x_rev_range(&redis, &params.args) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
+        };
+        let _ = writer.write_all(&response.encode()).await;
+        result
+    }
+}
+
+fn x_rev_range<S: RWStream>(redis: &Redis<S>, args: &[String]) -> Result<RedisType, RedisType> {
+    let invalid_id = || {
+        RedisType::SimpleError(
+            "ERR Invalid stream ID specified as stream command argument".to_string(),
+        )
+    };
+    let key = &args[0];
+    let end = parse_range_id(&args[1], false).ok_or_else(invalid_id)?;
+    let start = parse_range_id(&args[2], true).ok_or_else(invalid_id)?;
+    let count = match parse_count(&args[3..])? {
+        Some(0) => return Ok(RedisType::NullArray),
+        count => count.unwrap_or(usize::MAX),
+    };
+    let entries = match redis.get_stream(key)? {
+        Some(stream) => stream
+            .rev_range(start, end)
+            .take(count)
+            .map(|data| (&data).into())
+            .collect(),
+        None => vec![],
+    };
+    Ok(RedisType::Array(entries))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, stream},
+        redis::{config::Config, types::RedisType, value::ValueType, Redis},
+    };
+
+    use super::x_rev_range;
+
+    fn ids(response: RedisType) -> Vec<String> {
+        match response {
+            RedisType::Array(entries) => entries
+                .into_iter()
+                .map(|entry| match entry {
+                    RedisType::Array(entry) => entry[0].to_string(),
+                    _ => panic!("not an entry"),
+                })
+                .collect(),
+            _ => panic!("not an array"),
+        }
+    }
+
+    #[test]
+    fn test_x_rev_range() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        redis.set("s".to_string(), ValueType::Stream(stream(1..=5)), None);
+
+        let response = x_rev_range(&redis, &args(&["s", "+", "-", "COUNT", "2"])).unwrap();
+        assert_eq!(ids(response), vec!["5-0", "4-0"]);
+        let response = x_rev_range(&redis, &args(&["s", "(4-0", "2"])).unwrap();
+        assert_eq!(ids(response), vec!["3-0", "2-0"]);
+        let response = x_rev_range(&redis, &args(&["s", "2", "4"])).unwrap();
+        assert_eq!(ids(response), Vec::<String>::new());
+        let response = x_rev_range(&redis, &args(&["missing", "+", "-"])).unwrap();
+        assert_eq!(response, RedisType::Array(vec![]));
+        assert!(x_rev_range(&redis, &args(&["s", "+", "x"])).is_err());
+    }
+}
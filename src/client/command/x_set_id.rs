@@ -0,0 +1,162 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    notify::Event,
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{parse_id, StreamId},
+    Redis,
+};
+
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
+
+pub struct XSetIdHandler;
+
+//XSETID key last-id [ENTRIESADDED entries-added] [MAXDELETEDID max-deleted-id]
+impl Handler for XSetIdHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let mut redis = match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        let (response, result) = match x_set_id(&mut redis, &params.args) {
+            Ok(response) => (response, CommandReturn::Ok),
+            Err(e) => (e, CommandReturn::Error),
+        };
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        if result == CommandReturn::Ok {
+            let mut command: Vec<RedisType> = vec![Command::XSetId.into()];
+            command.extend(params.args.into_iter().map(RedisType::BulkString));
+            let command = RedisType::Array(command);
+            redis.replication.propagate_message(command.encode()).await;
+        }
+        result
+    }
+}
+
+fn x_set_id<S: RWStream>(redis: &mut Redis<S>, args: &[String]) -> Result<RedisType, RedisType> {
+    let invalid_id = || {
+        RedisType::SimpleError(
+            "ERR Invalid stream ID specified as stream command argument".to_string(),
+        )
+    };
+    let key = &args[0];
+    let id = parse_id(&args[1], 0).ok_or_else(invalid_id)?;
+    let mut entries_added = None;
+    let mut max_deleted_id: Option<StreamId> = None;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .ok_or_else(|| RedisType::SimpleError("ERR syntax error".to_string()))?;
+        match option.to_uppercase().as_str() {
+            "ENTRIESADDED" => {
+                let added = value.parse::<i64>().map_err(|_| {
+                    RedisType::SimpleError(
+                        "ERR value is not an integer or out of range".to_string(),
+                    )
+                })?;
+                if added < 0 {
+                    return Err(RedisType::SimpleError(
+                        "ERR entries_added must be positive".to_string(),
+                    ));
+                }
+                entries_added = Some(added as u64);
+            }
+            "MAXDELETEDID" => {
+                let max_deleted = parse_id(value, 0).ok_or_else(invalid_id)?;
+                if id < max_deleted {
+                    return Err(RedisType::SimpleError(
+                        "ERR The ID specified in XSETID is smaller than the provided max_deleted_entry_id".to_string(),
+                    ));
+                }
+                max_deleted_id = Some(max_deleted);
+            }
+            _ => return Err(RedisType::SimpleError("ERR syntax error".to_string())),
+        }
+    }
+
+    let stream = redis
+        .get_stream_mut(key)?
+        .ok_or_else(|| RedisType::SimpleError("ERR no such key".to_string()))?;
+    if entries_added.is_some_and(|added| added < stream.entries.len() as u64) {
+        return Err(RedisType::SimpleError(
+            "ERR The entries_added specified in XSETID is smaller than the target stream length"
+                .to_string(),
+        ));
+    }
+    //Only the entries still there matter, a deleted one may have had a greater ID
+    if stream.entries.last().is_some_and(|data| id < data.id) {
+        return Err(RedisType::SimpleError(
+            "ERR The ID specified in XSETID is smaller than the target stream top item".to_string(),
+        ));
+    }
+    stream.last_id = id;
+    if let Some(added) = entries_added {
+        stream.entries_added = added;
+    }
+    if let Some(max_deleted) = max_deleted_id {
+        stream.max_deleted_id = max_deleted;
+    }
+    redis.touch(key);
+    redis.notify(Event::Stream, "xsetid", key);
+    Ok(RedisType::SimpleString("OK".to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, stream},
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{stream::StreamId, ValueType},
+            Redis,
+        },
+    };
+
+    use super::x_set_id;
+
+    #[test]
+    fn test_x_set_id() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let error = |e: &str| Err(RedisType::SimpleError(e.to_string()));
+        assert_eq!(
+            x_set_id(&mut redis, &args(&["s", "5-0"])),
+            error("ERR no such key")
+        );
+        redis.set("s".to_string(), ValueType::Stream(stream(1..=3)), None);
+
+        assert_eq!(
+            x_set_id(&mut redis, &args(&["s", "2-0"])),
+            error("ERR The ID specified in XSETID is smaller than the target stream top item")
+        );
+        assert_eq!(
+            x_set_id(&mut redis, &args(&["s", "5-0", "ENTRIESADDED", "2"])),
+            error("ERR The entries_added specified in XSETID is smaller than the target stream length")
+        );
+        assert_eq!(
+            x_set_id(&mut redis, &args(&["s", "5-0", "MAXDELETEDID", "6-0"])),
+            error(
+                "ERR The ID specified in XSETID is smaller than the provided max_deleted_entry_id"
+            )
+        );
+        let setid = args(&["s", "5", "ENTRIESADDED", "7", "MAXDELETEDID", "4-0"]);
+        assert_eq!(
+            x_set_id(&mut redis, &setid),
+            Ok(RedisType::SimpleString("OK".to_string()))
+        );
+
+        let stream = redis.get_stream("s").unwrap().unwrap();
+        assert_eq!(stream.last_id, StreamId::new(5, 0));
+        assert_eq!(stream.entries_added, 7);
+        assert_eq!(stream.max_deleted_id, StreamId::new(4, 0));
+    }
+}
@@ -0,0 +1,206 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::redis::{
+    notify::Event,
+    replication::RWStream,
+    types::RedisType,
+    value::stream::{entries::NODE_MAX_ENTRIES, parse_id, Stream, Trim, TrimStrategy},
+    Redis,
+};
+
+use super::{lock_for_write, Command, CommandReturn, Handler, HandlerParams};
+
+pub struct XTrimHandler;
+
+//XTRIM key MAXLEN|MINID [=|~] threshold [LIMIT count]
+impl Handler for XTrimHandler {
+    async fn handle<'a, W: AsyncWrite + Unpin, S: RWStream>(
+        params: HandlerParams<'a, W, S>,
+    ) -> CommandReturn {
+        let mut writer = params.writer;
+        let mut redis = match lock_for_write(params.redis, &mut writer, params.should_reply).await {
+            Some(redis) => redis,
+            None => return CommandReturn::Error,
+        };
+        let (response, propagate) = match x_trim(&mut redis, &params.args) {
+            Ok(trimmed) => trimmed,
+            Err(e) => {
+                if params.should_reply {
+                    let _ = writer.write_all(&e.encode()).await;
+                }
+                return CommandReturn::Error;
+            }
+        };
+        if params.should_reply {
+            let _ = writer.write_all(&response.encode()).await;
+        }
+        if let Some(args) = propagate {
+            let mut command: Vec<RedisType> = vec![Command::XTrim.into()];
+            command.extend(args.into_iter().map(RedisType::BulkString));
+            let command = RedisType::Array(command);
+            redis.replication.propagate_message(command.encode()).await;
+        }
+        CommandReturn::Ok
+    }
+}
+
+//The reply and, if something was removed, the arguments to propagate
+fn x_trim<S: RWStream>(
+    redis: &mut Redis<S>,
+    args: &[String],
+) -> Result<(RedisType, Option<Vec<String>>), RedisType> {
+    let key = &args[0];
+    let (trim, taken) = parse_trim(&args[1..])?;
+    if taken != args.len() - 1 {
+        return Err(RedisType::SimpleError("ERR syntax error".to_string()));
+    }
+    let stream = match redis.get_stream_mut(key)? {
+        Some(stream) => stream,
+        None => return Ok((RedisType::Integer(0), None)),
+    };
+    let trimmed = stream.trim(&trim);
+    if trimmed == 0 {
+        return Ok((RedisType::Integer(0), None));
+    }
+    let mut propagate = vec![key.clone()];
+    propagate.extend(exact_trim(stream, &trim));
+    redis.touch(key);
+    redis.notify(Event::Stream, "xtrim", key);
+    Ok((RedisType::Integer(trimmed as i64), Some(propagate)))
+}
+
+//MAXLEN|MINID [=|~] threshold [LIMIT count], returns the trim and how many arguments it took
+pub(super) fn parse_trim(args: &[String]) -> Result<(Trim, usize), RedisType> {
+    let syntax_error = || RedisType::SimpleError("ERR syntax error".to_string());
+    let strategy = args.first().ok_or_else(syntax_error)?.to_uppercase();
+    //The operator is optional
+    let operator = match args.get(1).map(|arg| arg.as_str()) {
+        Some("~") => Some(true),
+        Some("=") => Some(false),
+        _ => None,
+    };
+    let approx = operator.unwrap_or(false);
+    let mut taken = if operator.is_some() { 2 } else { 1 };
+    let threshold = args.get(taken).ok_or_else(syntax_error)?;
+    taken += 1;
+    let strategy = match strategy.as_str() {
+        "MAXLEN" => {
+            let len = threshold.parse::<i64>().map_err(|_| {
+                RedisType::SimpleError("ERR value is not an integer or out of range".to_string())
+            })?;
+            if len < 0 {
+                return Err(RedisType::SimpleError(
+                    "ERR The MAXLEN argument must be >= 0.".to_string(),
+                ));
+            }
+            TrimStrategy::MaxLen(len as u64)
+        }
+        "MINID" => TrimStrategy::MinId(parse_id(threshold, 0).ok_or_else(|| {
+            RedisType::SimpleError(
+                "ERR Invalid stream ID specified as stream command argument".to_string(),
+            )
+        })?),
+        _ => return Err(syntax_error()),
+    };
+    //By default an approximate trim doesn't go through more than a hundred nodes
+    let mut limit = if approx { 100 * NODE_MAX_ENTRIES } else { 0 };
+    if args
+        .get(taken)
+        .is_some_and(|arg| arg.eq_ignore_ascii_case("LIMIT"))
+    {
+        if !approx {
+            return Err(RedisType::SimpleError(
+                "ERR syntax error, LIMIT cannot be used without the special ~ option".to_string(),
+            ));
+        }
+        let count = args.get(taken + 1).ok_or_else(syntax_error)?;
+        let count = count.parse::<i64>().map_err(|_| {
+            RedisType::SimpleError("ERR value is not an integer or out of range".to_string())
+        })?;
+        if count < 0 {
+            return Err(RedisType::SimpleError(
+                "ERR The LIMIT argument must be >= 0.".to_string(),
+            ));
+        }
+        limit = count as usize;
+        taken += 2;
+    }
+    let trim = Trim {
+        strategy,
+        approx,
+        limit,
+    };
+    Ok((trim, taken))
+}
+
+//The trim replicas apply, approximate ones depend on the nodes so they are made exact
+pub(super) fn exact_trim(stream: &Stream, trim: &Trim) -> Vec<String> {
+    let (strategy, threshold) = match trim.strategy {
+        TrimStrategy::MaxLen(_) if trim.approx => ("MAXLEN", stream.entries.len().to_string()),
+        TrimStrategy::MaxLen(len) => ("MAXLEN", len.to_string()),
+        TrimStrategy::MinId(_) if trim.approx && !stream.entries.is_empty() => {
+            ("MINID", stream.first_id().to_string())
+        }
+        TrimStrategy::MinId(id) => ("MINID", id.to_string()),
+    };
+    vec![strategy.to_string(), "=".to_string(), threshold]
+}
+
+#[cfg(test)]
+mod test {
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::test_util::{args, stream},
+        redis::{
+            config::Config,
+            types::RedisType,
+            value::{
+                stream::{StreamId, Trim, TrimStrategy},
+                ValueType,
+            },
+            Redis,
+        },
+    };
+
+    use super::{parse_trim, x_trim};
+
+    #[test]
+    fn test_parse_trim() {
+        let trim = Trim {
+            strategy: TrimStrategy::MaxLen(5),
+            approx: false,
+            limit: 0,
+        };
+        assert_eq!(parse_trim(&args(&["MAXLEN", "5", "x"])), Ok((trim, 2)));
+        let trim = Trim {
+            strategy: TrimStrategy::MinId(StreamId::new(7, 0)),
+            approx: true,
+            limit: 10,
+        };
+        let parsed = parse_trim(&args(&["minid", "~", "7", "LIMIT", "10"]));
+        assert_eq!(parsed, Ok((trim, 5)));
+        assert!(parse_trim(&args(&["MAXLEN", "=", "5", "LIMIT", "10"])).is_err());
+        assert!(parse_trim(&args(&["MAXLEN", "-1"])).is_err());
+        assert!(parse_trim(&args(&["MAXLEN"])).is_err());
+    }
+
+    #[test]
+    fn test_x_trim() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        redis.set("s".to_string(), ValueType::Stream(stream(1..=250)), None);
+
+        //Only whole nodes, replicas get the resulting length
+        let (response, propagate) = x_trim(&mut redis, &args(&["s", "MAXLEN", "~", "20"])).unwrap();
+        assert_eq!(response, RedisType::Integer(200));
+        assert_eq!(propagate, Some(args(&["s", "MAXLEN", "=", "50"])));
+        let (response, propagate) = x_trim(&mut redis, &args(&["s", "MINID", "240"])).unwrap();
+        assert_eq!(response, RedisType::Integer(39));
+        assert_eq!(propagate, Some(args(&["s", "MINID", "=", "240-0"])));
+        let (response, propagate) = x_trim(&mut redis, &args(&["s", "MINID", "240"])).unwrap();
+        assert_eq!(response, RedisType::Integer(0));
+        assert_eq!(propagate, None);
+        let (response, _) = x_trim(&mut redis, &args(&["missing", "MAXLEN", "0"])).unwrap();
+        assert_eq!(response, RedisType::Integer(0));
+    }
+}
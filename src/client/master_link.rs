@@ -0,0 +1,383 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    sync::RwLock,
+    time::timeout,
+};
+
+use crate::redis::{
+    rdb::{self, Checksum},
+    replication::{
+        failover::{abort_in_progress, FailoverState},
+        full_sync::EOF_MARK_SIZE,
+        link::LinkState,
+    },
+    types::RedisType,
+    Redis,
+};
+
+use super::Client;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+//Same as the "repl-timeout" default on redis
+const REPL_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+enum PsyncReply {
+    FullResync { replid: String, offset: u64 },
+    Continue { replid: Option<String> },
+}
+
+/*
+ Replica side of the replication, keeps the link with the master alive:
+ connect -> handshake -> sync -> streaming, and back to connect (after a backoff)
+ whenever something fails or the master goes away.
+ Runs for the whole life of the server, REPLICAOF wakes it up when the master changes.
+*/
+pub async fn run(redis: &'static RwLock<Redis<TcpStream>>) {
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        let (master, changed) = {
+            let redis = redis.read().await;
+            let replication = &redis.replication;
+            (
+                replication.replica_of.clone(),
+                replication.master_link.changed.clone(),
+            )
+        };
+        let (host, port) = match master {
+            Some(master) => master,
+            None => {
+                changed.notified().await;
+                continue;
+            }
+        };
+
+        tokio::select! {
+            _ = changed.notified() => {
+                println!("Master changed, dropping the current master link");
+                backoff = INITIAL_BACKOFF;
+                set_state(redis, LinkState::Connect).await;
+                continue;
+            }
+            result = session(redis, &host, port) => match result {
+                Ok(()) => {
+                    println!("Connection with master lost.");
+                    backoff = INITIAL_BACKOFF;
+                }
+                Err(e) => println!("Error condition on socket for SYNC: {}", e),
+            }
+        }
+
+        set_state(redis, LinkState::Connect).await;
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+//A single connection to the master, returns when the connection is lost
+async fn session(redis: &'static RwLock<Redis<TcpStream>>, host: &str, port: u16) -> Result<()> {
+    let result = connect_and_sync(redis, host, port).await;
+    {
+        let mut redis = redis.write().await;
+        if redis.replication.failover.state == FailoverState::InProgress {
+            match &result {
+                Ok(_) => {
+                    println!("Failover target {}:{} is now the master", host, port);
+                    redis.replication.failover.end();
+                }
+                Err(_) => {
+                    abort_in_progress(&mut redis, "Failover target rejected psync request").await
+                }
+            }
+        }
+    }
+    let stream = result?;
+    println!("MASTER <-> REPLICA sync: Finished with success");
+    set_state(redis, LinkState::Connected).await;
+    let client = Client {
+        stream,
+        should_reply: false,
+        redis,
+        addr: None,
+        hand_shake_port: None,
+        transaction: None,
+        watching: vec![],
+        subscriptions: Default::default(),
+    };
+    if let Err(e) = client.handle_stream().await {
+        println!("Error on master link: {:?}", e);
+    }
+    Ok(())
+}
+
+async fn set_state(redis: &RwLock<Redis<TcpStream>>, state: LinkState) {
+    let mut redis = redis.write().await;
+    let link = &mut redis.replication.master_link;
+    link.state = state;
+    if state == LinkState::Connected {
+        link.last_io = Some(Instant::now());
+    }
+}
+
+async fn connect_and_sync(
+    redis: &RwLock<Redis<TcpStream>>,
+    host: &str,
+    port: u16,
+) -> Result<BufReader<TcpStream>> {
+    println!("Connecting to MASTER {}:{}", host, port);
+    set_state(redis, LinkState::Connecting).await;
+    let stream = timeout(REPL_TIMEOUT, TcpStream::connect((host, port))).await??;
+    let mut stream = BufReader::new(stream);
+
+    let (listening_port, psync) = {
+        let redis = redis.read().await;
+        let replication = &redis.replication;
+        let mut psync = if replication.master_link.synced {
+            let offset = replication.slave_read_repl_offset + 1;
+            vec![replication.master_replid.clone(), offset.to_string()]
+        } else {
+            vec!["?".to_string(), "-1".to_string()]
+        };
+        //Asks the new master to take over, it only accepts if it has our history
+        if replication.failover.state == FailoverState::InProgress {
+            psync.push("FAILOVER".to_string());
+        }
+        (redis.config.port, psync)
+    };
+    let reply = handshake(&mut stream, listening_port, psync).await?;
+
+    match reply {
+        PsyncReply::FullResync { replid, offset } => {
+            println!("Full resync from master: {}:{}", replid, offset);
+            set_state(redis, LinkState::Sync).await;
+            let rdb = read_rdb(&mut stream).await?;
+            println!("MASTER <-> REPLICA sync: Loading DB in memory");
+            //Decoded before taking the lock, the dataset is swapped in one go
+            let file = rdb::decode(&rdb)?;
+            if let Checksum::Invalid { stored, computed } = file.checksum {
+                bail!(
+                    "Wrong RDB checksum expected: ({:#x}) got: ({:#x})",
+                    stored,
+                    computed
+                );
+            }
+            let mut redis = redis.write().await;
+            redis.load(file);
+            redis.replication.reset_history(replid, offset).await;
+            redis.replication.master_link.synced = true;
+        }
+        PsyncReply::Continue { replid } => {
+            println!("Successful partial resynchronization with master.");
+            let mut redis = redis.write().await;
+            redis.replication.continue_history(replid).await;
+        }
+    }
+    Ok(stream)
+}
+
+async fn handshake<S: AsyncBufRead + AsyncWrite + Unpin>(
+    stream: &mut S,
+    listening_port: u16,
+    psync: Vec<String>,
+) -> Result<PsyncReply> {
+    send_command(stream, &["PING"]).await?;
+    expect_ok(stream, "PING").await?;
+
+    let listening_port = listening_port.to_string();
+    send_command(stream, &["REPLCONF", "listening-port", &listening_port]).await?;
+    expect_ok(stream, "REPLCONF listening-port").await?;
+
+    send_command(stream, &["REPLCONF", "capa", "psync2"]).await?;
+    expect_ok(stream, "REPLCONF capa").await?;
+
+    let mut command = vec!["PSYNC"];
+    command.extend(psync.iter().map(String::as_str));
+    send_command(stream, &command).await?;
+    let line = read_line(stream).await?;
+    let mut parts = line.split_whitespace();
+    match parts.next() {
+        Some("+FULLRESYNC") => {
+            let replid = parts.next();
+            let offset = parts.next().and_then(|offset| offset.parse().ok());
+            match (replid, offset) {
+                (Some(replid), Some(offset)) => Ok(PsyncReply::FullResync {
+                    replid: replid.to_string(),
+                    offset,
+                }),
+                _ => bail!("Invalid FULLRESYNC reply: {}", line),
+            }
+        }
+        Some("+CONTINUE") => Ok(PsyncReply::Continue {
+            replid: parts.next().map(|replid| replid.to_string()),
+        }),
+        _ => bail!("Unexpected reply to PSYNC: {}", line),
+    }
+}
+
+/*
+ The RDB file is sent as a bulk string without the trailing \r\n, or for
+ diskless transfers (size unknown upfront) as $EOF:<mark> followed by the
+ file and the same mark.
+*/
+async fn read_rdb<S: AsyncBufRead + Unpin>(stream: &mut S) -> Result<Vec<u8>> {
+    let line = read_line(stream).await?;
+    if let Some(mark) = line.strip_prefix("$EOF:") {
+        if mark.len() != EOF_MARK_SIZE {
+            bail!("Invalid RDB transfer header: {}", line);
+        }
+        return read_until_mark(stream, mark.as_bytes()).await;
+    }
+    let len = match line.strip_prefix('$').map(|len| len.parse::<usize>()) {
+        Some(Ok(len)) => len,
+        _ => bail!("Invalid RDB transfer header: {}", line),
+    };
+    let mut rdb = vec![0; len];
+    timeout(REPL_TIMEOUT, stream.read_exact(&mut rdb)).await??;
+    Ok(rdb)
+}
+
+async fn read_until_mark<S: AsyncBufRead + Unpin>(stream: &mut S, mark: &[u8]) -> Result<Vec<u8>> {
+    let mut rdb = vec![];
+    loop {
+        let buf = timeout(REPL_TIMEOUT, stream.fill_buf()).await??;
+        if buf.is_empty() {
+            bail!("Master closed the connection");
+        }
+        let n = buf.len();
+        let old_len = rdb.len();
+        rdb.extend_from_slice(buf);
+        //The mark can be split between two reads
+        let search_from = old_len.saturating_sub(mark.len() - 1);
+        let position = rdb[search_from..]
+            .windows(mark.len())
+            .position(|window| window == mark);
+        if let Some(position) = position {
+            let end = search_from + position;
+            //Whatever follows the mark is already part of the command stream
+            stream.consume(end + mark.len() - old_len);
+            rdb.truncate(end);
+            return Ok(rdb);
+        }
+        stream.consume(n);
+    }
+}
+
+async fn send_command<S: AsyncWrite + Unpin>(stream: &mut S, args: &[&str]) -> Result<()> {
+    let command = RedisType::Array(
+        args.iter()
+            .map(|arg| RedisType::BulkString(arg.to_string()))
+            .collect(),
+    );
+    stream.write_all(&command.encode()).await?;
+    Ok(())
+}
+
+async fn expect_ok<S: AsyncBufRead + Unpin>(stream: &mut S, command: &str) -> Result<()> {
+    let line = read_line(stream).await?;
+    if !line.starts_with('+') {
+        bail!("Master replied to {} with: {}", command, line);
+    }
+    Ok(())
+}
+
+//Reads a single reply line without the \r\n, newlines sent as keepalive are skipped
+async fn read_line<S: AsyncBufRead + Unpin>(stream: &mut S) -> Result<String> {
+    loop {
+        let mut line = vec![];
+        let n = timeout(REPL_TIMEOUT, stream.read_until(b'\n', &mut line)).await??;
+        if n == 0 {
+            bail!("Master closed the connection");
+        }
+        let line = String::from_utf8_lossy(&line).trim_end().to_string();
+        if !line.is_empty() {
+            return Ok(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, BufReader};
+    use tokio_test::io::Builder;
+
+    use super::{handshake, read_rdb, PsyncReply};
+
+    #[tokio::test]
+    async fn test_handshake_full_resync() {
+        let mock = Builder::new()
+            .write(b"*1\r\n$4\r\nPING\r\n")
+            .read(b"+PONG\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$14\r\nlistening-port\r\n$4\r\n6380\r\n")
+            .read(b"+OK\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$4\r\ncapa\r\n$6\r\npsync2\r\n")
+            .read(b"+OK\r\n")
+            .write(b"*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n")
+            .read(b"\n+FULLRESYNC abc 42\r\n$5\r\nREDIS*1\r\n")
+            .build();
+        let mut stream = BufReader::new(mock);
+        let psync = vec!["?".to_string(), "-1".to_string()];
+        let reply = handshake(&mut stream, 6380, psync).await.unwrap();
+        assert_eq!(
+            reply,
+            PsyncReply::FullResync {
+                replid: "abc".to_string(),
+                offset: 42
+            }
+        );
+        let rdb = read_rdb(&mut stream).await.unwrap();
+        assert_eq!(rdb, b"REDIS");
+    }
+
+    #[tokio::test]
+    async fn test_read_rdb_eof_mark() {
+        let mark = "a".repeat(40);
+        let header = format!("$EOF:{}\r\n", mark);
+        //The mark arrives split in two reads and followed by the command stream
+        let mock = Builder::new()
+            .read(header.as_bytes())
+            .read(b"REDIS0011")
+            .read(&mark.as_bytes()[..10])
+            .read(format!("{}*1\r\n", &mark[10..]).as_bytes())
+            .build();
+        let mut stream = BufReader::new(mock);
+        let rdb = read_rdb(&mut stream).await.unwrap();
+        assert_eq!(rdb, b"REDIS0011");
+        let mut rest = vec![];
+        stream.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"*1\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_handshake_continue() {
+        let mock = Builder::new()
+            .write(b"*1\r\n$4\r\nPING\r\n")
+            .read(b"+PONG\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$14\r\nlistening-port\r\n$4\r\n6380\r\n")
+            .read(b"+OK\r\n")
+            .write(b"*3\r\n$8\r\nREPLCONF\r\n$4\r\ncapa\r\n$6\r\npsync2\r\n")
+            .read(b"+OK\r\n")
+            .write(b"*3\r\n$5\r\nPSYNC\r\n$3\r\nabc\r\n$2\r\n43\r\n")
+            .read(b"+CONTINUE\r\n")
+            .build();
+        let mut stream = BufReader::new(mock);
+        let psync = vec!["abc".to_string(), "43".to_string()];
+        let reply = handshake(&mut stream, 6380, psync).await.unwrap();
+        assert_eq!(reply, PsyncReply::Continue { replid: None });
+    }
+
+    #[tokio::test]
+    async fn test_handshake_error() {
+        let mock = Builder::new()
+            .write(b"*1\r\n$4\r\nPING\r\n")
+            .read(b"-NOAUTH Authentication required.\r\n")
+            .build();
+        let mut stream = BufReader::new(mock);
+        let psync = vec!["?".to_string(), "-1".to_string()];
+        assert!(handshake(&mut stream, 6380, psync).await.is_err());
+    }
+}
@@ -1,69 +1,176 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt, BufReader},
-    net::TcpStream,
-    sync::{Mutex, RwLock},
+    net::{tcp::ReadHalf, TcpStream},
+    sync::{Mutex, Notify, RwLock},
+    time::{interval_at, Instant},
 };
 
 use crate::redis::{
-    replication::{RWStream, Replica},
+    replication::{
+        ack::{read_acks, ReplicaAck},
+        RWStream, Replica,
+    },
     types::RedisType,
     Redis,
 };
 
-use self::command::{handle_command, Command, CommandReturn};
+use self::{
+    command::{handle_command, Command, CommandReturn},
+    subscription::Subscriptions,
+    transaction::{exec, unwatch, Transaction, WatchedKey},
+};
 
 mod command;
+pub mod master_link;
+mod subscription;
+mod transaction;
 
 impl RWStream for TcpStream {}
 
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct Client<'a> {
     pub stream: BufReader<TcpStream>,
     pub should_reply: bool,
     pub redis: &'a RwLock<Redis<TcpStream>>,
     pub addr: Option<SocketAddr>,
     pub hand_shake_port: Option<u16>,
+    //Open after MULTI until EXEC or DISCARD
+    pub transaction: Option<Transaction>,
+    //Keys of WATCH, EXEC fails if any of them changed
+    pub watching: Vec<WatchedKey>,
+    pub subscriptions: Subscriptions,
 }
 
 impl Client<'_> {
     pub async fn handle_stream(mut self) -> Result<()> {
+        let result = self.serve().await;
+        //A connection that is gone doesn't watch keys or listen to channels anymore
+        self.reset().await;
+        match result? {
+            Some(offset) => self.into_replica(offset).await,
+            None => Ok(()),
+        }
+    }
+
+    //Returns the offset the replica has when the connection turns out to be a replica
+    async fn serve(&mut self) -> Result<Option<u64>> {
         let mut buf = [0; 512];
+        //Bytes of a command that didn't fit in the last read
+        let mut pending = Vec::new();
+
+        //Replicas report their offset to the master every second
+        let mut ack_interval = interval_at(Instant::now() + ACK_INTERVAL, ACK_INTERVAL);
 
         loop {
-            let n = self.stream.read(&mut buf).await;
+            let n = tokio::select! {
+                n = self.stream.read(&mut buf) => n,
+                Some(message) = self.subscriptions.messages.recv() => {
+                    self.stream.write_all(&message.encode()).await?;
+                    continue;
+                }
+                _ = ack_interval.tick(), if !self.should_reply => {
+                    self.send_ack().await?;
+                    continue;
+                }
+            };
 
             let n = match n {
                 Ok(n) => n,
                 Err(e) => {
                     println!("Failed to read from socket; err = {:?}", e);
-                    return Ok(());
+                    return Ok(None);
                 }
             };
             if n == 0 {
                 break;
             }
+            if !self.should_reply {
+                let mut redis = self.redis.write().await;
+                redis.replication.master_link.last_io = Some(Instant::now().into_std());
+            }
 
-            let commands = self.get_commands(&mut buf, n)?;
+            pending.extend_from_slice(&buf[..n]);
+            let commands = self.get_commands(&mut pending)?;
             let should_reply = self.should_reply;
-            for command in commands {
-                let command_len = command.len();
+            for (command, raw) in commands {
                 let result = Command::split_type(command);
                 let (command, args) = match result {
                     Ok((c, a)) => (c, a),
-                    Err(_) => {
+                    Err(e) => {
+                        //A command that can't be queued fails the whole transaction
+                        if let Some(transaction) = &mut self.transaction {
+                            transaction.aborted = true;
+                        }
+                        if !should_reply {
+                            self.forward_from_master(&raw).await;
+                            continue;
+                        }
+                        let response = RedisType::SimpleError(e);
+                        self.stream.write_all(&response.encode()).await?;
+                        continue;
+                    }
+                };
+                match command {
+                    //The master link still counts them in the offset and passes them on
+                    Command::Quit => {
+                        if !should_reply {
+                            self.forward_from_master(&raw).await;
+                            return Ok(None);
+                        }
+                        let response = RedisType::SimpleString("OK".to_string());
+                        self.stream.write_all(&response.encode()).await?;
+                        return Ok(None);
+                    }
+                    Command::Reset => {
+                        self.reset().await;
                         if !should_reply {
+                            self.forward_from_master(&raw).await;
                             continue;
                         }
-                        let e = "-ERR unknown command\r\n".to_string();
-                        self.stream.write_all(e.as_bytes()).await?;
+                        let response = RedisType::SimpleString("RESET".to_string());
+                        self.stream.write_all(&response.encode()).await?;
+                        continue;
+                    }
+                    _ => {}
+                }
+                let (command, args) = match self.handle_pubsub(command, args).await? {
+                    Some(command) => command,
+                    None => continue,
+                };
+                let (command, args) = match self.handle_transaction(command, args).await? {
+                    Some(command) => command,
+                    None => {
+                        if !should_reply {
+                            self.forward_from_master(&raw).await;
+                        }
                         continue;
                     }
                 };
-                let (_, writer) = self.stream.get_mut().split();
-                let c_return =
-                    handle_command(command, args, &self.redis, writer, self.should_reply).await;
+                //A blocked command stops waiting if the client leaves, it still ends on its own
+                let c_return = {
+                    let (mut reader, writer) = self.stream.get_mut().split();
+                    let closed = Notify::new();
+                    let handled = handle_command(
+                        command,
+                        args,
+                        &self.redis,
+                        writer,
+                        self.should_reply,
+                        Some(&closed),
+                    );
+                    tokio::pin!(handled);
+                    tokio::select! {
+                        c_return = &mut handled => c_return,
+                        _ = disconnected(&mut reader) => {
+                            closed.notify_one();
+                            handled.await
+                        }
+                    }
+                };
                 /*This is a unnecessary hack,the "replication-11" test
                 doesn't really creates a replica of the redis server
                 it just pretends that it does, so we need to keep
@@ -78,39 +185,234 @@ impl Client<'_> {
                         }
                         self.hand_shake_port = Some(port);
                     }
-                    CommandReturn::HandShakeCompleted => {
+                    CommandReturn::HandShakeCompleted(offset) => {
                         if self.addr.is_none() || self.hand_shake_port.is_none() {
                             continue;
                         }
-
-                        let host = self.addr.unwrap().ip().to_string();
-                        let port = self.hand_shake_port.unwrap();
-                        println!("Replica connected from: {}:{}", host, port);
-                        let stream = self.stream;
-                        let stream = Mutex::new(stream);
-                        let replica = Replica { host, port, stream };
-                        let mut redis = self.redis.write().await;
-                        redis.replication.add_replica(replica);
-                        return Ok(());
+                        return Ok(Some(offset));
                     }
                     _ => {}
                 }
                 if !should_reply {
-                    let mut redis = self.redis.write().await;
-                    redis.replication.slave_read_repl_offset += command_len as u64;
+                    self.forward_from_master(&raw).await;
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    async fn into_replica(self, offset: u64) -> Result<()> {
+        let host = self.addr.unwrap().ip().to_string();
+        let port = self.hand_shake_port.unwrap();
+        println!("Replica connected from: {}:{}", host, port);
+        //Writes go through the replica, ACKs are read by a task of their own
+        let (reader, writer) = tokio::io::split(self.stream);
+        let ack = Arc::new(ReplicaAck::default());
+        let replica = Replica {
+            host,
+            port,
+            stream: Mutex::new(writer),
+            ack: ack.clone(),
+        };
+        let mut redis = self.redis.write().await;
+        let changed = redis.replication.acks_changed.clone();
+        if !redis.replication.add_replica(replica, offset).await {
+            println!("Replica is too far behind, dropping the link");
+            return Ok(());
+        }
+        tokio::spawn(read_acks(reader, ack, changed));
+        Ok(())
+    }
+
+    //(P|S)SUBSCRIBE, (P|S)UNSUBSCRIBE and the subscribed mode, returns the command otherwise
+    async fn handle_pubsub(
+        &mut self,
+        command: Command,
+        args: Vec<String>,
+    ) -> Result<Option<(Command, Vec<String>)>> {
+        let subscribing = matches!(
+            command,
+            Command::Subscribe
+                | Command::Unsubscribe
+                | Command::PSubscribe
+                | Command::PUnsubscribe
+                | Command::SSubscribe
+                | Command::SUnsubscribe
+        );
+        //Inside MULTI they are queued, and refused there
+        if self.transaction.is_some() || (!subscribing && self.subscriptions.count() == 0) {
+            return Ok(Some((command, args)));
+        }
+        let name = || {
+            let name: RedisType = command.clone().into();
+            name.to_string().to_lowercase()
+        };
+        let replies = match command {
+            _ if subscribing && !command.check_arity(&args) => vec![command.arity_error()],
+            Command::Subscribe => {
+                let pubsub = &mut self.redis.write().await.pubsub;
+                self.subscriptions.subscribe(pubsub, args)
+            }
+            Command::Unsubscribe => {
+                let pubsub = &mut self.redis.write().await.pubsub;
+                self.subscriptions.unsubscribe(pubsub, args)
+            }
+            Command::PSubscribe => {
+                let pubsub = &mut self.redis.write().await.pubsub;
+                self.subscriptions.psubscribe(pubsub, args)
+            }
+            Command::PUnsubscribe => {
+                let pubsub = &mut self.redis.write().await.pubsub;
+                self.subscriptions.punsubscribe(pubsub, args)
+            }
+            Command::SSubscribe => {
+                let pubsub = &mut self.redis.write().await.pubsub;
+                self.subscriptions.ssubscribe(pubsub, args)
+            }
+            Command::SUnsubscribe => {
+                let pubsub = &mut self.redis.write().await.pubsub;
+                self.subscriptions.sunsubscribe(pubsub, args)
+            }
+            //Subscribed connections can only get pushed arrays, even for PING
+            Command::Ping => vec![RedisType::Array(vec![
+                RedisType::BulkString("pong".to_string()),
+                RedisType::BulkString(args.into_iter().next().unwrap_or_default()),
+            ])],
+            _ => vec![RedisType::SimpleError(format!(
+                "ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context",
+                name()
+            ))],
+        };
+        if self.should_reply {
+            for reply in replies {
+                self.stream.write_all(&reply.encode()).await?;
+            }
+        }
+        Ok(None)
+    }
+
+    //RESET, the connection is like a new one afterwards
+    async fn reset(&mut self) {
+        self.transaction = None;
+        unwatch(self.redis, std::mem::take(&mut self.watching)).await;
+        self.subscriptions
+            .clear(&mut self.redis.write().await.pubsub);
+    }
+
+    //MULTI, EXEC, DISCARD, WATCH and the commands queued in between, returns the command otherwise
+    async fn handle_transaction(
+        &mut self,
+        command: Command,
+        args: Vec<String>,
+    ) -> Result<Option<(Command, Vec<String>)>> {
+        let response = match (command, self.transaction.take()) {
+            (Command::Multi, None) => {
+                self.transaction = Some(Transaction::default());
+                RedisType::SimpleString("OK".to_string())
+            }
+            (Command::Watch, Some(transaction)) => {
+                self.transaction = Some(transaction);
+                RedisType::SimpleError("ERR WATCH inside MULTI is not allowed".to_string())
+            }
+            (Command::Watch, None) if args.is_empty() => RedisType::SimpleError(
+                "ERR wrong number of arguments for 'watch' command".to_string(),
+            ),
+            (Command::Watch, None) => {
+                let mut redis = self.redis.write().await;
+                for key in args {
+                    if self.watching.iter().any(|watched| watched.key == key) {
+                        continue;
+                    }
+                    self.watching.push(WatchedKey {
+                        version: redis.watch(&key),
+                        alive: redis.is_alive(&key),
+                        key,
+                    });
+                }
+                RedisType::SimpleString("OK".to_string())
+            }
+            (Command::Unwatch, None) => {
+                unwatch(self.redis, std::mem::take(&mut self.watching)).await;
+                RedisType::SimpleString("OK".to_string())
+            }
+            (Command::Discard, Some(_)) => {
+                unwatch(self.redis, std::mem::take(&mut self.watching)).await;
+                RedisType::SimpleString("OK".to_string())
+            }
+            (Command::Discard, None) => {
+                RedisType::SimpleError("ERR DISCARD without MULTI".to_string())
+            }
+            (Command::Exec, None) => RedisType::SimpleError("ERR EXEC without MULTI".to_string()),
+            (Command::Exec, Some(transaction)) if transaction.aborted => {
+                unwatch(self.redis, std::mem::take(&mut self.watching)).await;
+                RedisType::SimpleError(
+                    "EXECABORT Transaction discarded because of previous errors.".to_string(),
+                )
+            }
+            (Command::Exec, Some(transaction)) => {
+                let watching = std::mem::take(&mut self.watching);
+                let replies = exec(
+                    self.redis,
+                    transaction.commands,
+                    watching,
+                    self.should_reply,
+                )
+                .await;
+                if self.should_reply {
+                    self.stream.write_all(&replies).await?;
                 }
+                return Ok(None);
             }
+            (command, Some(mut transaction)) => {
+                let response = match transaction.queue(command, args) {
+                    Ok(()) => RedisType::SimpleString("QUEUED".to_string()),
+                    Err(e) => RedisType::SimpleError(e),
+                };
+                self.transaction = Some(transaction);
+                response
+            }
+            (command, None) => return Ok(Some((command, args))),
+        };
+        if self.should_reply {
+            self.stream.write_all(&response.encode()).await?;
         }
+        Ok(None)
+    }
+
+    //Counts a command from the master as processed and passes it on to our own replicas
+    async fn forward_from_master(&self, raw: &[u8]) {
+        let mut redis = self.redis.write().await;
+        redis.replication.slave_read_repl_offset += raw.len() as u64;
+        redis.replication.proxy_message(raw).await;
+    }
+
+    async fn send_ack(&mut self) -> Result<()> {
+        let offset = self.redis.read().await.replication.slave_read_repl_offset;
+        let ack = RedisType::Array(vec![
+            RedisType::BulkString("REPLCONF".to_string()),
+            RedisType::BulkString("ACK".to_string()),
+            RedisType::BulkString(offset.to_string()),
+        ]);
+        self.stream.write_all(&ack.encode()).await?;
         Ok(())
     }
 
-    fn get_commands(&mut self, buff: &mut [u8; 512], n: usize) -> Result<Vec<RedisType>> {
-        let buff: &[u8] = &buff[..n];
-        let commands = match RedisType::from_buffer(buff) {
+    //Takes the complete commands out of the buffer, a trailing partial one is left there
+    fn get_commands(&mut self, buff: &mut Vec<u8>) -> Result<Vec<(RedisType, Vec<u8>)>> {
+        let commands = match RedisType::drain_raw_frames(buff) {
             Ok(c) => c,
-            Err(_) => return Err(anyhow::Error::msg("")),
+            Err(_) => return Err(anyhow::Error::msg("Protocol error")),
         };
 
         return Ok(commands);
     }
 }
+
+//Resolves once the client closed the connection, pending data means it is still there
+async fn disconnected(reader: &mut ReadHalf<'_>) {
+    let mut buf = [0; 1];
+    match reader.peek(&mut buf).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => std::future::pending().await,
+    }
+}
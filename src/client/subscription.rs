@@ -0,0 +1,233 @@
+use std::collections::BTreeSet;
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+use crate::redis::{
+    pubsub::{PubSub, Subscriber},
+    types::RedisType,
+};
+
+//Channels and patterns a connection subscribed to, and the messages published to them
+#[derive(Debug)]
+pub struct Subscriptions {
+    subscriber: Subscriber,
+    pub messages: UnboundedReceiver<RedisType>,
+    channels: BTreeSet<String>,
+    patterns: BTreeSet<String>,
+    shard_channels: BTreeSet<String>,
+}
+
+impl Default for Subscriptions {
+    fn default() -> Self {
+        let (subscriber, messages) = unbounded_channel();
+        Subscriptions {
+            subscriber,
+            messages,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+            shard_channels: BTreeSet::new(),
+        }
+    }
+}
+
+impl Subscriptions {
+    //Only the pub/sub commands are allowed while this is not zero
+    pub fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len() + self.shard_channels.len()
+    }
+
+    //The count in the replies, shard channels are counted apart
+    fn regular_count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    //Returns the confirmation of each channel, with the count after it
+    pub fn subscribe(&mut self, pubsub: &mut PubSub, channels: Vec<String>) -> Vec<RedisType> {
+        let mut replies = vec![];
+        for channel in channels {
+            pubsub.subscribe(channel.clone(), &self.subscriber);
+            self.channels.insert(channel.clone());
+            replies.push(reply("subscribe", Some(channel), self.regular_count()));
+        }
+        replies
+    }
+
+    pub fn psubscribe(&mut self, pubsub: &mut PubSub, patterns: Vec<String>) -> Vec<RedisType> {
+        let mut replies = vec![];
+        for pattern in patterns {
+            pubsub.psubscribe(pattern.clone(), &self.subscriber);
+            self.patterns.insert(pattern.clone());
+            replies.push(reply("psubscribe", Some(pattern), self.regular_count()));
+        }
+        replies
+    }
+
+    //No channels means all of them
+    pub fn unsubscribe(&mut self, pubsub: &mut PubSub, channels: Vec<String>) -> Vec<RedisType> {
+        let channels = if channels.is_empty() {
+            self.channels.iter().cloned().collect()
+        } else {
+            channels
+        };
+        if channels.is_empty() {
+            return vec![reply("unsubscribe", None, self.regular_count())];
+        }
+        let mut replies = vec![];
+        for channel in channels {
+            pubsub.unsubscribe(&channel, &self.subscriber);
+            self.channels.remove(&channel);
+            replies.push(reply("unsubscribe", Some(channel), self.regular_count()));
+        }
+        replies
+    }
+
+    pub fn punsubscribe(&mut self, pubsub: &mut PubSub, patterns: Vec<String>) -> Vec<RedisType> {
+        let patterns = if patterns.is_empty() {
+            self.patterns.iter().cloned().collect()
+        } else {
+            patterns
+        };
+        if patterns.is_empty() {
+            return vec![reply("punsubscribe", None, self.regular_count())];
+        }
+        let mut replies = vec![];
+        for pattern in patterns {
+            pubsub.punsubscribe(&pattern, &self.subscriber);
+            self.patterns.remove(&pattern);
+            replies.push(reply("punsubscribe", Some(pattern), self.regular_count()));
+        }
+        replies
+    }
+
+    pub fn ssubscribe(&mut self, pubsub: &mut PubSub, channels: Vec<String>) -> Vec<RedisType> {
+        let mut replies = vec![];
+        for channel in channels {
+            pubsub.ssubscribe(channel.clone(), &self.subscriber);
+            self.shard_channels.insert(channel.clone());
+            replies.push(reply(
+                "ssubscribe",
+                Some(channel),
+                self.shard_channels.len(),
+            ));
+        }
+        replies
+    }
+
+    pub fn sunsubscribe(&mut self, pubsub: &mut PubSub, channels: Vec<String>) -> Vec<RedisType> {
+        let channels = if channels.is_empty() {
+            self.shard_channels.iter().cloned().collect()
+        } else {
+            channels
+        };
+        if channels.is_empty() {
+            return vec![reply("sunsubscribe", None, 0)];
+        }
+        let mut replies = vec![];
+        for channel in channels {
+            pubsub.sunsubscribe(&channel, &self.subscriber);
+            self.shard_channels.remove(&channel);
+            replies.push(reply(
+                "sunsubscribe",
+                Some(channel),
+                self.shard_channels.len(),
+            ));
+        }
+        replies
+    }
+
+    //When the connection closes or is reset
+    pub fn clear(&mut self, pubsub: &mut PubSub) {
+        for channel in std::mem::take(&mut self.channels) {
+            pubsub.unsubscribe(&channel, &self.subscriber);
+        }
+        for pattern in std::mem::take(&mut self.patterns) {
+            pubsub.punsubscribe(&pattern, &self.subscriber);
+        }
+        for channel in std::mem::take(&mut self.shard_channels) {
+            pubsub.sunsubscribe(&channel, &self.subscriber);
+        }
+    }
+}
+
+fn reply(kind: &str, name: Option<String>, count: usize) -> RedisType {
+    RedisType::Array(vec![
+        RedisType::BulkString(kind.to_string()),
+        name.map_or(RedisType::NullBulkString, RedisType::BulkString),
+        RedisType::Integer(count as i64),
+    ])
+}
+
+#[cfg(test)]
+mod test {
+    use crate::redis::{pubsub::PubSub, types::RedisType};
+
+    use super::Subscriptions;
+
+    fn reply(kind: &str, name: Option<&str>, count: i64) -> RedisType {
+        RedisType::Array(vec![
+            RedisType::BulkString(kind.to_string()),
+            name.map_or(RedisType::NullBulkString, |name| {
+                RedisType::BulkString(name.to_string())
+            }),
+            RedisType::Integer(count),
+        ])
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let mut pubsub = PubSub::default();
+        let mut subscriptions = Subscriptions::default();
+
+        let replies = subscriptions.subscribe(&mut pubsub, names(&["a", "b", "a"]));
+        let expected = vec![
+            reply("subscribe", Some("a"), 1),
+            reply("subscribe", Some("b"), 2),
+            reply("subscribe", Some("a"), 2),
+        ];
+        assert_eq!(replies, expected);
+        let replies = subscriptions.psubscribe(&mut pubsub, names(&["a*"]));
+        assert_eq!(replies, vec![reply("psubscribe", Some("a*"), 3)]);
+
+        //The count covers channels and patterns
+        assert_eq!(pubsub.publish("a", "hello"), 2);
+        let replies = subscriptions.unsubscribe(&mut pubsub, vec![]);
+        let expected = vec![
+            reply("unsubscribe", Some("a"), 2),
+            reply("unsubscribe", Some("b"), 1),
+        ];
+        assert_eq!(replies, expected);
+        assert_eq!(pubsub.publish("a", "hello"), 1);
+
+        let replies = subscriptions.punsubscribe(&mut pubsub, vec![]);
+        assert_eq!(replies, vec![reply("punsubscribe", Some("a*"), 0)]);
+        let replies = subscriptions.unsubscribe(&mut pubsub, vec![]);
+        assert_eq!(replies, vec![reply("unsubscribe", None, 0)]);
+        assert_eq!(pubsub.publish("a", "hello"), 0);
+    }
+
+    #[test]
+    fn test_shard_subscriptions() {
+        let mut pubsub = PubSub::default();
+        let mut subscriptions = Subscriptions::default();
+        subscriptions.subscribe(&mut pubsub, names(&["a"]));
+
+        let replies = subscriptions.ssubscribe(&mut pubsub, names(&["a", "b"]));
+        let expected = vec![
+            reply("ssubscribe", Some("a"), 1),
+            reply("ssubscribe", Some("b"), 2),
+        ];
+        assert_eq!(replies, expected);
+        assert_eq!(subscriptions.count(), 3);
+
+        let replies = subscriptions.sunsubscribe(&mut pubsub, names(&["b"]));
+        assert_eq!(replies, vec![reply("sunsubscribe", Some("b"), 1)]);
+        subscriptions.clear(&mut pubsub);
+        assert_eq!(subscriptions.count(), 0);
+        assert_eq!(pubsub.spublish("a", "hello"), 0);
+        assert_eq!(pubsub.publish("a", "hello"), 0);
+    }
+}
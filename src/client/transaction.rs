@@ -0,0 +1,362 @@
+use tokio::sync::{RwLock, RwLockWriteGuard};
+
+use crate::redis::{
+    replication::{failover::wait_writes, RWStream},
+    types::RedisType,
+    Redis,
+};
+
+use super::command::{handle_command, wait_now, Command};
+
+//A key as it was when WATCH was called
+#[derive(Debug)]
+pub struct WatchedKey {
+    pub key: String,
+    pub version: u64,
+    //Expiring after WATCH counts as a change even before the key is removed
+    pub alive: bool,
+}
+
+//Commands queued after MULTI, run by EXEC
+#[derive(Debug, Default)]
+pub struct Transaction {
+    pub commands: Vec<(Command, Vec<String>)>,
+    //A command was rejected while queueing, EXEC discards the transaction
+    pub aborted: bool,
+}
+
+impl Transaction {
+    //Returns the error to reply with if the command can't be queued
+    pub fn queue(&mut self, command: Command, args: Vec<String>) -> Result<(), String> {
+        let error = match command {
+            Command::Multi => return Err("ERR MULTI calls can not be nested".to_string()),
+            //They take over the connection
+            Command::Subscribe
+            | Command::Unsubscribe
+            | Command::PSubscribe
+            | Command::PUnsubscribe
+            | Command::SSubscribe
+            | Command::SUnsubscribe
+            | Command::Psync
+            | Command::ReplConf => "ERR Command not allowed inside a transaction".to_string(),
+            //They wait for other clients, which can't run while EXEC holds the lock
+            Command::Failover | Command::Shutdown => {
+                "ERR Command not allowed inside a transaction".to_string()
+            }
+            _ if !command.check_arity(&args) => command.arity_error().to_string(),
+            _ => {
+                self.commands.push((command, args));
+                return Ok(());
+            }
+        };
+        self.aborted = true;
+        Err(error)
+    }
+}
+
+/*
+ The dataset moved out of the write lock so the handlers, which lock Redis themselves,
+ can run against it. It goes back into the lock when this is dropped, even if one
+ of them panics.
+*/
+struct Lent<'a, S: RWStream> {
+    guard: RwLockWriteGuard<'a, Redis<S>>,
+    dataset: RwLock<Redis<S>>,
+}
+
+impl<'a, S: RWStream> Lent<'a, S> {
+    fn new(mut guard: RwLockWriteGuard<'a, Redis<S>>) -> Self {
+        let dataset = RwLock::new(std::mem::take(&mut *guard));
+        Self { guard, dataset }
+    }
+}
+
+impl<S: RWStream> Drop for Lent<'_, S> {
+    fn drop(&mut self) {
+        std::mem::swap(&mut *self.guard, self.dataset.get_mut());
+    }
+}
+
+/*
+ Runs the queued commands with the write lock held the whole time so no other
+ client sees the dataset in between.
+ Returns the encoded array of replies, a null array if a watched key changed.
+*/
+pub async fn exec<S: RWStream>(
+    redis: &RwLock<Redis<S>>,
+    commands: Vec<(Command, Vec<String>)>,
+    watching: Vec<WatchedKey>,
+    should_reply: bool,
+) -> Vec<u8> {
+    let has_writes = commands.iter().any(|(command, _)| command.is_write());
+    let mut guard = loop {
+        //Writes are paused during a failover, nothing could resume them while we hold the lock
+        if has_writes && should_reply {
+            wait_writes(redis).await;
+        }
+        let guard = redis.write().await;
+        if !has_writes || !should_reply || !guard.replication.failover.in_progress() {
+            break guard;
+        }
+    };
+    let changed = watching.iter().any(|watched| {
+        guard.key_version(&watched.key) != watched.version
+            || (watched.alive && !guard.is_alive(&watched.key))
+    });
+    for watched in watching {
+        guard.unwatch(&watched.key);
+    }
+    if changed {
+        return RedisType::NullArray.encode();
+    }
+    let lent = Lent::new(guard);
+    let dataset = &lent.dataset;
+
+    //Replicas apply the block at once too
+    if has_writes {
+        let multi = RedisType::Array(vec![Command::Multi.into()]);
+        let mut redis = dataset.write().await;
+        redis.replication.propagate_message(multi.encode()).await;
+    }
+    let mut replies = format!("*{}\r\n", commands.len()).into_bytes();
+    for (command, args) in commands {
+        let args = non_blocking(&command, args);
+        if command == Command::Wait {
+            replies.extend(wait_now(&args, dataset).await.encode());
+            continue;
+        }
+        let mut reply = vec![];
+        handle_command(command, args, dataset, &mut reply, should_reply, None).await;
+        replies.extend(reply);
+    }
+    if has_writes {
+        let exec = RedisType::Array(vec![Command::Exec.into()]);
+        let mut redis = dataset.write().await;
+        redis.replication.propagate_message(exec.encode()).await;
+    }
+    replies
+}
+
+pub async fn unwatch<S: RWStream>(redis: &RwLock<Redis<S>>, watching: Vec<WatchedKey>) {
+    if watching.is_empty() {
+        return;
+    }
+    let mut redis = redis.write().await;
+    for watched in watching {
+        redis.unwatch(&watched.key);
+    }
+}
+
+//Blocking commands don't block inside a transaction, nothing could change while they wait
+fn non_blocking(command: &Command, args: Vec<String>) -> Vec<String> {
+    if *command != Command::XRead && *command != Command::XReadGroup {
+        return args;
+    }
+    let mut result = vec![];
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_uppercase().as_str() {
+            "BLOCK" => {
+                args.next();
+            }
+            "STREAMS" => {
+                result.push(arg);
+                result.extend(args.by_ref());
+            }
+            _ => result.push(arg),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::sync::RwLock;
+    use tokio_test::io::Mock;
+
+    use crate::{
+        client::command::{handle_command, test_util::args, Command, CommandReturn},
+        redis::{config::Config, types::RedisType, value::ValueType, Redis},
+    };
+
+    use super::{exec, non_blocking, Lent, Transaction, WatchedKey};
+
+    #[test]
+    fn test_queue() {
+        let mut transaction = Transaction::default();
+        assert_eq!(transaction.queue(Command::Set, args(&["a", "1"])), Ok(()));
+        assert_eq!(
+            transaction.queue(Command::Multi, vec![]),
+            Err("ERR MULTI calls can not be nested".to_string())
+        );
+        assert!(!transaction.aborted);
+        assert_eq!(
+            transaction.queue(Command::Get, vec![]),
+            Err("ERR wrong number of arguments for 'get' command".to_string())
+        );
+        assert!(transaction.aborted);
+        assert_eq!(transaction.commands.len(), 1);
+        for command in [Command::Failover, Command::Shutdown] {
+            assert_eq!(
+                transaction.queue(command, vec![]),
+                Err("ERR Command not allowed inside a transaction".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_non_blocking() {
+        let x_read = args(&["COUNT", "1", "BLOCK", "0", "STREAMS", "block", "0"]);
+        assert_eq!(
+            non_blocking(&Command::XRead, x_read),
+            args(&["COUNT", "1", "STREAMS", "block", "0"])
+        );
+        let x_read_group = args(&["GROUP", "g", "c", "BLOCK", "10", "STREAMS", "s", ">"]);
+        assert_eq!(
+            non_blocking(&Command::XReadGroup, x_read_group),
+            args(&["GROUP", "g", "c", "STREAMS", "s", ">"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exec() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        redis.replication.create_backlog();
+        let redis = Arc::new(RwLock::new(redis));
+
+        let commands = vec![
+            (Command::Set, args(&["a", "1"])),
+            (Command::Get, args(&["a"])),
+        ];
+        let replies = exec(&redis, commands, vec![], true).await;
+        let expected = RedisType::Array(vec![
+            RedisType::SimpleString("OK".to_string()),
+            RedisType::BulkString("1".to_string()),
+        ]);
+        assert_eq!(replies, expected.encode());
+
+        //Only the writes go to the replicas, inside MULTI/EXEC
+        let propagated = redis
+            .read()
+            .await
+            .replication
+            .backlog
+            .as_ref()
+            .and_then(|backlog| backlog.range_from(1))
+            .unwrap();
+        let mut expected = b"*1\r\n$5\r\nMULTI\r\n".to_vec();
+        expected.extend(b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n");
+        expected.extend(b"*1\r\n$4\r\nEXEC\r\n");
+        assert_eq!(propagated, expected);
+
+        //WAIT doesn't wait for replicas while the lock is held
+        let commands = vec![
+            (Command::Wait, args(&["1", "0"])),
+            (Command::Wait, args(&["x", "0"])),
+        ];
+        let replies = exec(&redis, commands, vec![], true).await;
+        let expected = RedisType::Array(vec![
+            RedisType::Integer(0),
+            RedisType::SimpleError("ERR invalid number of replicas".to_string()),
+        ]);
+        assert_eq!(replies, expected.encode());
+    }
+
+    #[test]
+    fn test_lent_panic() {
+        let redis: RwLock<Redis<Mock>> = RwLock::new(Redis::new(Config::default()));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let lent = Lent::new(redis.try_write().unwrap());
+            let mut dataset = lent.dataset.try_write().unwrap();
+            dataset.set("a".to_string(), ValueType::String("1".to_string()), None);
+            panic!("handler panicked");
+        }));
+        assert!(result.is_err());
+
+        //The dataset is back in the lock with what the handlers did to it
+        let redis = redis.try_read().unwrap();
+        assert_eq!(
+            redis.get_value("a"),
+            Some(&ValueType::String("1".to_string()))
+        );
+    }
+
+    async fn watch(redis: &RwLock<Redis<Mock>>, key: &str) -> WatchedKey {
+        let mut redis = redis.write().await;
+        WatchedKey {
+            key: key.to_string(),
+            version: redis.watch(key),
+            alive: redis.is_alive(key),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exec_watch() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let value = ValueType::String("1".to_string());
+        let redis = Arc::new(RwLock::new(redis));
+        let get = || vec![(Command::Get, args(&["a"]))];
+        let nil = RedisType::Array(vec![RedisType::NullBulkString]).encode();
+
+        //Untouched
+        let watching = vec![watch(&redis, "a").await];
+        assert_eq!(exec(&redis, get(), watching, true).await, nil);
+
+        //Created and deleted after WATCH
+        let watching = vec![watch(&redis, "a").await];
+        redis
+            .write()
+            .await
+            .set("a".to_string(), value.clone(), None);
+        redis.write().await.delete("a");
+        let replies = exec(&redis, get(), watching, true).await;
+        assert_eq!(replies, RedisType::NullArray.encode());
+
+        //Changes from before WATCH don't count
+        let watching = vec![watch(&redis, "a").await, watch(&redis, "b").await];
+        assert_eq!(exec(&redis, get(), watching, true).await, nil);
+
+        //Expired after WATCH, before the key is removed
+        let key = "expiring".to_string();
+        redis.write().await.set(key.clone(), value, Some(10));
+        let watching = vec![watch(&redis, &key).await];
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let replies = exec(&redis, get(), watching, true).await;
+        assert_eq!(replies, RedisType::NullArray.encode());
+    }
+
+    #[tokio::test]
+    async fn test_exec_watch_no_change() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let redis = Arc::new(RwLock::new(redis));
+        let run = |command, arguments: &[&str]| {
+            let redis = Arc::clone(&redis);
+            let arguments = args(arguments);
+            async move {
+                let mut reply = vec![];
+                handle_command(command, arguments, &redis, &mut reply, true, None).await
+            }
+        };
+        run(Command::XAdd, &["s", "2-0", "f", "v"]).await;
+        let get = || vec![(Command::Get, args(&["a"]))];
+        let nil = RedisType::Array(vec![RedisType::NullBulkString]).encode();
+
+        //Failed or no-op writes leave the key as it was
+        let watching = vec![watch(&redis, "s").await, watch(&redis, "missing").await];
+        let result = run(Command::XAdd, &["s", "1-0", "f", "v"]).await;
+        assert_eq!(result, CommandReturn::Error);
+        run(Command::XAdd, &["missing", "NOMKSTREAM", "*", "f", "v"]).await;
+        run(Command::XDel, &["s", "5-0"]).await;
+        run(Command::XTrim, &["s", "MAXLEN", "10"]).await;
+        run(Command::XAck, &["s", "g", "2-0"]).await;
+        run(Command::Del, &["missing"]).await;
+        assert_eq!(exec(&redis, get(), watching, true).await, nil);
+
+        let watching = vec![watch(&redis, "s").await];
+        run(Command::XDel, &["s", "2-0"]).await;
+        let replies = exec(&redis, get(), watching, true).await;
+        assert_eq!(replies, RedisType::NullArray.encode());
+    }
+}
@@ -4,14 +4,16 @@ use anyhow::Result;
 use client::Client;
 use tokio::{
     net::{TcpListener, TcpStream},
+    signal::unix::{signal, SignalKind},
     sync::RwLock,
 };
 
-use crate::redis::Redis;
+use crate::redis::{shutdown, Redis};
 
 mod args;
 mod client;
 mod redis;
+mod sentinel;
 mod util;
 
 const HOST: &str = "127.0.0.1";
@@ -19,6 +21,9 @@ const HOST: &str = "127.0.0.1";
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = args::Args::parse()?;
+    if args.sentinel {
+        return sentinel::run(args).await;
+    }
     let addr = format!("{}:{}", HOST, args.port);
     let listener = TcpListener::bind(addr).await?;
     let redis = redis::Redis::new(args.into());
@@ -27,31 +32,37 @@ async fn main() -> Result<()> {
 
     let redis: &'static RwLock<Redis<TcpStream>> = Box::leak(Box::new(redis));
 
-    if !redis.read().await.is_master() {
-        tokio::spawn(async move {
-            let stream = redis
-                .read()
-                .await
-                .hand_shake()
-                .await
-                .expect("Failed to connect to master");
-            let client = Client {
-                stream,
-                should_reply: false,
-                redis: &redis,
-                addr: None,
-                hand_shake_port: None,
-            };
-            if let Err(e) = client.handle_stream().await {
-                println!("Error on master listener: {:?}", e);
-            }
-        });
-    }
+    tokio::spawn(client::master_link::run(redis));
+    tokio::spawn(redis::replication::failover::run(redis));
 
     tokio::spawn(start_expiration_thread(&redis));
 
+    let shutdown = redis.read().await.shutdown.clone();
+    let mut sigterm = signal(SignalKind::terminate())?;
+
     loop {
-        let (stream, client_addr) = listener.accept().await?;
+        let (stream, client_addr) = tokio::select! {
+            accepted = listener.accept() => accepted?,
+            _ = shutdown.wait() => break,
+            _ = sigterm.recv() => {
+                println!("Received SIGTERM scheduling shutdown...");
+                if shutdown_from_signal(redis).await {
+                    break;
+                }
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                println!("Received SIGINT scheduling shutdown...");
+                if shutdown_from_signal(redis).await {
+                    break;
+                }
+                continue;
+            }
+        };
+        //Stop accepting new clients while a shutdown is in progress
+        if shutdown.in_progress() {
+            continue;
+        }
 
         let stream = tokio::io::BufReader::new(stream);
         let client = Client {
@@ -60,6 +71,9 @@ async fn main() -> Result<()> {
             redis: &redis,
             addr: Some(client_addr),
             hand_shake_port: None,
+            transaction: None,
+            watching: vec![],
+            subscriptions: Default::default(),
         };
         tokio::spawn(async move {
             if let Err(e) = client.handle_stream().await {
@@ -67,6 +81,17 @@ async fn main() -> Result<()> {
             }
         });
     }
+    Ok(())
+}
+
+async fn shutdown_from_signal(redis: &'static RwLock<Redis<TcpStream>>) -> bool {
+    match shutdown::run(redis, Default::default()).await {
+        Ok(_) => true,
+        Err(e) => {
+            println!("Shutdown aborted: {}", e);
+            false
+        }
+    }
 }
 
 async fn start_expiration_thread(redis: &'static RwLock<redis::Redis<TcpStream>>) {
@@ -0,0 +1,140 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{sync::Notify, time::Instant};
+
+/*
+ Clients blocked by XREAD and XREADGROUP, by the stream keys they wait on. XADD,
+ and anything replacing or removing a key or group, wakes up the clients of its key
+ and they look at the streams again. The waiters are behind their own lock so a
+ client can leave without the Redis one.
+*/
+#[derive(Debug, Default)]
+pub struct Blocking {
+    waiters: Arc<Mutex<HashMap<String, Vec<Arc<Notify>>>>>,
+}
+
+//A client waiting on some keys, it stops waiting when dropped
+#[derive(Debug)]
+pub struct Blocked {
+    keys: Vec<String>,
+    ready: Arc<Notify>,
+    waiters: Arc<Mutex<HashMap<String, Vec<Arc<Notify>>>>>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Wake {
+    //One of the keys got new entries
+    Ready,
+    Timeout,
+    //The client is gone
+    Closed,
+}
+
+impl Blocking {
+    //Has to be called with the Redis lock held, right after finding nothing to read
+    pub fn block(&self, keys: &[String]) -> Blocked {
+        let ready = Arc::new(Notify::new());
+        let mut waiters = self.waiters.lock().unwrap();
+        for key in keys {
+            waiters
+                .entry(key.clone())
+                .or_default()
+                .push(Arc::clone(&ready));
+        }
+        Blocked {
+            keys: keys.to_vec(),
+            ready,
+            waiters: Arc::clone(&self.waiters),
+        }
+    }
+
+    pub fn wake(&self, key: &str) {
+        if let Some(waiters) = self.waiters.lock().unwrap().get(key) {
+            for ready in waiters {
+                //Kept as a permit if the client isn't waiting yet
+                ready.notify_one();
+            }
+        }
+    }
+}
+
+impl Blocked {
+    //None as deadline waits until a key is ready or the client is gone
+    pub async fn wait(&self, deadline: Option<Instant>, closed: Option<&Notify>) -> Wake {
+        let ready = async {
+            match deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, self.ready.notified()).await {
+                        Ok(_) => Wake::Ready,
+                        Err(_) => Wake::Timeout,
+                    }
+                }
+                None => {
+                    self.ready.notified().await;
+                    Wake::Ready
+                }
+            }
+        };
+        match closed {
+            Some(closed) => tokio::select! {
+                wake = ready => wake,
+                _ = closed.notified() => Wake::Closed,
+            },
+            None => ready.await,
+        }
+    }
+}
+
+impl Drop for Blocked {
+    fn drop(&mut self) {
+        let mut waiters = self.waiters.lock().unwrap();
+        for key in &self.keys {
+            if let Some(ready) = waiters.get_mut(key) {
+                ready.retain(|ready| !Arc::ptr_eq(ready, &self.ready));
+                if ready.is_empty() {
+                    waiters.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use tokio::{sync::Notify, time::Instant};
+
+    use super::{Blocking, Wake};
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_blocking() {
+        let blocking = Blocking::default();
+        let blocked = blocking.block(&keys(&["a", "b"]));
+        let other = blocking.block(&keys(&["b"]));
+        assert_eq!(blocking.waiters.lock().unwrap()["b"].len(), 2);
+
+        //Woken up before waiting, the permit is kept
+        blocking.wake("a");
+        blocking.wake("c");
+        assert_eq!(blocked.wait(None, None).await, Wake::Ready);
+        let soon = Some(Instant::now() + Duration::from_millis(10));
+        assert_eq!(other.wait(soon, None).await, Wake::Timeout);
+
+        let closed = Notify::new();
+        closed.notify_one();
+        assert_eq!(other.wait(None, Some(&closed)).await, Wake::Closed);
+
+        drop(blocked);
+        assert!(!blocking.waiters.lock().unwrap().contains_key("a"));
+        drop(other);
+        assert!(blocking.waiters.lock().unwrap().is_empty());
+    }
+}
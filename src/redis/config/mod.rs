@@ -3,14 +3,41 @@ use std::{
     str::FromStr,
 };
 
-use super::types::RedisType;
+use super::{notify::NotifyFlags, types::RedisType};
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct Config {
     pub dir: Option<String>,
     pub db_file_name: Option<String>,
     pub port: u16,
     pub replica_of: Option<(String, u16)>,
+    pub replica_read_only: bool,
+    pub min_replicas_to_write: usize,
+    //Seconds since the last ACK for a replica to still count as good
+    pub min_replicas_max_lag: u64,
+    //Send the RDB file straight to the replicas instead of saving it to disk first
+    pub repl_diskless_sync: bool,
+    //Seconds to wait for more replicas before a diskless transfer starts
+    pub repl_diskless_sync_delay: u64,
+    //Classes of keyspace events published to the __keyspace@0__ and __keyevent@0__ channels
+    pub notify_keyspace_events: NotifyFlags,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            db_file_name: None,
+            port: 0,
+            replica_of: None,
+            replica_read_only: true,
+            min_replicas_to_write: 0,
+            min_replicas_max_lag: 10,
+            repl_diskless_sync: false,
+            repl_diskless_sync_delay: 5,
+            notify_keyspace_events: NotifyFlags::default(),
+        }
+    }
 }
 
 impl Config {
@@ -35,6 +62,18 @@ impl Config {
             self.inner_get_value(ConfigKey::Port),
             RedisType::BulkString("replicaof".to_string()),
             self.inner_get_value(ConfigKey::ReplicaOf),
+            RedisType::BulkString("replica-read-only".to_string()),
+            self.inner_get_value(ConfigKey::ReplicaReadOnly),
+            RedisType::BulkString("min-replicas-to-write".to_string()),
+            self.inner_get_value(ConfigKey::MinReplicasToWrite),
+            RedisType::BulkString("min-replicas-max-lag".to_string()),
+            self.inner_get_value(ConfigKey::MinReplicasMaxLag),
+            RedisType::BulkString("repl-diskless-sync".to_string()),
+            self.inner_get_value(ConfigKey::ReplDisklessSync),
+            RedisType::BulkString("repl-diskless-sync-delay".to_string()),
+            self.inner_get_value(ConfigKey::ReplDisklessSyncDelay),
+            RedisType::BulkString("notify-keyspace-events".to_string()),
+            self.inner_get_value(ConfigKey::NotifyKeyspaceEvents),
         ]);
         result
     }
@@ -48,12 +87,75 @@ impl Config {
                 .replica_of
                 .as_ref()
                 .map(|(host, port)| format!("{}:{}", host, port)),
+            ConfigKey::ReplicaReadOnly => Some(yes_no(self.replica_read_only)),
+            ConfigKey::MinReplicasToWrite => Some(self.min_replicas_to_write.to_string()),
+            ConfigKey::MinReplicasMaxLag => Some(self.min_replicas_max_lag.to_string()),
+            ConfigKey::ReplDisklessSync => Some(yes_no(self.repl_diskless_sync)),
+            ConfigKey::ReplDisklessSyncDelay => Some(self.repl_diskless_sync_delay.to_string()),
+            ConfigKey::NotifyKeyspaceEvents => Some(self.notify_keyspace_events.to_string()),
         };
         match value {
             Some(value) => RedisType::BulkString(value),
             None => RedisType::NullBulkString,
         }
     }
+
+    pub fn set_value(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let config_key = match ConfigKey::from_str(key) {
+            Ok(config_key) => config_key,
+            Err(_) => {
+                return Err(format!(
+                    "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+                    key
+                ))
+            }
+        };
+        let invalid = || {
+            format!(
+                "ERR Invalid argument '{}' for CONFIG SET '{}'",
+                value, config_key
+            )
+        };
+        match config_key {
+            ConfigKey::ReplicaReadOnly => {
+                self.replica_read_only = parse_yes_no(value).ok_or_else(invalid)?
+            }
+            ConfigKey::MinReplicasToWrite => {
+                self.min_replicas_to_write = value.parse().map_err(|_| invalid())?
+            }
+            ConfigKey::MinReplicasMaxLag => {
+                self.min_replicas_max_lag = value.parse().map_err(|_| invalid())?
+            }
+            ConfigKey::ReplDisklessSync => {
+                self.repl_diskless_sync = parse_yes_no(value).ok_or_else(invalid)?
+            }
+            ConfigKey::ReplDisklessSyncDelay => {
+                self.repl_diskless_sync_delay = value.parse().map_err(|_| invalid())?
+            }
+            ConfigKey::NotifyKeyspaceEvents => {
+                self.notify_keyspace_events = value.parse().map_err(|_| invalid())?
+            }
+            _ => {
+                return Err(format!(
+                    "ERR CONFIG SET failed (possibly related to argument '{}') - can't set immutable config",
+                    config_key
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+fn yes_no(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+fn parse_yes_no(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "yes" => Some(true),
+        "no" => Some(false),
+        _ => None,
+    }
 }
 
 pub enum ConfigKey {
@@ -61,6 +163,12 @@ pub enum ConfigKey {
     DbFileName,
     Port,
     ReplicaOf,
+    ReplicaReadOnly,
+    MinReplicasToWrite,
+    MinReplicasMaxLag,
+    ReplDisklessSync,
+    ReplDisklessSyncDelay,
+    NotifyKeyspaceEvents,
 }
 
 impl FromStr for ConfigKey {
@@ -72,6 +180,12 @@ impl FromStr for ConfigKey {
             "dbfilename" => Ok(ConfigKey::DbFileName),
             "port" => Ok(ConfigKey::Port),
             "replicaof" => Ok(ConfigKey::ReplicaOf),
+            "replica-read-only" | "slave-read-only" => Ok(ConfigKey::ReplicaReadOnly),
+            "min-replicas-to-write" | "min-slaves-to-write" => Ok(ConfigKey::MinReplicasToWrite),
+            "min-replicas-max-lag" | "min-slaves-max-lag" => Ok(ConfigKey::MinReplicasMaxLag),
+            "repl-diskless-sync" => Ok(ConfigKey::ReplDisklessSync),
+            "repl-diskless-sync-delay" => Ok(ConfigKey::ReplDisklessSyncDelay),
+            "notify-keyspace-events" => Ok(ConfigKey::NotifyKeyspaceEvents),
             _ => Err(()),
         }
     }
@@ -84,6 +198,12 @@ impl Display for ConfigKey {
             ConfigKey::DbFileName => write!(f, "dbfilename"),
             ConfigKey::Port => write!(f, "port"),
             ConfigKey::ReplicaOf => write!(f, "replicaof"),
+            ConfigKey::ReplicaReadOnly => write!(f, "replica-read-only"),
+            ConfigKey::MinReplicasToWrite => write!(f, "min-replicas-to-write"),
+            ConfigKey::MinReplicasMaxLag => write!(f, "min-replicas-max-lag"),
+            ConfigKey::ReplDisklessSync => write!(f, "repl-diskless-sync"),
+            ConfigKey::ReplDisklessSyncDelay => write!(f, "repl-diskless-sync-delay"),
+            ConfigKey::NotifyKeyspaceEvents => write!(f, "notify-keyspace-events"),
         }
     }
 }
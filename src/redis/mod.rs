@@ -1,25 +1,31 @@
 use std::{
     collections::{HashMap, HashSet},
-    string,
+    sync::Arc,
     time::{Duration, UNIX_EPOCH},
 };
 
-use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufReader},
-    net::TcpStream,
-};
-
-use bytes::{Buf, Bytes};
-
 use self::{
+    blocking::Blocking,
     config::Config,
+    notify::Event,
+    pubsub::PubSub,
+    rdb::{Checksum, RdbFile},
     replication::{role::Role, RWStream, Replication},
+    shutdown::Shutdown,
     types::RedisType,
-    value::{Value, ValueType},
+    value::{
+        stream::{Stream, StreamId},
+        Value, ValueType,
+    },
 };
 
+pub mod blocking;
 pub mod config;
+pub mod notify;
+pub mod pubsub;
+pub mod rdb;
 pub mod replication;
+pub mod shutdown;
 pub mod types;
 pub mod value;
 
@@ -32,8 +38,16 @@ pub struct Redis<S: RWStream> {
     expiry_size: u64,
     memory: HashMap<String, Value>,
     keys: HashSet<String>,
+    //Bumped on every change of a key, WATCH compares them
+    versions: HashMap<String, u64>,
+    last_version: u64,
+    //Clients watching each key, the versions of deleted keys are kept while watched
+    watchers: HashMap<String, usize>,
     pub replication: Replication<S>,
     pub config: Config,
+    pub shutdown: Arc<Shutdown>,
+    pub pubsub: PubSub,
+    pub blocking: Blocking,
 }
 
 impl<S: RWStream> Redis<S> {
@@ -53,11 +67,62 @@ impl<S: RWStream> Redis<S> {
     }
 
     pub fn set(&mut self, key: String, value: ValueType, expiration: Option<u64>) {
+        self.expire_if_needed(&key);
+        let new = !self.is_alive(&key);
         let value = Value::new(value, expiration);
         self.memory.insert(key.clone(), value);
+        self.touch(&key);
+        if new {
+            self.notify(Event::New, "new", &key);
+        }
+        self.blocking.wake(&key);
         self.keys.insert(key);
     }
 
+    //Bumps the version of a key that changed, callers of get_mut call it once they change it
+    pub fn touch(&mut self, key: &str) {
+        self.last_version += 1;
+        if self.memory.contains_key(key) || self.watchers.contains_key(key) {
+            self.versions.insert(key.to_string(), self.last_version);
+        } else {
+            self.versions.remove(key);
+        }
+    }
+
+    pub fn key_version(&self, key: &str) -> u64 {
+        self.versions.get(key).copied().unwrap_or_default()
+    }
+
+    //Whether the key holds a value that didn't expire yet
+    pub fn is_alive(&self, key: &str) -> bool {
+        self.memory
+            .get(key)
+            .is_some_and(|value| !value.is_expired())
+    }
+
+    pub fn watch(&mut self, key: &str) -> u64 {
+        *self.watchers.entry(key.to_string()).or_default() += 1;
+        self.key_version(key)
+    }
+
+    pub fn unwatch(&mut self, key: &str) {
+        let watchers = match self.watchers.get_mut(key) {
+            Some(watchers) => watchers,
+            None => return,
+        };
+        *watchers -= 1;
+        if *watchers == 0 {
+            self.watchers.remove(key);
+            if !self.memory.contains_key(key) {
+                self.versions.remove(key);
+            }
+        }
+    }
+
+    /*
+     Reads can't remove the keys that expired, they just don't see them. The expired
+     event of those comes once a write looks them up or expire_keys removes them.
+    */
     pub fn get_value(&self, key: &str) -> Option<&ValueType> {
         match self.memory.get(key) {
             Some(value) => {
@@ -77,69 +142,48 @@ impl<S: RWStream> Redis<S> {
         value
     }
 
-    pub fn get_x_range(&self, key: &str, start: (u64, u64), end: (u64, u64)) -> RedisType {
-        let mut result_vec = vec![];
-        let value = self.memory.get(key);
-        match value {
-            Some(value) => match &value.value {
-                ValueType::Stream(stream) => {
-                    let (first_start, second_start) = start;
-                    let (fist_end, second_end) = end;
-                    for data in stream {
-                        let (first_id, second_id) = data.id;
-                        let fist_in_range = first_id >= first_start && first_id <= fist_end;
-                        let second_in_range = second_id >= second_start && second_id <= second_end;
-                        if !fist_in_range || !second_in_range {
-                            continue;
-                        }
-
-                        result_vec.push(data.into());
-                    }
-                    RedisType::Array(result_vec)
-                }
-                _ => RedisType::SimpleError("ERR wrong type of value".to_string()),
-            },
-            None => RedisType::NullArray,
+    //Entries between the two IDs, both included, at most count of them
+    pub fn get_x_range(
+        &self,
+        key: &str,
+        start: StreamId,
+        end: StreamId,
+        count: Option<usize>,
+    ) -> RedisType {
+        match self.get_stream(key) {
+            Ok(Some(stream)) => {
+                let entries = stream
+                    .range(start, end)
+                    .take(count.unwrap_or(usize::MAX))
+                    .map(|data| (&data).into())
+                    .collect();
+                RedisType::Array(entries)
+            }
+            Ok(None) => RedisType::Array(vec![]),
+            Err(e) => e,
         }
     }
 
+    //Entries after each ID of its stream, streams without any are left out
     pub fn get_x_read(
         &self,
-        keys: &Vec<&String>,
-        ids: &Vec<(u64, u64)>,
+        keys: &[&String],
+        ids: &[StreamId],
         count: Option<usize>,
     ) -> RedisType {
         let mut result_vec = vec![];
-        let k_ids: Vec<(&&String, &(u64, u64))> = keys.iter().zip(ids.iter()).collect();
-        let count = count.unwrap_or(usize::MAX);
-        for (key, id) in k_ids {
-            let value = self.memory.get(*key);
-            let mut this_key_count = 0;
-            match value {
-                Some(value) => match &value.value {
-                    ValueType::Stream(stream) => {
-                        let mut inner_vec = vec![];
-                        inner_vec.push(RedisType::BulkString((*key).clone()));
-                        let mut inner_inner_vec = vec![];
-                        let (first_id, second_id) = id;
-                        for data in stream {
-                            let (first, second) = data.id;
-                            if first >= *first_id && second > *second_id {
-                                this_key_count += 1;
-                                inner_inner_vec.push(data.into());
-                            }
-                            if this_key_count >= count {
-                                break;
-                            }
-                        }
-                        if !inner_inner_vec.is_empty() {
-                            inner_vec.push(RedisType::Array(inner_inner_vec));
-                            result_vec.push(RedisType::Array(inner_vec));
-                        }
-                    }
-                    _ => {}
-                },
-                None => {}
+        for (key, id) in keys.iter().zip(ids) {
+            let stream = match self.get_stream(key) {
+                Ok(Some(stream)) => stream,
+                _ => continue,
+            };
+            let entries = stream.after(*id, count);
+            if !entries.is_empty() {
+                let entries = entries.iter().map(|data| data.into()).collect();
+                result_vec.push(RedisType::Array(vec![
+                    RedisType::BulkString((*key).clone()),
+                    RedisType::Array(entries),
+                ]));
             }
         }
         if result_vec.is_empty() {
@@ -150,6 +194,7 @@ impl<S: RWStream> Redis<S> {
     }
 
     pub fn get_mut(&mut self, key: &str) -> Option<&mut ValueType> {
+        self.expire_if_needed(key);
         match self.memory.get_mut(key) {
             Some(value) => {
                 if value.is_expired() {
@@ -162,8 +207,29 @@ impl<S: RWStream> Redis<S> {
         }
     }
 
+    //The stream at the key, the error to reply with if the key holds something else
+    pub fn get_stream(&self, key: &str) -> Result<Option<&Stream>, RedisType> {
+        match self.get_value(key) {
+            Some(ValueType::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(wrong_type()),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_stream_mut(&mut self, key: &str) -> Result<Option<&mut Stream>, RedisType> {
+        match self.get_mut(key) {
+            Some(ValueType::Stream(stream)) => Ok(Some(stream)),
+            Some(_) => Err(wrong_type()),
+            None => Ok(None),
+        }
+    }
+
     pub fn delete(&mut self, key: &str) -> bool {
-        self.memory.remove(key);
+        self.expire_if_needed(key);
+        if self.memory.remove(key).is_some() {
+            self.touch(key);
+            self.blocking.wake(key);
+        }
         self.keys.remove(key)
     }
 
@@ -180,94 +246,87 @@ impl<S: RWStream> Redis<S> {
             })
             .collect();
         for key in expired_keys {
-            self.memory.remove(&key);
-            self.keys.remove(&key);
+            self.expire(&key);
         }
     }
 
+    //Writes remove the expired key they look up, as Redis does
+    fn expire_if_needed(&mut self, key: &str) {
+        if self.memory.get(key).is_some_and(|value| value.is_expired()) {
+            self.expire(key);
+        }
+    }
+
+    fn expire(&mut self, key: &str) {
+        self.memory.remove(key);
+        self.touch(key);
+        self.blocking.wake(key);
+        self.keys.remove(key);
+        self.notify(Event::Expired, "expired", key);
+    }
+
     pub fn replication_info(&self) -> String {
-        self.replication.to_string()
+        let mut info = self.replication.to_string();
+        if let Some(good_replicas) = self.good_replicas() {
+            info.push_str(&format!("\nmin_slaves_good_slaves:{}", good_replicas));
+        }
+        info
+    }
+
+    //None unless min-replicas-to-write is enabled
+    pub fn good_replicas(&self) -> Option<usize> {
+        if self.config.min_replicas_to_write == 0 || self.config.min_replicas_max_lag == 0 {
+            return None;
+        }
+        Some(
+            self.replication
+                .good_replicas(self.config.min_replicas_max_lag),
+        )
     }
 
     pub fn is_master(&self) -> bool {
         self.replication.role == Role::Master
     }
 
-    pub async fn hand_shake(&self) -> Option<BufReader<TcpStream>> {
-        if let Some((host, port)) = &self.replication.replica_of {
-            //PING
-            let stream = TcpStream::connect((host.clone(), *port))
-                .await
-                .expect("Failed to connect to master");
-            let mut stream = BufReader::new(stream);
-            let ping_command = RedisType::Array(vec![RedisType::BulkString("PING".to_string())]);
-            let ping_command = ping_command.encode();
-            stream
-                .write_all(&ping_command)
-                .await
-                .expect("Failed to write to master");
-            let mut buffer = [0; 128];
-            stream
-                .read(&mut buffer)
-                .await
-                .expect("Failed to read from master");
-
-            //REPLCONF listening-port
-            let command = RedisType::Array(vec![
-                RedisType::BulkString("REPLCONF".to_string()),
-                RedisType::BulkString("listening-port".to_string()),
-                RedisType::BulkString(self.config.port.to_string()),
-            ]);
-            let command = command.encode();
-            stream
-                .write_all(&command)
-                .await
-                .expect("Failed to write to master");
-
-            let mut buffer = [0; 128];
-            stream
-                .read(&mut buffer)
-                .await
-                .expect("Failed to read from master");
-
-            //REPLCONF capa psync2
-            let command = RedisType::Array(vec![
-                RedisType::BulkString("REPLCONF".to_string()),
-                RedisType::BulkString("capa".to_string()),
-                RedisType::BulkString("psync2".to_string()),
-            ]);
-            let command = command.encode();
-            stream
-                .write_all(&command)
-                .await
-                .expect("Failed to write to master");
-
-            let mut buffer = [0; 128];
-            stream
-                .read(&mut buffer)
-                .await
-                .expect("Failed to read from master");
-
-            //PSYNC
-            let command = RedisType::Array(vec![
-                RedisType::BulkString("PSYNC".to_string()),
-                RedisType::BulkString("?".to_string()),
-                RedisType::BulkString("-1".to_string()),
-            ]);
-            let command = command.encode();
-            stream
-                .write_all(&command)
-                .await
-                .expect("Failed to write to master");
-
-            Some(stream)
-        } else {
-            None
-        }
+    pub fn has_persistence(&self) -> bool {
+        self.config.dir.is_some() && self.config.db_file_name.is_some()
     }
 
-    pub fn rdb_file_bytes(&self) -> Vec<u8> {
-        self.gen_rdb_file()
+    pub fn rdb_path(&self) -> String {
+        let dir = self.config.dir.as_deref().unwrap_or(".");
+        let file = self.config.db_file_name.as_deref().unwrap_or("dump.rdb");
+        format!("{}/{}", dir, file)
+    }
+
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let temp_path = self.temp_rdb_path();
+        std::fs::write(&temp_path, rdb::encode(&self.memory))?;
+        let snapshot = (
+            self.replication.master_replid.clone(),
+            self.replication.master_repl_offset,
+        );
+        self.install_rdb(&temp_path, snapshot)
+    }
+
+    //Saves write to a temp file first so a crash never leaves a truncated dump behind
+    pub fn temp_rdb_path(&self) -> String {
+        let dir = self.config.dir.as_deref().unwrap_or(".");
+        //Saves made without the lock can run at the same time
+        let suffix = crate::util::gen_rand_string(8);
+        format!("{}/temp-{}-{}.rdb", dir, std::process::id(), suffix)
+    }
+
+    //`snapshot` is the replid and offset the file was taken at
+    pub fn install_rdb(&mut self, temp_path: &str, snapshot: (String, u64)) -> std::io::Result<()> {
+        std::fs::rename(temp_path, self.rdb_path())?;
+        //The file can be sent to replicas that need a full sync
+        self.replication.rdb_snapshot = Some(snapshot);
+        Ok(())
+    }
+
+    //Copy of the dataset so the RDB file can be generated without holding the lock
+    pub fn snapshot(&self) -> HashMap<String, Value> {
+        self.memory.clone()
     }
 
     pub fn get_keys(&self) -> RedisType {
@@ -278,17 +337,6 @@ impl<S: RWStream> Redis<S> {
         RedisType::Array(arr)
     }
 
-    fn gen_rdb_file(&self) -> Vec<u8> {
-        let file = vec![
-            82, 69, 68, 73, 83, 48, 48, 48, 57, 250, 9, 114, 101, 100, 105, 115, 45, 118, 101, 114,
-            6, 54, 46, 48, 46, 49, 54, 250, 10, 114, 101, 100, 105, 115, 45, 98, 105, 116, 115,
-            192, 64, 250, 5, 99, 116, 105, 109, 101, 194, 31, 251, 241, 101, 250, 8, 117, 115, 101,
-            100, 45, 109, 101, 109, 194, 240, 90, 12, 0, 250, 12, 97, 111, 102, 45, 112, 114, 101,
-            97, 109, 98, 108, 101, 192, 0, 255, 245, 146, 4, 246, 233, 241, 232, 164,
-        ];
-        file
-    }
-
     fn re_config(&mut self, dir: &str, file: &str) {
         let path = format!("{}/{}", dir, file);
         let file = std::fs::read(&path);
@@ -300,121 +348,62 @@ impl<S: RWStream> Redis<S> {
                 return;
             }
         };
-        let mut file = Bytes::from(file);
-
-        let file_len = file.len();
-        let magic_number: [u8; 5] = file.get(0..5).unwrap().try_into().unwrap();
-        let version: [u8; 4] = file.get(5..9).unwrap().try_into().unwrap();
-
-        let mut _oxfbi = 0;
-        for i in 9..file_len {
-            if file[i] == 0xFE && file[i + 1] == 0x00 && file[i + 2] == 0xFB {
-                _oxfbi = i + 2;
-                break;
+        let file = match rdb::decode(&file) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Failed to load file:{}", path);
+                println!("Error:{}", e);
+                return;
             }
-        }
-        if _oxfbi == 0 {
+        };
+        //A corrupted file isn't loaded, like the master link and rdb-check refuse it
+        if let Checksum::Invalid { stored, computed } = file.checksum {
+            println!(
+                "Wrong RDB checksum expected: ({:#x}) got: ({:#x})",
+                stored, computed
+            );
             return;
         }
-        let _ = file.split_to(_oxfbi);
-        //Ignore the 0xFB byte
-        let _ = file.get_u8();
-
-        let length = file.get_u8();
-        let table_length = encode_length(&mut file, length);
-
-        let length = file.get_u8();
-        let table_expiry_length = encode_length(&mut file, length);
+        self.load(file);
+    }
 
-        self.magic_number = magic_number;
-        self.version = version;
-        self.table_size = table_length;
-        self.expiry_size = table_expiry_length;
+    //Replaces the whole dataset with the one in the RDB file
+    pub fn load(&mut self, file: RdbFile) {
+        self.magic_number = file.magic_number;
+        self.version = file.version;
+        self.table_size = 0;
+        self.expiry_size = 0;
 
         let mut new_memory = HashMap::new();
         let mut new_keys = HashSet::new();
 
-        for _ in 0..table_length {
-            let mut value_type = file.get_u8();
-            let mut expiration = None;
-            if value_type == 0xFD {
-                let mut arr = vec![];
-                for _ in 0..4 {
-                    arr.push(file.get_u8());
-                }
-                let dur = u64::from_le_bytes(arr.try_into().unwrap());
-                expiration = Some(UNIX_EPOCH + Duration::from_secs(dur));
-
-                value_type = file.get_u8();
-            } else if value_type == 0xFC {
-                let mut arr = vec![];
-                for _ in 0..8 {
-                    arr.push(file.get_u8());
-                }
-                let dur = u64::from_le_bytes(arr.try_into().unwrap());
-                let dur = dur / 1000;
-                expiration = Some(UNIX_EPOCH + Duration::from_secs(dur));
-
-                value_type = file.get_u8();
-            }
-            if value_type == 0 {
-                let key = encode_string(&mut file).unwrap();
-                let value = encode_string(&mut file).unwrap();
-                let value = ValueType::String(value);
-                new_memory.insert(key.clone(), Value::new_with_expiration(value, expiration));
-                new_keys.insert(key);
-            } else if value_type == 15 {
-                let key = encode_string(&mut file).unwrap();
-                println!("key:{}", key);
-                //TODO:Handle stream
+        for database in file.databases {
+            self.table_size += database.table_size;
+            self.expiry_size += database.expiry_size;
+            for entry in database.entries {
+                let expiration = entry
+                    .expires_at
+                    .map(|millis| UNIX_EPOCH + Duration::from_millis(millis));
+                let value = Value::new_with_expiration(entry.value, expiration);
+                new_memory.insert(entry.key.clone(), value);
+                new_keys.insert(entry.key);
             }
         }
 
+        let old_keys = std::mem::replace(&mut self.keys, new_keys);
         self.memory = new_memory;
-        self.keys = new_keys;
-    }
-}
-
-fn read_length(file: &mut Bytes) -> u64 {
-    let length = file.get_u8();
-    encode_length(file, length)
-}
-
-//See:https://rdb.fnordig.de/file_format.html#length-encoded
-fn encode_length(file: &mut Bytes, length: u8) -> u64 {
-    let mut bytes = vec![length];
-    let enc_type = (bytes[0] & 0xC0) >> 6;
-    match enc_type {
-        0..=3 => {
-            return bytes[0] as u64 & 0x3F;
-        }
-        14 => {
-            bytes.push(file.get_u8());
-            let init_b = bytes[0] as u64 & 0x3F;
-            let init_b = init_b << 8;
-            let second_b = bytes[1] as u64;
-            return init_b | second_b;
+        //Every key, old or new, may have changed
+        let changed: Vec<String> = old_keys.into_iter().chain(self.keys.clone()).collect();
+        for key in changed {
+            self.touch(&key);
         }
-        0x80 => {
-            let bytes = file.split_to(4).to_vec();
-            return u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as u64;
-        }
-        0x81 => {
-            println!("0x81:{}", 0x81);
-            let bytes = file.split_to(8).to_vec();
-            return u64::from_be_bytes([
-                bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7],
-            ]);
-        }
-        _ => panic!("Invalid encoding type"),
     }
 }
 
-//See:https://rdb.fnordig.de/file_format.html#string-encoding
-fn encode_string(file: &mut Bytes) -> Result<String, string::FromUtf8Error> {
-    let key_length = read_length(file);
-    let i = file.split_to(key_length.try_into().unwrap()).to_vec();
-    String::from_utf8(i)
+fn wrong_type() -> RedisType {
+    RedisType::SimpleError(
+        "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+    )
 }
 
 impl<S: RWStream> Default for Redis<S> {
@@ -426,8 +415,14 @@ impl<S: RWStream> Default for Redis<S> {
             expiry_size: 0,
             memory: HashMap::new(),
             keys: HashSet::new(),
+            versions: HashMap::new(),
+            last_version: 0,
+            watchers: HashMap::new(),
             replication: Replication::new(None),
             config: Config::default(),
+            shutdown: Arc::new(Shutdown::default()),
+            pubsub: PubSub::default(),
+            blocking: Blocking::default(),
         }
     }
 }
@@ -437,6 +432,8 @@ mod tests {
     use super::*;
     use tokio_test::io::Mock;
 
+    use self::value::stream::StreamData;
+
     #[test]
     fn test_create_from_file() {
         let file = vec![
@@ -456,10 +453,68 @@ mod tests {
             dir: Some(".".to_string()),
             port: 6379,
             replica_of: None,
+            ..Default::default()
         };
 
         let redis: Redis<Mock> = Redis::new(config);
         let keys = redis.keys.len();
         assert_eq!(keys, 3);
     }
+
+    #[test]
+    fn test_create_from_file_bad_checksum() {
+        let mut file = vec![
+            82, 69, 68, 73, 83, 48, 48, 48, 51, 250, 9, 114, 101, 100, 105, 115, 45, 118, 101, 114,
+            5, 55, 46, 50, 46, 48, 250, 10, 114, 101, 100, 105, 115, 45, 98, 105, 116, 115, 192,
+            64, 254, 0, 251, 3, 3, 252, 0, 156, 239, 18, 126, 1, 0, 0, 0, 9, 98, 108, 117, 101, 98,
+            101, 114, 114, 121, 4, 112, 101, 97, 114, 252, 0, 12, 40, 138, 199, 1, 0, 0, 0, 4, 112,
+            101, 97, 114, 9, 112, 105, 110, 101, 97, 112, 112, 108, 101, 252, 0, 12, 40, 138, 199,
+            1, 0, 0, 0, 5, 103, 114, 97, 112, 101, 9, 98, 108, 117, 101, 98, 101, 114, 114, 121,
+            255, 76, 205, 60, 203, 238, 60, 229, 217, 10,
+        ];
+        //Version 11 has a checksum, the bytes at the end don't match it
+        file[5..9].copy_from_slice(b"0011");
+        let path = "bad-checksum.rdb";
+        std::fs::write(path, file).expect("Failed to write file");
+        let config = Config {
+            db_file_name: Some(path.to_string()),
+            dir: Some(".".to_string()),
+            ..Default::default()
+        };
+
+        let redis: Redis<Mock> = Redis::new(config);
+        std::fs::remove_file(path).unwrap();
+        assert!(redis.keys.is_empty());
+    }
+
+    #[test]
+    fn test_get_x_read() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let entries = [(4, 3), (5, 0), (5, 1)]
+            .into_iter()
+            .map(|(ms, seq)| StreamData {
+                id: StreamId::new(ms, seq),
+                fields: vec![],
+            })
+            .collect::<Vec<_>>();
+        redis.set(
+            "s".to_string(),
+            ValueType::Stream(Stream::from(entries)),
+            None,
+        );
+        let key = "s".to_string();
+
+        //5-0 has a smaller sequence but a greater time
+        let response = redis.get_x_read(&[&key], &[StreamId::new(4, 3)], Some(1));
+        let expected = RedisType::Array(vec![RedisType::Array(vec![
+            RedisType::BulkString("s".to_string()),
+            RedisType::Array(vec![RedisType::Array(vec![
+                RedisType::BulkString("5-0".to_string()),
+                RedisType::Array(vec![]),
+            ])]),
+        ])]);
+        assert_eq!(response, expected);
+        let response = redis.get_x_read(&[&key], &[StreamId::new(5, 1)], None);
+        assert_eq!(response, RedisType::NullArray);
+    }
 }
@@ -0,0 +1,226 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use super::{replication::RWStream, Redis};
+
+/*
+ Classes of the keyspace events this server produces, each one has its flag letter
+ in notify-keyspace-events. The letters of the other types are accepted too.
+*/
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Event {
+    Generic,
+    String,
+    Expired,
+    Stream,
+    New,
+}
+
+impl Event {
+    fn flag(&self) -> u16 {
+        match self {
+            Event::Generic => GENERIC,
+            Event::String => STRING,
+            Event::Expired => EXPIRED,
+            Event::Stream => STREAM,
+            Event::New => NEW,
+        }
+    }
+}
+
+const KEYSPACE: u16 = 1 << 0;
+const KEYEVENT: u16 = 1 << 1;
+const GENERIC: u16 = 1 << 2;
+const STRING: u16 = 1 << 3;
+const LIST: u16 = 1 << 4;
+const SET: u16 = 1 << 5;
+const HASH: u16 = 1 << 6;
+const ZSET: u16 = 1 << 7;
+const EXPIRED: u16 = 1 << 8;
+const EVICTED: u16 = 1 << 9;
+const STREAM: u16 = 1 << 10;
+const KEY_MISS: u16 = 1 << 11;
+const MODULE: u16 = 1 << 12;
+const NEW: u16 = 1 << 13;
+//"A" is an alias for these, key misses and new keys have to be asked for apart
+const ALL: u16 = GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM | MODULE;
+
+//Letters in the order Redis prints them
+const LETTERS: [(char, u16); 14] = [
+    ('g', GENERIC),
+    ('$', STRING),
+    ('l', LIST),
+    ('s', SET),
+    ('h', HASH),
+    ('z', ZSET),
+    ('x', EXPIRED),
+    ('e', EVICTED),
+    ('t', STREAM),
+    ('d', MODULE),
+    ('K', KEYSPACE),
+    ('E', KEYEVENT),
+    ('m', KEY_MISS),
+    ('n', NEW),
+];
+
+//The notify-keyspace-events setting, nothing is published by default
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct NotifyFlags(u16);
+
+impl NotifyFlags {
+    //An event is published when its class and at least one of K and E are set
+    pub fn enabled(&self, event: Event) -> bool {
+        self.0 & (KEYSPACE | KEYEVENT) != 0 && self.0 & event.flag() != 0
+    }
+}
+
+impl FromStr for NotifyFlags {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = 0;
+        for c in s.chars() {
+            flags |= match c {
+                'A' => ALL,
+                c => LETTERS
+                    .iter()
+                    .find(|(letter, _)| *letter == c)
+                    .map(|(_, flag)| *flag)
+                    .ok_or(())?,
+            };
+        }
+        Ok(NotifyFlags(flags))
+    }
+}
+
+impl Display for NotifyFlags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut letters = String::new();
+        if self.0 & ALL == ALL {
+            letters.push('A');
+        }
+        for (letter, flag) in LETTERS {
+            let in_alias = flag & ALL != 0 && letters.starts_with('A');
+            if self.0 & flag != 0 && !in_alias {
+                letters.push(letter);
+            }
+        }
+        write!(f, "{}", letters)
+    }
+}
+
+impl<S: RWStream> Redis<S> {
+    /*
+     Publishes the event to __keyspace@0__:<key> (the message is the event name) and
+     to __keyevent@0__:<event> (the message is the key), as the flags allow.
+    */
+    pub fn notify(&mut self, event: Event, name: &str, key: &str) {
+        let flags = self.config.notify_keyspace_events;
+        if !flags.enabled(event) {
+            return;
+        }
+        if flags.0 & KEYSPACE != 0 {
+            let channel = format!("__keyspace@0__:{}", key);
+            self.pubsub.publish(&channel, name);
+        }
+        if flags.0 & KEYEVENT != 0 {
+            let channel = format!("__keyevent@0__:{}", name);
+            self.pubsub.publish(&channel, key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::mpsc::unbounded_channel;
+    use tokio_test::io::Mock;
+
+    use crate::redis::{config::Config, types::RedisType, value::ValueType, Redis};
+
+    use super::{Event, NotifyFlags};
+
+    #[test]
+    fn test_flags() {
+        let flags: NotifyFlags = "Kx".parse().unwrap();
+        assert!(flags.enabled(Event::Expired));
+        assert!(!flags.enabled(Event::Generic));
+        //No K nor E, nothing is published
+        let flags: NotifyFlags = "gx".parse().unwrap();
+        assert!(!flags.enabled(Event::Expired));
+
+        let flags: NotifyFlags = "EKtgx$lshzed".parse().unwrap();
+        assert_eq!(flags.to_string(), "AKE");
+        let flags: NotifyFlags = "AmnK".parse().unwrap();
+        assert_eq!(flags.to_string(), "AKmn");
+        assert!(flags.enabled(Event::New));
+        let flags: NotifyFlags = "E$g".parse().unwrap();
+        assert_eq!(flags.to_string(), "g$E");
+        assert_eq!(NotifyFlags::default().to_string(), "");
+        assert!("Kq".parse::<NotifyFlags>().is_err());
+    }
+
+    fn message(channel: &str, message: &str) -> RedisType {
+        RedisType::Array(vec![
+            RedisType::BulkString("message".to_string()),
+            RedisType::BulkString(channel.to_string()),
+            RedisType::BulkString(message.to_string()),
+        ])
+    }
+
+    #[test]
+    fn test_notify_expired() {
+        let config = Config {
+            notify_keyspace_events: "KEx".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        let (subscriber, mut messages) = unbounded_channel();
+        redis
+            .pubsub
+            .subscribe("__keyspace@0__:session".to_string(), &subscriber);
+        redis
+            .pubsub
+            .subscribe("__keyevent@0__:expired".to_string(), &subscriber);
+
+        let value = ValueType::String("1".to_string());
+        redis.set("session".to_string(), value, Some(10));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        redis.expire_keys();
+        assert_eq!(
+            messages.try_recv(),
+            Ok(message("__keyspace@0__:session", "expired"))
+        );
+        assert_eq!(
+            messages.try_recv(),
+            Ok(message("__keyevent@0__:expired", "session"))
+        );
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_notify_lazy_expired() {
+        let config = Config {
+            notify_keyspace_events: "Ex".parse().unwrap(),
+            ..Default::default()
+        };
+        let mut redis: Redis<Mock> = Redis::new(config);
+        let (subscriber, mut messages) = unbounded_channel();
+        redis
+            .pubsub
+            .subscribe("__keyevent@0__:expired".to_string(), &subscriber);
+
+        //A write looking up the expired key removes it
+        let value = ValueType::String("1".to_string());
+        redis.set("session".to_string(), value, Some(10));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        assert!(!redis.delete("session"));
+        assert_eq!(
+            messages.try_recv(),
+            Ok(message("__keyevent@0__:expired", "session"))
+        );
+        redis.expire_keys();
+        assert!(messages.try_recv().is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::util::glob_match;
+
+use super::types::RedisType;
+
+//A subscribed connection, messages are queued so PUBLISH never waits on its socket
+pub type Subscriber = UnboundedSender<RedisType>;
+
+//Who listens to what, the connections keep track of their own subscriptions
+#[derive(Debug, Default)]
+pub struct PubSub {
+    channels: HashMap<String, Vec<Subscriber>>,
+    patterns: HashMap<String, Vec<Subscriber>>,
+    //Shard channels are a namespace of their own, SPUBLISH only reaches SSUBSCRIBE
+    shard_channels: HashMap<String, Vec<Subscriber>>,
+}
+
+impl PubSub {
+    pub fn subscribe(&mut self, channel: String, subscriber: &Subscriber) {
+        add(&mut self.channels, channel, subscriber);
+    }
+
+    pub fn unsubscribe(&mut self, channel: &str, subscriber: &Subscriber) {
+        remove(&mut self.channels, channel, subscriber);
+    }
+
+    pub fn psubscribe(&mut self, pattern: String, subscriber: &Subscriber) {
+        add(&mut self.patterns, pattern, subscriber);
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &str, subscriber: &Subscriber) {
+        remove(&mut self.patterns, pattern, subscriber);
+    }
+
+    pub fn ssubscribe(&mut self, channel: String, subscriber: &Subscriber) {
+        add(&mut self.shard_channels, channel, subscriber);
+    }
+
+    pub fn sunsubscribe(&mut self, channel: &str, subscriber: &Subscriber) {
+        remove(&mut self.shard_channels, channel, subscriber);
+    }
+
+    /*
+     Returns the number of deliveries, a client subscribed to the channel and to a
+     matching pattern gets the message twice and counts twice.
+    */
+    pub fn publish(&mut self, channel: &str, message: &str) -> usize {
+        let mut count = 0;
+        if let Some(subscribers) = self.channels.get_mut(channel) {
+            let frame = RedisType::Array(vec![
+                RedisType::BulkString("message".to_string()),
+                RedisType::BulkString(channel.to_string()),
+                RedisType::BulkString(message.to_string()),
+            ]);
+            count += send(subscribers, &frame);
+            if subscribers.is_empty() {
+                self.channels.remove(channel);
+            }
+        }
+
+        let mut emptied = vec![];
+        for (pattern, subscribers) in self.patterns.iter_mut() {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            let frame = RedisType::Array(vec![
+                RedisType::BulkString("pmessage".to_string()),
+                RedisType::BulkString(pattern.clone()),
+                RedisType::BulkString(channel.to_string()),
+                RedisType::BulkString(message.to_string()),
+            ]);
+            count += send(subscribers, &frame);
+            if subscribers.is_empty() {
+                emptied.push(pattern.clone());
+            }
+        }
+        for pattern in emptied {
+            self.patterns.remove(&pattern);
+        }
+        count
+    }
+
+    //This node owns every shard, so the message stays here
+    pub fn spublish(&mut self, channel: &str, message: &str) -> usize {
+        let subscribers = match self.shard_channels.get_mut(channel) {
+            Some(subscribers) => subscribers,
+            None => return 0,
+        };
+        let frame = RedisType::Array(vec![
+            RedisType::BulkString("smessage".to_string()),
+            RedisType::BulkString(channel.to_string()),
+            RedisType::BulkString(message.to_string()),
+        ]);
+        let count = send(subscribers, &frame);
+        if count == 0 {
+            self.shard_channels.remove(channel);
+        }
+        count
+    }
+
+    //Channels with at least one subscriber, sorted, the ones matching the pattern if any
+    pub fn channels(&self, pattern: Option<&str>) -> Vec<String> {
+        active(&self.channels, pattern)
+    }
+
+    pub fn shard_channels(&self, pattern: Option<&str>) -> Vec<String> {
+        active(&self.shard_channels, pattern)
+    }
+
+    pub fn numsub(&self, channel: &str) -> usize {
+        self.channels
+            .get(channel)
+            .map_or(0, |subscribers| subscribers.len())
+    }
+
+    pub fn shard_numsub(&self, channel: &str) -> usize {
+        self.shard_channels
+            .get(channel)
+            .map_or(0, |subscribers| subscribers.len())
+    }
+
+    //Distinct patterns, not pattern subscriptions
+    pub fn numpat(&self) -> usize {
+        self.patterns.len()
+    }
+}
+
+fn active(map: &HashMap<String, Vec<Subscriber>>, pattern: Option<&str>) -> Vec<String> {
+    let mut channels: Vec<String> = map
+        .iter()
+        .filter(|(channel, subscribers)| {
+            !subscribers.is_empty() && pattern.is_none_or(|pattern| glob_match(pattern, channel))
+        })
+        .map(|(channel, _)| channel.clone())
+        .collect();
+    channels.sort();
+    channels
+}
+
+fn add(map: &mut HashMap<String, Vec<Subscriber>>, name: String, subscriber: &Subscriber) {
+    let subscribers = map.entry(name).or_default();
+    if !subscribers.iter().any(|s| s.same_channel(subscriber)) {
+        subscribers.push(subscriber.clone());
+    }
+}
+
+fn remove(map: &mut HashMap<String, Vec<Subscriber>>, name: &str, subscriber: &Subscriber) {
+    if let Some(subscribers) = map.get_mut(name) {
+        subscribers.retain(|s| !s.same_channel(subscriber));
+        if subscribers.is_empty() {
+            map.remove(name);
+        }
+    }
+}
+
+//Connections that are gone are dropped here
+fn send(subscribers: &mut Vec<Subscriber>, frame: &RedisType) -> usize {
+    subscribers.retain(|subscriber| subscriber.send(frame.clone()).is_ok());
+    subscribers.len()
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use crate::redis::types::RedisType;
+
+    use super::PubSub;
+
+    fn frame(values: &[&str]) -> RedisType {
+        RedisType::Array(
+            values
+                .iter()
+                .map(|value| RedisType::BulkString(value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_publish() {
+        let mut pubsub = PubSub::default();
+        let (subscriber, mut messages) = unbounded_channel();
+        pubsub.subscribe("news".to_string(), &subscriber);
+        pubsub.subscribe("news".to_string(), &subscriber);
+        pubsub.subscribe("sports".to_string(), &subscriber);
+
+        assert_eq!(pubsub.publish("news", "hello"), 1);
+        assert_eq!(pubsub.publish("weather", "sunny"), 0);
+        assert_eq!(
+            messages.try_recv(),
+            Ok(frame(&["message", "news", "hello"]))
+        );
+
+        pubsub.unsubscribe("news", &subscriber);
+        assert_eq!(pubsub.publish("news", "hello"), 0);
+
+        drop(messages);
+        assert_eq!(pubsub.publish("sports", "goal"), 0);
+        assert!(pubsub.channels.is_empty());
+    }
+
+    #[test]
+    fn test_publish_patterns() {
+        let mut pubsub = PubSub::default();
+        let (subscriber, mut messages) = unbounded_channel();
+        let (other, mut other_messages) = unbounded_channel();
+        pubsub.psubscribe("news.*".to_string(), &subscriber);
+        pubsub.subscribe("news.tech".to_string(), &subscriber);
+        pubsub.psubscribe("*".to_string(), &other);
+
+        //Once for the channel and once for the pattern
+        assert_eq!(pubsub.publish("news.tech", "rust"), 3);
+        assert_eq!(
+            messages.try_recv(),
+            Ok(frame(&["message", "news.tech", "rust"]))
+        );
+        assert_eq!(
+            messages.try_recv(),
+            Ok(frame(&["pmessage", "news.*", "news.tech", "rust"]))
+        );
+        assert_eq!(
+            other_messages.try_recv(),
+            Ok(frame(&["pmessage", "*", "news.tech", "rust"]))
+        );
+
+        pubsub.punsubscribe("news.*", &subscriber);
+        assert_eq!(pubsub.publish("news.sports", "goal"), 1);
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_introspection() {
+        let mut pubsub = PubSub::default();
+        let (subscriber, mut messages) = unbounded_channel();
+        let (other, _other_messages) = unbounded_channel();
+        pubsub.subscribe("news.tech".to_string(), &subscriber);
+        pubsub.subscribe("news.tech".to_string(), &other);
+        pubsub.subscribe("weather".to_string(), &other);
+        pubsub.psubscribe("news.*".to_string(), &subscriber);
+        pubsub.psubscribe("news.*".to_string(), &other);
+        pubsub.ssubscribe("orders".to_string(), &subscriber);
+
+        assert_eq!(pubsub.channels(None), vec!["news.tech", "weather"]);
+        assert_eq!(pubsub.channels(Some("news.*")), vec!["news.tech"]);
+        assert_eq!(pubsub.numsub("news.tech"), 2);
+        assert_eq!(pubsub.numsub("sports"), 0);
+        assert_eq!(pubsub.numpat(), 1);
+        assert_eq!(pubsub.shard_channels(None), vec!["orders"]);
+        assert_eq!(pubsub.shard_numsub("orders"), 1);
+
+        //Shard messages don't reach the other namespaces and the other way around
+        assert_eq!(pubsub.publish("orders", "1"), 0);
+        assert_eq!(pubsub.spublish("orders", "1"), 1);
+        assert_eq!(messages.try_recv(), Ok(frame(&["smessage", "orders", "1"])));
+        assert_eq!(pubsub.spublish("news.tech", "1"), 0);
+
+        pubsub.sunsubscribe("orders", &subscriber);
+        assert!(pubsub.shard_channels(None).is_empty());
+    }
+}
@@ -0,0 +1,27 @@
+//CRC-64/Jones (reflected), the checksum Redis appends to RDB files and DUMP payloads
+//See:https://github.com/redis/redis/blob/unstable/src/crc64.c
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+pub fn crc64(mut crc: u64, data: &[u8]) -> u64 {
+    for byte in data {
+        crc ^= *byte as u64;
+        for _ in 0..8 {
+            if crc & 1 == 1 {
+                crc = (crc >> 1) ^ POLY;
+            } else {
+                crc >>= 1;
+            }
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod test {
+    use super::crc64;
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+}
@@ -0,0 +1,253 @@
+use super::RdbError;
+
+const HEADER_SIZE: usize = 6;
+const EOF: u8 = 0xFF;
+//Number of elements is stored in a u16, bigger listpacks store this value instead
+const UNKNOWN_NUM_ELEMENTS: u16 = u16::MAX;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ListpackEntry {
+    Int(i64),
+    String(Vec<u8>),
+}
+
+impl ListpackEntry {
+    pub fn as_int(&self) -> Result<i64, RdbError> {
+        match self {
+            ListpackEntry::Int(i) => Ok(*i),
+            ListpackEntry::String(s) => std::str::from_utf8(s)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .ok_or(RdbError::InvalidListpack),
+        }
+    }
+
+    pub fn into_string(self) -> Result<String, RdbError> {
+        match self {
+            ListpackEntry::Int(i) => Ok(i.to_string()),
+            ListpackEntry::String(s) => String::from_utf8(s).map_err(|_| RdbError::InvalidString),
+        }
+    }
+}
+
+impl From<&str> for ListpackEntry {
+    fn from(value: &str) -> Self {
+        ListpackEntry::String(value.as_bytes().to_vec())
+    }
+}
+
+impl From<i64> for ListpackEntry {
+    fn from(value: i64) -> Self {
+        ListpackEntry::Int(value)
+    }
+}
+
+//See:https://github.com/antirez/listpack/blob/master/listpack.md
+pub fn decode(bytes: &[u8]) -> Result<Vec<ListpackEntry>, RdbError> {
+    if bytes.len() < HEADER_SIZE + 1 {
+        return Err(RdbError::InvalidListpack);
+    }
+    let total_bytes = u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    if total_bytes != bytes.len() {
+        return Err(RdbError::InvalidListpack);
+    }
+
+    let mut entries = vec![];
+    let mut i = HEADER_SIZE;
+    loop {
+        let encoding = *bytes.get(i).ok_or(RdbError::InvalidListpack)?;
+        if encoding == EOF {
+            break;
+        }
+        let (entry, size) = decode_entry(&bytes[i..])?;
+        entries.push(entry);
+        i += size + backlen_size(size);
+    }
+    Ok(entries)
+}
+
+pub fn encode(entries: &[ListpackEntry]) -> Vec<u8> {
+    let mut bytes = vec![0; HEADER_SIZE];
+    for entry in entries {
+        let start = bytes.len();
+        encode_entry(&mut bytes, entry);
+        let size = bytes.len() - start;
+        encode_backlen(&mut bytes, size);
+    }
+    bytes.push(EOF);
+
+    let total_bytes = bytes.len() as u32;
+    let num_elements = u16::try_from(entries.len())
+        .ok()
+        .filter(|n| *n < UNKNOWN_NUM_ELEMENTS)
+        .unwrap_or(UNKNOWN_NUM_ELEMENTS);
+    bytes[0..4].copy_from_slice(&total_bytes.to_le_bytes());
+    bytes[4..6].copy_from_slice(&num_elements.to_le_bytes());
+    bytes
+}
+
+//Returns the entry and the size of encoding + data (without the backlen)
+fn decode_entry(bytes: &[u8]) -> Result<(ListpackEntry, usize), RdbError> {
+    let get = |range: std::ops::Range<usize>| bytes.get(range).ok_or(RdbError::InvalidListpack);
+    let encoding = bytes[0];
+    if encoding & 0x80 == 0 {
+        //7 bit unsigned int
+        return Ok((ListpackEntry::Int((encoding & 0x7F) as i64), 1));
+    }
+    if encoding & 0xC0 == 0x80 {
+        //6 bit length string
+        let len = (encoding & 0x3F) as usize;
+        let s = get(1..1 + len)?;
+        return Ok((ListpackEntry::String(s.to_vec()), 1 + len));
+    }
+    if encoding & 0xE0 == 0xC0 {
+        //13 bit signed int
+        let next = get(1..2)?[0];
+        let value = (((encoding & 0x1F) as i64) << 8) | next as i64;
+        let value = if value >= 1 << 12 {
+            value - (1 << 13)
+        } else {
+            value
+        };
+        return Ok((ListpackEntry::Int(value), 2));
+    }
+    if encoding & 0xF0 == 0xE0 {
+        //12 bit length string
+        let next = get(1..2)?[0];
+        let len = (((encoding & 0x0F) as usize) << 8) | next as usize;
+        let s = get(2..2 + len)?;
+        return Ok((ListpackEntry::String(s.to_vec()), 2 + len));
+    }
+    match encoding {
+        0xF0 => {
+            //32 bit length string
+            let len = u32::from_le_bytes(get(1..5)?.try_into().unwrap()) as usize;
+            let s = get(5..5 + len)?;
+            Ok((ListpackEntry::String(s.to_vec()), 5 + len))
+        }
+        0xF1 => {
+            let value = i16::from_le_bytes(get(1..3)?.try_into().unwrap());
+            Ok((ListpackEntry::Int(value as i64), 3))
+        }
+        0xF2 => {
+            let b = get(1..4)?;
+            //Sign extend the 24 bit value
+            let value = i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8;
+            Ok((ListpackEntry::Int(value as i64), 4))
+        }
+        0xF3 => {
+            let value = i32::from_le_bytes(get(1..5)?.try_into().unwrap());
+            Ok((ListpackEntry::Int(value as i64), 5))
+        }
+        0xF4 => {
+            let value = i64::from_le_bytes(get(1..9)?.try_into().unwrap());
+            Ok((ListpackEntry::Int(value), 9))
+        }
+        _ => Err(RdbError::InvalidListpack),
+    }
+}
+
+fn encode_entry(bytes: &mut Vec<u8>, entry: &ListpackEntry) {
+    match entry {
+        ListpackEntry::Int(value) => {
+            let value = *value;
+            if (0..=127).contains(&value) {
+                bytes.push(value as u8);
+            } else if (-4096..=4095).contains(&value) {
+                let value = (value as u64) & 0x1FFF;
+                bytes.push(0xC0 | (value >> 8) as u8);
+                bytes.push(value as u8);
+            } else if i16::try_from(value).is_ok() {
+                bytes.push(0xF1);
+                bytes.extend_from_slice(&(value as i16).to_le_bytes());
+            } else if (-(1 << 23)..(1 << 23)).contains(&value) {
+                bytes.push(0xF2);
+                bytes.extend_from_slice(&(value as i32).to_le_bytes()[..3]);
+            } else if i32::try_from(value).is_ok() {
+                bytes.push(0xF3);
+                bytes.extend_from_slice(&(value as i32).to_le_bytes());
+            } else {
+                bytes.push(0xF4);
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        ListpackEntry::String(s) => {
+            let len = s.len();
+            if len < 1 << 6 {
+                bytes.push(0x80 | len as u8);
+            } else if len < 1 << 12 {
+                bytes.push(0xE0 | (len >> 8) as u8);
+                bytes.push(len as u8);
+            } else {
+                bytes.push(0xF0);
+                bytes.extend_from_slice(&(len as u32).to_le_bytes());
+            }
+            bytes.extend_from_slice(s);
+        }
+    }
+}
+
+fn backlen_size(size: usize) -> usize {
+    match size {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
+
+//The backlen is written so it can be parsed right to left, 7 bits per byte
+fn encode_backlen(bytes: &mut Vec<u8>, size: usize) {
+    let n = backlen_size(size);
+    for i in (0..n).rev() {
+        let byte = ((size >> (7 * i)) & 127) as u8;
+        if i == n - 1 {
+            bytes.push(byte);
+        } else {
+            bytes.push(byte | 128);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, ListpackEntry};
+
+    #[test]
+    fn test_encode_decode() {
+        let entries = vec![
+            ListpackEntry::Int(0),
+            ListpackEntry::Int(127),
+            ListpackEntry::Int(-1),
+            ListpackEntry::Int(4095),
+            ListpackEntry::Int(-4096),
+            ListpackEntry::Int(30000),
+            ListpackEntry::Int(-8_000_000),
+            ListpackEntry::Int(2_000_000_000),
+            ListpackEntry::Int(1_526_919_030_474),
+            ListpackEntry::from("field"),
+            ListpackEntry::String(vec![b'a'; 200]),
+            ListpackEntry::String(vec![b'b'; 5000]),
+        ];
+        let bytes = encode(&entries);
+        assert_eq!(
+            u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize,
+            bytes.len()
+        );
+        assert_eq!(u16::from_le_bytes(bytes[4..6].try_into().unwrap()), 12);
+        assert_eq!(decode(&bytes).unwrap(), entries);
+    }
+
+    #[test]
+    fn test_decode_redis_listpack() {
+        //Listpack with "a", 1024 as written by redis
+        let bytes = [13, 0, 0, 0, 2, 0, 0x81, b'a', 2, 0xC4, 0x00, 2, 0xFF];
+        let entries = decode(&bytes).unwrap();
+        assert_eq!(
+            entries,
+            vec![ListpackEntry::from("a"), ListpackEntry::Int(1024)]
+        );
+        assert_eq!(encode(&entries), bytes);
+    }
+}
@@ -0,0 +1,674 @@
+use std::{collections::HashMap, time::UNIX_EPOCH};
+
+use bytes::{Buf, Bytes};
+use thiserror::Error;
+
+use super::value::{
+    stream::{
+        entries::{FLAG_DELETED, FLAG_SAME_FIELDS},
+        ConsumerGroup, PendingEntry, Stream, StreamData, StreamId,
+    },
+    Value, ValueType,
+};
+
+pub mod crc64;
+pub mod listpack;
+pub mod stream;
+
+const MAGIC_NUMBER: &[u8; 5] = b"REDIS";
+const VERSION: &[u8; 4] = b"0011";
+//Same version, as written on the DUMP payload footer
+const RDB_VERSION: u16 = 11;
+
+const OP_FUNCTION2: u8 = 0xF5;
+const OP_MODULE_AUX: u8 = 0xF7;
+const OP_IDLE: u8 = 0xF8;
+const OP_FREQ: u8 = 0xF9;
+const OP_AUX: u8 = 0xFA;
+const OP_RESIZEDB: u8 = 0xFB;
+const OP_EXPIRETIME_MS: u8 = 0xFC;
+const OP_EXPIRETIME: u8 = 0xFD;
+const OP_SELECTDB: u8 = 0xFE;
+const OP_EOF: u8 = 0xFF;
+
+const TYPE_STRING: u8 = 0;
+
+const ENC_INT8: u8 = 0;
+const ENC_INT16: u8 = 1;
+const ENC_INT32: u8 = 2;
+const ENC_LZF: u8 = 3;
+
+//The longest back reference takes 3 bytes and copies 264
+const LZF_MAX_EXPANSION: usize = 88;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RdbError {
+    #[error("unexpected end of file")]
+    UnexpectedEof,
+    #[error("invalid magic number")]
+    InvalidMagicNumber,
+    #[error("invalid version number")]
+    InvalidVersion,
+    #[error("unsupported value type {0}")]
+    UnsupportedType(u8),
+    #[error("unsupported opcode {0:#x}")]
+    UnsupportedOpcode(u8),
+    #[error("invalid string encoding {0}")]
+    InvalidEncoding(u8),
+    #[error("invalid LZF compressed string")]
+    InvalidLzf,
+    #[error("string is not valid utf-8")]
+    InvalidString,
+    #[error("invalid listpack")]
+    InvalidListpack,
+    #[error("DUMP payload version or checksum are wrong")]
+    InvalidDumpPayload,
+    #[error("Bad data format")]
+    BadDataFormat,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Checksum {
+    //Files older than version 5 have no checksum
+    Missing,
+    //A zero checksum means the file was saved with "rdbchecksum no"
+    Disabled,
+    Valid(u64),
+    Invalid { stored: u64, computed: u64 },
+}
+
+#[derive(Debug)]
+pub struct Entry {
+    pub key: String,
+    pub value: ValueType,
+    //Unix time in milliseconds
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+#[allow(dead_code)]
+pub struct Database {
+    pub index: u64,
+    pub table_size: u64,
+    pub expiry_size: u64,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)]
+pub struct RdbFile {
+    pub magic_number: [u8; 5],
+    pub version: [u8; 4],
+    pub aux: Vec<(String, String)>,
+    pub databases: Vec<Database>,
+    pub checksum: Checksum,
+}
+
+impl RdbFile {
+    #[allow(dead_code)]
+    pub fn version_number(&self) -> u32 {
+        std::str::from_utf8(&self.version)
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+//See:https://rdb.fnordig.de/file_format.html
+pub fn decode(bytes: &[u8]) -> Result<RdbFile, RdbError> {
+    let mut file = Bytes::copy_from_slice(bytes);
+    let magic_number: [u8; 5] = read_bytes(&mut file, 5)?.as_ref().try_into().unwrap();
+    if &magic_number != MAGIC_NUMBER {
+        return Err(RdbError::InvalidMagicNumber);
+    }
+    let version: [u8; 4] = read_bytes(&mut file, 4)?.as_ref().try_into().unwrap();
+    let version_number: u32 = std::str::from_utf8(&version)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .ok_or(RdbError::InvalidVersion)?;
+
+    let mut aux = vec![];
+    let mut databases: Vec<Database> = vec![];
+    let mut expires_at = None;
+    loop {
+        let op_code = read_u8(&mut file)?;
+        match op_code {
+            OP_AUX => {
+                let key = read_string(&mut file)?;
+                let value = read_string(&mut file)?;
+                aux.push((key, value));
+            }
+            OP_SELECTDB => {
+                let index = read_length(&mut file)?;
+                databases.push(Database {
+                    index,
+                    ..Default::default()
+                });
+            }
+            OP_RESIZEDB => {
+                let table_size = read_length(&mut file)?;
+                let expiry_size = read_length(&mut file)?;
+                if databases.is_empty() {
+                    databases.push(Database::default());
+                }
+                let database = databases.last_mut().unwrap();
+                database.table_size = table_size;
+                database.expiry_size = expiry_size;
+            }
+            OP_EXPIRETIME_MS => {
+                let millis = read_bytes(&mut file, 8)?;
+                expires_at = Some(u64::from_le_bytes(millis.as_ref().try_into().unwrap()));
+            }
+            OP_EXPIRETIME => {
+                let secs = read_bytes(&mut file, 4)?;
+                let secs = u32::from_le_bytes(secs.as_ref().try_into().unwrap());
+                expires_at = Some(secs as u64 * 1000);
+            }
+            //LRU idle time and LFU frequency are not tracked, just skip them
+            OP_IDLE => {
+                read_length(&mut file)?;
+            }
+            OP_FREQ => {
+                read_u8(&mut file)?;
+            }
+            OP_FUNCTION2 | OP_MODULE_AUX => return Err(RdbError::UnsupportedOpcode(op_code)),
+            OP_EOF => break,
+            value_type => {
+                let key = read_string(&mut file)?;
+                let value = read_value(&mut file, value_type)?;
+                if databases.is_empty() {
+                    databases.push(Database::default());
+                }
+                databases.last_mut().unwrap().entries.push(Entry {
+                    key,
+                    value,
+                    expires_at: expires_at.take(),
+                });
+            }
+        }
+    }
+
+    let checksum = if version_number < 5 {
+        Checksum::Missing
+    } else {
+        let body_len = bytes.len() - file.remaining();
+        let stored = read_bytes(&mut file, 8)?;
+        let stored = u64::from_le_bytes(stored.as_ref().try_into().unwrap());
+        let computed = crc64::crc64(0, &bytes[..body_len]);
+        if stored == 0 {
+            Checksum::Disabled
+        } else if stored == computed {
+            Checksum::Valid(stored)
+        } else {
+            Checksum::Invalid { stored, computed }
+        }
+    };
+
+    Ok(RdbFile {
+        magic_number,
+        version,
+        aux,
+        databases,
+        checksum,
+    })
+}
+
+//See:https://rdb.fnordig.de/file_format.html
+pub fn encode(memory: &HashMap<String, Value>) -> Vec<u8> {
+    Encoder::new(memory, usize::MAX)
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+/*
+ Writes the RDB file in chunks of (at least) `chunk_size` bytes so it can be
+ sent while it is being generated, the checksum is computed as it goes.
+*/
+pub struct Encoder<'a> {
+    header: Option<Vec<u8>>,
+    entries: std::vec::IntoIter<(&'a String, &'a Value)>,
+    chunk_size: usize,
+    checksum: u64,
+    done: bool,
+}
+
+impl<'a> Encoder<'a> {
+    pub fn new(memory: &'a HashMap<String, Value>, chunk_size: usize) -> Self {
+        let mut header = vec![];
+        header.extend_from_slice(MAGIC_NUMBER);
+        header.extend_from_slice(VERSION);
+
+        header.push(OP_AUX);
+        write_string(&mut header, b"redis-ver");
+        write_string(&mut header, b"7.2.0");
+        header.push(OP_AUX);
+        write_string(&mut header, b"redis-bits");
+        //Integer encoded string (8 bit)
+        header.extend_from_slice(&[0xC0, 64]);
+
+        let entries: Vec<(&String, &Value)> = memory
+            .iter()
+            .filter(|(_, value)| !value.is_expired())
+            .collect();
+        let expiry_size = entries
+            .iter()
+            .filter(|(_, value)| value.expires_at.is_some())
+            .count();
+
+        header.push(OP_SELECTDB);
+        write_length(&mut header, 0);
+        header.push(OP_RESIZEDB);
+        write_length(&mut header, entries.len() as u64);
+        write_length(&mut header, expiry_size as u64);
+
+        Self {
+            header: Some(header),
+            entries: entries.into_iter(),
+            chunk_size,
+            checksum: 0,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Encoder<'_> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut chunk = self.header.take().unwrap_or_default();
+        while chunk.len() < self.chunk_size {
+            let (key, value) = match self.entries.next() {
+                Some(entry) => entry,
+                None => {
+                    chunk.push(OP_EOF);
+                    self.checksum = crc64::crc64(self.checksum, &chunk);
+                    chunk.extend_from_slice(&self.checksum.to_le_bytes());
+                    self.done = true;
+                    return Some(chunk);
+                }
+            };
+            if let Some(expires_at) = value.expires_at {
+                let millis = expires_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64;
+                chunk.push(OP_EXPIRETIME_MS);
+                chunk.extend_from_slice(&millis.to_le_bytes());
+            }
+            chunk.push(value_type(&value.value));
+            write_string(&mut chunk, key.as_bytes());
+            write_value(&mut chunk, &value.value);
+        }
+        self.checksum = crc64::crc64(self.checksum, &chunk);
+        Some(chunk)
+    }
+}
+
+/*
+ DUMP payload: the value type and value as they are written on the RDB file,
+ followed by the RDB version (2 bytes) and the CRC64 of everything before it (8 bytes)
+*/
+pub fn dump(value: &ValueType) -> Vec<u8> {
+    let mut payload = vec![value_type(value)];
+    write_value(&mut payload, value);
+    payload.extend_from_slice(&RDB_VERSION.to_le_bytes());
+    let checksum = crc64::crc64(0, &payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+    payload
+}
+
+pub fn restore(payload: &[u8]) -> Result<ValueType, RdbError> {
+    if payload.len() < 10 {
+        return Err(RdbError::InvalidDumpPayload);
+    }
+    let (body, footer) = payload.split_at(payload.len() - 10);
+    let version = u16::from_le_bytes(footer[0..2].try_into().unwrap());
+    if version > RDB_VERSION {
+        return Err(RdbError::InvalidDumpPayload);
+    }
+    let checksum = u64::from_le_bytes(footer[2..10].try_into().unwrap());
+    if checksum != crc64::crc64(0, &payload[..payload.len() - 8]) {
+        return Err(RdbError::InvalidDumpPayload);
+    }
+
+    let mut file = Bytes::copy_from_slice(body);
+    let value_type = read_u8(&mut file).map_err(|_| RdbError::BadDataFormat)?;
+    let value = read_value(&mut file, value_type).map_err(|_| RdbError::BadDataFormat)?;
+    if file.has_remaining() {
+        return Err(RdbError::BadDataFormat);
+    }
+    Ok(value)
+}
+
+fn value_type(value: &ValueType) -> u8 {
+    match value {
+        ValueType::String(_) => TYPE_STRING,
+        ValueType::Stream(_) => stream::TYPE_STREAM_LISTPACKS_3,
+    }
+}
+
+fn write_value(file: &mut Vec<u8>, value: &ValueType) {
+    match value {
+        ValueType::String(s) => write_string(file, s.as_bytes()),
+        ValueType::Stream(s) => stream::write_stream(file, s),
+    }
+}
+
+fn read_value(file: &mut Bytes, value_type: u8) -> Result<ValueType, RdbError> {
+    match value_type {
+        TYPE_STRING => Ok(ValueType::String(read_string(file)?)),
+        stream::TYPE_STREAM_LISTPACKS
+        | stream::TYPE_STREAM_LISTPACKS_2
+        | stream::TYPE_STREAM_LISTPACKS_3 => {
+            Ok(ValueType::Stream(stream::read_stream(file, value_type)?))
+        }
+        _ => Err(RdbError::UnsupportedType(value_type)),
+    }
+}
+
+fn read_u8(file: &mut Bytes) -> Result<u8, RdbError> {
+    if !file.has_remaining() {
+        return Err(RdbError::UnexpectedEof);
+    }
+    Ok(file.get_u8())
+}
+
+fn read_bytes(file: &mut Bytes, len: usize) -> Result<Bytes, RdbError> {
+    if file.remaining() < len {
+        return Err(RdbError::UnexpectedEof);
+    }
+    Ok(file.split_to(len))
+}
+
+//Returns the length and whether it's a special encoding (two most significant bits set)
+//See:https://rdb.fnordig.de/file_format.html#length-encoding
+fn read_encoded_length(file: &mut Bytes) -> Result<(u64, bool), RdbError> {
+    let first = read_u8(file)?;
+    match (first & 0xC0) >> 6 {
+        0 => Ok(((first & 0x3F) as u64, false)),
+        1 => {
+            let second = read_u8(file)?;
+            Ok(((((first & 0x3F) as u64) << 8) | second as u64, false))
+        }
+        2 => match first {
+            0x80 => {
+                let bytes = read_bytes(file, 4)?;
+                let length = u32::from_be_bytes(bytes.as_ref().try_into().unwrap());
+                Ok((length as u64, false))
+            }
+            0x81 => {
+                let bytes = read_bytes(file, 8)?;
+                Ok((
+                    u64::from_be_bytes(bytes.as_ref().try_into().unwrap()),
+                    false,
+                ))
+            }
+            _ => Err(RdbError::InvalidEncoding(first)),
+        },
+        _ => Ok(((first & 0x3F) as u64, true)),
+    }
+}
+
+fn read_length(file: &mut Bytes) -> Result<u64, RdbError> {
+    match read_encoded_length(file)? {
+        (length, false) => Ok(length),
+        (encoding, true) => Err(RdbError::InvalidEncoding(encoding as u8)),
+    }
+}
+
+fn read_raw_string(file: &mut Bytes) -> Result<Vec<u8>, RdbError> {
+    let (length, encoded) = read_encoded_length(file)?;
+    if !encoded {
+        return Ok(read_bytes(file, length as usize)?.to_vec());
+    }
+    match length as u8 {
+        ENC_INT8 => Ok((read_u8(file)? as i8).to_string().into_bytes()),
+        ENC_INT16 => {
+            let bytes = read_bytes(file, 2)?;
+            let value = i16::from_le_bytes(bytes.as_ref().try_into().unwrap());
+            Ok(value.to_string().into_bytes())
+        }
+        ENC_INT32 => {
+            let bytes = read_bytes(file, 4)?;
+            let value = i32::from_le_bytes(bytes.as_ref().try_into().unwrap());
+            Ok(value.to_string().into_bytes())
+        }
+        ENC_LZF => {
+            let compressed_len = read_length(file)? as usize;
+            let len = read_length(file)? as usize;
+            let compressed = read_bytes(file, compressed_len)?;
+            lzf_decompress(&compressed, len)
+        }
+        encoding => Err(RdbError::InvalidEncoding(encoding)),
+    }
+}
+
+//See:https://rdb.fnordig.de/file_format.html#string-encoding
+fn read_string(file: &mut Bytes) -> Result<String, RdbError> {
+    let bytes = read_raw_string(file)?;
+    String::from_utf8(bytes).map_err(|_| RdbError::InvalidString)
+}
+
+//See:http://oldhome.schmorp.de/marc/liblzf.html
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>, RdbError> {
+    //The length comes from the file, don't allocate more than the input can expand to
+    if len > input.len().saturating_mul(LZF_MAX_EXPANSION) {
+        return Err(RdbError::InvalidLzf);
+    }
+    let mut output = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 1 << 5 {
+            //Literal run
+            let run = ctrl + 1;
+            let literal = input.get(i..i + run).ok_or(RdbError::InvalidLzf)?;
+            output.extend_from_slice(literal);
+            i += run;
+        } else {
+            //Back reference
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or(RdbError::InvalidLzf)? as usize;
+                i += 1;
+            }
+            let low = *input.get(i).ok_or(RdbError::InvalidLzf)? as usize;
+            i += 1;
+            let back = ((ctrl & 0x1F) << 8) + low + 1;
+            if back > output.len() {
+                return Err(RdbError::InvalidLzf);
+            }
+            let start = output.len() - back;
+            for j in 0..run + 2 {
+                output.push(output[start + j]);
+            }
+        }
+    }
+    if output.len() != len {
+        return Err(RdbError::InvalidLzf);
+    }
+    Ok(output)
+}
+
+//See:https://rdb.fnordig.de/file_format.html#length-encoding
+fn write_length(file: &mut Vec<u8>, length: u64) {
+    if length < 1 << 6 {
+        file.push(length as u8);
+    } else if length < 1 << 14 {
+        file.push(0x40 | (length >> 8) as u8);
+        file.push(length as u8);
+    } else if length <= u32::MAX as u64 {
+        file.push(0x80);
+        file.extend_from_slice(&(length as u32).to_be_bytes());
+    } else {
+        file.push(0x81);
+        file.extend_from_slice(&length.to_be_bytes());
+    }
+}
+
+//See:https://rdb.fnordig.de/file_format.html#string-encoding
+fn write_string(file: &mut Vec<u8>, s: &[u8]) {
+    write_length(file, s.len() as u64);
+    file.extend_from_slice(s);
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::{
+        crc64::crc64, decode, dump, encode, lzf_decompress, restore, Checksum, Encoder, RdbError,
+        Stream, StreamData, StreamId, Value, ValueType,
+    };
+
+    #[test]
+    fn test_encode() {
+        let mut memory = HashMap::new();
+        memory.insert(
+            "key".to_string(),
+            Value::new(ValueType::String("value".to_string()), None),
+        );
+        memory.insert(
+            "expiring".to_string(),
+            Value::new(ValueType::String("value".to_string()), Some(100_000)),
+        );
+        let file = encode(&memory);
+        assert_eq!(&file[0..9], b"REDIS0011");
+
+        let (body, checksum) = file.split_at(file.len() - 8);
+        assert_eq!(body.last(), Some(&0xFF));
+        assert_eq!(
+            crc64(0, body),
+            u64::from_le_bytes(checksum.try_into().unwrap())
+        );
+
+        //Same file when it is generated in chunks
+        let chunks: Vec<Vec<u8>> = Encoder::new(&memory, 1).collect();
+        assert_eq!(chunks.len(), 4);
+        assert_eq!(chunks.concat(), file);
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut memory = HashMap::new();
+        memory.insert(
+            "key".to_string(),
+            Value::new(ValueType::String("value".repeat(20)), None),
+        );
+        memory.insert(
+            "expiring".to_string(),
+            Value::new(ValueType::String("value".to_string()), Some(100_000)),
+        );
+        let file = encode(&memory);
+        let rdb = decode(&file).unwrap();
+        assert_eq!(rdb.version_number(), 11);
+        assert!(matches!(rdb.checksum, Checksum::Valid(_)));
+        assert_eq!(
+            rdb.aux,
+            vec![
+                ("redis-ver".to_string(), "7.2.0".to_string()),
+                ("redis-bits".to_string(), "64".to_string())
+            ]
+        );
+        assert_eq!(rdb.databases.len(), 1);
+        let database = &rdb.databases[0];
+        assert_eq!(database.table_size, 2);
+        assert_eq!(database.expiry_size, 1);
+        let entry = database.entries.iter().find(|e| e.key == "key").unwrap();
+        assert_eq!(entry.value, ValueType::String("value".repeat(20)));
+        assert_eq!(entry.expires_at, None);
+        let entry = database
+            .entries
+            .iter()
+            .find(|e| e.key == "expiring")
+            .unwrap();
+        assert!(entry.expires_at.is_some());
+
+        //Corrupt the value of a key
+        let mut corrupted = file.clone();
+        let i = corrupted.len() - 12;
+        corrupted[i] = b'X';
+        let rdb = decode(&corrupted).unwrap();
+        assert!(matches!(rdb.checksum, Checksum::Invalid { .. }));
+
+        let truncated = &file[..file.len() - 10];
+        assert_eq!(decode(truncated).unwrap_err(), RdbError::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_lzf_decompress() {
+        //"aaaaaaaaaa" compressed: literal "a" followed by a back reference of 9 bytes
+        let compressed = [0x00, b'a', 0xE0, 0x00, 0x00];
+        let result = lzf_decompress(&compressed, 10).unwrap();
+        assert_eq!(result, b"aaaaaaaaaa");
+        assert_eq!(lzf_decompress(&compressed, 11), Err(RdbError::InvalidLzf));
+        assert_eq!(
+            lzf_decompress(&compressed, 1 << 40),
+            Err(RdbError::InvalidLzf)
+        );
+    }
+
+    #[test]
+    fn test_decode_lzf_oversized() {
+        let mut file = b"REDIS0011".to_vec();
+        file.extend_from_slice(&[0xFE, 0x00, 0x00, 0x01, b'k']);
+        //LZF string claiming 1<<40 bytes once decompressed
+        file.extend_from_slice(&[0xC3, 0x05, 0x81]);
+        file.extend_from_slice(&(1u64 << 40).to_be_bytes());
+        file.extend_from_slice(&[0x00, b'a', 0xE0, 0x00, 0x00, 0xFF]);
+        assert_eq!(decode(&file).unwrap_err(), RdbError::InvalidLzf);
+    }
+
+    #[test]
+    fn test_dump_restore() {
+        let value = ValueType::String("value".to_string());
+        let payload = dump(&value);
+        //Type, value, RDB version and the checksum of everything before it
+        assert_eq!(&payload[..9], &[0, 5, b'v', b'a', b'l', b'u', b'e', 11, 0]);
+        assert_eq!(
+            u64::from_le_bytes(payload[9..].try_into().unwrap()),
+            crc64(0, &payload[..9])
+        );
+        assert_eq!(restore(&payload).unwrap(), value);
+
+        let stream = ValueType::Stream(
+            vec![StreamData {
+                id: StreamId::new(1, 1),
+                fields: vec![("field".to_string(), "value".to_string())]
+                    .into_iter()
+                    .collect(),
+            }]
+            .into(),
+        );
+        assert_eq!(restore(&dump(&stream)).unwrap(), stream);
+
+        //Deleted entries are kept in their node until the whole node goes
+        let mut stream: Stream = (1..=150)
+            .map(|i| StreamData {
+                id: StreamId::new(i, 0),
+                fields: vec![("n".to_string(), i.to_string())],
+            })
+            .collect::<Vec<_>>()
+            .into();
+        for i in (1..=100).chain([120, 150]) {
+            stream.delete(StreamId::new(i, 0));
+        }
+        assert_eq!(stream.entries.node_count(), 1);
+        let stream = ValueType::Stream(stream);
+        assert_eq!(restore(&dump(&stream)).unwrap(), stream);
+
+        let mut corrupted = payload.clone();
+        corrupted[2] = b'V';
+        assert_eq!(restore(&corrupted), Err(RdbError::InvalidDumpPayload));
+
+        let mut newer_version = payload[..payload.len() - 10].to_vec();
+        newer_version.extend_from_slice(&12u16.to_le_bytes());
+        let checksum = crc64(0, &newer_version);
+        newer_version.extend_from_slice(&checksum.to_le_bytes());
+        assert_eq!(restore(&newer_version), Err(RdbError::InvalidDumpPayload));
+    }
+}
@@ -0,0 +1,311 @@
+use bytes::{Buf, Bytes};
+
+use super::{
+    listpack::{self, ListpackEntry},
+    read_bytes, read_length, read_raw_string, read_string, write_length, write_string,
+    ConsumerGroup, PendingEntry, RdbError, Stream, StreamData, StreamId, FLAG_DELETED,
+    FLAG_SAME_FIELDS,
+};
+
+pub const TYPE_STREAM_LISTPACKS: u8 = 15;
+pub const TYPE_STREAM_LISTPACKS_2: u8 = 19;
+pub const TYPE_STREAM_LISTPACKS_3: u8 = 21;
+
+//-1 on redis, for the entries read and the active time of consumers that never got anything
+const UNKNOWN: u64 = u64::MAX;
+
+/*
+ Each node is a listpack keyed by its master ID, the first entry of the node.
+ The master entry holds the field names of the first entry so the following
+ entries with the same fields only store their values. The nodes are written
+ as they are in memory, deleted entries included.
+ See:https://github.com/redis/redis/blob/unstable/src/t_stream.c
+*/
+pub fn write_stream(file: &mut Vec<u8>, stream: &Stream) {
+    write_length(file, stream.entries.node_count() as u64);
+    for (master, node) in stream.entries.nodes() {
+        write_string(file, &raw_id(&master));
+
+        let mut entries: Vec<ListpackEntry> = vec![
+            ((node.count - node.deleted) as i64).into(),
+            (node.deleted as i64).into(),
+            (node.master_fields.len() as i64).into(),
+        ];
+        for field in &node.master_fields {
+            entries.push(field.as_str().into());
+        }
+        //Master entry terminator
+        entries.push(0.into());
+
+        for header in node.iter(master) {
+            let fields = node.fields(&header);
+            entries.push((header.flags as i64).into());
+            entries.push((header.id.ms.wrapping_sub(master.ms) as i64).into());
+            entries.push((header.id.seq.wrapping_sub(master.seq) as i64).into());
+            if header.flags & FLAG_SAME_FIELDS != 0 {
+                for (_, value) in &fields {
+                    entries.push(value.as_str().into());
+                }
+                entries.push((fields.len() as i64 + 3).into());
+            } else {
+                entries.push((fields.len() as i64).into());
+                for (field, value) in &fields {
+                    entries.push(field.as_str().into());
+                    entries.push(value.as_str().into());
+                }
+                entries.push((fields.len() as i64 * 2 + 4).into());
+            }
+        }
+        write_string(file, &listpack::encode(&entries));
+    }
+
+    write_length(file, stream.entries.len() as u64);
+    for id in [stream.last_id, stream.first_id(), stream.max_deleted_id] {
+        write_length(file, id.ms);
+        write_length(file, id.seq);
+    }
+    write_length(file, stream.entries_added);
+
+    write_length(file, stream.groups.len() as u64);
+    for (name, group) in &stream.groups {
+        write_string(file, name.as_bytes());
+        write_length(file, group.last_id.ms);
+        write_length(file, group.last_id.seq);
+        write_length(file, group.entries_read.unwrap_or(UNKNOWN));
+        write_length(file, group.pending.len() as u64);
+        for (id, entry) in &group.pending {
+            file.extend_from_slice(&raw_id(id));
+            file.extend_from_slice(&entry.delivery_time.to_le_bytes());
+            write_length(file, entry.delivery_count);
+        }
+        //The consumers only list the IDs, the rest is in the group pending entries
+        write_length(file, group.consumers.len() as u64);
+        for (name, consumer) in &group.consumers {
+            write_string(file, name.as_bytes());
+            file.extend_from_slice(&consumer.seen_time.to_le_bytes());
+            let active_time = consumer.active_time.unwrap_or(UNKNOWN);
+            file.extend_from_slice(&active_time.to_le_bytes());
+            write_length(file, consumer.pending.len() as u64);
+            for id in &consumer.pending {
+                file.extend_from_slice(&raw_id(id));
+            }
+        }
+    }
+}
+
+//IDs are stored big endian so they sort as bytes
+fn raw_id(id: &StreamId) -> [u8; 16] {
+    let mut raw = [0; 16];
+    raw[..8].copy_from_slice(&id.ms.to_be_bytes());
+    raw[8..].copy_from_slice(&id.seq.to_be_bytes());
+    raw
+}
+
+fn read_raw_id(file: &mut Bytes) -> Result<StreamId, RdbError> {
+    let mut raw = read_bytes(file, 16)?;
+    Ok(StreamId::new(raw.get_u64(), raw.get_u64()))
+}
+
+fn read_millis(file: &mut Bytes) -> Result<u64, RdbError> {
+    Ok(read_bytes(file, 8)?.get_u64_le())
+}
+
+pub fn read_stream(file: &mut Bytes, value_type: u8) -> Result<Stream, RdbError> {
+    let mut stream = Stream::default();
+    //Entries are stored as deltas from the first of their node, which needs them in order
+    let mut previous = None;
+    let nodes = read_length(file)?;
+    for _ in 0..nodes {
+        let master_key = read_raw_string(file)?;
+        if master_key.len() != 16 {
+            return Err(RdbError::InvalidListpack);
+        }
+        let master = StreamId::new(
+            u64::from_be_bytes(master_key[0..8].try_into().unwrap()),
+            u64::from_be_bytes(master_key[8..16].try_into().unwrap()),
+        );
+
+        let node = read_raw_string(file)?;
+        let mut entries = listpack::decode(&node)?.into_iter();
+        //Valid and deleted counts
+        next_entry(&mut entries)?;
+        next_entry(&mut entries)?;
+        let num_master_fields = next_entry(&mut entries)?.as_int()?;
+        let mut master_fields = vec![];
+        for _ in 0..num_master_fields {
+            master_fields.push(next_entry(&mut entries)?.into_string()?);
+        }
+        //Master entry terminator
+        next_entry(&mut entries)?;
+
+        while let Some(flags) = entries.next() {
+            let flags = flags.as_int()?;
+            let ms = master
+                .ms
+                .wrapping_add(next_entry(&mut entries)?.as_int()? as u64);
+            let seq = master
+                .seq
+                .wrapping_add(next_entry(&mut entries)?.as_int()? as u64);
+            let mut fields = Vec::new();
+            if flags & FLAG_SAME_FIELDS as i64 != 0 {
+                for field in &master_fields {
+                    let value = next_entry(&mut entries)?.into_string()?;
+                    fields.push((field.clone(), value));
+                }
+            } else {
+                let num_fields = next_entry(&mut entries)?.as_int()?;
+                for _ in 0..num_fields {
+                    let field = next_entry(&mut entries)?.into_string()?;
+                    let value = next_entry(&mut entries)?.into_string()?;
+                    fields.push((field, value));
+                }
+            }
+            //lp-count, only needed to iterate backwards
+            next_entry(&mut entries)?;
+            let id = StreamId::new(ms, seq);
+            if previous.is_some_and(|previous| id <= previous) {
+                return Err(RdbError::BadDataFormat);
+            }
+            previous = Some(id);
+            if flags & FLAG_DELETED as i64 == 0 {
+                stream.entries.push(StreamData { id, fields });
+            }
+        }
+    }
+
+    let length = read_length(file)?;
+    stream.last_id = StreamId::new(read_length(file)?, read_length(file)?);
+    stream.entries_added = length;
+    if value_type >= TYPE_STREAM_LISTPACKS_2 {
+        //The first ID comes from the entries
+        read_length(file)?;
+        read_length(file)?;
+        stream.max_deleted_id = StreamId::new(read_length(file)?, read_length(file)?);
+        stream.entries_added = read_length(file)?;
+    }
+
+    let groups = read_length(file)?;
+    for _ in 0..groups {
+        let name = read_string(file)?;
+        let mut group = ConsumerGroup::new(StreamId::new(read_length(file)?, read_length(file)?));
+        if value_type >= TYPE_STREAM_LISTPACKS_2 {
+            let entries_read = read_length(file)?;
+            group.entries_read = (entries_read != UNKNOWN).then_some(entries_read);
+        }
+        let pending = read_length(file)?;
+        for _ in 0..pending {
+            let id = read_raw_id(file)?;
+            let entry = PendingEntry {
+                consumer: String::new(),
+                delivery_time: read_millis(file)?,
+                delivery_count: read_length(file)?,
+            };
+            group.pending.insert(id, entry);
+        }
+        let consumers = read_length(file)?;
+        for _ in 0..consumers {
+            let consumer = read_string(file)?;
+            let seen_time = read_millis(file)?;
+            group.create_consumer(&consumer, seen_time);
+            if value_type >= TYPE_STREAM_LISTPACKS_3 {
+                let active_time = read_millis(file)?;
+                group.consumers.get_mut(&consumer).unwrap().active_time =
+                    (active_time != UNKNOWN).then_some(active_time);
+            }
+            let pending = read_length(file)?;
+            for _ in 0..pending {
+                let id = read_raw_id(file)?;
+                //Every ID of a consumer has to be in the group pending entries
+                let entry = group.pending.get(&id).ok_or(RdbError::BadDataFormat)?;
+                group.assign(id, &consumer, entry.delivery_time, entry.delivery_count);
+            }
+        }
+        stream.groups.insert(name, group);
+    }
+
+    Ok(stream)
+}
+
+fn next_entry(
+    entries: &mut impl Iterator<Item = ListpackEntry>,
+) -> Result<ListpackEntry, RdbError> {
+    entries.next().ok_or(RdbError::InvalidListpack)
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::{
+        super::super::value::stream::entries::NODE_MAX_ENTRIES, raw_id, read_stream, write_stream,
+        ConsumerGroup, RdbError, Stream, StreamData, StreamId, TYPE_STREAM_LISTPACKS_3,
+    };
+
+    #[test]
+    fn test_write_read_stream() {
+        let mut stream = Stream::default();
+        for i in 0..250 {
+            let mut fields = vec![("temperature".to_string(), i.to_string())];
+            if i % 3 == 0 {
+                fields.insert(0, ("humidity".to_string(), "10".to_string()));
+            }
+            //Repeated names are kept
+            if i % 5 == 0 {
+                fields.push(("temperature".to_string(), "0".to_string()));
+            }
+            stream.add(StreamData {
+                id: StreamId::new(1_526_919_030_474 + i / 2, i % 2),
+                fields,
+            });
+        }
+        stream.delete(StreamId::new(1_526_919_030_474 + 10, 0));
+        let mut group = ConsumerGroup::new(StreamId::new(1_526_919_030_474, 1));
+        group.create_consumer("alice", 1_700_000_000_000);
+        group.create_consumer("bob", 1_700_000_000_000);
+        group.assign(
+            StreamId::new(1_526_919_030_474, 0),
+            "alice",
+            1_700_000_000_500,
+            1,
+        );
+        group.assign(
+            StreamId::new(1_526_919_030_474, 1),
+            "bob",
+            1_700_000_000_600,
+            3,
+        );
+        group.seen("bob", 1_700_000_000_600, true);
+        group.entries_read = Some(2);
+        stream.groups.insert("workers".to_string(), group);
+
+        let mut file = vec![];
+        write_stream(&mut file, &stream);
+        let mut file = Bytes::from(file);
+        let result = read_stream(&mut file, TYPE_STREAM_LISTPACKS_3).unwrap();
+        assert_eq!(result, stream);
+        assert!(file.is_empty());
+    }
+
+    #[test]
+    fn test_read_stream_unordered() {
+        let mut stream = Stream::default();
+        for i in 0..=NODE_MAX_ENTRIES as u64 {
+            stream.add(StreamData {
+                id: StreamId::new(100 + i, 0),
+                fields: vec![("f".to_string(), i.to_string())],
+            });
+        }
+        let mut file = vec![];
+        write_stream(&mut file, &stream);
+
+        //The second node starts before the end of the first one
+        let master = raw_id(&StreamId::new(100 + NODE_MAX_ENTRIES as u64, 0));
+        let start = file
+            .windows(master.len())
+            .position(|window| window == master)
+            .unwrap();
+        file[start..start + 16].copy_from_slice(&raw_id(&StreamId::new(150, 0)));
+        let result = read_stream(&mut Bytes::from(file), TYPE_STREAM_LISTPACKS_3);
+        assert_eq!(result, Err(RdbError::BadDataFormat));
+    }
+}
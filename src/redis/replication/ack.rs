@@ -0,0 +1,193 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::Notify,
+    time::Instant,
+};
+
+use crate::redis::types::RedisType;
+
+//Replication offset a replica confirmed with REPLCONF ACK
+#[derive(Debug)]
+pub struct ReplicaAck {
+    offset: AtomicU64,
+    //Unix time in milliseconds of the last ACK, starts when the replica is attached
+    last_ack: AtomicU64,
+}
+
+impl Default for ReplicaAck {
+    fn default() -> Self {
+        Self {
+            offset: AtomicU64::new(0),
+            last_ack: AtomicU64::new(now_ms()),
+        }
+    }
+}
+
+impl ReplicaAck {
+    pub fn offset(&self) -> u64 {
+        self.offset.load(Ordering::SeqCst)
+    }
+
+    //Seconds since the replica last sent an ACK
+    pub fn lag(&self) -> u64 {
+        now_ms().saturating_sub(self.last_ack.load(Ordering::SeqCst)) / 1000
+    }
+
+    fn update(&self, offset: u64) {
+        self.offset.fetch_max(offset, Ordering::SeqCst);
+        self.last_ack.store(now_ms(), Ordering::SeqCst);
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/*
+ Reads what a replica sends back on its link (only REPLCONF ACK <offset> is
+ expected) so the acknowledged offsets are known without touching the Redis lock.
+*/
+pub async fn read_acks<R: AsyncRead + Unpin>(
+    mut reader: R,
+    ack: Arc<ReplicaAck>,
+    changed: Arc<Notify>,
+) {
+    let mut buf = [0; 512];
+    let mut pending = Vec::new();
+    loop {
+        let n = match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        pending.extend_from_slice(&buf[..n]);
+        let frames = match RedisType::drain_frames(&mut pending) {
+            Ok(frames) => frames,
+            Err(_) => {
+                println!("Invalid data on replica link");
+                return;
+            }
+        };
+        for frame in frames {
+            if let Some(offset) = parse_ack(frame) {
+                ack.update(offset);
+                changed.notify_waiters();
+            }
+        }
+    }
+}
+
+fn parse_ack(frame: RedisType) -> Option<u64> {
+    let args = match frame {
+        RedisType::Array(args) => args,
+        _ => return None,
+    };
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    match args.as_slice() {
+        [command, sub_command, offset, ..]
+            if command.eq_ignore_ascii_case("replconf")
+                && sub_command.eq_ignore_ascii_case("ack") =>
+        {
+            offset.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+//Waits for the replicas to acknowledge everything propagated up to `offset`
+pub struct AckWaiter {
+    pub offset: u64,
+    pub acks: Vec<Arc<ReplicaAck>>,
+    pub changed: Arc<Notify>,
+}
+
+impl AckWaiter {
+    pub fn replicas(&self) -> usize {
+        self.acks.len()
+    }
+
+    pub fn count(&self) -> usize {
+        self.acks
+            .iter()
+            .filter(|ack| ack.offset() >= self.offset)
+            .count()
+    }
+
+    //Returns as soon as `target` replicas are in sync, None as timeout waits forever
+    pub async fn wait(&self, target: usize, timeout: Option<Duration>) -> usize {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            //Registered before counting so an ACK in between is not missed
+            let notified = self.changed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let count = self.count();
+            if count >= target {
+                return count;
+            }
+            match deadline {
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        return self.count();
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::sync::Notify;
+    use tokio_test::io::Builder;
+
+    use super::{read_acks, AckWaiter, ReplicaAck};
+
+    #[tokio::test]
+    async fn test_read_acks() {
+        //The ACK arrives split in two reads
+        let mock = Builder::new()
+            .read(b"*3\r\n$8\r\nREPLCONF\r\n$3\r\nACK\r\n")
+            .read(b"$2\r\n31\r\n")
+            .build();
+        let ack = Arc::new(ReplicaAck::default());
+        let changed = Arc::new(Notify::new());
+        read_acks(mock, ack.clone(), changed).await;
+        assert_eq!(ack.offset(), 31);
+        assert_eq!(ack.lag(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_ack_waiter() {
+        let ack = Arc::new(ReplicaAck::default());
+        let changed = Arc::new(Notify::new());
+        let waiter = AckWaiter {
+            offset: 10,
+            acks: vec![ack.clone(), Arc::new(ReplicaAck::default())],
+            changed: changed.clone(),
+        };
+        let count = waiter.wait(1, Some(Duration::from_millis(10))).await;
+        assert_eq!(count, 0);
+
+        tokio::spawn(async move {
+            ack.update(10);
+            changed.notify_waiters();
+        });
+        let count = waiter.wait(1, None).await;
+        assert_eq!(count, 1);
+    }
+}
@@ -0,0 +1,115 @@
+//Same as the "repl-backlog-size" default on redis
+pub const DEFAULT_BACKLOG_SIZE: usize = 1024 * 1024;
+
+/*
+ Circular buffer with the last bytes propagated to the replicas, used to
+ answer PSYNC with +CONTINUE when a replica only missed a few commands.
+ Offsets follow the replication offset, the first byte ever propagated is 1.
+*/
+#[derive(Debug)]
+pub struct Backlog {
+    buffer: Vec<u8>,
+    //Next position to write in the buffer
+    idx: usize,
+    histlen: usize,
+    //Replication offset of the first byte in the backlog
+    offset: u64,
+}
+
+impl Backlog {
+    pub fn new(size: usize, repl_offset: u64) -> Self {
+        Self {
+            buffer: vec![0; size],
+            idx: 0,
+            histlen: 0,
+            offset: repl_offset + 1,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn histlen(&self) -> usize {
+        self.histlen
+    }
+
+    pub fn first_byte_offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn feed(&mut self, mut data: &[u8]) {
+        let size = self.size();
+        let total = data.len();
+        while !data.is_empty() {
+            let n = data.len().min(size - self.idx);
+            self.buffer[self.idx..self.idx + n].copy_from_slice(&data[..n]);
+            self.idx = (self.idx + n) % size;
+            data = &data[n..];
+        }
+        let histlen = self.histlen + total;
+        if histlen > size {
+            self.offset += (histlen - size) as u64;
+            self.histlen = size;
+        } else {
+            self.histlen = histlen;
+        }
+    }
+
+    //Whether the bytes from `offset` onwards are still in the backlog
+    pub fn contains(&self, offset: u64) -> bool {
+        offset >= self.offset && offset <= self.offset + self.histlen as u64
+    }
+
+    //Bytes from `offset` up to the end of the backlog, None if they are no longer in it
+    pub fn range_from(&self, offset: u64) -> Option<Vec<u8>> {
+        if !self.contains(offset) {
+            return None;
+        }
+        let size = self.size();
+        let skip = (offset - self.offset) as usize;
+        let len = self.histlen - skip;
+        let start = (self.idx + size - self.histlen + skip) % size;
+        let mut data = Vec::with_capacity(len);
+        if start + len <= size {
+            data.extend_from_slice(&self.buffer[start..start + len]);
+        } else {
+            data.extend_from_slice(&self.buffer[start..]);
+            data.extend_from_slice(&self.buffer[..len - (size - start)]);
+        }
+        Some(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Backlog;
+
+    #[test]
+    fn test_backlog() {
+        let mut backlog = Backlog::new(8, 10);
+        assert_eq!(backlog.range_from(11), Some(vec![]));
+        assert_eq!(backlog.range_from(12), None);
+
+        backlog.feed(b"abcde");
+        assert_eq!(backlog.histlen(), 5);
+        assert_eq!(backlog.first_byte_offset(), 11);
+        assert_eq!(backlog.range_from(13), Some(b"cde".to_vec()));
+
+        //Wraps around and drops the oldest bytes
+        backlog.feed(b"fghijk");
+        assert_eq!(backlog.histlen(), 8);
+        assert_eq!(backlog.first_byte_offset(), 14);
+        assert_eq!(backlog.range_from(13), None);
+        assert!(!backlog.contains(13));
+        assert!(backlog.contains(22));
+        assert_eq!(backlog.range_from(14), Some(b"defghijk".to_vec()));
+        assert_eq!(backlog.range_from(20), Some(b"jk".to_vec()));
+        assert_eq!(backlog.range_from(22), Some(vec![]));
+
+        //Bigger than the whole backlog
+        backlog.feed(b"0123456789");
+        assert_eq!(backlog.first_byte_offset(), 24);
+        assert_eq!(backlog.range_from(24), Some(b"23456789".to_vec()));
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Notify, RwLock};
+
+use crate::redis::Redis;
+
+use super::RWStream;
+
+//How often the target is checked while waiting for it to catch up
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum FailoverState {
+    #[default]
+    NoFailover,
+    //Writes are paused until the target has all of them
+    WaitingForSync,
+    //We are a replica of the target, waiting for it to accept PSYNC FAILOVER
+    InProgress,
+}
+
+impl Display for FailoverState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailoverState::NoFailover => write!(f, "no-failover"),
+            FailoverState::WaitingForSync => write!(f, "waiting-for-sync"),
+            FailoverState::InProgress => write!(f, "failover-in-progress"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Failover {
+    pub state: FailoverState,
+    //None means the first replica that catches up
+    pub target: Option<(String, u16)>,
+    //Promote the target on timeout even if it didn't catch up
+    pub force: bool,
+    pub deadline: Option<Instant>,
+    //Notified on every state change, paused writes and the failover task wait on it
+    pub changed: Arc<Notify>,
+}
+
+impl Failover {
+    pub fn in_progress(&self) -> bool {
+        self.state != FailoverState::NoFailover
+    }
+
+    pub fn start(&mut self, target: Option<(String, u16)>, timeout: Option<Duration>, force: bool) {
+        self.state = FailoverState::WaitingForSync;
+        self.target = target;
+        self.force = force;
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self.changed.notify_waiters();
+    }
+
+    pub fn end(&mut self) {
+        *self = Self {
+            changed: self.changed.clone(),
+            ..Default::default()
+        };
+        self.changed.notify_waiters();
+    }
+}
+
+/*
+ Drives FAILOVER: once the target replica has every write (they are paused
+ meanwhile) this server becomes a replica of it, the master link then sends
+ PSYNC FAILOVER so the target takes over.
+*/
+pub async fn run<S: RWStream>(redis: &RwLock<Redis<S>>) {
+    let changed = redis.read().await.replication.failover.changed.clone();
+    loop {
+        let notified = changed.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+
+        let state = redis.read().await.replication.failover.state;
+        if state == FailoverState::WaitingForSync {
+            wait_for_sync(redis).await;
+        } else {
+            notified.await;
+        }
+    }
+}
+
+async fn wait_for_sync<S: RWStream>(redis: &RwLock<Redis<S>>) {
+    //Writes are paused, the offset to reach doesn't move anymore
+    let waiter = redis.write().await.replication.request_acks().await;
+    loop {
+        let notified = waiter.changed.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        {
+            let mut redis = redis.write().await;
+            let replication = &redis.replication;
+            let failover = &replication.failover;
+            //Aborted
+            if failover.state != FailoverState::WaitingForSync {
+                return;
+            }
+            let synced = replication.synced_replica(waiter.offset, failover.target.as_ref());
+            let timed_out = failover
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline);
+            let target = match synced {
+                Some(target) => Some(target),
+                None if timed_out && failover.force => failover.target.clone(),
+                None => None,
+            };
+            if let Some((host, port)) = target {
+                println!("FAILOVER to {}:{} in progress", host, port);
+                redis.replication.failover.state = FailoverState::InProgress;
+                redis.replication.failover.changed.notify_waiters();
+                redis.config.replica_of = Some((host.clone(), port));
+                redis.replication.set_master(host, port);
+                return;
+            }
+            if timed_out {
+                println!("FAILOVER aborted: Replica never caught up before timeout");
+                redis.replication.failover.end();
+                return;
+            }
+        }
+        let _ = tokio::time::timeout(CHECK_INTERVAL, notified).await;
+    }
+}
+
+//The target didn't take over, we go back to being the master
+pub async fn abort_in_progress<S: RWStream>(redis: &mut Redis<S>, reason: &str) {
+    println!("FAILOVER aborted: {}", reason);
+    redis.config.replica_of = None;
+    redis.replication.promote().await;
+    redis.replication.failover.end();
+}
+
+//Writes wait here while a failover is running
+pub async fn wait_writes<S: RWStream>(redis: &RwLock<Redis<S>>) {
+    let changed = redis.read().await.replication.failover.changed.clone();
+    loop {
+        let notified = changed.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+
+        if !redis.read().await.replication.failover.in_progress() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::{
+        io::BufReader,
+        sync::{Mutex, RwLock},
+    };
+    use tokio_test::io::{Builder, Mock};
+
+    use crate::redis::{
+        config::Config,
+        replication::{ack::ReplicaAck, role::Role, Replica},
+        Redis,
+    };
+
+    use super::{wait_for_sync, FailoverState};
+
+    #[tokio::test]
+    async fn test_wait_for_sync() {
+        let mut redis: Redis<Mock> = Redis::new(Config::default());
+        let (_, writer) = tokio::io::split(BufReader::new(Builder::new().build()));
+        let replica = Replica {
+            host: "127.0.0.1".to_string(),
+            port: 6380,
+            stream: Mutex::new(writer),
+            ack: Arc::new(ReplicaAck::default()),
+        };
+        assert!(redis.replication.add_replica(replica, 0).await);
+        let target = Some(("127.0.0.1".to_string(), 6380));
+        redis
+            .replication
+            .failover
+            .start(target.clone(), None, false);
+        let redis = RwLock::new(redis);
+
+        //The replica already has every write
+        wait_for_sync(&redis).await;
+        let redis = redis.read().await;
+        assert_eq!(redis.replication.failover.state, FailoverState::InProgress);
+        assert_eq!(redis.replication.role, Role::Slave);
+        assert_eq!(redis.replication.replica_of, target);
+    }
+}
@@ -0,0 +1,77 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc::{Receiver, Sender},
+};
+
+use crate::{
+    redis::{rdb::Encoder, types::RedisType, value::Value},
+    util,
+};
+
+//Length of the delimiter used by diskless transfers ($EOF:<mark>)
+pub const EOF_MARK_SIZE: usize = 40;
+//Bytes of the RDB file generated at a time
+const CHUNK_SIZE: usize = 16 * 1024;
+
+//What the replicas sharing a diskless transfer receive
+#[derive(Debug)]
+pub enum SyncEvent {
+    Start { replid: String, offset: u64 },
+    Data(Arc<Vec<u8>>),
+}
+
+/*
+ Generates the RDB file of `memory` and sends it to every replica while it is
+ being generated. The size is not known upfront so the file is sent as
+ $EOF:<mark> followed by the file and the same mark.
+*/
+pub async fn stream_snapshot(
+    memory: &HashMap<String, Value>,
+    replid: String,
+    offset: u64,
+    replicas: Vec<Sender<SyncEvent>>,
+) {
+    for replica in &replicas {
+        let start = SyncEvent::Start {
+            replid: replid.clone(),
+            offset,
+        };
+        //A replica that is gone just stops receiving
+        let _ = replica.send(start).await;
+    }
+    let mark = util::gen_rand_string(EOF_MARK_SIZE);
+    let header = format!("$EOF:{}\r\n", mark).into_bytes();
+    let chunks = std::iter::once(header)
+        .chain(Encoder::new(memory, CHUNK_SIZE))
+        .chain(std::iter::once(mark.into_bytes()));
+    for chunk in chunks {
+        let chunk = Arc::new(chunk);
+        for replica in &replicas {
+            let _ = replica.send(SyncEvent::Data(chunk.clone())).await;
+        }
+    }
+}
+
+//Writes the transfer to the replica, returns the offset the RDB file was taken at
+pub async fn receive<W: AsyncWrite + Unpin>(
+    mut events: Receiver<SyncEvent>,
+    writer: &mut W,
+) -> std::io::Result<u64> {
+    let mut offset = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            SyncEvent::Start {
+                replid,
+                offset: start,
+            } => {
+                let response = RedisType::SimpleString(format!("FULLRESYNC {} {}", replid, start));
+                writer.write_all(&response.encode()).await?;
+                offset = Some(start);
+            }
+            SyncEvent::Data(data) => writer.write_all(&data).await?,
+        }
+    }
+    offset.ok_or_else(|| std::io::Error::other("full sync aborted"))
+}
@@ -0,0 +1,47 @@
+use std::{fmt::Display, sync::Arc, time::Instant};
+
+use tokio::sync::Notify;
+
+//State of the replica's link with its master
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum LinkState {
+    #[default]
+    Connect,
+    Connecting,
+    Sync,
+    Connected,
+}
+
+impl Display for LinkState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkState::Connected => write!(f, "up"),
+            _ => write!(f, "down"),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct MasterLink {
+    pub state: LinkState,
+    pub last_io: Option<Instant>,
+    //Set after the first sync, until then the replica can only ask for a full resync
+    pub synced: bool,
+    //Wakes up the master link task when REPLICAOF changes the master
+    pub changed: Arc<Notify>,
+}
+
+impl MasterLink {
+    pub fn last_io_seconds_ago(&self) -> i64 {
+        match self.last_io {
+            Some(last_io) if self.state == LinkState::Connected => {
+                last_io.elapsed().as_secs() as i64
+            }
+            _ => -1,
+        }
+    }
+
+    pub fn sync_in_progress(&self) -> bool {
+        self.state == LinkState::Sync
+    }
+}
@@ -1,17 +1,28 @@
-use std::{fmt::Display, time::Duration};
+use std::{fmt::Display, sync::Arc};
 
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufReader},
-    sync::Mutex,
-    time::timeout,
+    io::{AsyncReadExt, AsyncWriteExt, BufReader, WriteHalf},
+    sync::{mpsc::Sender, Mutex, Notify},
 };
 
 use crate::util;
 
-use self::role::Role;
+use self::{
+    ack::{AckWaiter, ReplicaAck},
+    backlog::{Backlog, DEFAULT_BACKLOG_SIZE},
+    failover::Failover,
+    full_sync::SyncEvent,
+    link::MasterLink,
+    role::Role,
+};
 
 use super::types::RedisType;
 
+pub mod ack;
+pub mod backlog;
+pub mod failover;
+pub mod full_sync;
+pub mod link;
 pub mod role;
 
 pub trait RWStream: AsyncWriteExt + AsyncReadExt + Unpin {}
@@ -19,7 +30,8 @@ pub trait RWStream: AsyncWriteExt + AsyncReadExt + Unpin {}
 pub struct Replica<S: RWStream> {
     pub host: String,
     pub port: u16,
-    pub stream: Mutex<BufReader<S>>,
+    pub stream: Mutex<WriteHalf<BufReader<S>>>,
+    pub ack: Arc<ReplicaAck>,
 }
 
 #[derive(Debug)]
@@ -29,13 +41,22 @@ pub struct Replication<S: RWStream> {
     pub connected_slaves: usize,
     pub master_replid: String,
     pub master_repl_offset: u64,
-    pub second_repl_offset: i32,
-    pub repl_backlog_active: i32,
-    pub repl_backlog_size: i32,
-    pub repl_backlog_first_byte_offset: i32,
-    pub repl_backlog_histlen: i32,
+    //Replication ID of the previous master, replicas of it can still partially resync
+    pub master_replid2: String,
+    pub second_repl_offset: i64,
+    pub repl_backlog_size: usize,
+    //Created when the first replica attaches
+    pub backlog: Option<Backlog>,
     pub replicas: Vec<Replica<S>>,
     pub slave_read_repl_offset: u64,
+    pub master_link: MasterLink,
+    //Notified every time a replica acknowledges an offset
+    pub acks_changed: Arc<Notify>,
+    //Replicas waiting for the diskless transfer that is about to start
+    pub pending_sync: Option<Vec<Sender<SyncEvent>>>,
+    //Replication ID and offset of the RDB file last saved for a full sync
+    pub rdb_snapshot: Option<(String, u64)>,
+    pub failover: Failover,
 }
 
 impl<S: RWStream> Replication<S> {
@@ -53,22 +74,148 @@ impl<S: RWStream> Replication<S> {
         }
     }
 
-    pub fn add_replica(&mut self, replica: Replica<S>) {
+    /*
+     `offset` is the last byte the replica already has, whatever was propagated
+     after it (while the RDB file or +CONTINUE was being sent) comes from the backlog.
+     Returns false if the replica is too far behind or the link is gone.
+    */
+    pub async fn add_replica(&mut self, replica: Replica<S>, offset: u64) -> bool {
+        self.create_backlog();
+        let missing = match self.backlog.as_ref().and_then(|b| b.range_from(offset + 1)) {
+            Some(missing) => missing,
+            None => return false,
+        };
+        if replica
+            .stream
+            .lock()
+            .await
+            .write_all(&missing)
+            .await
+            .is_err()
+        {
+            return false;
+        }
         self.connected_slaves += 1;
         self.replicas.push(replica);
+        true
+    }
+
+    pub fn create_backlog(&mut self) {
+        if self.backlog.is_none() {
+            self.backlog = Some(Backlog::new(
+                self.repl_backlog_size,
+                self.master_repl_offset,
+            ));
+        }
+    }
+
+    //Every byte sent to the replicas goes through here so the backlog matches the offset
+    fn feed_backlog(&mut self, message: &[u8]) {
+        self.master_repl_offset += message.len() as u64;
+        if let Some(backlog) = &mut self.backlog {
+            backlog.feed(message);
+        }
+    }
+
+    //Whether a replica that asked for `replid` `offset` can continue from the backlog
+    pub fn partial_resync(&self, replid: &str, offset: u64) -> bool {
+        let same_history = replid == self.master_replid
+            || (replid == self.master_replid2 && offset as i64 <= self.second_repl_offset);
+        if !same_history {
+            return false;
+        }
+        match &self.backlog {
+            Some(backlog) => backlog.contains(offset),
+            None => false,
+        }
+    }
+
+    //Full sync with the master, the replicas have to sync again with the new history
+    pub async fn reset_history(&mut self, replid: String, offset: u64) {
+        self.disconnect_replicas().await;
+        self.master_replid = replid;
+        self.master_replid2 = "0".repeat(40);
+        self.second_repl_offset = -1;
+        self.master_repl_offset = offset;
+        self.slave_read_repl_offset = offset;
+        self.backlog = None;
+        self.create_backlog();
+        self.rdb_snapshot = None;
+    }
+
+    //+CONTINUE from the master, a new replid means it was failed over
+    pub async fn continue_history(&mut self, replid: Option<String>) {
+        self.create_backlog();
+        let replid = match replid {
+            Some(replid) if replid != self.master_replid => replid,
+            _ => return,
+        };
+        self.master_replid2 = std::mem::replace(&mut self.master_replid, replid);
+        self.second_repl_offset = self.master_repl_offset as i64 + 1;
+        //They reconnect and continue using the previous replid
+        self.disconnect_replicas().await;
+    }
+
+    //Closes the links, the replicas will reconnect and PSYNC again
+    pub async fn disconnect_replicas(&mut self) {
+        for replica in self.replicas.drain(..) {
+            let _ = replica.stream.lock().await.shutdown().await;
+        }
+        self.connected_slaves = 0;
+    }
+
+    //REPLICAOF NO ONE, the dataset is kept and the history is continued under a new replid
+    pub async fn promote(&mut self) {
+        if self.role == Role::Master {
+            return;
+        }
+        self.role = Role::Master;
+        self.replica_of = None;
+        self.master_link = MasterLink {
+            changed: self.master_link.changed.clone(),
+            ..Default::default()
+        };
+        self.master_repl_offset = self.slave_read_repl_offset;
+        self.master_replid2 = std::mem::replace(&mut self.master_replid, util::gen_rand_string(40));
+        self.second_repl_offset = self.master_repl_offset as i64 + 1;
+        //The backlog holds the stream of the old master, which is the same history
+        self.create_backlog();
+        //They reconnect and continue using the previous replid
+        self.disconnect_replicas().await;
+        self.master_link.changed.notify_one();
+    }
+
+    //REPLICAOF host port
+    pub fn set_master(&mut self, host: String, port: u16) {
+        if self.role == Role::Master {
+            //Our own history can be continued if the new master was one of our replicas
+            self.slave_read_repl_offset = self.master_repl_offset;
+            self.master_link.synced = true;
+        }
+        self.role = Role::Slave;
+        self.replica_of = Some((host, port));
+        self.master_link.changed.notify_one();
     }
 
     pub async fn propagate_message(&mut self, message: Vec<u8>) {
         if self.role != Role::Master {
             return;
         }
-        self.master_repl_offset += message.len() as u64;
+        self.proxy_message(&message).await;
+    }
+
+    /*
+     A replica forwards the stream it gets from its master as is, so its own
+     replicas see the same replid and offsets as if they were attached to the master.
+    */
+    pub async fn proxy_message(&mut self, message: &[u8]) {
+        self.feed_backlog(message);
 
         let mut remove = Vec::new();
         for (i, replica) in self.replicas.iter().enumerate() {
             let stream = &replica.stream;
             let mut stream = stream.lock().await;
-            let response = stream.write_all(&message).await;
+            let response = stream.write_all(message).await;
             if let Err(e) = response {
                 println!("Failed to send message to replica: {}", e);
                 self.connected_slaves -= 1;
@@ -81,118 +228,59 @@ impl<S: RWStream> Replication<S> {
         }
     }
 
-    pub async fn count_sync_replicas(&mut self, mut target: usize, time_limit: u64) -> usize {
-        let mut now = std::time::SystemTime::now();
-        let time_out = now
-            .checked_add(std::time::Duration::from_millis(time_limit))
-            .unwrap();
-
-        let offset = self.master_repl_offset;
-        let mut sync_replicas = 0;
-        let command = RedisType::Array(vec![
-            RedisType::BulkString("REPLCONF".to_string()),
-            RedisType::BulkString("GETACK".to_string()),
-            RedisType::BulkString("*".to_string()),
-        ]);
-        let command_len = command.len();
-        let command = command.encode();
-        let r_len = self.replicas.len();
-        target = if target > r_len { r_len } else { target };
-        println!("Counting sync replicas");
-        println!("Target: {}", target);
-        println!("Time limit: {}", time_limit);
-        println!("Replicas: {}", r_len);
-        println!("Offset: {}", offset);
-        //All replicas are already in sync
-        if offset == 0 {
-            return r_len;
-        }
-        while now < time_out {
-            for replica in &self.replicas {
-                let stream = &replica.stream;
-                let mut stream = stream.lock().await;
-                let write_fut = stream.write_all(&command);
-                let response = timeout(Duration::from_millis(1), write_fut).await;
-                let response = match response {
-                    Ok(r) => r,
-                    Err(_) => {
-                        println!("Failed to send message to replica: timeout");
-                        continue;
-                    }
-                };
-                if let Err(e) = response {
-                    println!("Failed to send message to replica: {}", e);
-                }
-                let mut buffer = [0; 128];
-
-                let read_fut = stream.read(&mut buffer);
-                let response = timeout(Duration::from_millis(1), read_fut).await;
-                let n = match response {
-                    Ok(response) => {
-                        let n = match response {
-                            Ok(n) => n,
-                            Err(e) => {
-                                println!("Failed to read from replica: {}", e);
-                                continue;
-                            }
-                        };
-                        if n == 0 {
-                            println!("Replica disconnected");
-                            continue;
-                        }
-                        n
-                    }
-                    Err(_) => {
-                        continue;
-                    }
-                };
-
-                let buffer = &buffer[..n];
-
-                let mut response = match RedisType::from_buffer(&buffer) {
-                    Ok(r) => r,
-                    Err(_) => {
-                        println!("Buffer: {:?}", buffer);
-                        println!("Failed to parse response from replica");
-                        continue;
-                    }
-                };
-                let response = match response.pop() {
-                    Some(RedisType::Array(response)) => response,
-                    _ => {
-                        println!("Invalid response from replica");
-                        continue;
-                    }
-                };
-                let r_offset = match response.get(2) {
-                    Some(RedisType::BulkString(offset)) => offset,
-                    _ => {
-                        println!("Offset not found in response from replica");
-                        continue;
-                    }
-                };
-                let r_offset = match r_offset.parse::<u64>() {
-                    Ok(r_offset) => r_offset,
-                    Err(_) => {
-                        println!("Failed to parse offset from response from replica");
-                        continue;
-                    }
-                };
-                if r_offset >= offset {
-                    sync_replicas += 1;
-                } else {
-                    println!("Replica offset: {}, Master offset: {}", r_offset, offset);
-                }
-            }
-            self.master_repl_offset += command_len as u64;
+    pub fn has_replica(&self, host: &str, port: u16) -> bool {
+        self.replicas
+            .iter()
+            .any(|replica| replica.host == host && replica.port == port)
+    }
 
-            if sync_replicas >= target {
-                break;
-            }
-            now = std::time::SystemTime::now();
-        }
+    //A replica that acknowledged `offset`, it has to be `target` if there is one
+    pub fn synced_replica(
+        &self,
+        offset: u64,
+        target: Option<&(String, u16)>,
+    ) -> Option<(String, u16)> {
+        self.replicas
+            .iter()
+            .filter(|replica| {
+                target.is_none_or(|(host, port)| replica.host == *host && replica.port == *port)
+            })
+            .find(|replica| replica.ack.offset() >= offset)
+            .map(|replica| (replica.host.clone(), replica.port))
+    }
 
-        return sync_replicas;
+    //Replicas that sent an ACK in the last `max_lag` seconds
+    pub fn good_replicas(&self, max_lag: u64) -> usize {
+        self.replicas
+            .iter()
+            .filter(|replica| replica.ack.lag() <= max_lag)
+            .count()
+    }
+
+    //Replicas that acknowledged everything propagated so far
+    pub fn synced_replicas(&self) -> usize {
+        self.replicas
+            .iter()
+            .filter(|replica| replica.ack.offset() >= self.master_repl_offset)
+            .count()
+    }
+
+    //Asks the replicas for their offset, the result is used to wait without holding the lock
+    pub async fn request_acks(&mut self) -> AckWaiter {
+        let waiter = AckWaiter {
+            offset: self.master_repl_offset,
+            acks: self.replicas.iter().map(|r| r.ack.clone()).collect(),
+            changed: self.acks_changed.clone(),
+        };
+        if waiter.count() < waiter.replicas() {
+            let command = RedisType::Array(vec![
+                RedisType::BulkString("REPLCONF".to_string()),
+                RedisType::BulkString("GETACK".to_string()),
+                RedisType::BulkString("*".to_string()),
+            ]);
+            self.propagate_message(command.encode()).await;
+        }
+        waiter
     }
 }
 
@@ -205,9 +293,42 @@ impl<S: RWStream> Display for Replication<S> {
         let role = format!("role:{}\n", role);
         bulk_string.push_str(&role);
 
+        if let (Role::Slave, Some((host, port))) = (&self.role, &self.replica_of) {
+            bulk_string.push_str(&format!("master_host:{}\n", host));
+            bulk_string.push_str(&format!("master_port:{}\n", port));
+            let link = &self.master_link;
+            bulk_string.push_str(&format!("master_link_status:{}\n", link.state));
+            bulk_string.push_str(&format!(
+                "master_last_io_seconds_ago:{}\n",
+                link.last_io_seconds_ago()
+            ));
+            bulk_string.push_str(&format!(
+                "master_sync_in_progress:{}\n",
+                link.sync_in_progress() as u8
+            ));
+            bulk_string.push_str(&format!(
+                "slave_read_repl_offset:{}\n",
+                self.slave_read_repl_offset
+            ));
+        }
+
         let con_s_str = self.connected_slaves.to_string();
         let con_s_str = format!("connected_slaves:{}\n", con_s_str);
         bulk_string.push_str(&con_s_str);
+        //Sentinels discover the replicas from these lines
+        for (i, replica) in self.replicas.iter().enumerate() {
+            bulk_string.push_str(&format!(
+                "slave{}:ip={},port={},state=online,offset={},lag={}\n",
+                i,
+                replica.host,
+                replica.port,
+                replica.ack.offset(),
+                replica.ack.lag()
+            ));
+        }
+
+        let failover_state = format!("master_failover_state:{}\n", self.failover.state);
+        bulk_string.push_str(&failover_state);
 
         let master_replid = &self.master_replid;
         let master_replid = format!("master_replid:{}\n", master_replid);
@@ -217,11 +338,15 @@ impl<S: RWStream> Display for Replication<S> {
         let master_repl_offset = format!("master_repl_offset:{}\n", master_repl_offset);
         bulk_string.push_str(&master_repl_offset);
 
+        let master_replid2 = &self.master_replid2;
+        let master_replid2 = format!("master_replid2:{}\n", master_replid2);
+        bulk_string.push_str(&master_replid2);
+
         let second_repl_offset = &self.second_repl_offset;
         let second_repl_offset = format!("second_repl_offset:{}\n", second_repl_offset);
         bulk_string.push_str(&second_repl_offset);
 
-        let repl_backlog_active = &self.repl_backlog_active;
+        let repl_backlog_active = self.backlog.is_some() as u8;
         let repl_backlog_active = format!("repl_backlog_active:{}\n", repl_backlog_active);
         bulk_string.push_str(&repl_backlog_active);
 
@@ -229,14 +354,20 @@ impl<S: RWStream> Display for Replication<S> {
         let repl_backlog_size = format!("repl_backlog_size:{}\n", repl_backlog_size);
         bulk_string.push_str(&repl_backlog_size);
 
-        let repl_backlog_first_byte_offset = &self.repl_backlog_first_byte_offset;
+        let repl_backlog_first_byte_offset = match &self.backlog {
+            Some(backlog) => backlog.first_byte_offset(),
+            None => 0,
+        };
         let repl_backlog_first_byte_offset = format!(
             "repl_backlog_first_byte_offset:{}\n",
             repl_backlog_first_byte_offset
         );
         bulk_string.push_str(&repl_backlog_first_byte_offset);
 
-        let repl_backlog_histlen = &self.repl_backlog_histlen;
+        let repl_backlog_histlen = match &self.backlog {
+            Some(backlog) => backlog.histlen(),
+            None => 0,
+        };
         let repl_backlog_histlen = format!("repl_backlog_histlen:{}", repl_backlog_histlen);
         bulk_string.push_str(&repl_backlog_histlen);
 
@@ -252,13 +383,56 @@ impl<S: RWStream> Default for Replication<S> {
             connected_slaves: Default::default(),
             master_replid: Default::default(),
             master_repl_offset: Default::default(),
-            second_repl_offset: Default::default(),
-            repl_backlog_active: Default::default(),
-            repl_backlog_size: Default::default(),
-            repl_backlog_first_byte_offset: Default::default(),
-            repl_backlog_histlen: Default::default(),
+            master_replid2: "0".repeat(40),
+            second_repl_offset: -1,
+            repl_backlog_size: DEFAULT_BACKLOG_SIZE,
+            backlog: Default::default(),
             replicas: Default::default(),
             slave_read_repl_offset: Default::default(),
+            master_link: Default::default(),
+            acks_changed: Default::default(),
+            pending_sync: Default::default(),
+            rdb_snapshot: Default::default(),
+            failover: Default::default(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use tokio::{io::BufReader, sync::Mutex};
+    use tokio_test::io::{Builder, Mock};
+
+    use super::{ack::ReplicaAck, Replica, Replication};
+
+    #[tokio::test]
+    async fn test_proxy_message() {
+        let set = b"*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let mut replication: Replication<Mock> =
+            Replication::new(Some(("localhost".to_string(), 6379)));
+        replication.reset_history("a".repeat(40), 10).await;
+
+        //A sub-replica gets the stream of the master as is
+        let mock = Builder::new().write(set).build();
+        let (_, writer) = tokio::io::split(BufReader::new(mock));
+        let replica = Replica {
+            host: "localhost".to_string(),
+            port: 6381,
+            stream: Mutex::new(writer),
+            ack: Arc::new(ReplicaAck::default()),
+        };
+        assert!(replication.add_replica(replica, 10).await);
+        replication.proxy_message(set).await;
+        assert_eq!(replication.master_repl_offset, 10 + set.len() as u64);
+        assert!(replication.partial_resync(&"a".repeat(40), 11));
+
+        //The master was failed over, the old replid can still be continued
+        replication.continue_history(Some("b".repeat(40))).await;
+        assert_eq!(replication.connected_slaves, 0);
+        assert_eq!(replication.master_replid2, "a".repeat(40));
+        assert!(replication.partial_resync(&"a".repeat(40), 11));
+        assert!(replication.partial_resync(&"b".repeat(40), 11));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{Notify, RwLock, RwLockWriteGuard};
+
+use super::{replication::RWStream, Redis};
+
+//Same as the "shutdown-timeout" default on redis
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct ShutdownOptions {
+    //None means "save only if persistence is configured"
+    pub save: Option<bool>,
+    pub now: bool,
+    pub force: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    in_progress: AtomicBool,
+    aborted: AtomicBool,
+    completed: Notify,
+}
+
+impl Shutdown {
+    pub fn in_progress(&self) -> bool {
+        self.in_progress.load(Ordering::SeqCst)
+    }
+
+    pub fn abort(&self) -> bool {
+        if !self.in_progress() {
+            return false;
+        }
+        self.aborted.store(true, Ordering::SeqCst);
+        true
+    }
+
+    //Resolves once a shutdown went through and the process should exit
+    pub async fn wait(&self) {
+        self.completed.notified().await;
+    }
+
+    pub fn complete(&self) {
+        self.completed.notify_one();
+    }
+
+    fn begin(&self) -> bool {
+        self.aborted.store(false, Ordering::SeqCst);
+        !self.in_progress.swap(true, Ordering::SeqCst)
+    }
+
+    fn cancel(&self) {
+        self.aborted.store(false, Ordering::SeqCst);
+        self.in_progress.store(false, Ordering::SeqCst);
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+}
+
+/*
+ Runs the shutdown sequence shared by the SHUTDOWN command and the signal handlers:
+ stop accepting clients, let the replicas catch up (unless NOW), save the snapshot
+ and hand back the write lock so nothing else runs until the process exits.
+*/
+pub async fn run<S: RWStream>(
+    redis: &RwLock<Redis<S>>,
+    options: ShutdownOptions,
+) -> Result<RwLockWriteGuard<'_, Redis<S>>, String> {
+    let shutdown = redis.read().await.shutdown.clone();
+    if !shutdown.begin() {
+        return Err("ERR shutdown already in progress".to_string());
+    }
+
+    if !options.now {
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        let waiter = redis.write().await.replication.request_acks().await;
+        let replicas = waiter.replicas();
+        loop {
+            if shutdown.is_aborted() {
+                shutdown.cancel();
+                return Err("ERR Errors trying to SHUTDOWN. Check logs.".to_string());
+            }
+            //Short rounds so SHUTDOWN ABORT is noticed
+            let round = deadline
+                .saturating_duration_since(Instant::now())
+                .min(Duration::from_millis(100));
+            let synced = waiter.wait(replicas, Some(round)).await;
+            if synced >= replicas || Instant::now() >= deadline {
+                break;
+            }
+        }
+    }
+
+    let mut redis = redis.write().await;
+    if shutdown.is_aborted() {
+        shutdown.cancel();
+        return Err("ERR Errors trying to SHUTDOWN. Check logs.".to_string());
+    }
+
+    let save = options.save.unwrap_or(redis.has_persistence());
+    if save {
+        if let Err(e) = redis.save() {
+            println!("Error trying to save the DB: {}", e);
+            if !options.force {
+                shutdown.cancel();
+                return Err("ERR Errors trying to SHUTDOWN. Check logs.".to_string());
+            }
+        }
+    }
+
+    println!("Redis is now ready to exit, bye bye...");
+    Ok(redis)
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::sync::RwLock;
+    use tokio_test::io::Mock;
+
+    use crate::redis::{config::Config, value::ValueType, Redis};
+
+    use super::{run, ShutdownOptions};
+
+    #[tokio::test]
+    async fn test_shutdown_save() {
+        let dir =
+            std::env::temp_dir().join(format!("shutdown-{}", crate::util::gen_rand_string(8)));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = Config {
+            dir: Some(dir.to_string_lossy().to_string()),
+            db_file_name: Some("dump.rdb".to_string()),
+            ..Default::default()
+        };
+
+        let mut redis: Redis<Mock> = Redis::new(config.clone());
+        redis.set(
+            "key".to_string(),
+            ValueType::String("value".to_string()),
+            None,
+        );
+        let redis = RwLock::new(redis);
+
+        let options = ShutdownOptions {
+            now: true,
+            ..Default::default()
+        };
+        let guard = run(&redis, options).await;
+        assert!(guard.is_ok());
+        assert!(guard.unwrap().shutdown.in_progress());
+
+        let redis: Redis<Mock> = Redis::new(config);
+        assert_eq!(
+            redis.get_value("key"),
+            Some(&ValueType::String("value".to_string()))
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_abort() {
+        let redis: Redis<Mock> = Redis::new(Config::default());
+        let shutdown = redis.shutdown.clone();
+        assert!(!shutdown.abort());
+
+        let redis = RwLock::new(redis);
+        let guard = redis.read().await;
+        let options = ShutdownOptions {
+            save: Some(false),
+            ..Default::default()
+        };
+        let (result, _) = tokio::join!(run(&redis, options), async {
+            //Wait for the shutdown to start before aborting it
+            while !shutdown.in_progress() {
+                tokio::task::yield_now().await;
+            }
+            assert!(shutdown.abort());
+            drop(guard);
+        });
+        assert!(result.is_err());
+        assert!(!shutdown.in_progress());
+    }
+}
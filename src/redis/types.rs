@@ -13,6 +13,8 @@ pub enum RedisType {
     Array(Vec<RedisType>),
     NullArray,
     Bytes(Vec<u8>),
+    //Bulk string that is not valid utf-8 (e.g. DUMP payloads)
+    BulkBytes(Vec<u8>),
 }
 
 impl RedisType {
@@ -43,42 +45,78 @@ impl RedisType {
                 result.extend(value);
                 result
             }
+            RedisType::BulkBytes(value) => {
+                let mut result = format!("${}\r\n", value.len()).into_bytes();
+                result.extend(value);
+                result.extend(b"\r\n");
+                result
+            }
         }
     }
 
-    pub fn len(&self) -> usize {
-        match self {
-            RedisType::SimpleString(value) => value.len() + 3,
-            RedisType::SimpleError(value) => value.len() + 3,
-            RedisType::Integer(value) => value.to_string().len() + 3,
-            RedisType::BulkString(value) => {
-                //The String itself
-                let len = value.len();
-                //The value that's going to be used to represent the length of the string
-                let len_len = len.to_string().len();
-                //+4 for the 2 \r\n and +1  for the $
-                len + len_len + 5
-            }
-            RedisType::NullBulkString => 5,
-            RedisType::Array(values) => {
-                let mut result = 0;
-                let values_len = values.len();
-                for value in values {
-                    result += value.len();
+    /*
+     Length of the first complete value in the buffer, None if more bytes are needed.
+     Lets readers keep partial values around until the rest of them arrives.
+    */
+    pub fn frame_len(buffer: &[u8]) -> Result<Option<usize>, ()> {
+        let line_end = match buffer.windows(2).position(|w| w == b"\r\n") {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let header = &buffer[1..line_end];
+        let parse_len = || -> Result<i64, ()> {
+            std::str::from_utf8(header)
+                .map_err(|_| ())?
+                .parse::<i64>()
+                .map_err(|_| ())
+        };
+        let mut i = line_end + 2;
+        match buffer.first() {
+            Some(b'+') | Some(b'-') | Some(b':') => Ok(Some(i)),
+            Some(b'$') => {
+                let len = parse_len()?;
+                if len < 0 {
+                    return Ok(Some(i));
+                }
+                i += len as usize;
+                match buffer.get(i..i + 2) {
+                    Some(b"\r\n") => Ok(Some(i + 2)),
+                    //Bulk bytes without the trailing \r\n (the RDB file)
+                    Some(_) => Ok(Some(i)),
+                    None => Ok(None),
                 }
-                //+1 for the *, +2 for the \r\n, + the length of the array
-                result + values_len.to_string().len() + 3
             }
-            RedisType::NullArray => 5,
-            RedisType::Bytes(value) => {
-                let len = value.len();
-                let len_len = len.to_string().len();
-                //+2 for the \r\n and +1 for the $
-                len + len_len + 3
+            Some(b'*') => {
+                let len = parse_len()?;
+                for _ in 0..len.max(0) {
+                    match Self::frame_len(&buffer[i..])? {
+                        Some(element_len) => i += element_len,
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(i))
             }
+            _ => Err(()),
         }
     }
 
+    //Takes the complete values out of the buffer, a trailing partial one is left there
+    pub fn drain_frames(buffer: &mut Vec<u8>) -> Result<Vec<RedisType>, ()> {
+        let frames = Self::drain_raw_frames(buffer)?;
+        Ok(frames.into_iter().map(|(frame, _)| frame).collect())
+    }
+
+    //Same as drain_frames, along with the bytes each frame was parsed from
+    pub fn drain_raw_frames(buffer: &mut Vec<u8>) -> Result<Vec<(RedisType, Vec<u8>)>, ()> {
+        let mut result = vec![];
+        while let Some(len) = Self::frame_len(buffer)? {
+            let raw: Vec<u8> = buffer.drain(..len).collect();
+            let frame = Self::from_buffer(&raw)?.into_iter().next().ok_or(())?;
+            result.push((frame, raw));
+        }
+        Ok(result)
+    }
+
     fn inner_from_buffer(buffer: &[u8], result: &mut Vec<RedisType>) -> Result<(), ()> {
         if buffer.is_empty() {
             return Ok(());
@@ -272,8 +310,10 @@ impl RedisType {
 
             if next1 == Some(&b'\r') && next2 == Some(&b'\n') {
                 i = i + 2;
-                let str = std::str::from_utf8(&bytes).map_err(|_| ())?;
-                return Ok((i, Self::BulkString(str.to_string())));
+                return match String::from_utf8(bytes) {
+                    Ok(str) => Ok((i, Self::BulkString(str))),
+                    Err(e) => Ok((i, Self::BulkBytes(e.into_bytes()))),
+                };
             } else {
                 return Ok((i, Self::Bytes(bytes)));
             }
@@ -356,15 +396,18 @@ impl Display for RedisType {
                 write!(f, "{}", result)
             }
             RedisType::Bytes(_) => write!(f, "bytes",),
+            //One char per byte, so command arguments keep every byte of binary values
+            RedisType::BulkBytes(value) => {
+                let value: String = value.iter().map(|b| *b as char).collect();
+                write!(f, "{}", value)
+            }
         }
     }
 }
 
 impl<'a> From<&'a StreamData> for RedisType {
     fn from(value: &'a StreamData) -> Self {
-        let (first_id, second_id) = value.id;
-        let id = format!("{}-{}", first_id, second_id);
-        let mut vec = vec![RedisType::BulkString(id)];
+        let mut vec = vec![RedisType::BulkString(value.id.to_string())];
 
         let mut inner_vec = vec![];
         for (field, value) in &value.fields {
@@ -378,7 +421,10 @@ impl<'a> From<&'a StreamData> for RedisType {
 
 #[cfg(test)]
 mod test {
-    use super::RedisType;
+    use super::{
+        super::value::stream::{StreamData, StreamId},
+        RedisType,
+    };
 
     #[test]
     fn test_from_buffer() {
@@ -519,32 +565,60 @@ mod test {
     }
 
     #[test]
-    fn test_len() {
-        //$11\r\nhello world\r\ = 18
-        let hello_world = RedisType::BulkString("hello world".to_string());
-        assert_eq!(hello_world.len(), 18);
-        //+hello world\r\n = 14
-        let hello_world = RedisType::SimpleString("hello world".to_string());
-        assert_eq!(hello_world.len(), 14);
-        //-Error message\r\n = 6
-        let error = RedisType::SimpleError("Error message".to_string());
-        assert_eq!(error.len(), 16);
-        //$-1\r\n = 5
-        let null = RedisType::NullBulkString;
-        assert_eq!(null.len(), 5);
-
-        //:100\r\n = 6
-        let integer = RedisType::Integer(100);
-        assert_eq!(integer.len(), 6);
-        //:-3214\r\n = 8
-        let integer = RedisType::Integer(-3214);
-        assert_eq!(integer.len(), 8);
-
-        let result = RedisType::Array(vec![
-            RedisType::BulkString("SET".to_string()),
-            RedisType::BulkString("key".to_string()),
-            RedisType::BulkString("value".to_string()),
+    fn test_from_buffer_binary() {
+        let buffer = b"*2\r\n$4\r\nDUMP\r\n$3\r\n\x00\xff\xfe\r\n";
+        let result = RedisType::from_buffer(buffer).unwrap();
+        assert_eq!(
+            result,
+            vec![RedisType::Array(vec![
+                RedisType::BulkString("DUMP".to_string()),
+                RedisType::BulkBytes(vec![0x00, 0xff, 0xfe]),
+            ])]
+        );
+        let bytes = RedisType::BulkBytes(vec![0x00, 0xff, 0xfe]);
+        assert_eq!(bytes.encode(), b"$3\r\n\x00\xff\xfe\r\n");
+        assert_eq!(bytes.to_string(), "\u{0}\u{ff}\u{fe}");
+    }
+
+    #[test]
+    fn test_frame_len() {
+        let command = b"*2\r\n$4\r\nECHO\r\n$5\r\nhello\r\n";
+        for i in 0..command.len() {
+            assert_eq!(RedisType::frame_len(&command[..i]), Ok(None));
+        }
+        assert_eq!(RedisType::frame_len(command), Ok(Some(command.len())));
+        let mut buffer = command.to_vec();
+        buffer.extend(b"+OK\r\n");
+        assert_eq!(RedisType::frame_len(&buffer), Ok(Some(command.len())));
+        assert_eq!(RedisType::frame_len(b"$-1\r\n:1\r\n"), Ok(Some(5)));
+        assert_eq!(RedisType::frame_len(b"PING\r\n"), Err(()));
+
+        let frames = RedisType::drain_raw_frames(&mut buffer).unwrap();
+        assert_eq!(frames[0].1, command);
+        assert_eq!(frames[1].1, b"+OK\r\n");
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_from_stream_data() {
+        let data = StreamData {
+            id: StreamId::new(1, 2),
+            fields: vec![
+                ("b".to_string(), "1".to_string()),
+                ("a".to_string(), "2".to_string()),
+                ("b".to_string(), "3".to_string()),
+            ],
+        };
+        //Fields as XADD got them, repeated ones included
+        let expected = RedisType::Array(vec![
+            RedisType::BulkString("1-2".to_string()),
+            RedisType::Array(
+                ["b", "1", "a", "2", "b", "3"]
+                    .iter()
+                    .map(|s| RedisType::BulkString(s.to_string()))
+                    .collect(),
+            ),
         ]);
-        assert_eq!(result.len(), 33);
+        assert_eq!(RedisType::from(&data), expected);
     }
 }
@@ -1,19 +1,19 @@
 use std::time::SystemTime;
 
-use self::stream::StreamData;
+use self::stream::Stream;
 
 use super::types::RedisType;
 
 pub mod stream;
 
 #[allow(dead_code)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ValueType {
     String(String),
-    Stream(Vec<StreamData>),
+    Stream(Stream),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Value {
     pub value: ValueType,
     pub _created_at: SystemTime,
@@ -58,8 +58,8 @@ impl Into<RedisType> for Value {
             ValueType::String(s) => RedisType::BulkString(s),
             ValueType::Stream(s) => {
                 let mut result_vec = vec![];
-                for stream in &s {
-                    result_vec.push(stream.into());
+                for stream in s.entries.iter() {
+                    result_vec.push((&stream).into());
                 }
                 RedisType::Array(result_vec)
             }
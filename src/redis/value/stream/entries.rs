@@ -0,0 +1,556 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    ops::Bound,
+};
+
+use super::{StreamData, StreamId, Trim, TrimStrategy};
+
+//Same as the "stream-node-max-entries" and "stream-node-max-bytes" defaults on redis
+pub const NODE_MAX_ENTRIES: usize = 100;
+pub const NODE_MAX_BYTES: usize = 4096;
+
+//Flags every entry of a node starts with, the same ones redis uses
+pub const FLAG_DELETED: u8 = 1;
+pub const FLAG_SAME_FIELDS: u8 = 2;
+
+/*
+ The entries of a stream, split in nodes keyed by the ID of their first entry (the
+ master entry) so seeking an ID only walks one node. Inside a node every entry is
+ stored as its ID delta from the master ID, and entries with the same field names as
+ the master one only store their values. Deleting an entry only flags it, the node
+ goes once all its entries are deleted.
+*/
+#[derive(Clone, Default)]
+pub struct Entries {
+    nodes: BTreeMap<StreamId, Node>,
+    //Entries not deleted
+    len: usize,
+}
+
+#[derive(Clone)]
+pub struct Node {
+    //Field names of the master entry
+    pub master_fields: Vec<String>,
+    //Entries one after another: flags, ms delta, seq delta and then the fields
+    data: Vec<u8>,
+    //Entries in the node, deleted ones included
+    pub count: usize,
+    pub deleted: usize,
+    //ID of the last entry, deleted or not
+    last_id: StreamId,
+}
+
+//An entry of a node without its fields decoded
+#[derive(Debug, Clone, Copy)]
+pub struct Header {
+    pub flags: u8,
+    pub id: StreamId,
+    //Where the flags and the fields are in the node data
+    offset: usize,
+    fields: usize,
+}
+
+pub struct NodeIter<'a> {
+    node: &'a Node,
+    master: StreamId,
+    pos: usize,
+}
+
+impl Header {
+    pub fn is_deleted(&self) -> bool {
+        self.flags & FLAG_DELETED != 0
+    }
+}
+
+impl Node {
+    fn new(data: &StreamData) -> Self {
+        let mut node = Node {
+            master_fields: data.fields.iter().map(|(field, _)| field.clone()).collect(),
+            data: Vec::new(),
+            count: 0,
+            deleted: 0,
+            last_id: data.id,
+        };
+        node.append(data.id, data);
+        node
+    }
+
+    fn is_full(&self) -> bool {
+        self.count >= NODE_MAX_ENTRIES || self.data.len() >= NODE_MAX_BYTES
+    }
+
+    fn append(&mut self, master: StreamId, data: &StreamData) {
+        let same_fields = data.fields.len() == self.master_fields.len()
+            && data
+                .fields
+                .iter()
+                .zip(&self.master_fields)
+                .all(|((field, _), master)| field == master);
+        self.data
+            .push(if same_fields { FLAG_SAME_FIELDS } else { 0 });
+        write_varint(&mut self.data, data.id.ms - master.ms);
+        //The sequence goes back when the milliseconds change
+        write_varint(&mut self.data, zigzag(data.id.seq.wrapping_sub(master.seq)));
+        if same_fields {
+            for (_, value) in &data.fields {
+                write_bytes(&mut self.data, value.as_bytes());
+            }
+        } else {
+            write_varint(&mut self.data, data.fields.len() as u64);
+            for (field, value) in &data.fields {
+                write_bytes(&mut self.data, field.as_bytes());
+                write_bytes(&mut self.data, value.as_bytes());
+            }
+        }
+        self.count += 1;
+        self.last_id = data.id;
+    }
+
+    //Every entry, deleted ones included
+    pub fn iter(&self, master: StreamId) -> NodeIter<'_> {
+        NodeIter {
+            node: self,
+            master,
+            pos: 0,
+        }
+    }
+
+    pub fn fields(&self, header: &Header) -> Vec<(String, String)> {
+        let mut pos = header.fields;
+        if header.flags & FLAG_SAME_FIELDS != 0 {
+            return self
+                .master_fields
+                .iter()
+                .map(|field| (field.clone(), read_string(&self.data, &mut pos)))
+                .collect();
+        }
+        let len = read_varint(&self.data, &mut pos);
+        (0..len)
+            .map(|_| {
+                let field = read_string(&self.data, &mut pos);
+                (field, read_string(&self.data, &mut pos))
+            })
+            .collect()
+    }
+
+    pub fn entry(&self, header: &Header) -> StreamData {
+        StreamData {
+            id: header.id,
+            fields: self.fields(header),
+        }
+    }
+
+    //Flags the entry as deleted, returns true if the whole node is deleted
+    fn delete(&mut self, header: &Header) -> bool {
+        self.data[header.offset] |= FLAG_DELETED;
+        self.deleted += 1;
+        self.deleted == self.count
+    }
+
+    fn valid(&self) -> usize {
+        self.count - self.deleted
+    }
+}
+
+impl Iterator for NodeIter<'_> {
+    type Item = Header;
+
+    fn next(&mut self) -> Option<Header> {
+        let data = &self.node.data;
+        if self.pos >= data.len() {
+            return None;
+        }
+        let offset = self.pos;
+        let flags = data[offset];
+        let mut pos = offset + 1;
+        let ms = self.master.ms + read_varint(data, &mut pos);
+        let seq = self
+            .master
+            .seq
+            .wrapping_add(unzigzag(read_varint(data, &mut pos)));
+        let fields = pos;
+        //Skip the fields without decoding them
+        let strings = if flags & FLAG_SAME_FIELDS != 0 {
+            self.node.master_fields.len() as u64
+        } else {
+            read_varint(data, &mut pos) * 2
+        };
+        for _ in 0..strings {
+            let len = read_varint(data, &mut pos);
+            pos += len as usize;
+        }
+        self.pos = pos;
+        Some(Header {
+            flags,
+            id: StreamId::new(ms, seq),
+            offset,
+            fields,
+        })
+    }
+}
+
+impl Entries {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    //Nodes with the ID of their master entry
+    pub fn nodes(&self) -> impl Iterator<Item = (StreamId, &Node)> {
+        self.nodes.iter().map(|(master, node)| (*master, node))
+    }
+
+    //The ID has to be greater than the last one
+    pub fn push(&mut self, data: StreamData) {
+        self.len += 1;
+        if let Some(mut last) = self.nodes.last_entry() {
+            if !last.get().is_full() {
+                let master = *last.key();
+                last.get_mut().append(master, &data);
+                return;
+            }
+        }
+        self.nodes.insert(data.id, Node::new(&data));
+    }
+
+    pub fn first_id(&self) -> Option<StreamId> {
+        self.nodes
+            .iter()
+            .flat_map(|(master, node)| node.iter(*master))
+            .find(|header| !header.is_deleted())
+            .map(|header| header.id)
+    }
+
+    pub fn first(&self) -> Option<StreamData> {
+        self.iter().next()
+    }
+
+    pub fn last(&self) -> Option<StreamData> {
+        self.rev_range(StreamId::MIN, StreamId::MAX).next()
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<StreamData> {
+        let (master, node) = self.nodes.range(..=id).next_back()?;
+        if id > node.last_id {
+            return None;
+        }
+        node.iter(*master)
+            .find(|header| header.id >= id)
+            .filter(|header| header.id == id && !header.is_deleted())
+            .map(|header| node.entry(&header))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = StreamData> + '_ {
+        self.range(StreamId::MIN, StreamId::MAX)
+    }
+
+    //Entries between the two IDs, both included
+    pub fn range(&self, start: StreamId, end: StreamId) -> impl Iterator<Item = StreamData> + '_ {
+        self.nodes
+            .range(self.node_bounds(start, end))
+            .flat_map(|(master, node)| node.iter(*master).map(move |header| (node, header)))
+            .filter(|(_, header)| !header.is_deleted())
+            .skip_while(move |(_, header)| header.id < start)
+            .take_while(move |(_, header)| header.id <= end)
+            .map(|(node, header)| node.entry(&header))
+    }
+
+    //Same as range, from the end
+    pub fn rev_range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl Iterator<Item = StreamData> + '_ {
+        self.nodes
+            .range(self.node_bounds(start, end))
+            .rev()
+            .flat_map(move |(master, node)| {
+                //Entries can only be walked forward inside a node
+                let headers = node
+                    .iter(*master)
+                    .filter(|header| !header.is_deleted() && header.id >= start && header.id <= end)
+                    .collect::<Vec<_>>();
+                headers
+                    .into_iter()
+                    .rev()
+                    .map(move |header| node.entry(&header))
+            })
+    }
+
+    //Nodes that may have entries in the range, from the one where start would be
+    fn node_bounds(&self, start: StreamId, end: StreamId) -> (Bound<StreamId>, Bound<StreamId>) {
+        if start > end {
+            return (Bound::Included(end), Bound::Excluded(end));
+        }
+        let from = match self.nodes.range(..=start).next_back() {
+            Some((master, _)) => *master,
+            None => start,
+        };
+        (Bound::Included(from), Bound::Included(end))
+    }
+
+    //Entries with a greater ID
+    pub fn count_after(&self, id: StreamId) -> usize {
+        let whole = self
+            .nodes
+            .range((Bound::Excluded(id), Bound::Unbounded))
+            .map(|(_, node)| node.valid())
+            .sum::<usize>();
+        let partial = match self.nodes.range(..=id).next_back() {
+            Some((master, node)) => node
+                .iter(*master)
+                .filter(|header| !header.is_deleted() && header.id > id)
+                .count(),
+            None => 0,
+        };
+        whole + partial
+    }
+
+    //Returns false if there is no such entry
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        let Some((master, node)) = self.nodes.range_mut(..=id).next_back() else {
+            return false;
+        };
+        let master = *master;
+        let header = node
+            .iter(master)
+            .find(|header| header.id == id && !header.is_deleted());
+        let Some(header) = header else {
+            return false;
+        };
+        self.len -= 1;
+        if node.delete(&header) {
+            self.nodes.remove(&master);
+        }
+        true
+    }
+
+    /*
+     Whole nodes go first while the trim allows it, only an exact trim flags the
+     entries of the node where it stops. Returns how many entries were removed.
+    */
+    pub fn trim(&mut self, trim: &Trim) -> usize {
+        let mut removed = 0;
+        while let Some(mut first) = self.nodes.first_entry() {
+            let node = first.get();
+            let whole = match trim.strategy {
+                TrimStrategy::MaxLen(len) => (self.len - node.valid()) as u64 >= len,
+                TrimStrategy::MinId(id) => node.last_id < id,
+            };
+            if whole {
+                if trim.limit > 0 && removed + node.valid() > trim.limit {
+                    break;
+                }
+                removed += node.valid();
+                self.len -= node.valid();
+                first.remove();
+                continue;
+            }
+            if trim.approx {
+                break;
+            }
+            let master = *first.key();
+            let node = first.get_mut();
+            let headers = node.iter(master).collect::<Vec<_>>();
+            for header in headers.iter().filter(|header| !header.is_deleted()) {
+                let done = match trim.strategy {
+                    TrimStrategy::MaxLen(len) => self.len as u64 <= len,
+                    TrimStrategy::MinId(id) => header.id >= id,
+                };
+                if done {
+                    break;
+                }
+                removed += 1;
+                self.len -= 1;
+                if node.delete(header) {
+                    first.remove();
+                    break;
+                }
+            }
+            break;
+        }
+        removed
+    }
+}
+
+//Equal when they have the same entries, however they are split in nodes
+impl PartialEq for Entries {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len && self.iter().eq(other.iter())
+    }
+}
+
+impl Debug for Entries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("master_fields", &self.master_fields)
+            .field("count", &self.count)
+            .field("deleted", &self.deleted)
+            .field("last_id", &self.last_id)
+            .finish()
+    }
+}
+
+fn zigzag(n: u64) -> u64 {
+    let n = n as i64;
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> u64 {
+    (n >> 1) ^ (n & 1).wrapping_neg()
+}
+
+fn write_varint(data: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        data.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    data.push(n as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> u64 {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte < 0x80 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
+fn write_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(data, bytes.len() as u64);
+    data.extend_from_slice(bytes);
+}
+
+fn read_string(data: &[u8], pos: &mut usize) -> String {
+    let len = read_varint(data, pos) as usize;
+    let string = String::from_utf8_lossy(&data[*pos..*pos + len]).into_owned();
+    *pos += len;
+    string
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Entries, StreamData, StreamId, Trim, TrimStrategy, NODE_MAX_ENTRIES};
+
+    fn data(ms: u64, seq: u64, fields: &[(&str, &str)]) -> StreamData {
+        StreamData {
+            id: StreamId::new(ms, seq),
+            fields: fields
+                .iter()
+                .map(|(field, value)| (field.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn ids(entries: impl Iterator<Item = StreamData>) -> Vec<StreamId> {
+        entries.map(|data| data.id).collect()
+    }
+
+    #[test]
+    fn test_nodes() {
+        let mut entries = Entries::default();
+        for i in 1..=250 {
+            entries.push(data(i, 7, &[("a", "1"), ("b", &i.to_string())]));
+        }
+        //Other fields, a lower sequence and a big value in the last node
+        entries.push(data(251, 0, &[("b", "2"), ("a", "1"), ("a", "3")]));
+        entries.push(data(251, 1, &[("big", &"x".repeat(5000))]));
+        entries.push(data(251, 2, &[]));
+        assert_eq!(entries.len(), 253);
+        assert_eq!(entries.node_count(), 4);
+        let nodes = entries.nodes().collect::<Vec<_>>();
+        assert_eq!(nodes[0].0, StreamId::new(1, 7));
+        assert_eq!(nodes[0].1.count, NODE_MAX_ENTRIES);
+        assert_eq!(nodes[2].1.count, 52);
+        assert_eq!(nodes[3].0, StreamId::new(251, 2));
+
+        assert_eq!(
+            entries.get(StreamId::new(150, 7)),
+            Some(data(150, 7, &[("a", "1"), ("b", "150")]))
+        );
+        assert_eq!(
+            entries.get(StreamId::new(251, 0)),
+            Some(data(251, 0, &[("b", "2"), ("a", "1"), ("a", "3")]))
+        );
+        assert_eq!(entries.get(StreamId::new(150, 0)), None);
+        assert_eq!(entries.last(), Some(data(251, 2, &[])));
+        assert_eq!(
+            ids(entries.range(StreamId::new(99, 8), StreamId::new(102, 0))),
+            vec![StreamId::new(100, 7), StreamId::new(101, 7)]
+        );
+        assert_eq!(
+            ids(entries.rev_range(StreamId::new(250, 0), StreamId::new(251, 0))),
+            vec![StreamId::new(251, 0), StreamId::new(250, 7)]
+        );
+        assert_eq!(
+            entries
+                .range(StreamId::new(3, 0), StreamId::new(2, 0))
+                .count(),
+            0
+        );
+        assert_eq!(entries.count_after(StreamId::new(200, 7)), 53);
+    }
+
+    #[test]
+    fn test_remove_trim() {
+        let mut entries = Entries::default();
+        for i in 1..=250 {
+            entries.push(data(i, 0, &[]));
+        }
+        assert!(entries.remove(StreamId::new(1, 0)));
+        assert!(!entries.remove(StreamId::new(1, 0)));
+        assert!(!entries.remove(StreamId::new(1, 1)));
+        assert_eq!(entries.first_id(), Some(StreamId::new(2, 0)));
+        assert_eq!(entries.get(StreamId::new(1, 0)), None);
+        //The node goes with its last entry
+        for i in 2..=100 {
+            assert!(entries.remove(StreamId::new(i, 0)));
+        }
+        assert_eq!(entries.node_count(), 2);
+        assert_eq!(entries.len(), 150);
+
+        let approx = Trim {
+            strategy: TrimStrategy::MaxLen(50),
+            approx: true,
+            limit: 0,
+        };
+        assert_eq!(entries.trim(&approx), 100);
+        assert_eq!(entries.trim(&approx), 0);
+        //Only an exact trim goes into the node
+        let exact = Trim {
+            strategy: TrimStrategy::MaxLen(20),
+            approx: false,
+            limit: 0,
+        };
+        assert_eq!(entries.trim(&exact), 30);
+        assert_eq!(entries.first_id(), Some(StreamId::new(231, 0)));
+        assert_eq!(entries.node_count(), 1);
+        let exact = Trim {
+            strategy: TrimStrategy::MinId(StreamId::new(300, 0)),
+            ..exact
+        };
+        assert_eq!(entries.trim(&exact), 20);
+        assert!(entries.is_empty());
+        assert_eq!(entries.node_count(), 0);
+    }
+}
@@ -0,0 +1,472 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
+};
+
+pub mod entries;
+
+use entries::Entries;
+
+//Milliseconds and sequence number, ordered by the milliseconds first
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct StreamData {
+    pub id: StreamId,
+    //In the order XADD got them, field names may repeat
+    pub fields: Vec<(String, String)>,
+}
+
+//Entries sorted by ID and the consumer groups reading them
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Stream {
+    pub entries: Entries,
+    pub groups: BTreeMap<String, ConsumerGroup>,
+    //The greatest ID ever added, deleting entries doesn't lower it
+    pub last_id: StreamId,
+    //The greatest ID removed by XDEL
+    pub max_deleted_id: StreamId,
+    //Entries added over the stream lifetime, deleted ones included
+    pub entries_added: u64,
+}
+
+//How XADD and XTRIM cut the head of the stream
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TrimStrategy {
+    MaxLen(u64),
+    MinId(StreamId),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    //"~", only whole nodes are removed
+    pub approx: bool,
+    //Most entries an approximate trim removes, zero is no limit
+    pub limit: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ConsumerGroup {
+    //Entries up to this one were delivered to some consumer
+    pub last_id: StreamId,
+    //Entries the group read, None when it can't be known (e.g. after deletions)
+    pub entries_read: Option<u64>,
+    //Delivered but not acknowledged yet
+    pub pending: BTreeMap<StreamId, PendingEntry>,
+    pub consumers: BTreeMap<String, Consumer>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct PendingEntry {
+    pub consumer: String,
+    //Unix time in milliseconds of the last delivery
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Consumer {
+    //Unix time in milliseconds of the last read or claim attempt
+    pub seen_time: u64,
+    //Same, but only the attempts that got something
+    pub active_time: Option<u64>,
+    //The IDs this consumer has in the group pending entries
+    pub pending: BTreeSet<StreamId>,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId::new(0, 0);
+    pub const MAX: StreamId = StreamId::new(u64::MAX, u64::MAX);
+
+    pub const fn new(ms: u64, seq: u64) -> Self {
+        StreamId { ms, seq }
+    }
+
+    //The smallest greater ID, None after the greatest one
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    //The greatest smaller ID, None before the smallest one
+    pub fn previous(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+impl From<Vec<StreamData>> for Stream {
+    fn from(entries: Vec<StreamData>) -> Self {
+        let mut stream = Stream::default();
+        for data in entries {
+            stream.add(data);
+        }
+        stream
+    }
+}
+
+impl Stream {
+    pub fn first_id(&self) -> StreamId {
+        self.entries.first_id().unwrap_or(StreamId::MIN)
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<StreamData> {
+        self.entries.get(id)
+    }
+
+    //Entries with a greater ID, at most count of them
+    pub fn after(&self, id: StreamId, count: Option<usize>) -> Vec<StreamData> {
+        match id.next() {
+            Some(start) => self
+                .entries
+                .range(start, StreamId::MAX)
+                .take(count.unwrap_or(usize::MAX))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    //The ID has to be greater than the last one
+    pub fn add(&mut self, data: StreamData) {
+        self.last_id = data.id;
+        self.entries_added += 1;
+        self.entries.push(data);
+    }
+
+    //Returns false if there is no such entry
+    pub fn delete(&mut self, id: StreamId) -> bool {
+        if !self.entries.remove(id) {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    //Returns how many entries were removed
+    pub fn trim(&mut self, trim: &Trim) -> usize {
+        self.entries.trim(trim)
+    }
+
+    //Entries between the two IDs, both included
+    pub fn range(&self, start: StreamId, end: StreamId) -> impl Iterator<Item = StreamData> + '_ {
+        self.entries.range(start, end)
+    }
+
+    //Same as range, from the last one
+    pub fn rev_range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl Iterator<Item = StreamData> + '_ {
+        self.entries.rev_range(start, end)
+    }
+
+    /*
+     How many entries were added up to the ID, counting the deleted ones. Only known
+     when no entry after the first one was deleted, or for the end of the stream.
+    */
+    pub fn entries_read_at(&self, id: StreamId) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        if id > self.last_id {
+            return None;
+        }
+        if self.entries.is_empty() || id == self.last_id {
+            return Some(self.entries_added);
+        }
+        if self.has_tombstones(self.first_id()) {
+            return None;
+        }
+        let after = self.entries.count_after(id);
+        Some(self.entries_added - after as u64)
+    }
+
+    //The entries read of a group once it reads the ID
+    pub fn read_entry(&self, entries_read: Option<u64>, id: StreamId) -> Option<u64> {
+        match entries_read {
+            Some(read) if !self.has_tombstones(id) => Some(read + 1),
+            _ => self.entries_read_at(id),
+        }
+    }
+
+    //Entries the group has yet to read, None when it can't be known
+    pub fn lag(&self, group: &ConsumerGroup) -> Option<u64> {
+        if self.entries_added == 0 {
+            return Some(0);
+        }
+        let read = match group.entries_read {
+            Some(read) if !self.has_tombstones(group.last_id) => Some(read),
+            _ => self.entries_read_at(group.last_id),
+        };
+        read.map(|read| self.entries_added.saturating_sub(read))
+    }
+
+    //Entries deleted at or after the ID, as long as they were after the first one
+    fn has_tombstones(&self, id: StreamId) -> bool {
+        !self.entries.is_empty()
+            && self.max_deleted_id != StreamId::MIN
+            && self.max_deleted_id >= self.first_id()
+            && self.max_deleted_id >= id
+    }
+}
+
+impl ConsumerGroup {
+    pub fn new(last_id: StreamId) -> Self {
+        ConsumerGroup {
+            last_id,
+            ..Default::default()
+        }
+    }
+
+    //Returns true if the consumer didn't exist
+    pub fn create_consumer(&mut self, name: &str, now: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        let consumer = Consumer {
+            seen_time: now,
+            ..Default::default()
+        };
+        self.consumers.insert(name.to_string(), consumer);
+        true
+    }
+
+    //Returns the pending entries the consumer had
+    pub fn delete_consumer(&mut self, name: &str) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    //Gives the entry to the consumer, taking it from whoever had it
+    pub fn assign(
+        &mut self,
+        id: StreamId,
+        consumer: &str,
+        delivery_time: u64,
+        delivery_count: u64,
+    ) {
+        if let Some(previous) = self.pending.get(&id) {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        self.pending.insert(
+            id,
+            PendingEntry {
+                consumer: consumer.to_string(),
+                delivery_time,
+                delivery_count,
+            },
+        );
+        if let Some(owner) = self.consumers.get_mut(consumer) {
+            owner.pending.insert(id);
+        }
+    }
+
+    //Returns false if the entry wasn't pending
+    pub fn ack(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(entry) => {
+                if let Some(owner) = self.consumers.get_mut(&entry.consumer) {
+                    owner.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    //A read or claim from the consumer, active when it got something
+    pub fn seen(&mut self, consumer: &str, now: u64, active: bool) {
+        if let Some(consumer) = self.consumers.get_mut(consumer) {
+            consumer.seen_time = now;
+            if active {
+                consumer.active_time = Some(now);
+            }
+        }
+    }
+}
+
+//"ms-seq" or just "ms", the sequence is then the given one
+pub fn parse_id(id: &str, default_seq: u64) -> Option<StreamId> {
+    match id.split_once('-') {
+        Some((ms, seq)) => Some(StreamId::new(ms.parse().ok()?, seq.parse().ok()?)),
+        None => Some(StreamId::new(id.parse().ok()?, default_seq)),
+    }
+}
+
+/*
+ A bound of an ID range: "-" and "+" are the smallest and greatest IDs, a "(" prefix
+ makes it exclusive. A missing sequence covers the whole millisecond.
+*/
+pub fn parse_range_id(id: &str, start: bool) -> Option<StreamId> {
+    match id {
+        "-" => return Some(StreamId::MIN),
+        "+" => return Some(StreamId::MAX),
+        _ => {}
+    }
+    let default_seq = if start { 0 } else { u64::MAX };
+    match id.strip_prefix('(') {
+        Some(id) if start => parse_id(id, default_seq)?.next(),
+        Some(id) => parse_id(id, default_seq)?.previous(),
+        None => parse_id(id, default_seq),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        entries::NODE_MAX_ENTRIES, parse_id, parse_range_id, ConsumerGroup, Stream, StreamData,
+        StreamId, Trim, TrimStrategy,
+    };
+
+    #[test]
+    fn test_after() {
+        let entries = (1..=5)
+            .map(|i| StreamData {
+                id: StreamId::new(i, 0),
+                fields: vec![],
+            })
+            .collect::<Vec<_>>();
+        let stream = Stream::from(entries);
+        let ids = |entries: Vec<StreamData>| entries.iter().map(|e| e.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(stream.after(StreamId::new(2, 0), None)),
+            vec![
+                StreamId::new(3, 0),
+                StreamId::new(4, 0),
+                StreamId::new(5, 0)
+            ]
+        );
+        assert_eq!(
+            ids(stream.after(StreamId::new(0, 0), Some(2))),
+            vec![StreamId::new(1, 0), StreamId::new(2, 0)]
+        );
+        assert!(stream.after(StreamId::new(5, 0), None).is_empty());
+        assert!(stream.get(StreamId::new(3, 0)).is_some());
+        assert!(stream.get(StreamId::new(3, 1)).is_none());
+    }
+
+    #[test]
+    fn test_delete_trim() {
+        let entries = (1..=250)
+            .map(|i| StreamData {
+                id: StreamId::new(i, 0),
+                fields: vec![],
+            })
+            .collect::<Vec<_>>();
+        let mut stream = Stream::from(entries);
+        assert_eq!(stream.entries_read_at(StreamId::new(100, 0)), Some(100));
+
+        assert!(stream.delete(StreamId::new(250, 0)));
+        assert!(!stream.delete(StreamId::new(250, 0)));
+        assert_eq!(stream.last_id, StreamId::new(250, 0));
+        assert_eq!(stream.max_deleted_id, StreamId::new(250, 0));
+        //A deleted entry is after the first one
+        assert_eq!(stream.entries_read_at(StreamId::new(100, 0)), None);
+        assert_eq!(stream.entries_read_at(StreamId::new(250, 0)), Some(250));
+
+        let approx = Trim {
+            strategy: TrimStrategy::MaxLen(10),
+            approx: true,
+            limit: 0,
+        };
+        assert_eq!(stream.trim(&approx), 2 * NODE_MAX_ENTRIES);
+        let limited = Trim {
+            limit: 99,
+            ..approx
+        };
+        assert_eq!(stream.trim(&limited), 0);
+        let exact = Trim {
+            strategy: TrimStrategy::MinId(StreamId::new(240, 0)),
+            approx: false,
+            limit: 0,
+        };
+        assert_eq!(stream.trim(&exact), 39);
+        assert_eq!(stream.first_id(), StreamId::new(240, 0));
+        assert_eq!(
+            stream
+                .range(StreamId::new(0, 0), StreamId::new(241, 5))
+                .count(),
+            2
+        );
+        assert!(stream
+            .range(StreamId::new(245, 0), StreamId::new(241, 0))
+            .next()
+            .is_none());
+    }
+
+    #[test]
+    fn test_pending() {
+        let mut group = ConsumerGroup::new(StreamId::new(0, 0));
+        assert!(group.create_consumer("alice", 1));
+        assert!(!group.create_consumer("alice", 2));
+        group.create_consumer("bob", 1);
+
+        group.assign(StreamId::new(1, 0), "alice", 10, 1);
+        group.assign(StreamId::new(2, 0), "alice", 10, 1);
+        //Claimed by bob
+        group.assign(StreamId::new(1, 0), "bob", 20, 2);
+        assert_eq!(group.pending[&StreamId::new(1, 0)].consumer, "bob");
+        assert_eq!(group.consumers["alice"].pending.len(), 1);
+        assert_eq!(group.consumers["bob"].pending.len(), 1);
+
+        assert!(group.ack(StreamId::new(1, 0)));
+        assert!(!group.ack(StreamId::new(1, 0)));
+        assert!(group.consumers["bob"].pending.is_empty());
+
+        assert_eq!(group.delete_consumer("alice"), Some(1));
+        assert!(group.pending.is_empty());
+        assert_eq!(group.delete_consumer("alice"), None);
+    }
+
+    #[test]
+    fn test_parse_id() {
+        assert_eq!(parse_id("5-3", 0), Some(StreamId::new(5, 3)));
+        assert_eq!(parse_id("5", u64::MAX), Some(StreamId::new(5, u64::MAX)));
+        assert_eq!(parse_id("5-x", 0), None);
+        assert_eq!(parse_range_id("-", true), Some(StreamId::new(0, 0)));
+        assert_eq!(parse_range_id("5", false), Some(StreamId::new(5, u64::MAX)));
+        assert_eq!(parse_range_id("(5-3", true), Some(StreamId::new(5, 4)));
+        assert_eq!(
+            parse_range_id("(5-0", false),
+            Some(StreamId::new(4, u64::MAX))
+        );
+        assert_eq!(parse_range_id("(0-0", false), None);
+    }
+
+    #[test]
+    fn test_stream_id_order() {
+        //Milliseconds first, the sequence only breaks ties
+        assert!(StreamId::new(1, 7) < StreamId::new(2, 0));
+        assert!(StreamId::new(4, 3) < StreamId::new(5, 0));
+        assert!(StreamId::new(5, 0) < StreamId::new(5, 1));
+        assert_eq!(StreamId::new(4, u64::MAX).next(), Some(StreamId::new(5, 0)));
+        assert_eq!(
+            StreamId::new(5, 0).previous(),
+            Some(StreamId::new(4, u64::MAX))
+        );
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(StreamId::MIN.previous(), None);
+        assert_eq!(StreamId::new(5, 3).to_string(), "5-3");
+    }
+}
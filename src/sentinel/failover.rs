@@ -0,0 +1,367 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::RwLock, time::sleep};
+
+use crate::{redis::types::RedisType, util::gen_rand_number};
+
+use super::{link, Addr, Master, Sentinel};
+
+const TICK: Duration = Duration::from_millis(100);
+//How often the other sentinels are asked about the master while it looks down
+const ASK_PERIOD: Duration = Duration::from_secs(1);
+//Older answers from the other sentinels don't count
+const REPLY_VALIDITY: Duration = Duration::from_secs(5);
+const ELECTION_TIMEOUT: Duration = Duration::from_secs(10);
+//Replicas with an older INFO or PING reply are not promoted
+const REPLICA_VALIDITY: Duration = Duration::from_secs(5);
+const MAX_DESYNC_MS: u32 = 1000;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Stage {
+    //Waiting for the other sentinels to elect us
+    WaitStart,
+    SelectReplica,
+    //REPLICAOF NO ONE was sent, waiting for INFO to say it is a master
+    WaitPromotion(Addr),
+    ReconfReplicas(Addr),
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stage::WaitStart => write!(f, "wait_start"),
+            Stage::SelectReplica => write!(f, "select_slave"),
+            Stage::WaitPromotion(_) => write!(f, "wait_promotion"),
+            Stage::ReconfReplicas(_) => write!(f, "reconf_slaves"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Failover {
+    pub stage: Stage,
+    pub epoch: u64,
+    pub started: Instant,
+}
+
+//Random delay so sentinels that noticed the failure together don't all ask for votes at once
+pub fn desync() -> Duration {
+    Duration::from_millis((gen_rand_number() % MAX_DESYNC_MS) as u64)
+}
+
+/*
+ Decides when the master is down: subjectively when it doesn't answer PING for
+ down-after-milliseconds, objectively when the quorum of sentinels agrees.
+ Then the sentinels elect a leader that promotes the best replica.
+*/
+pub async fn watch(sentinel: &'static RwLock<Sentinel>, name: String) {
+    let mut last_ask: Option<Instant> = None;
+    loop {
+        sleep(TICK).await;
+        let mut guard = sentinel.write().await;
+        let Sentinel {
+            myid,
+            current_epoch,
+            masters,
+            ..
+        } = &mut *guard;
+        let master = match masters.get_mut(&name) {
+            Some(master) => master,
+            None => return,
+        };
+        check_down(master);
+
+        if master.master().s_down && last_ask.is_none_or(|last| last.elapsed() >= ASK_PERIOD) {
+            ask_sentinels(sentinel, master, myid, *current_epoch);
+            last_ask = Some(Instant::now());
+        }
+
+        if master.failover.is_some() {
+            step(master, myid);
+            continue;
+        }
+
+        let may_start = master.o_down
+            && master.failover_start.is_none_or(|start| {
+                Instant::now().saturating_duration_since(start) >= master.failover_timeout * 2
+            });
+        if !may_start {
+            continue;
+        }
+        //Someone else may ask for our vote meanwhile, then it's their failover
+        let vote = master.leader.clone();
+        drop(guard);
+        sleep(desync()).await;
+
+        let mut sentinel_state = sentinel.write().await;
+        let master = &sentinel_state.masters[&name];
+        if !master.o_down || master.failover.is_some() || master.leader != vote {
+            continue;
+        }
+        sentinel_state.current_epoch += 1;
+        let epoch = sentinel_state.current_epoch;
+        let myid = sentinel_state.myid.clone();
+        println!("+new-epoch {}", epoch);
+        let master = sentinel_state.masters.get_mut(&name).unwrap();
+        println!("+try-failover {}", master.describe(&master.addr));
+        master.failover = Some(Failover {
+            stage: Stage::WaitStart,
+            epoch,
+            started: Instant::now(),
+        });
+        master.failover_start = Some(Instant::now());
+        sentinel_state.vote(&name, &myid, epoch);
+        //Ask for votes right away
+        last_ask = None;
+    }
+}
+
+fn check_down(master: &mut Master) {
+    let down_after = master.down_after;
+    let mut events = vec![];
+    for (addr, instance) in master.instances.iter_mut() {
+        let s_down = instance.last_ok_ping.elapsed() > down_after;
+        if s_down != instance.s_down {
+            instance.s_down = s_down;
+            events.push((if s_down { "+sdown" } else { "-sdown" }, addr.clone()));
+        }
+    }
+    for (event, addr) in events {
+        println!("{} {}", event, master.describe(&addr));
+    }
+
+    let o_down = master.master().s_down && {
+        let agreeing = master
+            .sentinels
+            .values()
+            .filter(|peer| {
+                peer.master_down
+                    && peer
+                        .last_reply
+                        .is_some_and(|last| last.elapsed() < REPLY_VALIDITY)
+            })
+            .count();
+        agreeing + 1 >= master.quorum
+    };
+    if o_down != master.o_down {
+        master.o_down = o_down;
+        let event = if o_down { "+odown" } else { "-odown" };
+        println!(
+            "{} {} #quorum {}",
+            event,
+            master.describe(&master.addr),
+            master.quorum
+        );
+    }
+}
+
+//Asks every other sentinel if the master is down, and for its vote while we try a failover
+fn ask_sentinels(
+    sentinel: &'static RwLock<Sentinel>,
+    master: &Master,
+    myid: &str,
+    current_epoch: u64,
+) {
+    let runid = match &master.failover {
+        Some(failover) if failover.stage == Stage::WaitStart => myid.to_string(),
+        _ => "*".to_string(),
+    };
+    for (peer_id, peer) in master.sentinels.iter() {
+        let args = [
+            "SENTINEL".to_string(),
+            "is-master-down-by-addr".to_string(),
+            master.addr.0.clone(),
+            master.addr.1.to_string(),
+            current_epoch.to_string(),
+            runid.clone(),
+        ];
+        let name = master.name.clone();
+        let peer_id = peer_id.clone();
+        let addr = peer.addr.clone();
+        tokio::spawn(async move {
+            let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+            let values = match link::command(&addr, &args).await {
+                Ok(RedisType::Array(values)) if values.len() == 3 => values,
+                _ => return,
+            };
+            let (down, leader, epoch) = match (&values[0], &values[1], &values[2]) {
+                (RedisType::Integer(down), leader, RedisType::Integer(epoch)) => {
+                    (*down == 1, leader.to_string(), *epoch as u64)
+                }
+                _ => return,
+            };
+            let mut sentinel = sentinel.write().await;
+            let peer = sentinel
+                .masters
+                .get_mut(&name)
+                .and_then(|master| master.sentinels.get_mut(&peer_id));
+            if let Some(peer) = peer {
+                peer.master_down = down;
+                peer.last_reply = Some(Instant::now());
+                if leader != "*" {
+                    peer.leader = Some((leader, epoch));
+                }
+            }
+        });
+    }
+}
+
+fn step(master: &mut Master, myid: &str) {
+    let (stage, epoch, started) = match &master.failover {
+        Some(failover) => (failover.stage.clone(), failover.epoch, failover.started),
+        None => return,
+    };
+    let elapsed = started.elapsed();
+    match stage {
+        Stage::WaitStart => {
+            if master.leader(epoch).as_deref() == Some(myid) {
+                println!("+elected-leader {}", master.describe(&master.addr));
+                println!(
+                    "+failover-state-select-slave {}",
+                    master.describe(&master.addr)
+                );
+                master.failover.as_mut().unwrap().stage = Stage::SelectReplica;
+            } else if elapsed > ELECTION_TIMEOUT.min(master.failover_timeout) {
+                println!(
+                    "-failover-abort-not-elected {}",
+                    master.describe(&master.addr)
+                );
+                master.failover = None;
+            }
+        }
+        Stage::SelectReplica => match best_replica(master) {
+            Some(replica) => {
+                println!("+selected-slave {}", master.describe(&replica));
+                println!(
+                    "+failover-state-send-slaveof-noone {}",
+                    master.describe(&replica)
+                );
+                tokio::spawn(replica_of(replica.clone(), None));
+                master.failover.as_mut().unwrap().stage = Stage::WaitPromotion(replica);
+            }
+            //The replicas INFO is refreshed every second now, give it time to come in
+            None if elapsed <= master.failover_timeout => {}
+            None => {
+                println!(
+                    "-failover-abort-no-good-slave {}",
+                    master.describe(&master.addr)
+                );
+                master.failover = None;
+            }
+        },
+        Stage::WaitPromotion(replica) => {
+            let promoted = master.instances[&replica]
+                .info
+                .as_ref()
+                .is_some_and(|info| info.role == "master");
+            if promoted {
+                println!("+promoted-slave {}", master.describe(&replica));
+                println!(
+                    "+failover-state-reconf-slaves {}",
+                    master.describe(&master.addr)
+                );
+                master.failover.as_mut().unwrap().stage = Stage::ReconfReplicas(replica);
+            } else if elapsed > master.failover_timeout {
+                println!(
+                    "-failover-abort-slave-timeout {}",
+                    master.describe(&master.addr)
+                );
+                master.failover = None;
+            }
+        }
+        Stage::ReconfReplicas(promoted) => {
+            //The old master is reconfigured too once it comes back
+            let others: Vec<Addr> = master
+                .instances
+                .keys()
+                .filter(|addr| **addr != promoted)
+                .cloned()
+                .collect();
+            for addr in others {
+                println!("+slave-reconf-sent {}", master.describe(&addr));
+                tokio::spawn(replica_of(addr, Some(promoted.clone())));
+            }
+            println!("+failover-end {}", master.describe(&master.addr));
+            master.config_epoch = epoch;
+            master.switch(promoted);
+        }
+    }
+}
+
+//Reachable replicas with recent information, the most up to date one wins
+fn best_replica(master: &Master) -> Option<Addr> {
+    master
+        .replicas()
+        .filter(|(_, instance)| {
+            !instance.s_down
+                && instance.last_ok_ping.elapsed() < REPLICA_VALIDITY
+                && instance
+                    .info_refresh
+                    .is_some_and(|refresh| refresh.elapsed() < REPLICA_VALIDITY)
+        })
+        .filter_map(|(addr, instance)| {
+            let info = instance.info.as_ref()?;
+            (info.role == "slave").then_some((addr, info.offset))
+        })
+        .min_by(|(a_addr, a_offset), (b_addr, b_offset)| {
+            b_offset.cmp(a_offset).then_with(|| a_addr.cmp(b_addr))
+        })
+        .map(|(addr, _)| addr.clone())
+}
+
+//REPLICAOF to an instance, None promotes it
+pub async fn replica_of(addr: Addr, master: Option<Addr>) {
+    let (host, port) = match master {
+        Some((host, port)) => (host, port.to_string()),
+        None => ("NO".to_string(), "ONE".to_string()),
+    };
+    if let Err(e) = link::command(&addr, &["REPLICAOF", &host, &port]).await {
+        println!("REPLICAOF to {}:{} failed: {}", addr.0, addr.1, e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::sentinel::{Info, Instance, Master, Monitor};
+
+    use super::best_replica;
+
+    #[test]
+    fn test_best_replica() {
+        let monitor = Monitor {
+            name: "mymaster".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 6379,
+            quorum: 2,
+        };
+        let timeout = Duration::from_secs(1);
+        let mut master = Master::new(monitor, timeout, timeout);
+        assert_eq!(best_replica(&master), None);
+
+        for (port, offset) in [(6380, 10), (6381, 42), (6382, 42)] {
+            let mut instance = Instance::new();
+            instance.info = Some(Info {
+                role: "slave".to_string(),
+                offset,
+                ..Default::default()
+            });
+            instance.info_refresh = Some(instance.last_ok_ping);
+            master
+                .instances
+                .insert(("127.0.0.1".to_string(), port), instance);
+        }
+        assert_eq!(best_replica(&master), Some(("127.0.0.1".to_string(), 6381)));
+
+        let down = master
+            .instances
+            .get_mut(&("127.0.0.1".to_string(), 6381))
+            .unwrap();
+        down.s_down = true;
+        assert_eq!(best_replica(&master), Some(("127.0.0.1".to_string(), 6382)));
+    }
+}
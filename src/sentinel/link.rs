@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+
+use crate::redis::types::RedisType;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+//Connection to a monitored instance or to another sentinel
+pub struct Link {
+    stream: TcpStream,
+    //Bytes of a reply that didn't arrive completely yet
+    buffer: Vec<u8>,
+}
+
+impl Link {
+    pub async fn connect(addr: &(String, u16)) -> Result<Link> {
+        let stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await??;
+        Ok(Link {
+            stream,
+            buffer: vec![],
+        })
+    }
+
+    pub async fn send(&mut self, args: &[&str]) -> Result<()> {
+        let command = RedisType::Array(
+            args.iter()
+                .map(|arg| RedisType::BulkString(arg.to_string()))
+                .collect(),
+        );
+        self.stream.write_all(&command.encode()).await?;
+        Ok(())
+    }
+
+    //Waits for the next value, pushed messages of a subscribed link included
+    pub async fn read(&mut self) -> Result<RedisType> {
+        let mut buf = [0; 512];
+        loop {
+            let frame_len = match RedisType::frame_len(&self.buffer) {
+                Ok(frame_len) => frame_len,
+                Err(_) => bail!("Protocol error"),
+            };
+            if let Some(frame_len) = frame_len {
+                let frame: Vec<u8> = self.buffer.drain(..frame_len).collect();
+                return match RedisType::from_buffer(&frame) {
+                    Ok(mut values) if !values.is_empty() => Ok(values.remove(0)),
+                    _ => bail!("Protocol error"),
+                };
+            }
+            let n = self.stream.read(&mut buf).await?;
+            if n == 0 {
+                bail!("Connection closed");
+            }
+            self.buffer.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    pub async fn command(&mut self, args: &[&str]) -> Result<RedisType> {
+        self.send(args).await?;
+        timeout(REPLY_TIMEOUT, self.read()).await?
+    }
+}
+
+//One shot command on a new connection
+pub async fn command(addr: &(String, u16), args: &[&str]) -> Result<RedisType> {
+    let mut link = Link::connect(addr).await?;
+    link.command(args).await
+}
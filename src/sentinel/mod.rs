@@ -0,0 +1,337 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use tokio::{net::TcpListener, sync::RwLock};
+
+use crate::{args::Args, util::gen_rand_string, HOST};
+
+use self::failover::Failover;
+
+mod failover;
+mod link;
+mod monitor;
+mod server;
+
+//Sentinels find each other through this channel of the monitored instances
+const HELLO_CHANNEL: &str = "__sentinel__:hello";
+
+pub type Addr = (String, u16);
+
+//A master given with --monitor <name> <host> <port> <quorum>
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    pub quorum: usize,
+}
+
+#[derive(Debug)]
+pub struct Sentinel {
+    pub myid: String,
+    pub addr: Addr,
+    pub current_epoch: u64,
+    pub masters: BTreeMap<String, Master>,
+}
+
+#[derive(Debug)]
+pub struct Master {
+    pub name: String,
+    pub addr: Addr,
+    pub quorum: usize,
+    pub down_after: Duration,
+    pub failover_timeout: Duration,
+    //Epoch of the failover that made addr the master
+    pub config_epoch: u64,
+    //The master and its replicas, an instance is monitored while it is here
+    pub instances: BTreeMap<Addr, Instance>,
+    //Other sentinels monitoring this master by run id
+    pub sentinels: BTreeMap<String, Peer>,
+    pub o_down: bool,
+    //Our vote in the last election (run id, epoch)
+    pub leader: Option<(String, u64)>,
+    pub failover: Option<Failover>,
+    //A new failover isn't tried until twice the failover timeout after this
+    pub failover_start: Option<Instant>,
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    pub last_ok_ping: Instant,
+    pub s_down: bool,
+    pub info: Option<Info>,
+    pub info_refresh: Option<Instant>,
+}
+
+//What an instance reports in INFO replication
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Info {
+    pub role: String,
+    pub master: Option<Addr>,
+    pub master_link_up: bool,
+    pub offset: u64,
+    pub replicas: Vec<Addr>,
+}
+
+#[derive(Debug)]
+pub struct Peer {
+    pub addr: Addr,
+    pub last_hello: Instant,
+    //Last answer to SENTINEL is-master-down-by-addr
+    pub master_down: bool,
+    pub last_reply: Option<Instant>,
+    pub leader: Option<(String, u64)>,
+}
+
+impl Sentinel {
+    fn new(port: u16) -> Sentinel {
+        Sentinel {
+            myid: gen_rand_string(40),
+            addr: (HOST.to_string(), port),
+            current_epoch: 0,
+            masters: BTreeMap::new(),
+        }
+    }
+
+    //Payload published on the hello channel of every instance of the master
+    fn hello(&self, master: &Master) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{}",
+            self.addr.0,
+            self.addr.1,
+            self.myid,
+            self.current_epoch,
+            master.name,
+            master.addr.0,
+            master.addr.1,
+            master.config_epoch
+        )
+    }
+
+    //The first sentinel asking in an epoch gets our vote, returns the vote we gave
+    fn vote(&mut self, name: &str, runid: &str, epoch: u64) -> Option<(String, u64)> {
+        if epoch > self.current_epoch {
+            self.current_epoch = epoch;
+            println!("+new-epoch {}", epoch);
+        }
+        let myid = self.myid.clone();
+        let master = self.masters.get_mut(name)?;
+        if master.leader.as_ref().is_none_or(|(_, e)| *e < epoch) {
+            master.leader = Some((runid.to_string(), epoch));
+            println!("+vote-for-leader {} {}", runid, epoch);
+            //Give the sentinel we voted for time to finish before trying ourselves
+            if runid != myid {
+                master.failover_start = Some(Instant::now() + failover::desync());
+            }
+        }
+        master.leader.clone()
+    }
+}
+
+impl Master {
+    fn new(monitor: Monitor, down_after: Duration, failover_timeout: Duration) -> Master {
+        let addr = (monitor.host, monitor.port);
+        Master {
+            name: monitor.name,
+            addr: addr.clone(),
+            quorum: monitor.quorum,
+            down_after,
+            failover_timeout,
+            config_epoch: 0,
+            instances: BTreeMap::from([(addr, Instance::new())]),
+            sentinels: BTreeMap::new(),
+            o_down: false,
+            leader: None,
+            failover: None,
+            failover_start: None,
+        }
+    }
+
+    pub fn master(&self) -> &Instance {
+        &self.instances[&self.addr]
+    }
+
+    pub fn tracks(&self, addr: &Addr) -> bool {
+        self.instances.contains_key(addr)
+    }
+
+    pub fn replicas(&self) -> impl Iterator<Item = (&Addr, &Instance)> {
+        self.instances
+            .iter()
+            .filter(|(addr, _)| **addr != self.addr)
+    }
+
+    //Instance name as it shows up in the events
+    fn describe(&self, addr: &Addr) -> String {
+        if *addr == self.addr {
+            return format!("master {} {} {}", self.name, addr.0, addr.1);
+        }
+        format!(
+            "slave {}:{} {} {} @ {} {} {}",
+            addr.0, addr.1, addr.0, addr.1, self.name, self.addr.0, self.addr.1
+        )
+    }
+
+    fn flags(&self) -> String {
+        let mut flags = "master".to_string();
+        if self.master().s_down {
+            flags.push_str(",s_down");
+        }
+        if self.o_down {
+            flags.push_str(",o_down");
+        }
+        if self.failover.is_some() {
+            flags.push_str(",failover_in_progress");
+        }
+        flags
+    }
+
+    //Winner of the election in epoch, it needs a majority of the sentinels and the quorum
+    fn leader(&self, epoch: u64) -> Option<String> {
+        let mut votes: HashMap<&str, usize> = HashMap::new();
+        let peer_votes = self
+            .sentinels
+            .values()
+            .filter_map(|peer| peer.leader.as_ref());
+        for (runid, vote_epoch) in peer_votes.chain(self.leader.as_ref()) {
+            if *vote_epoch == epoch {
+                *votes.entry(runid).or_default() += 1;
+            }
+        }
+        let voters = self.sentinels.len() + 1;
+        let needed = self.quorum.max(voters / 2 + 1);
+        votes
+            .into_iter()
+            .filter(|(_, count)| *count >= needed)
+            .map(|(runid, _)| runid.to_string())
+            .next()
+    }
+
+    //Returns true if the new master wasn't monitored yet
+    fn switch(&mut self, addr: Addr) -> bool {
+        println!(
+            "+switch-master {} {} {} {} {}",
+            self.name, self.addr.0, self.addr.1, addr.0, addr.1
+        );
+        self.addr = addr.clone();
+        self.o_down = false;
+        self.failover = None;
+        for peer in self.sentinels.values_mut() {
+            peer.master_down = false;
+        }
+        if self.tracks(&addr) {
+            return false;
+        }
+        self.instances.insert(addr, Instance::new());
+        true
+    }
+}
+
+impl Instance {
+    fn new() -> Instance {
+        Instance {
+            last_ok_ping: Instant::now(),
+            s_down: false,
+            info: None,
+            info_refresh: None,
+        }
+    }
+}
+
+impl Peer {
+    fn new(addr: Addr) -> Peer {
+        Peer {
+            addr,
+            last_hello: Instant::now(),
+            master_down: false,
+            last_reply: None,
+            leader: None,
+        }
+    }
+}
+
+/*
+ Sentinel mode: monitors the masters given with --monitor and their replicas,
+ agrees with the other sentinels when a master is down and promotes one
+ of its replicas, clients ask us where the master is.
+*/
+pub async fn run(args: Args) -> Result<()> {
+    let listener = TcpListener::bind(format!("{}:{}", HOST, args.port)).await?;
+    let mut sentinel = Sentinel::new(args.port);
+    let down_after = Duration::from_millis(args.down_after_milliseconds);
+    let failover_timeout = Duration::from_millis(args.failover_timeout);
+    for monitor in args.monitors {
+        println!(
+            "+monitor master {} {} {} quorum {}",
+            monitor.name, monitor.host, monitor.port, monitor.quorum
+        );
+        let master = Master::new(monitor, down_after, failover_timeout);
+        sentinel.masters.insert(master.name.clone(), master);
+    }
+    println!("Sentinel ID is {}", sentinel.myid);
+
+    let sentinel: &'static RwLock<Sentinel> = Box::leak(Box::new(RwLock::new(sentinel)));
+    for (name, master) in sentinel.read().await.masters.iter() {
+        tokio::spawn(monitor::run(sentinel, name.clone(), master.addr.clone()));
+        tokio::spawn(failover::watch(sentinel, name.clone()));
+    }
+    server::serve(listener, sentinel).await
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{Master, Monitor, Peer, Sentinel};
+
+    fn sentinel() -> Sentinel {
+        let mut sentinel = Sentinel::new(26379);
+        let monitor = Monitor {
+            name: "mymaster".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 6379,
+            quorum: 2,
+        };
+        let timeout = Duration::from_secs(1);
+        let master = Master::new(monitor, timeout, timeout);
+        sentinel.masters.insert(master.name.clone(), master);
+        sentinel
+    }
+
+    #[test]
+    fn test_vote() {
+        let mut sentinel = sentinel();
+        let vote = Some(("a".to_string(), 1));
+        assert_eq!(sentinel.vote("mymaster", "a", 1), vote);
+        assert_eq!(sentinel.current_epoch, 1);
+        //One vote per epoch
+        assert_eq!(sentinel.vote("mymaster", "b", 1), vote);
+        assert_eq!(
+            sentinel.vote("mymaster", "b", 2),
+            Some(("b".to_string(), 2))
+        );
+        assert_eq!(sentinel.vote("unknown", "b", 2), None);
+    }
+
+    #[test]
+    fn test_leader() {
+        let mut sentinel = sentinel();
+        let myid = sentinel.myid.clone();
+        sentinel.vote("mymaster", &myid, 1);
+        let master = sentinel.masters.get_mut("mymaster").unwrap();
+        for (runid, vote) in [("b", "b"), ("c", &myid)] {
+            let mut peer = Peer::new(("127.0.0.1".to_string(), 26380));
+            peer.leader = Some((vote.to_string(), 1));
+            master.sentinels.insert(runid.to_string(), peer);
+        }
+        assert_eq!(master.leader(1), Some(myid.clone()));
+        assert_eq!(master.leader(2), None);
+
+        //Votes from other epochs don't count, one vote isn't a majority of three sentinels
+        master.sentinels.get_mut("c").unwrap().leader = Some((myid.clone(), 0));
+        assert_eq!(master.leader(1), None);
+    }
+}
@@ -0,0 +1,360 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use tokio::{
+    sync::RwLock,
+    time::{interval, sleep, timeout},
+};
+
+use crate::redis::types::RedisType;
+
+use super::{link::Link, Addr, Info, Instance, Peer, Sentinel, HELLO_CHANNEL};
+
+const PING_PERIOD: Duration = Duration::from_secs(1);
+const INFO_PERIOD: Duration = Duration::from_secs(10);
+const HELLO_PERIOD: Duration = Duration::from_secs(2);
+//How long the hello subscription waits before checking if the instance is still monitored
+const SUBSCRIBE_CHECK: Duration = Duration::from_secs(5);
+
+//A sentinel announcing itself and its view of a master
+#[derive(Debug, PartialEq)]
+struct Hello {
+    addr: Addr,
+    runid: String,
+    current_epoch: u64,
+    master_name: String,
+    master_addr: Addr,
+    config_epoch: u64,
+}
+
+/*
+ Watches one instance (the master or one of its replicas) while it is monitored:
+ PING every second, INFO replication to learn its role and replicas, and a hello
+ published so the other sentinels know about us.
+*/
+pub async fn run(sentinel: &'static RwLock<Sentinel>, name: String, addr: Addr) {
+    tokio::spawn(subscribe_hello(sentinel, name.clone(), addr.clone()));
+    while tracked(sentinel, &name, &addr).await {
+        if let Ok(link) = Link::connect(&addr).await {
+            let _ = poll(sentinel, &name, &addr, link).await;
+        }
+        sleep(PING_PERIOD).await;
+    }
+}
+
+//Instances found later are monitored by tasks of their own
+fn spawn(sentinel: &'static RwLock<Sentinel>, name: String, addr: Addr) {
+    tokio::spawn(run(sentinel, name, addr));
+}
+
+async fn tracked(sentinel: &RwLock<Sentinel>, name: &str, addr: &Addr) -> bool {
+    let sentinel = sentinel.read().await;
+    sentinel
+        .masters
+        .get(name)
+        .is_some_and(|master| master.tracks(addr))
+}
+
+async fn poll(
+    sentinel: &'static RwLock<Sentinel>,
+    name: &str,
+    addr: &Addr,
+    mut link: Link,
+) -> Result<()> {
+    let mut last_info: Option<Instant> = None;
+    let mut last_hello: Option<Instant> = None;
+    let mut ping = interval(PING_PERIOD);
+    loop {
+        ping.tick().await;
+        let (info_period, hello) = {
+            let sentinel = sentinel.read().await;
+            let master = match sentinel.masters.get(name) {
+                Some(master) if master.tracks(addr) => master,
+                _ => return Ok(()),
+            };
+            //The replicas are followed closely while the master is down or being replaced
+            let info_period = if master.master().s_down || master.failover.is_some() {
+                PING_PERIOD
+            } else {
+                INFO_PERIOD
+            };
+            (info_period, sentinel.hello(master))
+        };
+
+        let reply = link.command(&["PING"]).await?;
+        if valid_ping(&reply) {
+            let mut sentinel = sentinel.write().await;
+            let instance = sentinel
+                .masters
+                .get_mut(name)
+                .and_then(|master| master.instances.get_mut(addr));
+            if let Some(instance) = instance {
+                instance.last_ok_ping = Instant::now();
+            }
+        }
+
+        if last_info.is_none_or(|last| last.elapsed() >= info_period) {
+            let reply = link.command(&["INFO", "replication"]).await?;
+            handle_info(sentinel, name, addr, parse_info(&reply.to_string())).await;
+            last_info = Some(Instant::now());
+        }
+
+        if last_hello.is_none_or(|last| last.elapsed() >= HELLO_PERIOD) {
+            link.command(&["PUBLISH", HELLO_CHANNEL, &hello]).await?;
+            last_hello = Some(Instant::now());
+        }
+    }
+}
+
+//An instance that is loading or lost its master still counts as reachable
+fn valid_ping(reply: &RedisType) -> bool {
+    match reply {
+        RedisType::SimpleString(pong) | RedisType::BulkString(pong) => pong == "PONG",
+        RedisType::SimpleError(e) => e.starts_with("LOADING") || e.starts_with("MASTERDOWN"),
+        _ => false,
+    }
+}
+
+fn parse_info(info: &str) -> Info {
+    let mut parsed = Info::default();
+    let mut master_host = None;
+    let mut master_port = None;
+    for line in info.lines() {
+        let (key, value) = match line.trim().split_once(':') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        match key {
+            "role" => parsed.role = value.to_string(),
+            "master_host" => master_host = Some(value.to_string()),
+            "master_port" => master_port = value.parse().ok(),
+            "master_link_status" => parsed.master_link_up = value == "up",
+            "master_repl_offset" if parsed.role == "master" => {
+                parsed.offset = value.parse().unwrap_or_default()
+            }
+            "slave_repl_offset" | "slave_read_repl_offset" => {
+                parsed.offset = value.parse().unwrap_or_default()
+            }
+            //slave0:ip=127.0.0.1,port=6380,state=online,offset=42,lag=0
+            key if key.starts_with("slave") && key[5..].parse::<usize>().is_ok() => {
+                let mut ip = None;
+                let mut port = None;
+                for field in value.split(',') {
+                    match field.split_once('=') {
+                        Some(("ip", v)) => ip = Some(v.to_string()),
+                        Some(("port", v)) => port = v.parse().ok(),
+                        _ => {}
+                    }
+                }
+                if let (Some(ip), Some(port)) = (ip, port) {
+                    parsed.replicas.push((ip, port));
+                }
+            }
+            _ => {}
+        }
+    }
+    if let (Some(host), Some(port)) = (master_host, master_port) {
+        parsed.master = Some((host, port));
+    }
+    parsed
+}
+
+async fn handle_info(
+    sentinel_lock: &'static RwLock<Sentinel>,
+    name: &str,
+    addr: &Addr,
+    info: Info,
+) {
+    let mut sentinel = sentinel_lock.write().await;
+    let master = match sentinel.masters.get_mut(name) {
+        Some(master) => master,
+        None => return,
+    };
+    match master.instances.get_mut(addr) {
+        Some(instance) => {
+            instance.info = Some(info.clone());
+            instance.info_refresh = Some(Instant::now());
+        }
+        None => return,
+    }
+
+    if *addr == master.addr {
+        for replica in info.replicas {
+            if master.tracks(&replica) {
+                continue;
+            }
+            master.instances.insert(replica.clone(), Instance::new());
+            println!("+slave {}", master.describe(&replica));
+            spawn(sentinel_lock, name.to_string(), replica);
+        }
+        return;
+    }
+
+    //A replica following someone else (an old master that came back), point it to the master
+    let wrong_master = info.role == "master" || info.master.as_ref() != Some(&master.addr);
+    let master_ok = !master.master().s_down
+        && master.failover.is_none()
+        && master
+            .master()
+            .info
+            .as_ref()
+            .is_some_and(|info| info.role == "master");
+    if wrong_master && master_ok {
+        let event = if info.role == "master" {
+            "+convert-to-slave"
+        } else {
+            "+fix-slave-config"
+        };
+        println!("{} {}", event, master.describe(addr));
+        tokio::spawn(super::failover::replica_of(
+            addr.clone(),
+            Some(master.addr.clone()),
+        ));
+    }
+}
+
+async fn subscribe_hello(sentinel: &'static RwLock<Sentinel>, name: String, addr: Addr) {
+    while tracked(sentinel, &name, &addr).await {
+        if let Ok(link) = Link::connect(&addr).await {
+            let _ = receive_hellos(sentinel, &name, &addr, link).await;
+        }
+        sleep(PING_PERIOD).await;
+    }
+}
+
+async fn receive_hellos(
+    sentinel: &'static RwLock<Sentinel>,
+    name: &str,
+    addr: &Addr,
+    mut link: Link,
+) -> Result<()> {
+    link.send(&["SUBSCRIBE", HELLO_CHANNEL]).await?;
+    loop {
+        let message = timeout(SUBSCRIBE_CHECK, link.read()).await;
+        if !tracked(sentinel, name, addr).await {
+            return Ok(());
+        }
+        let values = match message {
+            Ok(Ok(RedisType::Array(values))) => values,
+            Ok(Ok(_)) | Err(_) => continue,
+            Ok(Err(e)) => return Err(e),
+        };
+        if values.len() != 3 || values[0].to_string() != "message" {
+            continue;
+        }
+        if let Some(hello) = parse_hello(&values[2].to_string()) {
+            handle_hello(sentinel, hello).await;
+        }
+    }
+}
+
+fn parse_hello(payload: &str) -> Option<Hello> {
+    let fields: Vec<&str> = payload.split(',').collect();
+    if fields.len() != 8 {
+        return None;
+    }
+    Some(Hello {
+        addr: (fields[0].to_string(), fields[1].parse().ok()?),
+        runid: fields[2].to_string(),
+        current_epoch: fields[3].parse().ok()?,
+        master_name: fields[4].to_string(),
+        master_addr: (fields[5].to_string(), fields[6].parse().ok()?),
+        config_epoch: fields[7].parse().ok()?,
+    })
+}
+
+async fn handle_hello(sentinel_lock: &'static RwLock<Sentinel>, hello: Hello) {
+    let mut sentinel = sentinel_lock.write().await;
+    if hello.runid == sentinel.myid {
+        return;
+    }
+    if hello.current_epoch > sentinel.current_epoch {
+        sentinel.current_epoch = hello.current_epoch;
+        println!("+new-epoch {}", hello.current_epoch);
+    }
+    let master = match sentinel.masters.get_mut(&hello.master_name) {
+        Some(master) => master,
+        None => return,
+    };
+
+    //A sentinel that restarted shows up on the same address with a new id
+    master
+        .sentinels
+        .retain(|runid, peer| peer.addr != hello.addr || *runid == hello.runid);
+    if !master.sentinels.contains_key(&hello.runid) {
+        println!(
+            "+sentinel sentinel {} {} {} @ {} {} {}",
+            hello.runid, hello.addr.0, hello.addr.1, master.name, master.addr.0, master.addr.1
+        );
+    }
+    master
+        .sentinels
+        .entry(hello.runid)
+        .or_insert_with(|| Peer::new(hello.addr))
+        .last_hello = Instant::now();
+
+    //Another sentinel did a failover we missed
+    if hello.config_epoch > master.config_epoch {
+        master.config_epoch = hello.config_epoch;
+        if hello.master_addr != master.addr {
+            println!("+config-update-from {}", master.name);
+            if master.switch(hello.master_addr.clone()) {
+                let name = master.name.clone();
+                spawn(sentinel_lock, name, hello.master_addr);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::sentinel::Info;
+
+    use super::{parse_hello, parse_info, Hello};
+
+    #[test]
+    fn test_parse_info() {
+        let info = "# Replication\r\nrole:master\r\nconnected_slaves:2\r\n\
+            slave0:ip=127.0.0.1,port=6380,state=online,offset=42,lag=0\r\n\
+            slave1:ip=127.0.0.1,port=6381,state=online,offset=40,lag=1\r\n\
+            master_replid:8371b4fb1155b71f4a04d3e1bc3e18c4a990aeeb\r\nmaster_repl_offset:42\r\n";
+        let expected = Info {
+            role: "master".to_string(),
+            master: None,
+            master_link_up: false,
+            offset: 42,
+            replicas: vec![
+                ("127.0.0.1".to_string(), 6380),
+                ("127.0.0.1".to_string(), 6381),
+            ],
+        };
+        assert_eq!(parse_info(info), expected);
+
+        let info = "# Replication\nrole:slave\nmaster_host:127.0.0.1\nmaster_port:6379\n\
+            master_link_status:up\nslave_read_repl_offset:40\nconnected_slaves:0\n\
+            master_repl_offset:40\n";
+        let expected = Info {
+            role: "slave".to_string(),
+            master: Some(("127.0.0.1".to_string(), 6379)),
+            master_link_up: true,
+            offset: 40,
+            replicas: vec![],
+        };
+        assert_eq!(parse_info(info), expected);
+    }
+
+    #[test]
+    fn test_parse_hello() {
+        let hello = parse_hello("127.0.0.1,26380,abc,3,mymaster,127.0.0.1,6379,2");
+        let expected = Hello {
+            addr: ("127.0.0.1".to_string(), 26380),
+            runid: "abc".to_string(),
+            current_epoch: 3,
+            master_name: "mymaster".to_string(),
+            master_addr: ("127.0.0.1".to_string(), 6379),
+            config_epoch: 2,
+        };
+        assert_eq!(hello, Some(expected));
+        assert_eq!(parse_hello("127.0.0.1,26380,abc"), None);
+    }
+}
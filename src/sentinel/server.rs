@@ -0,0 +1,247 @@
+use anyhow::Result;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::RwLock,
+};
+
+use crate::redis::types::RedisType;
+
+use super::{Addr, Instance, Master, Peer, Sentinel};
+
+//Sentinels answer a few commands of their own instead of the data commands
+pub async fn serve(listener: TcpListener, sentinel: &'static RwLock<Sentinel>) -> Result<()> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = handle_stream(stream, sentinel).await {
+                println!("Error: {:?}", e);
+            }
+        });
+    }
+}
+
+async fn handle_stream(mut stream: TcpStream, sentinel: &RwLock<Sentinel>) -> Result<()> {
+    let mut buf = [0; 512];
+    let mut pending = Vec::new();
+    loop {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        pending.extend_from_slice(&buf[..n]);
+        let commands = match RedisType::drain_raw_frames(&mut pending) {
+            Ok(commands) => commands,
+            Err(_) => return Err(anyhow::Error::msg("Protocol error")),
+        };
+        for (command, _) in commands {
+            let args: Vec<String> = match command {
+                RedisType::Array(values) => values.iter().map(|v| v.to_string()).collect(),
+                _ => vec![],
+            };
+            let response = execute(sentinel, &args).await;
+            stream.write_all(&response.encode()).await?;
+        }
+    }
+}
+
+async fn execute(sentinel: &RwLock<Sentinel>, args: &[String]) -> RedisType {
+    let command = args.first().map(|c| c.to_uppercase()).unwrap_or_default();
+    match command.as_str() {
+        "PING" => RedisType::SimpleString("PONG".to_string()),
+        "INFO" => RedisType::BulkString(info(&*sentinel.read().await)),
+        "SENTINEL" => sentinel_command(sentinel, &args[1..]).await,
+        _ => RedisType::SimpleError(format!("ERR unknown command '{}'", command.to_lowercase())),
+    }
+}
+
+async fn sentinel_command(sentinel: &RwLock<Sentinel>, args: &[String]) -> RedisType {
+    let subcommand = args.first().map(|c| c.to_lowercase()).unwrap_or_default();
+    let wrong_arity = || {
+        RedisType::SimpleError(format!(
+            "ERR wrong number of arguments for 'sentinel|{}' command",
+            subcommand
+        ))
+    };
+    let no_such_master = || RedisType::SimpleError("ERR No such master with that name".to_string());
+
+    //SENTINEL is-master-down-by-addr <ip> <port> <current-epoch> <runid>
+    if subcommand == "is-master-down-by-addr" {
+        if args.len() != 5 {
+            return wrong_arity();
+        }
+        let port = args[2].parse::<u16>();
+        let epoch = args[3].parse::<u64>();
+        let (port, epoch) = match (port, epoch) {
+            (Ok(port), Ok(epoch)) => (port, epoch),
+            _ => {
+                return RedisType::SimpleError(
+                    "ERR value is not an integer or out of range".to_string(),
+                )
+            }
+        };
+        let addr = (args[1].clone(), port);
+        let mut sentinel = sentinel.write().await;
+        let master = sentinel
+            .masters
+            .values()
+            .find(|master| master.addr == addr)
+            .map(|master| (master.name.clone(), master.master().s_down));
+        let (name, down) = master.unwrap_or_default();
+        //"*" only asks about the master, a run id asks for our vote too
+        let leader = match args[4].as_str() {
+            "*" => None,
+            runid if !name.is_empty() => sentinel.vote(&name, runid, epoch),
+            _ => None,
+        };
+        let (leader, leader_epoch) = leader.unwrap_or(("*".to_string(), 0));
+        return RedisType::Array(vec![
+            RedisType::Integer(down as i64),
+            RedisType::BulkString(leader),
+            RedisType::Integer(leader_epoch as i64),
+        ]);
+    }
+
+    let sentinel = sentinel.read().await;
+    match (subcommand.as_str(), args.len()) {
+        ("myid", 1) => RedisType::BulkString(sentinel.myid.clone()),
+        ("masters", 1) => RedisType::Array(sentinel.masters.values().map(master_fields).collect()),
+        ("master", 2) => match sentinel.masters.get(&args[1]) {
+            Some(master) => master_fields(master),
+            None => no_such_master(),
+        },
+        ("replicas" | "slaves", 2) => match sentinel.masters.get(&args[1]) {
+            Some(master) => RedisType::Array(
+                master
+                    .replicas()
+                    .map(|(addr, instance)| replica_fields(addr, instance))
+                    .collect(),
+            ),
+            None => no_such_master(),
+        },
+        ("sentinels", 2) => match sentinel.masters.get(&args[1]) {
+            Some(master) => RedisType::Array(
+                master
+                    .sentinels
+                    .iter()
+                    .map(|(runid, peer)| sentinel_fields(runid, peer))
+                    .collect(),
+            ),
+            None => no_such_master(),
+        },
+        ("get-master-addr-by-name", 2) => match sentinel.masters.get(&args[1]) {
+            Some(master) => RedisType::Array(vec![
+                RedisType::BulkString(master.addr.0.clone()),
+                RedisType::BulkString(master.addr.1.to_string()),
+            ]),
+            None => RedisType::NullArray,
+        },
+        ("myid" | "masters" | "master" | "replicas" | "slaves" | "sentinels", _)
+        | ("get-master-addr-by-name", _) => wrong_arity(),
+        _ => RedisType::SimpleError(format!("ERR Unknown sentinel subcommand '{}'", subcommand)),
+    }
+}
+
+//Replies are flat lists of field names and values
+fn fields(fields: Vec<(&str, String)>) -> RedisType {
+    RedisType::Array(
+        fields
+            .into_iter()
+            .flat_map(|(name, value)| {
+                [
+                    RedisType::BulkString(name.to_string()),
+                    RedisType::BulkString(value),
+                ]
+            })
+            .collect(),
+    )
+}
+
+fn master_fields(master: &Master) -> RedisType {
+    let mut master_fields = vec![
+        ("name", master.name.clone()),
+        ("ip", master.addr.0.clone()),
+        ("port", master.addr.1.to_string()),
+        ("flags", master.flags()),
+        ("num-slaves", master.replicas().count().to_string()),
+        ("num-other-sentinels", master.sentinels.len().to_string()),
+        ("quorum", master.quorum.to_string()),
+        ("config-epoch", master.config_epoch.to_string()),
+        (
+            "down-after-milliseconds",
+            master.down_after.as_millis().to_string(),
+        ),
+        (
+            "failover-timeout",
+            master.failover_timeout.as_millis().to_string(),
+        ),
+    ];
+    if let Some(failover) = &master.failover {
+        master_fields.push(("failover-state", failover.stage.to_string()));
+    }
+    fields(master_fields)
+}
+
+fn replica_fields(addr: &Addr, instance: &Instance) -> RedisType {
+    let mut flags = "slave".to_string();
+    if instance.s_down {
+        flags.push_str(",s_down");
+    }
+    let info = instance.info.clone().unwrap_or_default();
+    let (master_host, master_port) = info.master.unwrap_or(("?".to_string(), 0));
+    let link_status = if info.master_link_up { "ok" } else { "err" };
+    fields(vec![
+        ("name", format!("{}:{}", addr.0, addr.1)),
+        ("ip", addr.0.clone()),
+        ("port", addr.1.to_string()),
+        ("flags", flags),
+        (
+            "last-ok-ping-reply",
+            instance.last_ok_ping.elapsed().as_millis().to_string(),
+        ),
+        ("master-link-status", link_status.to_string()),
+        ("master-host", master_host),
+        ("master-port", master_port.to_string()),
+        ("slave-repl-offset", info.offset.to_string()),
+    ])
+}
+
+fn sentinel_fields(runid: &str, peer: &Peer) -> RedisType {
+    fields(vec![
+        ("name", runid.to_string()),
+        ("ip", peer.addr.0.clone()),
+        ("port", peer.addr.1.to_string()),
+        ("runid", runid.to_string()),
+        ("flags", "sentinel".to_string()),
+        (
+            "last-hello-message",
+            peer.last_hello.elapsed().as_millis().to_string(),
+        ),
+    ])
+}
+
+fn info(sentinel: &Sentinel) -> String {
+    let mut info = String::new();
+    info.push_str("# Sentinel\n");
+    info.push_str(&format!("sentinel_masters:{}\n", sentinel.masters.len()));
+    for (i, master) in sentinel.masters.values().enumerate() {
+        let status = if master.o_down {
+            "odown"
+        } else if master.master().s_down {
+            "sdown"
+        } else {
+            "ok"
+        };
+        info.push_str(&format!(
+            "master{}:name={},status={},address={}:{},slaves={},sentinels={}\n",
+            i,
+            master.name,
+            status,
+            master.addr.0,
+            master.addr.1,
+            master.replicas().count(),
+            master.sentinels.len() + 1
+        ));
+    }
+    info
+}
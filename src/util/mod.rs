@@ -20,9 +20,84 @@ pub fn gen_rand_number() -> u32 {
     hasher.finish() as u32
 }
 
+//Unix time in milliseconds
+pub fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+//Redis glob patterns: * ? [abc] [^a-z] and \ to match the next character literally
+pub fn glob_match(pattern: &str, string: &str) -> bool {
+    glob_match_bytes(pattern.as_bytes(), string.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], string: &[u8]) -> bool {
+    match pattern {
+        [] => string.is_empty(),
+        [b'*', ..] => {
+            //Consecutive stars match the same as one
+            let rest = match pattern.iter().position(|c| *c != b'*') {
+                Some(i) => &pattern[i..],
+                None => return true,
+            };
+            (0..=string.len()).any(|i| glob_match_bytes(rest, &string[i..]))
+        }
+        [b'?', rest @ ..] => !string.is_empty() && glob_match_bytes(rest, &string[1..]),
+        [b'[', class @ ..] => match string.first() {
+            Some(c) => {
+                let (matched, rest) = match_class(class, *c);
+                matched && glob_match_bytes(rest, &string[1..])
+            }
+            None => false,
+        },
+        [b'\\', escaped, rest @ ..] | [escaped, rest @ ..] => {
+            string.first() == Some(escaped) && glob_match_bytes(rest, &string[1..])
+        }
+    }
+}
+
+//Matches a character against [...], returns the pattern after the closing bracket
+fn match_class(mut pattern: &[u8], c: u8) -> (bool, &[u8]) {
+    let negate = pattern.first() == Some(&b'^');
+    if negate {
+        pattern = &pattern[1..];
+    }
+    let mut matched = false;
+    //An unclosed class ends with the pattern
+    loop {
+        match pattern {
+            [] => break,
+            [b']', rest @ ..] => {
+                pattern = rest;
+                break;
+            }
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                pattern = rest;
+            }
+            [start, b'-', end, rest @ ..] => {
+                let (low, high) = if start <= end {
+                    (*start, *end)
+                } else {
+                    (*end, *start)
+                };
+                matched |= (low..=high).contains(&c);
+                pattern = rest;
+            }
+            [literal, rest @ ..] => {
+                matched |= *literal == c;
+                pattern = rest;
+            }
+        }
+    }
+    (matched != negate, pattern)
+}
+
 #[cfg(test)]
 mod test {
-    use crate::util::gen_rand_string;
+    use crate::util::{gen_rand_string, glob_match};
 
     #[test]
     fn test_gen_rand_string() {
@@ -30,4 +105,21 @@ mod test {
         let s2 = gen_rand_string(40);
         assert_ne!(s1, s2);
     }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("news.*", "news.sports"));
+        assert!(!glob_match("news.*", "weather"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-b]llo", "hbllo"));
+        assert!(glob_match("h[b-a]llo", "hallo"));
+        assert!(glob_match("h\\*llo", "h*llo"));
+        assert!(!glob_match("h\\*llo", "hello"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "axxbyy"));
+    }
 }